@@ -192,10 +192,18 @@ pub fn bytes_to_sockaddr(bytes: &[u8]) -> Result<SocketAddr, Error> {
             Ok(SocketAddr::new(IpAddr::V4(ip), port))
         }
 
-        18 => Err(Error::new_frame(
-            None,
-            Some("IPv6 is not yet implemented".to_owned()),
-        )),
+        18 => {
+            let octets: [u8; 16] = bytes[..16]
+                .try_into()
+                .map_err(|e: std::array::TryFromSliceError| {
+                    Error::new_frame(Some(Box::new(e)), None)
+                })?;
+            let ip = Ipv6Addr::from(octets);
+
+            let port: u16 = u16::from_be_bytes([bytes[16], bytes[17]]);
+
+            Ok(SocketAddr::new(IpAddr::V6(ip), port))
+        }
 
         _ => Err(Error::new_frame(
             None,
@@ -278,6 +286,14 @@ mod test {
         assert_eq!(map1, map);
     }
 
+    #[test]
+    fn test_sockaddr_v6() {
+        let sockaddr: SocketAddr = "[2001:db8::1]:6881".parse().unwrap();
+        let bytes = sockaddr_to_bytes(&sockaddr);
+        assert_eq!(18, bytes.len());
+        assert_eq!(sockaddr, bytes_to_sockaddr(&bytes).unwrap());
+    }
+
     #[test]
     fn test_be_bytes_to_u32() {
         let bytes = [0, 0, 0, 2, b'a', b'b'];
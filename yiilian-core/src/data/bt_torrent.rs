@@ -1,18 +1,30 @@
-use std::fmt;
+use std::{collections::BTreeMap, fmt};
 
 use bytes::Bytes;
 use hex::ToHex;
 use sha1::{Digest, Sha1};
+use sha2::Sha256;
 use crate::{
     common::error::Error,
-    data::{BencodeData, Encode},
+    data::{BencodeData, Encode, Magnet},
 };
 
+/// v2 info_hash (SHA-256) 的字节数
+pub const INFO_HASH_V2_SIZE: usize = 32;
+/// v2 中每个 piece 哈希 (SHA-256) 的字节数
+pub const PIECE_HASH_V2_SIZE: usize = 32;
+
 #[derive(Debug, Clone)]
 pub struct BtTorrent {
+    /// 十六进制大写的 info_hash。v1 和混合种子为 SHA-1，
+    /// 纯 v2 种子为截断到 20 字节的 SHA-256（与握手、DHT 中使用的一致）
     pub info_hash: String,
+    /// 十六进制大写的 v2 info_hash (SHA-256)，v1 种子为 None
+    pub info_hash_v2: Option<String>,
     pub announce: String,
     pub info: MetaInfo,
+    /// 解析前的种子文件内容，用于保存做种的会话，手动构造时可以为空
+    pub raw: Bytes,
 }
 
 #[derive(Clone)]
@@ -29,6 +41,17 @@ pub enum MetaInfo {
         pieces: Bytes,
         piece_length: usize,
     },
+    /// v2 (BEP52) 种子，包括同时带有 v1 信息的混合种子
+    V2 {
+        /// 展开后的 file tree
+        files: Vec<FileInfoV2>,
+        name: String,
+        piece_length: usize,
+        /// pieces root => 该文件的 piece 哈希列表，通过 ut_metadata 获取的种子没有该字段
+        piece_layers: BTreeMap<Bytes, Bytes>,
+        /// 混合种子中 v1 的 pieces，纯 v2 种子为 None
+        v1_pieces: Option<Bytes>,
+    },
 }
 
 impl fmt::Debug for MetaInfo {
@@ -58,18 +81,83 @@ impl fmt::Debug for MetaInfo {
                 f.entry(&"pieces", &format!("...({} bytes)...", pieces.len()));
                 f.entry(&"piece length", piece_length);
             }
+            MetaInfo::V2 {
+                files,
+                name,
+                piece_length,
+                piece_layers,
+                v1_pieces,
+            } => {
+                f.entry(&"files", files);
+                f.entry(&"name", name);
+                f.entry(&"piece length", piece_length);
+                f.entry(&"piece layers", &format!("...({} files)...", piece_layers.len()));
+                if let Some(v1_pieces) = v1_pieces {
+                    f.entry(&"pieces", &format!("...({} bytes)...", v1_pieces.len()));
+                }
+            }
         }
 
         f.finish()
     }
 }
 
+impl BtTorrent {
+    /// 资源的总字节数
+    pub fn total_length(&self) -> i64 {
+        match &self.info {
+            MetaInfo::SingleFile { length, .. } => *length,
+            MetaInfo::MultiFile { files, .. } => files.iter().map(|item| item.length).sum(),
+            MetaInfo::V2 { files, .. } => files.iter().map(|item| item.length).sum(),
+        }
+    }
+
+    /// 生成磁力链接，包含名称、总长度和 announce tracker
+    pub fn to_magnet(&self) -> Result<Magnet, Error> {
+        let info_hash: [u8; 20] = hex::decode(&self.info_hash)
+            .ok()
+            .and_then(|val| val.try_into().ok())
+            .ok_or_else(|| Error::new_decode(&format!("BtTorrent info_hash is invalid: {}", self.info_hash)))?;
+
+        let name = match &self.info {
+            MetaInfo::SingleFile { name, .. } => name,
+            MetaInfo::MultiFile { name, .. } => name,
+            MetaInfo::V2 { name, .. } => name,
+        };
+
+        let mut magnet = Magnet::new(info_hash);
+        if let Some(info_hash_v2) = &self.info_hash_v2 {
+            let info_hash_v2: [u8; INFO_HASH_V2_SIZE] = hex::decode(info_hash_v2)
+                .ok()
+                .and_then(|val| val.try_into().ok())
+                .ok_or_else(|| Error::new_decode(&format!("BtTorrent info_hash_v2 is invalid: {}", info_hash_v2)))?;
+            magnet.info_hash_v2 = Some(info_hash_v2);
+        }
+        magnet.display_name = Some(name.to_owned());
+        magnet.exact_length = Some(self.total_length() as u64);
+        if !self.announce.is_empty() {
+            magnet.trackers.push(self.announce.clone());
+        }
+
+        Ok(magnet)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FileInfo {
     pub length: i64,
     pub path: String,
 }
 
+#[derive(Debug, Clone)]
+pub struct FileInfoV2 {
+    pub length: i64,
+    /// file tree 中的路径，用 '/' 拼接
+    pub path: String,
+    /// 文件 merkle 树的根哈希，空文件为 None
+    pub pieces_root: Option<Bytes>,
+}
+
 impl TryFrom<&[u8]> for BtTorrent {
     type Error = Error;
 
@@ -95,6 +183,30 @@ impl TryFrom<&[u8]> for BtTorrent {
                 i_hash.encode_hex_upper()
             };
 
+            if is_v2_info(info) {
+                let info_hash_v2: String = {
+                    let mut hasher = Sha256::new();
+                    hasher.update(info.encode());
+                    hasher.finalize().to_vec().encode_hex_upper()
+                };
+                let info = decode_v2_info(info, data.get(&b"piece layers"[..]))?;
+
+                // 纯 v2 种子使用截断的 SHA-256 作为 info_hash
+                let info_hash = if let MetaInfo::V2 { v1_pieces: None, .. } = info {
+                    info_hash_v2[..40].to_owned()
+                } else {
+                    info_hash
+                };
+
+                return Ok(BtTorrent {
+                    announce,
+                    info,
+                    info_hash,
+                    info_hash_v2: Some(info_hash_v2),
+                    raw: Bytes::copy_from_slice(value),
+                });
+            }
+
             if info.has_key("length") {
                 let info = info.as_map()?;
                 let length = info
@@ -176,9 +288,13 @@ impl TryFrom<&[u8]> for BtTorrent {
                             .unwrap_or(&BencodeData::Int(0))
                             .as_int()? as usize;
                         let path = if let Some(path) = item.get(&b"path"[..]) {
-                            let path = path.as_list()?;
-                            let path = path[0].as_bstr()?;
-                            unsafe { String::from_utf8_unchecked(path.to_vec()) }
+                            // path 是多级目录列表，用 '/' 拼接
+                            let mut tmp_path = vec![];
+                            for item in path.as_list()? {
+                                let item = item.as_bstr()?;
+                                tmp_path.push(unsafe { String::from_utf8_unchecked(item.to_vec()) });
+                            }
+                            tmp_path.join("/")
                         } else {
                             Err(Error::new_decode(&format!(
                                 "BtTorrent 'name' field decode error : {:?}",
@@ -221,6 +337,255 @@ impl TryFrom<&[u8]> for BtTorrent {
             )))?
         };
 
-        Ok(BtTorrent { announce, info, info_hash })
+        Ok(BtTorrent {
+            announce,
+            info,
+            info_hash,
+            info_hash_v2: None,
+            raw: Bytes::copy_from_slice(value),
+        })
+    }
+}
+
+fn is_v2_info(info: &BencodeData) -> bool {
+    let meta_version = info
+        .get_dict_item("meta version")
+        .and_then(|val| val.as_int().ok());
+
+    meta_version == Some(2) && info.has_key("file tree")
+}
+
+fn decode_v2_info(info: &BencodeData, piece_layers: Option<&BencodeData>) -> Result<MetaInfo, Error> {
+    let info = info.as_map()?;
+
+    let name = if let Some(name) = info.get(&b"name"[..]) {
+        String::from_utf8_lossy(name.as_bstr()?).into_owned()
+    } else {
+        Err(Error::new_decode("BtTorrent v2 'name' field not found"))?
+    };
+    let piece_length = if let Some(piece_length) = info.get(&b"piece length"[..]) {
+        piece_length.as_int()? as usize
+    } else {
+        Err(Error::new_decode("BtTorrent v2 'piece length' field not found"))?
+    };
+
+    let mut files = vec![];
+    if let Some(file_tree) = info.get(&b"file tree"[..]) {
+        decode_file_tree(file_tree, &mut vec![], &mut files)?;
+    }
+
+    let piece_layers = match piece_layers {
+        Some(piece_layers) => {
+            let mut rst = BTreeMap::new();
+            for (pieces_root, layer) in piece_layers.as_map()? {
+                let layer = layer.as_bstr()?;
+                if layer.len() % PIECE_HASH_V2_SIZE != 0 {
+                    Err(Error::new_decode(&format!(
+                        "BtTorrent 'piece layers' len is invalid: {}",
+                        layer.len()
+                    )))?
+                }
+                rst.insert(pieces_root.clone(), layer.clone());
+            }
+            rst
+        }
+        None => BTreeMap::new(),
+    };
+
+    let v1_pieces = match info.get(&b"pieces"[..]) {
+        Some(pieces) => Some(pieces.as_bstr()?.to_owned()),
+        None => None,
+    };
+
+    Ok(MetaInfo::V2 {
+        files,
+        name,
+        piece_length,
+        piece_layers,
+        v1_pieces,
+    })
+}
+
+/// 深度优先展开 file tree，键为 "" 的节点是文件
+fn decode_file_tree(node: &BencodeData, path: &mut Vec<String>, files: &mut Vec<FileInfoV2>) -> Result<(), Error> {
+    for (key, child) in node.as_map()? {
+        if key.is_empty() {
+            if path.is_empty() {
+                Err(Error::new_decode("BtTorrent 'file tree' has file without path"))?
+            }
+
+            let length = child
+                .get_dict_item("length")
+                .map(|val| val.as_int())
+                .unwrap_or(Ok(0))?;
+            let pieces_root = match child.get_dict_item("pieces root") {
+                Some(root) => {
+                    let root = root.as_bstr()?;
+                    if root.len() != PIECE_HASH_V2_SIZE {
+                        Err(Error::new_decode(&format!(
+                            "BtTorrent 'pieces root' len is invalid: {}",
+                            root.len()
+                        )))?
+                    }
+                    Some(root.clone())
+                }
+                None => None,
+            };
+
+            files.push(FileInfoV2 {
+                length,
+                path: path.join("/"),
+                pieces_root,
+            });
+        } else {
+            path.push(String::from_utf8_lossy(key).into_owned());
+            decode_file_tree(child, path, files)?;
+            path.pop();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use bytes::Bytes;
+    use sha2::{Digest, Sha256};
+
+    use crate::{
+        data::{BencodeData, Encode},
+        map,
+    };
+
+    use super::{BtTorrent, MetaInfo};
+
+    fn file_node(length: i64, pieces_root: Option<&'static [u8]>) -> BencodeData {
+        let mut file: BTreeMap<Bytes, BencodeData> = map! {
+            "length".into() => length.into(),
+        };
+        if let Some(pieces_root) = pieces_root {
+            file.insert("pieces root".into(), pieces_root.into());
+        }
+
+        let node: BTreeMap<Bytes, BencodeData> = map! {
+            "".into() => file.into(),
+        };
+        node.into()
+    }
+
+    #[test]
+    fn test_v2_and_hybrid() {
+        let dir1: BTreeMap<Bytes, BencodeData> = map! {
+            "a.txt".into() => file_node(20000, Some(&[1; 32])),
+        };
+        let file_tree: BTreeMap<Bytes, BencodeData> = map! {
+            "dir1".into() => dir1.into(),
+            "b.txt".into() => file_node(0, None),
+        };
+        let mut info: BTreeMap<Bytes, BencodeData> = map! {
+            "file tree".into() => file_tree.into(),
+            "meta version".into() => 2.into(),
+            "name".into() => "test".into(),
+            "piece length".into() => 16384.into(),
+        };
+        let piece_layers: BTreeMap<Bytes, BencodeData> = map! {
+            Bytes::from(&[1u8; 32][..]) => vec![2u8; 64].into(),
+        };
+
+        let torrent: BTreeMap<Bytes, BencodeData> = map! {
+            "info".into() => info.clone().into(),
+            "piece layers".into() => piece_layers.into(),
+        };
+        let bt_torrent = BtTorrent::try_from(&torrent.encode()[..]).unwrap();
+
+        let info_hash_v2: String = hex::encode_upper(Sha256::digest(info.encode()));
+        assert_eq!(Some(info_hash_v2.clone()), bt_torrent.info_hash_v2);
+        assert_eq!(info_hash_v2[..40], bt_torrent.info_hash);
+        assert_eq!(20000, bt_torrent.total_length());
+
+        match &bt_torrent.info {
+            MetaInfo::V2 { files, piece_length, piece_layers, v1_pieces, .. } => {
+                assert_eq!(16384, *piece_length);
+                assert_eq!("b.txt", files[0].path);
+                assert!(files[0].pieces_root.is_none());
+                assert_eq!("dir1/a.txt", files[1].path);
+                assert_eq!(20000, files[1].length);
+                assert_eq!(64, piece_layers[&[1u8; 32][..]].len());
+                assert!(v1_pieces.is_none());
+            }
+            _ => panic!("expect MetaInfo::V2"),
+        }
+
+        // 混合种子：info_hash 仍为 SHA-1
+        info.insert("pieces".into(), vec![3u8; 40].into());
+        info.insert("length".into(), 20000.into());
+        let torrent: BTreeMap<Bytes, BencodeData> = map! {
+            "info".into() => info.into(),
+        };
+        let bt_torrent = BtTorrent::try_from(&torrent.encode()[..]).unwrap();
+        assert_eq!(40, bt_torrent.info_hash.len());
+        assert_eq!(64, bt_torrent.info_hash_v2.as_ref().unwrap().len());
+        assert_ne!(bt_torrent.info_hash_v2.as_ref().unwrap()[..40], bt_torrent.info_hash);
+        match &bt_torrent.info {
+            MetaInfo::V2 { v1_pieces, piece_layers, .. } => {
+                assert_eq!(40, v1_pieces.as_ref().unwrap().len());
+                assert!(piece_layers.is_empty());
+            }
+            _ => panic!("expect MetaInfo::V2"),
+        }
+
+        let magnet = bt_torrent.to_magnet().unwrap();
+        assert!(magnet.to_string().contains("&xt=urn:btmh:1220"));
+    }
+
+    #[test]
+    fn test_to_magnet() {
+        let info: BTreeMap<Bytes, BencodeData> = map! {
+            "length".into() => 20000.into(),
+            "name".into() => "a b.txt".into(),
+            "piece length".into() => 16384.into(),
+            "pieces".into() => vec![3u8; 40].into(),
+        };
+        let torrent: BTreeMap<Bytes, BencodeData> = map! {
+            "announce".into() => "udp://tracker.example.com:80/announce".into(),
+            "info".into() => info.into(),
+        };
+        let bt_torrent = BtTorrent::try_from(&torrent.encode()[..]).unwrap();
+
+        let magnet = bt_torrent.to_magnet().unwrap().to_string();
+        assert_eq!(
+            format!(
+                "magnet:?xt=urn:btih:{}&dn=a%20b.txt&xl=20000&tr=udp%3A%2F%2Ftracker.example.com%3A80%2Fannounce",
+                bt_torrent.info_hash
+            ),
+            magnet
+        );
+
+        // 纯 v2 种子只有 btmh，不能输出截断的 btih
+        let file_tree: BTreeMap<Bytes, BencodeData> = map! {
+            "a.txt".into() => file_node(20000, Some(&[1; 32])),
+        };
+        let info: BTreeMap<Bytes, BencodeData> = map! {
+            "file tree".into() => file_tree.into(),
+            "meta version".into() => 2.into(),
+            "name".into() => "test".into(),
+            "piece length".into() => 16384.into(),
+        };
+        let torrent: BTreeMap<Bytes, BencodeData> = map! {
+            "info".into() => info.into(),
+        };
+        let bt_torrent = BtTorrent::try_from(&torrent.encode()[..]).unwrap();
+
+        let magnet = bt_torrent.to_magnet().unwrap().to_string();
+        assert_eq!(
+            format!(
+                "magnet:?xt=urn:btmh:1220{}&dn=test&xl=20000",
+                bt_torrent.info_hash_v2.as_ref().unwrap()
+            ),
+            magnet
+        );
+        assert!(!magnet.contains("urn:btih"));
     }
 }
@@ -0,0 +1,300 @@
+use std::{fmt, net::SocketAddr, str::FromStr};
+
+use hex::ToHex;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::common::error::Error;
+
+const MAGNET_PREFIX: &str = "magnet:?";
+const BTIH_PREFIX: &str = "urn:btih:";
+/// v2 的 multihash 前缀：0x12 (sha2-256) + 0x20 (32 字节)
+const BTMH_PREFIX: &str = "urn:btmh:1220";
+const INFO_HASH_SIZE: usize = 20;
+const INFO_HASH_V2_SIZE: usize = 32;
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// 参数值中需要百分号编码的字符（保留 RFC3986 中的 unreserved 字符）
+const MAGNET_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// 磁力链接 (BEP9)：magnet:?xt=urn:btih:<info-hash>&dn=<name>&tr=<tracker>&x.pe=<peer>&xl=<length>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Magnet {
+    /// btih，只有 btmh 时为 v2 info_hash 截断到 20 字节
+    pub info_hash: [u8; INFO_HASH_SIZE],
+    /// btmh (BEP52)
+    pub info_hash_v2: Option<[u8; INFO_HASH_V2_SIZE]>,
+    /// dn
+    pub display_name: Option<String>,
+    /// tr
+    pub trackers: Vec<String>,
+    /// x.pe
+    pub peers: Vec<SocketAddr>,
+    /// 以域名表示的 x.pe (host:port)，使用前需要解析
+    pub peer_hosts: Vec<String>,
+    /// xl
+    pub exact_length: Option<u64>,
+}
+
+impl Magnet {
+    pub fn new(info_hash: [u8; INFO_HASH_SIZE]) -> Self {
+        Magnet {
+            info_hash,
+            info_hash_v2: None,
+            display_name: None,
+            trackers: vec![],
+            peers: vec![],
+            peer_hosts: vec![],
+            exact_length: None,
+        }
+    }
+
+    /// 大写的十六进制 info_hash，与 BtTorrent::info_hash 格式一致
+    pub fn info_hash_hex(&self) -> String {
+        self.info_hash.encode_hex_upper()
+    }
+
+    /// 纯 v2 资源没有 btih，info_hash 是截断的 btmh
+    pub fn is_v2_only(&self) -> bool {
+        match &self.info_hash_v2 {
+            Some(info_hash_v2) => info_hash_v2[..INFO_HASH_SIZE] == self.info_hash,
+            None => false,
+        }
+    }
+
+    pub fn parse(uri: &str) -> Result<Self, Error> {
+        let query = match uri.get(..MAGNET_PREFIX.len()) {
+            Some(prefix) if prefix.eq_ignore_ascii_case(MAGNET_PREFIX) => &uri[MAGNET_PREFIX.len()..],
+            _ => Err(Error::new_decode(&format!("magnet uri prefix is invalid: {}", uri)))?,
+        };
+
+        let mut info_hash = None;
+        let mut magnet = Magnet::new([0; INFO_HASH_SIZE]);
+
+        for param in query.split('&').filter(|item| !item.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let value = decode_value(value)?;
+
+            // 同名参数可以带序号后缀，如 tr.1、tr.2
+            let key = match key.rsplit_once('.') {
+                Some((base, index)) if !index.is_empty() && index.bytes().all(|c| c.is_ascii_digit()) => base,
+                _ => key,
+            };
+
+            match key {
+                "xt" => {
+                    // 只取第一个 btih 和 btmh，其它 urn 忽略
+                    if let Some(hash) = strip_prefix_ignore_case(&value, BTIH_PREFIX) {
+                        if info_hash.is_none() {
+                            info_hash = Some(decode_info_hash(hash)?);
+                        }
+                    } else if let Some(hash) = strip_prefix_ignore_case(&value, BTMH_PREFIX) {
+                        if magnet.info_hash_v2.is_none() {
+                            let hash = hex::decode(hash)
+                                .ok()
+                                .and_then(|val| val.try_into().ok())
+                                .ok_or_else(|| Error::new_decode(&format!("btmh is invalid: {}", hash)))?;
+                            magnet.info_hash_v2 = Some(hash);
+                        }
+                    }
+                }
+                "dn" => magnet.display_name = Some(value),
+                "tr" => {
+                    if !magnet.trackers.contains(&value) {
+                        magnet.trackers.push(value);
+                    }
+                }
+                "x.pe" => match value.parse() {
+                    Ok(peer) => magnet.peers.push(peer),
+                    Err(_) if is_host_port(&value) => magnet.peer_hosts.push(value),
+                    Err(_) => Err(Error::new_decode(&format!("magnet x.pe is invalid: {}", value)))?,
+                },
+                "xl" => {
+                    let length = value.parse().map_err(|_| {
+                        Error::new_decode(&format!("magnet xl is invalid: {}", value))
+                    })?;
+                    magnet.exact_length = Some(length);
+                }
+                _ => {}
+            }
+        }
+
+        match (info_hash, magnet.info_hash_v2) {
+            (Some(info_hash), _) => {
+                magnet.info_hash = info_hash;
+                Ok(magnet)
+            }
+            (None, Some(info_hash_v2)) => {
+                magnet.info_hash.copy_from_slice(&info_hash_v2[..INFO_HASH_SIZE]);
+                Ok(magnet)
+            }
+            (None, None) => Err(Error::new_decode(&format!("magnet uri has no btih or btmh: {}", uri))),
+        }
+    }
+}
+
+impl FromStr for Magnet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Magnet::parse(s)
+    }
+}
+
+impl fmt::Display for Magnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut params = vec![];
+
+        // 纯 v2 资源只写 btmh，不写截断的 btih
+        if !self.is_v2_only() {
+            params.push(format!("xt={}{}", BTIH_PREFIX, self.info_hash_hex()));
+        }
+
+        if let Some(info_hash_v2) = &self.info_hash_v2 {
+            params.push(format!("xt={}{}", BTMH_PREFIX, info_hash_v2.encode_hex_upper::<String>()));
+        }
+
+        if let Some(display_name) = &self.display_name {
+            params.push(format!("dn={}", utf8_percent_encode(display_name, MAGNET_VALUE)));
+        }
+
+        if let Some(exact_length) = self.exact_length {
+            params.push(format!("xl={}", exact_length));
+        }
+
+        for tracker in &self.trackers {
+            params.push(format!("tr={}", utf8_percent_encode(tracker, MAGNET_VALUE)));
+        }
+
+        for peer in &self.peers {
+            params.push(format!("x.pe={}", utf8_percent_encode(&peer.to_string(), MAGNET_VALUE)));
+        }
+
+        for peer in &self.peer_hosts {
+            params.push(format!("x.pe={}", utf8_percent_encode(peer, MAGNET_VALUE)));
+        }
+
+        write!(f, "{}{}", MAGNET_PREFIX, params.join("&"))
+    }
+}
+
+/// 是否为 host:port 形式的 peer
+fn is_host_port(value: &str) -> bool {
+    match value.rsplit_once(':') {
+        Some((host, port)) => {
+            !host.is_empty()
+                && port.parse::<u16>().is_ok()
+                && host.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'.')
+        }
+        None => false,
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    match value.get(..prefix.len()) {
+        Some(val) if val.eq_ignore_ascii_case(prefix) => Some(&value[prefix.len()..]),
+        _ => None,
+    }
+}
+
+fn decode_value(value: &str) -> Result<String, Error> {
+    // 部分客户端用 '+' 表示空格
+    let value = value.replace('+', " ");
+
+    percent_decode_str(&value)
+        .decode_utf8()
+        .map(|val| val.into_owned())
+        .map_err(|error| Error::new_decode(&format!("magnet value is not utf8: {}, {}", value, error)))
+}
+
+/// info_hash 可以是 40 位十六进制或 32 位 base32
+fn decode_info_hash(hash: &str) -> Result<[u8; INFO_HASH_SIZE], Error> {
+    let bytes = match hash.len() {
+        40 => hex::decode(hash)
+            .map_err(|error| Error::new_decode(&format!("btih hex is invalid: {}, {}", hash, error)))?,
+        32 => base32_decode(hash)?,
+        _ => Err(Error::new_decode(&format!("btih len is invalid: {}", hash)))?,
+    };
+
+    bytes
+        .try_into()
+        .map_err(|_| Error::new_decode(&format!("btih is invalid: {}", hash)))
+}
+
+fn base32_decode(value: &str) -> Result<Vec<u8>, Error> {
+    let mut rst = Vec::with_capacity(value.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in value.bytes() {
+        let index = BASE32_ALPHABET
+            .iter()
+            .position(|item| *item == c.to_ascii_uppercase())
+            .ok_or_else(|| Error::new_decode(&format!("base32 char is invalid: {}", value)))?;
+
+        buffer = (buffer << 5) | index as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            rst.push((buffer >> bits) as u8);
+        }
+    }
+
+    Ok(rst)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::Magnet;
+
+    #[test]
+    fn test_parse() {
+        let magnet = Magnet::parse(
+            "magnet:?xt=urn:btih:fa84a39c18d5960b0272d3e1d2a7900fb09f5eb3&dn=Big+Buck%20Bunny\
+            &tr.1=udp%3A%2F%2Ftracker.example.com%3A80&tr.2=http%3A%2F%2Ftracker.example.org%2Fannounce\
+            &x.pe=1.2.3.4:6881&x.pe=[::1]:6882&x.pe=host.example.com:6883&xl=1024",
+        )
+        .unwrap();
+
+        assert_eq!("FA84A39C18D5960B0272D3E1D2A7900FB09F5EB3", magnet.info_hash_hex());
+        assert_eq!(Some("Big Buck Bunny".to_owned()), magnet.display_name);
+        assert_eq!(
+            vec!["udp://tracker.example.com:80", "http://tracker.example.org/announce"],
+            magnet.trackers
+        );
+        assert_eq!(
+            vec!["1.2.3.4:6881".parse::<SocketAddr>().unwrap(), "[::1]:6882".parse().unwrap()],
+            magnet.peers
+        );
+        assert_eq!(vec!["host.example.com:6883"], magnet.peer_hosts);
+        assert_eq!(Some(1024), magnet.exact_length);
+
+        // 格式化后再解析，结果一致
+        let uri = magnet.to_string();
+        assert!(uri.starts_with("magnet:?xt=urn:btih:FA84A39C18D5960B0272D3E1D2A7900FB09F5EB3&dn=Big%20Buck%20Bunny"));
+        assert_eq!(magnet, uri.parse::<Magnet>().unwrap());
+
+        // base32
+        let magnet = Magnet::parse("magnet:?xt=urn:btih:7KCKHHAY2WLAWATS2PQ5FJ4QB6YJ6XVT").unwrap();
+        assert_eq!("FA84A39C18D5960B0272D3E1D2A7900FB09F5EB3", magnet.info_hash_hex());
+
+        // 混合种子同时带有 btih 和 btmh
+        let btmh = "urn:btmh:1220caf1e1c30e81cb361b9ee167c4aa64228a7fa4fa9f6105232b28ad099f3a302e";
+        let magnet = Magnet::parse(&format!("magnet:?xt={}", btmh)).unwrap();
+        assert_eq!("CAF1E1C30E81CB361B9EE167C4AA64228A7FA4FA", magnet.info_hash_hex());
+        assert!(magnet.info_hash_v2.is_some());
+        assert!(magnet.is_v2_only());
+        assert_eq!(format!("magnet:?xt={}", btmh.to_uppercase().replace("URN:BTMH:", "urn:btmh:")), magnet.to_string());
+        assert_eq!(magnet, magnet.to_string().parse::<Magnet>().unwrap());
+
+        assert!(Magnet::parse("http://example.com").is_err());
+        assert!(Magnet::parse("magnet:?dn=abc").is_err());
+        assert!(Magnet::parse("magnet:?xt=urn:btih:abc").is_err());
+        assert!(Magnet::parse("magnet:?xt=urn:btih:7KCKHHAY2WLAWATS2PQ5FJ4QB6YJ6XVT&x.pe=host").is_err());
+    }
+}
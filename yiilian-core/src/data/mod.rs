@@ -4,10 +4,12 @@ mod response;
 mod body;
 mod bt_handshake;
 mod bt_torrent;
+mod magnet;
 
 pub use request::*; 
 pub use bencode::*;
 pub use response::*;
 pub use body::*;
 pub use bt_handshake::*;
-pub use bt_torrent::*;
\ No newline at end of file
+pub use bt_torrent::*;
+pub use magnet::*;
\ No newline at end of file
@@ -1,12 +1,15 @@
 use std::error::Error as StdError;
 
 use bytes::Bytes;
-use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream, time::timeout};
+use tokio::{io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt}, time::timeout};
 
 use crate::{common::error::Error, data::{BtHandshake, HANDSHAKE_LEN, MESSAGE_EXTENSION_ENABLE}};
 
 // read reads size-length bytes from conn to data.
-pub async fn read(stream: &mut TcpStream, buf: &mut [u8]) -> Result<usize, Box<dyn StdError + Send + Sync>> {
+pub async fn read<S>(stream: &mut S, buf: &mut [u8]) -> Result<usize, Box<dyn StdError + Send + Sync>>
+where
+    S: AsyncRead + Unpin,
+{
     let duration = tokio::time::Duration::from_secs(10);
     
     let n = timeout(duration, stream.read_exact(buf)).await??;
@@ -15,11 +18,14 @@ pub async fn read(stream: &mut TcpStream, buf: &mut [u8]) -> Result<usize, Box<d
     Ok(n)
 }
 
-pub async fn send_bt_handshake(
-    stream: &mut TcpStream,
+pub async fn send_bt_handshake<S>(
+    stream: &mut S,
     info_hash: &[u8],
     peer_id: &[u8],
-) -> Result<(), Error> {
+) -> Result<(), Error>
+where
+    S: AsyncWrite + Unpin,
+{
     let hs = BtHandshake::new(&MESSAGE_EXTENSION_ENABLE, &info_hash, &peer_id);
     let hs: Bytes = hs.into();
 
@@ -32,7 +38,10 @@ pub async fn send_bt_handshake(
 }
 
 /// 读取并校验 bt 握手消息
-pub async fn read_bt_handshake(stream: &mut TcpStream) -> Result<BtHandshake, Error> {
+pub async fn read_bt_handshake<S>(stream: &mut S) -> Result<BtHandshake, Error>
+where
+    S: AsyncRead + Unpin,
+{
     let mut buf: [u8; HANDSHAKE_LEN] = [0; HANDSHAKE_LEN];
     read(stream, &mut buf)
         .await
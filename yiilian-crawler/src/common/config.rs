@@ -5,11 +5,13 @@ use std::{collections::HashSet, fs, net::IpAddr, path::PathBuf};
 use serde::{Deserialize, Serialize};
 use yiilian_core::{net::block_list::BlockAddr, common::util::atoi};
 use yiilian_dl::bt::common::BtConfig;
+use yiilian_mq::topic_config::TopicConfig;
 
 #[derive(Deserialize, Default, Debug)]
 pub struct Config {
     pub dht_cluster: DhtClusterConfig,
     pub bt: BtConfig,
+    pub mq: Option<MqConfig>,
 }
 
 impl Config {
@@ -51,10 +53,18 @@ pub struct DhtClusterConfig {
     pub ports: Vec<u16>,
     pub workers: Option<usize>,
     pub firewall: Option<FirewallConfig>,
+    /// 是否同时加入 IPv6 DHT 网络 (BEP32)
+    pub ipv6: Option<bool>,
 }
 
 #[derive(Default, Deserialize, Serialize, Debug)]
 pub struct FirewallConfig {
     pub max_trace: Option<usize>,
     pub max_block: Option<usize>,
-}
\ No newline at end of file
+}
+/// 各 topic 的保留策略，未配置时沿用 topic 元数据文件中的配置
+#[derive(Default, Deserialize, Serialize, Debug)]
+pub struct MqConfig {
+    pub info_hash: Option<TopicConfig>,
+    pub info_index: Option<TopicConfig>,
+}
@@ -1,8 +1,8 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use tokio::sync::broadcast::{error::RecvError, Receiver};
 use yiilian_core::data::Request;
-use yiilian_dht::data::body::{BodyKind, KrpcBody, Query};
+use yiilian_dht::data::body::{BodyKind, KrpcBody, Query, Reply};
 use yiilian_mq::{engine::Engine, message::in_message::InMessage};
 
 use crate::info_message::{InfoMessage, MessageType};
@@ -10,11 +10,11 @@ use crate::info_message::{InfoMessage, MessageType};
 #[derive(Debug)]
 pub struct RecvAnnounceListener<T> {
     rx: Receiver<Arc<T>>,
-    mq_engine: Arc<Mutex<Engine>>,
+    mq_engine: Arc<Engine>,
 }
 
 impl RecvAnnounceListener<Request<KrpcBody>> {
-    pub fn new(rx: Receiver<Arc<Request<KrpcBody>>>, mq_engine: Arc<Mutex<Engine>>) -> Self {
+    pub fn new(rx: Receiver<Arc<Request<KrpcBody>>>, mq_engine: Arc<Engine>) -> Self {
 
         RecvAnnounceListener { 
             rx, 
@@ -45,7 +45,7 @@ impl RecvAnnounceListener<Request<KrpcBody>> {
 
                             log::debug!(target: "yiilian_crawler::event::announce_listener", "Send message: {:?}", data);
 
-                            self.mq_engine.lock().expect("lock mq_engin").push_message("info_hash", InMessage(data.into())).ok();
+                            self.mq_engine.push_keyed_message("info_hash", data.key(), InMessage(data.into())).ok();
                         }
                         BodyKind::Query(Query::AnnouncePeer(val)) => {
                             let remote_addr = {
@@ -77,7 +77,24 @@ impl RecvAnnounceListener<Request<KrpcBody>> {
 
                             log::debug!(target: "yiilian_crawler::event::announce_listener", "Send message: {:?}", data);
 
-                            self.mq_engine.lock().expect("lock mq_engin").push_message("info_hash", InMessage(data.into())).ok();
+                            self.mq_engine.push_keyed_message("info_hash", data.key(), InMessage(data.into())).ok();
+                        }
+                        BodyKind::Reply(Reply::SampleInfoHashes(val)) => {
+                            // 爬虫主动发出的 sample_infohashes 请求(BEP51)的响应
+                            for sample in &val.samples {
+                                let info_hash: [u8; 20] = sample.get_bytes()[0..]
+                                    .try_into()
+                                    .expect("Decode info_hash error");
+
+                                let data = InfoMessage {
+                                    try_times: 1,
+                                    info_type: MessageType::Normal(info_hash),
+                                };
+
+                                log::debug!(target: "yiilian_crawler::event::announce_listener", "Send message: {:?}", data);
+
+                                self.mq_engine.push_keyed_message("info_hash", data.key(), InMessage(data.into())).ok();
+                            }
                         }
                         _ => (),
                     }
@@ -19,6 +19,23 @@ pub enum MessageType {
     AnnouncePeer {info_hash: [u8; 20], remote_addr: SocketAddr}
 }
 
+impl InfoMessage {
+    /// 在 mq 中的消息 key，用于去重和压缩。announce_peer 消息带有来源地址，按 info_hash + 地址区分
+    pub fn key(&self) -> Bytes {
+        let mut rst = BytesMut::new();
+
+        match &self.info_type {
+            MessageType::Normal(info_hash) => rst.extend_from_slice(info_hash),
+            MessageType::AnnouncePeer { info_hash, remote_addr } => {
+                rst.extend_from_slice(info_hash);
+                rst.extend_from_slice(&sockaddr_to_bytes(remote_addr));
+            }
+        }
+
+        rst.into()
+    }
+}
+
 impl From<InfoMessage> for Bytes {
     fn from(value: InfoMessage) -> Self {
         let mut rst = BytesMut::new();
@@ -1,9 +1,9 @@
 use std::{
-    fs::{self, File}, io::{Read, Write}, net::SocketAddr, path::PathBuf, sync::{Arc, Mutex, RwLock}, time::Duration
+    fs::{self, File}, io::{Read, Write}, net::SocketAddr, path::PathBuf, sync::{Arc, RwLock}, time::Duration
 };
 
 use bloomfilter::Bloom;
-use futures::future::join_all;
+use futures::{future::join_all, StreamExt};
 
 use hex::ToHex;
 use tokio::{
@@ -46,6 +46,9 @@ use yiilian_crawler::{
 const BLOOM_STATE_FILE: &str = "bloom_state.dat";
 const HASH_TOPIC_NAME: &str = "info_hash";
 const INDEX_TOPIC_NAME: &str = "info_index";
+const DOWNLOAD_META_CLIENT: &str = "download_meta_client";
+/// 下载元数据的消息租约时长
+const META_VISIBILITY_SECS: u64 = 10 * 60;
 const CONFIG_FILE: &str = "yiilian-crawler.yml";
 const LOG_CONFIG_FILE: &str = "log4rs.yml";
 const RES_TEMPLATE_DB: &str = "res_template.db";
@@ -63,15 +66,21 @@ async fn main() {
     let (tx, rx) = broadcast::channel(1024);
     let dht_list = create_dht_list(&config, shutdown_rx.clone(), tx, wd.home_dir()).unwrap();
     let mq_engine = {
-        let mut engine = Engine::new(LOG_DATA_SIZE, wd.home_dir()).expect("create mq engine");
-        engine
-            .open_topic(HASH_TOPIC_NAME)
-            .expect(&format!("open {} topic", HASH_TOPIC_NAME));
-        engine
-            .open_topic(INDEX_TOPIC_NAME)
-            .expect(&format!("open {} topic", INDEX_TOPIC_NAME));
-
-        Arc::new(Mutex::new(engine))
+        let engine = Engine::new(LOG_DATA_SIZE, wd.home_dir()).expect("create mq engine");
+        let mq_config = config.mq.as_ref();
+
+        for (topic_name, topic_config) in [
+            (HASH_TOPIC_NAME, mq_config.and_then(|c| c.info_hash.clone())),
+            (INDEX_TOPIC_NAME, mq_config.and_then(|c| c.info_index.clone())),
+        ] {
+            match topic_config {
+                Some(topic_config) => engine.open_topic_with(topic_name, topic_config),
+                None => engine.open_topic(topic_name),
+            }
+            .expect(&format!("open {} topic", topic_name));
+        }
+
+        Arc::new(engine)
     };
 
     let mut announce_listener = RecvAnnounceListener::new(rx, mq_engine.clone());
@@ -145,6 +154,12 @@ async fn main() {
 
     let mut term_sig = tokio::signal::unix::signal(SignalKind::terminate()).unwrap();
 
+    // 每个分区一个下载任务，租约保证同一条消息不会同时分发给多个任务
+    let download_meta_tasks = {
+        let partitions = mq_engine.partition_count(HASH_TOPIC_NAME).unwrap_or(1);
+        join_all((0..partitions).map(|_| download_meta_by_msg(mq_engine.clone(), &bt_downloader, bloom.clone())))
+    };
+
     tokio::select! {
         _  = async {
             let mut futs = vec![];
@@ -157,7 +172,7 @@ async fn main() {
         } => (),
         _ = announce_listener.listen() => (),
         _ = bt_downloader.run_loop() => (),
-        _ = download_meta_by_msg(mq_engine.clone(), &bt_downloader, bloom.clone()) => (),
+        _ = download_meta_tasks => (),
         _ = hook(&bt_downloader, bloom.clone(), config.bt.download_port, mq_engine.clone()) => (),
         _= mq_db.persist_loop() => (),
         _= db_doc.index_loop() => (),
@@ -192,7 +207,7 @@ async fn hook(
     bt_downloader: &BtDownloader,
     bloom: Arc<RwLock<Bloom<u64>>>,
     port: u16,
-    mq_engine: Arc<Mutex<Engine>>,
+    mq_engine: Arc<Engine>,
 ) {
     let bind_addr: SocketAddr = format!("0.0.0.0:{port}")
         .parse()
@@ -233,6 +248,14 @@ async fn hook(
                 };
                 let info_str: String = info_hash.encode_hex_upper();
 
+                // 本地正在下载或做种的资源，由 bt_downloader 在后台上传分片
+                if bt_downloader.has_session(&info_hash) {
+                    if let Err(error) = bt_downloader.serve_peer(stream, &info_hash) {
+                        log::trace!(target: "yiilian_crawler::main::hook", "{}", error);
+                    }
+                    continue;
+                }
+
                 let bloom_val = hex::encode(info_hash);
                 let bloom_val = hash_it(bloom_val);
                 let chk_rst = bloom.read().expect("bloom.read() error").check(&bloom_val);
@@ -254,8 +277,6 @@ async fn hook(
                             };
                             let message = yiilian_mq::message::in_message::InMessage(path.into());
                             if let Err(error) = mq_engine
-                                .lock()
-                                .expect("lock mq_engin")
                                 .push_message(INDEX_TOPIC_NAME, message)
                             {
                                 log::trace!(target: "yiilian_crawler::main::hook", "push_message error: {}", error);
@@ -274,152 +295,122 @@ async fn hook(
 }
 
 async fn download_meta_by_msg(
-    mq_engine: Arc<Mutex<Engine>>,
+    mq_engine: Arc<Engine>,
     bt_downloader: &BtDownloader,
     bloom: Arc<RwLock<Bloom<u64>>>,
 ) {
-    loop {
-        let msg_rst = mq_engine
-            .lock()
-            .expect("lock mq_engin")
-            .poll_message(HASH_TOPIC_NAME, "download_meta_client");
-
-        if let Some(msg) = msg_rst {
-            log::trace!(target: "yiilian_crawler::main", "poll message offset : {}", msg.offset());
-
-            // todo! info_message and if not download then change message to normal and send into mq again
-
-            let info_message: InfoMessage = {
-                match msg.value().try_into() {
-                    Ok(msg) => msg,
-                    Err(error) => {
-                        log::trace!(target: "yiilian_crawler::download_meta", "Decode info_message error: {:?} ", error);
-                        continue;
-                    }
+    let mut leases = match mq_engine.subscribe_lease(
+        HASH_TOPIC_NAME,
+        DOWNLOAD_META_CLIENT,
+        Duration::from_secs(META_VISIBILITY_SECS),
+    ) {
+        Ok(leases) => Box::pin(leases),
+        Err(error) => {
+            log::error!(target: "yiilian_crawler::main::download_meta_by_msg", "subscribe {} error: {}", HASH_TOPIC_NAME, error);
+            return;
+        }
+    };
+
+    while let Some((msg, lease)) = leases.next().await {
+        log::trace!(target: "yiilian_crawler::main", "poll message offset : {}, delivery_count: {}", msg.offset(), lease.delivery_count);
+
+        let rst = match InfoMessage::try_from(msg.value()) {
+            Ok(info_message) => {
+                // 下载失败的消息 nack 后由 mq 延迟重新分发，进程中途退出的消息在可见性超时后也会重新分发。
+                // try_times 表示最多分发的次数，用完后转入死信 topic
+                if download_meta_by_info(&info_message, &mq_engine, bt_downloader, &bloom).await {
+                    mq_engine.ack_partition(HASH_TOPIC_NAME, lease.partition, DOWNLOAD_META_CLIENT, lease.offset)
+                } else if lease.delivery_count >= info_message.try_times as u32 {
+                    mq_engine.dead_letter_partition(HASH_TOPIC_NAME, lease.partition, DOWNLOAD_META_CLIENT, lease.offset)
+                } else {
+                    mq_engine.nack_partition(HASH_TOPIC_NAME, lease.partition, DOWNLOAD_META_CLIENT, lease.offset)
                 }
-            };
+            }
+            Err(error) => {
+                log::trace!(target: "yiilian_crawler::download_meta", "Decode info_message error: {:?} ", error);
+                mq_engine.dead_letter_partition(HASH_TOPIC_NAME, lease.partition, DOWNLOAD_META_CLIENT, lease.offset)
+            }
+        };
 
-            match info_message.info_type {
-                MessageType::Normal(info_hash) => {
-                    if info_message.try_times <= 0 {
-                        continue;
-                    }
+        if let Err(error) = rst {
+            log::trace!(target: "yiilian_crawler::main::download_meta_by_msg", "commit message error: {}", error);
+        }
+    }
+}
 
-                    let mut blocked_addrs = vec![];
-                    let info_str: String = info_hash.encode_hex_upper();
+/// 返回 false 表示下载失败，需要重试
+async fn download_meta_by_info(
+    info_message: &InfoMessage,
+    mq_engine: &Arc<Engine>,
+    bt_downloader: &BtDownloader,
+    bloom: &Arc<RwLock<Bloom<u64>>>,
+) -> bool {
+    let (info_hash, path_rst) = match info_message.info_type {
+        MessageType::Normal(info_hash) => {
+            let bloom_val = hash_it(hex::encode(info_hash));
+            if bloom.read().expect("bloom.read() error").check(&bloom_val) {
+                return true;
+            }
 
-                    match bt_downloader
-                        .download_meta(&info_hash, &mut blocked_addrs, false)
-                        .await
-                    {
-                        Ok(path) => {
-                            let bloom_val = hex::encode(info_hash);
-                            let bloom_val = hash_it(bloom_val);
-                            let chk_rst =
-                                bloom.read().expect("bloom.read() error").check(&bloom_val);
-
-                            if !chk_rst {
-                                // 如果没命中且成功下载，则加入到布隆过滤其中，并输出到日志
-                                bloom.write().expect("bloom.write() error").set(&bloom_val);
-
-                                log::debug!(target: "yiilian_crawler::main", "{} is downloaded", info_str);
-
-                                let path = match path.to_str() {
-                                    Some(p) => p.to_owned(),
-                                    None => continue,
-                                };
-                                let message =
-                                    yiilian_mq::message::in_message::InMessage(path.into());
-                                if let Err(error) = mq_engine
-                                    .lock()
-                                    .expect("lock mq_engin")
-                                    .push_message(INDEX_TOPIC_NAME, message)
-                                {
-                                    log::trace!(target: "yiilian_crawler::main::hook", "push_message error: {}", error);
-                                }
-                            }
-                        }
-                        Err(error) => {
-                            log::trace!(target: "yiilian_crawler::main::download_meta_by_msg", "Resend message by error: {error}");
+            let mut blocked_addrs = vec![];
+            let rst = bt_downloader
+                .download_meta(&info_hash, &mut blocked_addrs, false)
+                .await;
 
-                            let msg_data = InfoMessage {
-                                try_times: info_message.try_times - 1,
-                                info_type: MessageType::Normal(info_hash),
-                            };
+            (info_hash, rst)
+        }
+        MessageType::AnnouncePeer {
+            info_hash,
+            remote_addr,
+        } => {
+            let bloom_val = hash_it(hex::encode(info_hash));
+            if bloom.read().expect("bloom.read() error").check(&bloom_val) {
+                return true;
+            }
 
-                            mq_engine
-                                .lock()
-                                .expect("lock mq_engin")
-                                .push_message(HASH_TOPIC_NAME, InMessage(msg_data.into()))
-                                .ok();
-                        }
-                    }
+            let stream = match tokio::net::TcpStream::connect(remote_addr).await {
+                Ok(s) => s,
+                Err(error) => {
+                    log::trace!(target: "yiilian_crawler::main::download_meta_by_msg", "Connect {} error: {error}", remote_addr);
+                    return false;
                 }
-                MessageType::AnnouncePeer {
-                    info_hash,
-                    remote_addr,
-                } => {
-                    if info_message.try_times <= 0 {
-                        continue;
-                    }
+            };
 
-                    let info_str: String = info_hash.encode_hex_upper();
-                    let bloom_val = hex::encode(info_hash);
-                    let bloom_val = hash_it(bloom_val);
-                    let chk_rst = bloom.read().expect("bloom.read() error").check(&bloom_val);
-
-                    let stream = if let Ok(s) = tokio::net::TcpStream::connect(remote_addr).await {
-                        s
-                    } else {
-                        continue;
-                    };
-
-                    if !chk_rst {
-                        match bt_downloader
-                            .download_meta_from_target(stream, &info_hash, false)
-                            .await
-                        {
-                            Ok(path) => {
-                                // 如果没命中且成功下载，则加入到布隆过滤其中，并输出到日志
-                                bloom.write().expect("bloom.write() error").set(&bloom_val);
-
-                                log::debug!(target: "yiilian_crawler::main::download_meta_by_msg", "{} is downloaded", info_str);
-
-                                let path = match path.to_str() {
-                                    Some(p) => p.to_owned(),
-                                    None => continue,
-                                };
-                                let message =
-                                    yiilian_mq::message::in_message::InMessage(path.into());
-                                if let Err(error) = mq_engine
-                                    .lock()
-                                    .expect("lock mq_engin")
-                                    .push_message(INDEX_TOPIC_NAME, message)
-                                {
-                                    log::trace!(target: "yiilian_crawler::main::hook", "push_message error: {}", error);
-                                }
-                            }
-                            Err(error) => {
-                                log::trace!(target: "yiilian_crawler::main::download_meta_by_msg", "Resend message by error: {error}");
-
-                                let msg_data = InfoMessage {
-                                    try_times: info_message.try_times - 1,
-                                    info_type: MessageType::Normal(info_hash),
-                                };
-
-                                mq_engine
-                                    .lock()
-                                    .expect("lock mq_engin")
-                                    .push_message(HASH_TOPIC_NAME, InMessage(msg_data.into()))
-                                    .ok();
-                            }
-                        }
-                    }
-                }
-            }
+            let rst = bt_downloader
+                .download_meta_from_target(stream, &info_hash, false)
+                .await;
+
+            (info_hash, rst)
         }
+    };
+
+    let info_str: String = info_hash.encode_hex_upper();
+
+    match path_rst {
+        Ok(path) => {
+            // 下载成功，则加入到布隆过滤其中，并输出到日志
+            let bloom_val = hash_it(hex::encode(info_hash));
+            bloom.write().expect("bloom.write() error").set(&bloom_val);
 
-        tokio::time::sleep(Duration::from_secs(1)).await;
+            log::debug!(target: "yiilian_crawler::main::download_meta_by_msg", "{} is downloaded", info_str);
+
+            let path = match path.to_str() {
+                Some(p) => p.to_owned(),
+                None => return true,
+            };
+            let message = InMessage(path.into());
+            if let Err(error) = mq_engine
+                .push_message(INDEX_TOPIC_NAME, message)
+            {
+                log::trace!(target: "yiilian_crawler::main::hook", "push_message error: {}", error);
+            }
+
+            true
+        }
+        Err(error) => {
+            log::trace!(target: "yiilian_crawler::main::download_meta_by_msg", "Download {} error: {error}", info_str);
+            false
+        }
     }
 }
 
@@ -440,12 +431,14 @@ fn create_dht_list(
     let block_ips = config.get_dht_block_list();
     let workers = config.dht_cluster.workers;
 
-    let settings = if let Some(routers) = &config.dht_cluster.routers {
-        let mut st = SettingsBuilder::new().build();
-        st.routers = routers.clone();
-        Some(st)
-    } else {
-        None
+    let settings = {
+        let ipv6 = config.dht_cluster.ipv6.unwrap_or(false);
+        Some(
+            SettingsBuilder::new()
+                .routers(&config.dht_cluster.routers)
+                .ipv6(ipv6)
+                .build(),
+        )
     };
 
     let (firewall_max_trace, firewall_max_block) = {
@@ -1,21 +1,23 @@
-use std::{collections::HashMap, sync::{Mutex, RwLock}};
+use std::sync::{Arc, Mutex, RwLock, Weak};
 
-use once_cell::sync::OnceCell;
-use yiilian_core::common::expect_log::ExpectLog;
+use yiilian_core::common::error::Error;
 
 use crate::{
-    net::Client, peer::PeerManager, routing_table::RoutingTable, transaction::TransactionManager
+    item::ItemManager, net::Client, peer::PeerManager, routing_table::RoutingTable, transaction::TransactionManager
 };
 
 use super::{setting::Settings, state::State};
 
-pub static mut DHT_CONTEXT: OnceCell<HashMap<u16, Context>> = OnceCell::new();
-
+/// 一个 DHT 实例的全部状态，由 Dht 持有。
+///
+/// TransactionManager 和 RoutingTable 属于 Context，只持有它的 Weak，避免循环引用，
+/// 所以 Dht 被 drop 后 Context 及其 socket 会随之释放
 pub struct Context {
     settings: Settings,
     state: RwLock<State>,
     routing_table: Mutex<RoutingTable>,
     peer_manager: Mutex<PeerManager>,
+    item_manager: Mutex<ItemManager>,
     transaction_manager: TransactionManager,
     client: Client,
 }
@@ -26,6 +28,7 @@ impl Context {
         state: RwLock<State>,
         routing_table: Mutex<RoutingTable>,
         peer_manager: Mutex<PeerManager>,
+        item_manager: Mutex<ItemManager>,
         transaction_manager: TransactionManager,
         client: Client,
     ) -> Self {
@@ -34,6 +37,7 @@ impl Context {
             state,
             routing_table,
             peer_manager,
+            item_manager,
             transaction_manager,
             client,
         }
@@ -55,6 +59,10 @@ impl Context {
         &self.peer_manager
     }
 
+    pub fn item_manager(&self) -> &Mutex<ItemManager> {
+        &self.item_manager
+    }
+
     pub fn transaction_manager(&self) -> &TransactionManager {
         &self.transaction_manager
     }
@@ -64,49 +72,27 @@ impl Context {
     }
 }
 
-pub fn dht_ctx(ctx_index: u16) -> &'static Context {
-    let ctx_map = unsafe { DHT_CONTEXT.get().expect_error("DHT_CONTEXT get() is None") };
-    let ctx = ctx_map.get(&ctx_index).expect_error("Item in DHT_CONTEXT Map is not set");
-
-    ctx
-}
-
-pub fn dht_ctx_insert(ctx_index: u16, context: Context) {
-    unsafe { 
-        DHT_CONTEXT.get_or_init(|| {
-            HashMap::new()
-        });
-
-        let map = DHT_CONTEXT.get_mut().expect_error("DHT_CONTEXT get_mut() is None");
-        map.insert(ctx_index, context);
-    };
-}
-
-pub fn dht_ctx_drop(ctx_index: u16) {
-    let ctx_map = unsafe { DHT_CONTEXT.get_mut().expect_error("DHT_CONTEXT get() is None") };
-    ctx_map.remove(&ctx_index);
-}
-
-pub fn dht_ctx_settings(ctx_index: u16) -> &'static Settings {
-    dht_ctx(ctx_index).settings()
-}
-
-pub fn dht_ctx_state(ctx_index: u16) -> &'static RwLock<State> {
-    dht_ctx(ctx_index).state()
-}
-
-pub fn dht_ctx_routing_tbl(ctx_index: u16) -> &'static Mutex<RoutingTable> {
-    dht_ctx(ctx_index).routing_table()
+/// DHT 实例的句柄，可以 clone 到其它任务中使用。
+///
+/// 只持有 Context 的 Weak，不会阻止 Dht 释放，Dht 被 drop 后的请求返回错误
+#[derive(Clone, Debug)]
+pub struct DhtHandle {
+    ctx: Weak<Context>,
 }
 
-pub fn dht_ctx_peer_mgr(ctx_index: u16) -> &'static Mutex<PeerManager> {
-    dht_ctx(ctx_index).peer_manager()
-}
+impl DhtHandle {
+    pub fn new(ctx: Weak<Context>) -> Self {
+        DhtHandle { ctx }
+    }
 
-pub fn dht_ctx_trans_mgr(ctx_index: u16) -> &'static TransactionManager {
-    dht_ctx(ctx_index).transaction_manager()
-}
+    /// Dht 已被 drop 时返回错误
+    pub fn ctx(&self) -> Result<Arc<Context>, Error> {
+        self.ctx
+            .upgrade()
+            .ok_or_else(|| Error::new_general("DHT context is dropped"))
+    }
 
-pub fn dht_ctx_client(ctx_index: u16) -> &'static Client {
-    dht_ctx(ctx_index).client()
+    pub fn is_closed(&self) -> bool {
+        self.ctx.strong_count() == 0
+    }
 }
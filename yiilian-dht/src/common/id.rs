@@ -267,15 +267,18 @@ impl IdPrefixMagic {
             IpAddr::V6(ipv6) => {
                 let r64: u64 = seed_r.into();
                 let magic: u64 = 0x0103070f1f3f7fff;
+                // BEP42 只对 IPv6 地址的前 64 位做 hash
                 let ip_int: u64 = u64::from_be_bytes(
-                    ipv6.octets()[8..]
+                    ipv6.octets()[..8]
                         .try_into()
                         .expect("Failed to get IPv6 bytes"),
                 );
                 let nonsense: u64 = ip_int & magic | (r64 << 61);
                 let crc: u32 = CASTAGNOLI.checksum(&nonsense.to_be_bytes());
                 IdPrefixMagic {
-                    prefix: crc.to_be_bytes()[..2].try_into().expect("Failed to poop"),
+                    prefix: crc.to_be_bytes()[..3]
+                        .try_into()
+                        .expect("Failed to convert bytes 0-2 of the crc into a 3-byte array"),
                     suffix: seed_r,
                 }
             }
@@ -339,6 +342,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_from_ip_v6() {
+        // CRC32-C((前 64 位 & 0x0103070f1f3f7fff) | (r << 61)) 的前 21 位
+        assert_eq!(
+            IdPrefixMagic::from_ip(&"2001:db8:85a3:8d3:1319:8a2e:370:7348".parse().unwrap(), 1),
+            IdPrefixMagic {
+                prefix: [0x81, 0x13, 0xdd],
+                suffix: 1
+            }
+        );
+        assert_eq!(
+            IdPrefixMagic::from_ip(&"2a01:4f8:c17:1a2b::1".parse().unwrap(), 86),
+            IdPrefixMagic {
+                prefix: [0x08, 0x2c, 0x93],
+                suffix: 0x56
+            }
+        );
+        assert_eq!(
+            IdPrefixMagic::from_ip(&"fe80::1".parse().unwrap(), 0),
+            IdPrefixMagic {
+                prefix: [0x8c, 0x28, 0xb2],
+                suffix: 0
+            }
+        );
+
+        // 后 64 位不参与计算
+        assert_eq!(
+            IdPrefixMagic::from_ip(&"2a01:4f8:c17:1a2b::1".parse().unwrap(), 86),
+            IdPrefixMagic::from_ip(&"2a01:4f8:c17:1a2b:ffff:ffff:ffff:ffff".parse().unwrap(), 86),
+        );
+    }
+
     #[test]
     fn test_generate_valid_id() {
         let ip = IpAddr::V4(Ipv4Addr::new(124, 31, 75, 21));
@@ -346,6 +381,17 @@ mod tests {
         assert!(id.is_valid_for_ip(&ip, &HashSet::new()));
     }
 
+    #[test]
+    fn test_generate_valid_id_v6() {
+        let ip: IpAddr = "2001:db8::ff00:42:8329".parse().unwrap();
+        let id = Id::from_ip(&ip);
+        assert!(id.is_valid_for_ip(&ip, &HashSet::new()));
+
+        // 同一 /64 网段内的地址共用节点 id
+        let other: IpAddr = "2001:db8::1".parse().unwrap();
+        assert!(id.is_valid_for_ip(&other, &HashSet::new()));
+    }
+
     #[test]
     fn test_id_xor() {
         let h1 = Id::from_hex("0000000000000000000000000000000000000001").unwrap();
@@ -1,27 +1,32 @@
-
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 use yiilian_core::common::expect_log::ExpectLog;
 
 #[derive(Clone, Debug)]
 /// 群体投票决策权重的IP
-struct IPV4Vote {
-    ip: Ipv4Addr,
+struct IPVote<A> {
+    ip: A,
     votes: i32,
 }
 
-/// An IPV4Source that takes a certain number of "votes" from other nodes on the network to make its decision.
+/// An IP source that takes a certain number of "votes" from other nodes on the network to make its decision.
 /// 有群体投票决策权重的 IP 列表, votes.first() 就是投票最多的 IP
 #[derive(Clone, Debug)]
-pub struct IPV4Consensus {
+pub struct IPConsensus<A> {
     min_votes: usize,
     max_votes: usize,
-    votes: Vec<IPV4Vote>, // 本机 IP 和 被投票数
+    votes: Vec<IPVote<A>>, // 本机 IP 和 被投票数
 }
 
-impl IPV4Consensus {
-    pub fn new(min_votes: usize, max_votes: usize) -> IPV4Consensus {
-        IPV4Consensus {
+/// IPv4 外网地址投票
+pub type IPV4Consensus = IPConsensus<Ipv4Addr>;
+
+/// IPv6 外网地址投票 (BEP32)
+pub type IPV6Consensus = IPConsensus<Ipv6Addr>;
+
+impl<A> IPConsensus<A> {
+    pub fn new(min_votes: usize, max_votes: usize) -> IPConsensus<A> {
+        IPConsensus {
             min_votes,
             max_votes,
             votes: Vec::new(),
@@ -29,21 +34,34 @@ impl IPV4Consensus {
     }
 }
 
-impl IPV4Consensus {
-    /// Retrieves the IPv4 address that the source thinks we should have,
+impl IPConsensus<Ipv4Addr> {
+    /// 返回当前最佳猜测的本机外网 IPv4 地址
+    pub fn get_best_ipv4(&self) -> Option<Ipv4Addr> {
+        self.get_best_ip()
+    }
+}
+
+impl IPConsensus<Ipv6Addr> {
+    /// 返回当前最佳猜测的本机外网 IPv6 地址
+    pub fn get_best_ipv6(&self) -> Option<Ipv6Addr> {
+        self.get_best_ip()
+    }
+}
+
+impl<A: Copy + PartialEq> IPConsensus<A> {
+    /// Retrieves the IP address that the source thinks we should have,
     /// or None if it can't make a determination at this time.
     ///
     /// This method will be called periodically by the DHT. Implementations
-    /// should return their current best guess for the external (globally routable) IPv4 address
+    /// should return their current best guess for the external (globally routable) IP address
     /// of the DHT.
     ///
-    /// 该方法将被 DHT 定期调用。 返回当前最佳猜测的本机外网（全局可路由）IPv4 地址。
-    /// 取出被投票数最多的外网 ipv4 地址，如果获取的投票数没超过阈值，则返回 None
-    pub fn get_best_ipv4(&self) -> Option<Ipv4Addr> {
+    /// 该方法将被 DHT 定期调用。 返回当前最佳猜测的本机外网（全局可路由）IP 地址。
+    /// 取出被投票数最多的外网 IP 地址，如果获取的投票数没超过阈值，则返回 None
+    pub fn get_best_ip(&self) -> Option<A> {
         let first = self.votes.first();
         match first {
             Some(vote_info) => {
-                // log::debug!(target: "rustydht_lib::IPV4AddrSource", "Best IPv4 address {:?} has {} votes", vote_info.ip, vote_info.votes);
                 if vote_info.votes
                     >= self
                         .min_votes
@@ -63,14 +81,14 @@ impl IPV4Consensus {
     /// Adds a "vote" from another node in the DHT in respose to our queries.
     ///
     /// DHT will call this method when it receive a "hint" from another DHT node
-    /// about our external IPv4 address. An IPV4AddrSource implementation can
+    /// about our external IP address. An IP source implementation can
     /// use these "hints" or "votes", or ignore them.
     ///
     /// # Parameters
     /// * `their_addr` - The IP address of the DHT node that we're learning this information from.
     /// * `proposed_addr` - The external IP address that the other DHT node says we have.
     /// 投票并排序，proposed_addr 是被投票的本机（外网） IP
-    pub fn add_vote(&mut self, _: Ipv4Addr, proposed_addr: Ipv4Addr) {
+    pub fn add_vote(&mut self, _: A, proposed_addr: A) {
         let mut do_sort = false;
         for vote in self.votes.iter_mut() {
             if vote.ip == proposed_addr {
@@ -85,7 +103,7 @@ impl IPV4Consensus {
             // 降序排列
             self.votes.sort_by(|a, b| b.votes.cmp(&a.votes));
         } else {
-            self.votes.push(IPV4Vote {
+            self.votes.push(IPVote {
                 ip: proposed_addr,
                 votes: 1,
             });
@@ -147,4 +165,20 @@ mod tests {
         // Nobody wins now
         assert_eq!(None, src.get_best_ipv4());
     }
+
+    #[test]
+    fn test_consensus_src_v6() {
+        let mut src = IPV6Consensus::new(2, 4);
+        let voter = Ipv6Addr::UNSPECIFIED;
+        let addr: Ipv6Addr = "2001:db8::1".parse().unwrap();
+
+        src.add_vote(voter, addr);
+        assert_eq!(None, src.get_best_ipv6());
+
+        src.add_vote(voter, addr);
+        assert_eq!(Some(addr), src.get_best_ipv6());
+
+        src.decay();
+        assert_eq!(None, src.get_best_ipv6());
+    }
 }
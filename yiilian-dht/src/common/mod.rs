@@ -6,7 +6,7 @@ mod context;
 mod util;
 
 pub use id::{Id, ID_SIZE};
-pub use ip::IPV4Consensus;
+pub use ip::{IPConsensus, IPV4Consensus, IPV6Consensus};
 pub use state::State;
 pub use setting::{Settings, SettingsBuilder};
 pub use context::*;
@@ -10,8 +10,6 @@
 #[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct Settings {
-    pub ctx_index: i32,
-
     pub block_list_max_size: usize,
 
     /// block duration
@@ -68,6 +66,21 @@ pub struct Settings {
     /// Max number of peers per torrent to store
     pub max_peers_per_resource: usize,
 
+    /// Max number of BEP44 items to store
+    pub max_items: usize,
+
+    /// When asked to provide an item, we'll only provide ones that were put within this time
+    pub item_freshness_secs: u64,
+
+    /// Max number of info_hash samples to provide in response to a sample_infohashes (BEP51)
+    pub max_samples_response: usize,
+
+    /// The interval we ask other nodes to wait before sending another sample_infohashes
+    pub sample_infohashes_interval_secs: u64,
+
+    /// 爬虫模式下，主动发出 sample_infohashes 请求的时间间隔
+    pub sample_infohashes_loop_interval_secs: u64,
+
     /// We'll think about pinging and pruning nodes at this interval
     pub ping_check_interval_secs: u64,
 
@@ -83,6 +96,11 @@ pub struct Settings {
     /// 如果我们位于限制性 NAT/防火墙后面并且无法接受来自我们尚未发送任何内容的 IP 的传入数据包，则这非常有用。
     pub read_only: bool,
 
+    /// If true, the DHT will bind a dual-stack socket and join the IPv6 network too (BEP32)
+    ///
+    /// 如果为 true，DHT 将绑定双栈 socket，同时加入 IPv6 网络 (BEP32)
+    pub ipv6: bool,
+
     /// Vector of hostnames/ports that the DHT will use as DHT routers for
     /// bootstrapping purposes.
     ///
@@ -95,6 +113,12 @@ pub struct Settings {
     /// 发送 query 超时时长
     pub send_query_timeout_sec: u64,
 
+    /// 迭代查找中每个 query 的超时时长
+    pub lookup_query_timeout_sec: u64,
+
+    /// 迭代查找中同时在途的 query 数
+    pub lookup_alpha: usize,
+
     /// 发送下一次 query 的时间间隔
     pub send_next_query_interval_sec: u64,
 
@@ -109,7 +133,6 @@ pub struct Settings {
 impl Default for Settings {
     fn default() -> Self {
         Settings {
-            ctx_index: -1,
             block_list_max_size: 65535,
 
             timeout_block_duration_sec: 10,
@@ -129,9 +152,15 @@ impl Default for Settings {
             find_nodes_skip_count: 32,
             max_resources: 50,
             max_peers_per_resource: 100,
+            max_items: 500,
+            item_freshness_secs: 2 * 60 * 60,
+            max_samples_response: 20,
+            sample_infohashes_interval_secs: 6 * 60 * 60,
+            sample_infohashes_loop_interval_secs: 1,
             ping_check_interval_secs: 10,
             outgoing_request_prune_secs: 30,
             read_only: false,
+            ipv6: false,
             routers: vec![
                 // "127.0.0.1:6111".to_string(),
                 // "87.98.162.88:6881".to_string(),
@@ -141,6 +170,8 @@ impl Default for Settings {
             ],
             transaction_cleanup_interval_sec: 10,
             send_query_timeout_sec: 15,
+            lookup_query_timeout_sec: 5,
+            lookup_alpha: 3,
             send_next_query_interval_sec: 1,
             token_refresh_interval_sec: 300,
             ip4_maintenance_interval_sec: 10,
@@ -167,7 +198,6 @@ impl SettingsBuilder {
     pub fn new() -> SettingsBuilder {
         Self::default()
     }
-    make_builder_method!(ctx_index, i32);
     make_builder_method!(token_secret_size, usize);
     make_builder_method!(max_peers_response, usize);
     make_builder_method!(router_ping_interval_secs, u64);
@@ -179,11 +209,19 @@ impl SettingsBuilder {
     make_builder_method!(find_nodes_skip_count, usize);
     make_builder_method!(max_resources, usize);
     make_builder_method!(max_peers_per_resource, usize);
+    make_builder_method!(max_items, usize);
+    make_builder_method!(item_freshness_secs, u64);
+    make_builder_method!(max_samples_response, usize);
+    make_builder_method!(sample_infohashes_interval_secs, u64);
+    make_builder_method!(sample_infohashes_loop_interval_secs, u64);
     make_builder_method!(ping_check_interval_secs, u64);
     make_builder_method!(outgoing_request_prune_secs, u64);
     make_builder_method!(read_only, bool);
+    make_builder_method!(ipv6, bool);
     make_builder_method!(transaction_cleanup_interval_sec, u64);
     make_builder_method!(send_query_timeout_sec, u64);
+    make_builder_method!(lookup_query_timeout_sec, u64);
+    make_builder_method!(lookup_alpha, usize);
     make_builder_method!(send_next_query_interval_sec, u64);
     make_builder_method!(token_refresh_interval_sec, u64);
     make_builder_method!(ip4_maintenance_interval_sec, u64);
@@ -1,5 +1,5 @@
 
-use super::{id::Id, ip::IPV4Consensus};
+use super::{id::Id, ip::{IPV4Consensus, IPV6Consensus}};
 
 #[derive(Debug)]
 /// 存放当前 DHT 的各项状态参数
@@ -10,6 +10,9 @@ pub struct State {
     /// 有群体投票决策权重的 IP 列表
     pub ip4_source: IPV4Consensus,
 
+    /// 有群体投票决策权重的 IPv6 列表
+    pub ip6_source: IPV6Consensus,
+
     /// 当前生成 token 的密钥
     pub token_secret: Vec<u8>,
 
@@ -24,11 +27,13 @@ impl State {
     pub fn new(
         local_id: Id,
         ip4_source: IPV4Consensus,
+        ip6_source: IPV6Consensus,
         token_secret: Vec<u8>,
     ) -> Self {
         State {
             local_id,
             ip4_source,
+            ip6_source,
             old_token_secret: token_secret.clone(),
             token_secret,
             is_join_kad: false,
@@ -45,18 +45,40 @@ pub fn calculate_token<T: AsRef<[u8]>>(remote: &SocketAddr, secret: T) -> [u8; 4
 /// assert_eq!(node, rst[0]);
 /// ```
 pub fn bytes_to_nodes4(bytes: &[u8], id_size: usize) -> Result<Vec<Node>, Error> {
-    let bytes = bytes.as_ref();
-    let node4_byte_size: usize = id_size + 6;
-    if bytes.len() % node4_byte_size != 0 {
+    bytes_to_nodes(bytes, id_size, 6)
+}
+
+/// 紧凑格式转 IPv6 Node 数组 (node_id + ipv6 + port)，BEP32
+///
+/// # Example
+/// ```
+/// # use yiilian_dht::routing_table::*;
+/// # use yiilian_dht::common::*;
+/// use std::net::SocketAddr;
+///
+/// let mut data = vec![0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,1];
+/// data.extend([0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,1, 0,80]);
+/// let rst = bytes_to_nodes6(&data, 20).unwrap();
+/// let id = Id::from_bytes(&vec![0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,1]).unwrap();
+/// let sock_addr: SocketAddr = "[::1]:80".parse().unwrap();
+/// assert_eq!(Node::new(id, sock_addr), rst[0]);
+/// ```
+pub fn bytes_to_nodes6(bytes: &[u8], id_size: usize) -> Result<Vec<Node>, Error> {
+    bytes_to_nodes(bytes, id_size, 18)
+}
+
+fn bytes_to_nodes(bytes: &[u8], id_size: usize, addr_size: usize) -> Result<Vec<Node>, Error> {
+    let node_byte_size: usize = id_size + addr_size;
+    if !bytes.len().is_multiple_of(node_byte_size) {
         Err(Error::new_frame(None, Some(format!("Wrong number of bytes for nodes message ({})", bytes.len()))))?;
     }
 
-    let expected_num = bytes.len() / node4_byte_size;
+    let expected_num = bytes.len() / node_byte_size;
     let mut to_ret = Vec::with_capacity(expected_num);
-    for i in 0..bytes.len() / node4_byte_size {
-        let i = i * node4_byte_size;
+    for i in 0..expected_num {
+        let i = i * node_byte_size;
         let id = Id::from_bytes(&bytes[i..i + id_size])?;
-        let sockaddr = bytes_to_sockaddr(&bytes[i + id_size..i + node4_byte_size])?;
+        let sockaddr = bytes_to_sockaddr(&bytes[i + id_size..i + node_byte_size])?;
         let node = Node::<>::new(id, sockaddr);
         to_ret.push(node);
     }
@@ -65,6 +87,8 @@ pub fn bytes_to_nodes4(bytes: &[u8], id_size: usize) -> Result<Vec<Node>, Error>
 }
 
 ///  Node 数组转紧凑格式 (node_id + ip + port)
+///
+/// IPv6 节点会被编码成 38 字节 (node_id + ipv6 + port)，调用方需要保证 nodes 中的地址族一致
 /// 
 /// # Example
 /// ```
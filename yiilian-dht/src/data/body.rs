@@ -10,8 +10,10 @@ use crate::transaction::TransactionId;
 use super::frame::Frame;
 use super::{
     announce_peer::AnnouncePeer, error::RError, find_node::FindNode,
-    find_node_reply::FindNodeReply, get_peers::GetPeers, get_peers_reply::GetPeersReply,
-    ping::Ping, ping_announce_replay::PingOrAnnounceReply,
+    find_node_reply::FindNodeReply, get::Get, get_peers::GetPeers,
+    get_peers_reply::GetPeersReply, get_reply::GetReply, ping::Ping,
+    ping_announce_replay::PingOrAnnounceReply, put::Put,
+    sample_infohashes::SampleInfoHashes, sample_infohashes_reply::SampleInfoHashesReply,
 };
 
 #[derive(Debug, Clone)]
@@ -71,6 +73,9 @@ pub enum Query {
     FindNode(FindNode),
     GetPeers(GetPeers),
     AnnouncePeer(AnnouncePeer),
+    Get(Get),
+    Put(Put),
+    SampleInfoHashes(SampleInfoHashes),
 }
 
 #[derive(Debug, Clone)]
@@ -78,6 +83,8 @@ pub enum Reply {
     PingOrAnnounce(PingOrAnnounceReply),
     FindNode(FindNodeReply),
     GetPeers(GetPeersReply),
+    Get(GetReply),
+    SampleInfoHashes(SampleInfoHashesReply),
 }
 
 impl Default for BodyKind {
@@ -123,6 +130,9 @@ impl Query {
             Query::FindNode(val) => val.t.clone(),
             Query::GetPeers(val) => val.t.clone(),
             Query::AnnouncePeer(val) => val.t.clone(),
+            Query::Get(val) => val.t.clone(),
+            Query::Put(val) => val.t.clone(),
+            Query::SampleInfoHashes(val) => val.t.clone(),
         }
     }
 
@@ -132,6 +142,9 @@ impl Query {
             Query::FindNode(val) => val.id.to_owned(),
             Query::GetPeers(val) => val.id.to_owned(),
             Query::AnnouncePeer(val) => val.id.to_owned(),
+            Query::Get(val) => val.id.to_owned(),
+            Query::Put(val) => val.id.to_owned(),
+            Query::SampleInfoHashes(val) => val.id.to_owned(),
         }
     }
 
@@ -141,6 +154,9 @@ impl Query {
             Query::FindNode(val) => val.ro,
             Query::GetPeers(val) => val.ro,
             Query::AnnouncePeer(val) => val.ro,
+            Query::Get(val) => val.ro,
+            Query::Put(val) => val.ro,
+            Query::SampleInfoHashes(val) => val.ro,
         };
 
         match ro {
@@ -162,6 +178,8 @@ impl Reply {
             Reply::PingOrAnnounce(val) => val.t.clone(),
             Reply::FindNode(val) => val.t.clone(),
             Reply::GetPeers(val) => val.t.clone(),
+            Reply::Get(val) => val.t.clone(),
+            Reply::SampleInfoHashes(val) => val.t.clone(),
         }
     }
 
@@ -170,6 +188,8 @@ impl Reply {
             Reply::PingOrAnnounce(val) => val.id.clone(),
             Reply::FindNode(val) => val.id.clone(),
             Reply::GetPeers(val) => val.id.clone(),
+            Reply::Get(val) => val.id,
+            Reply::SampleInfoHashes(val) => val.id,
         }
     }
 
@@ -178,6 +198,8 @@ impl Reply {
             Reply::PingOrAnnounce(val) => val.ip.clone(),
             Reply::FindNode(val) => val.ip.clone(),
             Reply::GetPeers(val) => val.ip.clone(),
+            Reply::Get(val) => val.ip,
+            Reply::SampleInfoHashes(val) => val.ip,
         }
     }
 
@@ -186,6 +208,8 @@ impl Reply {
             Reply::PingOrAnnounce(val) => val.into(),
             Reply::FindNode(val) => val.into(),
             Reply::GetPeers(val) => val.into(),
+            Reply::Get(val) => val.into(),
+            Reply::SampleInfoHashes(val) => val.into(),
         }
     }
 }
@@ -203,12 +227,23 @@ impl TryFrom<Frame> for BodyKind {
                 return Ok(BodyKind::Query(Query::GetPeers(frame.try_into()?)));
             } else if frame.is_exist_items(&[("q", "announce_peer")]) {
                 return Ok(BodyKind::Query(Query::AnnouncePeer(frame.try_into()?)));
+            } else if frame.is_exist_items(&[("q", "get")]) {
+                return Ok(BodyKind::Query(Query::Get(frame.try_into()?)));
+            } else if frame.is_exist_items(&[("q", "put")]) {
+                return Ok(BodyKind::Query(Query::Put(frame.try_into()?)));
+            } else if frame.is_exist_items(&[("q", "sample_infohashes")]) {
+                return Ok(BodyKind::Query(Query::SampleInfoHashes(frame.try_into()?)));
             }
         } else if frame.is_exist_items(&[("y", "r")]) {
             if let Some(params) = frame.get("r") {
-                if params.has_key("token") {
+                // 没有携带数据项的 get 响应和 get_peers 响应格式相同，会被解析为 GetPeersReply
+                if params.has_key("samples") {
+                    return Ok(BodyKind::Reply(Reply::SampleInfoHashes(frame.try_into()?)));
+                } else if params.has_key("token") && params.has_key("v") {
+                    return Ok(BodyKind::Reply(Reply::Get(frame.try_into()?)));
+                } else if params.has_key("token") {
                     return Ok(BodyKind::Reply(Reply::GetPeers(frame.try_into()?)));
-                } else if params.has_key("nodes") {
+                } else if params.has_key("nodes") || params.has_key("nodes6") {
                     return Ok(BodyKind::Reply(Reply::FindNode(frame.try_into()?)));
                 } else {
                     return Ok(BodyKind::Reply(Reply::PingOrAnnounce(frame.try_into()?)));
@@ -241,6 +276,9 @@ impl From<Query> for Frame {
             Query::FindNode(val) => val.into(),
             Query::GetPeers(val) => val.into(),
             Query::AnnouncePeer(val) => val.into(),
+            Query::Get(val) => val.into(),
+            Query::Put(val) => val.into(),
+            Query::SampleInfoHashes(val) => val.into(),
         }
     }
 }
@@ -251,6 +289,8 @@ impl From<Reply> for Frame {
             Reply::PingOrAnnounce(val) => val.into(),
             Reply::FindNode(val) => val.into(),
             Reply::GetPeers(val) => val.into(),
+            Reply::Get(val) => val.into(),
+            Reply::SampleInfoHashes(val) => val.into(),
         }
     }
 }
@@ -7,6 +7,24 @@ use crate::{gen_frame_common_field, transaction::TransactionId};
 
 use super::{frame::Frame, util::extract_frame_common_field};
 
+/// KRPC 错误码（BEP5），畸形的包、无效的参数或错误的 token
+pub const PROTOCOL_ERROR: i64 = 203;
+
+/// BEP44 put 错误码：v 字段过大
+pub const VALUE_TOO_BIG: i64 = 205;
+
+/// BEP44 put 错误码：签名无效
+pub const INVALID_SIGNATURE: i64 = 206;
+
+/// BEP44 put 错误码：salt 字段过大
+pub const SALT_TOO_BIG: i64 = 207;
+
+/// BEP44 put 错误码：cas 与当前 seq 不符
+pub const CAS_MISMATCH: i64 = 301;
+
+/// BEP44 put 错误码：seq 小于当前值（或 seq 相同但 v 不同）
+pub const SEQ_LESS_THAN_CURRENT: i64 = 302;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RError {
     /// transaction_id
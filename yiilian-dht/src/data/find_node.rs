@@ -5,7 +5,7 @@ use yiilian_core::{common::error::Error, data::BencodeData};
 
 use crate::{common::Id, gen_frame_common_field, transaction::TransactionId};
 
-use super::{frame::Frame, util::extract_frame_common_field};
+use super::{frame::Frame, util::{extract_frame_common_field, Want}};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FindNode {
@@ -27,6 +27,9 @@ pub struct FindNode {
 
     /// 要查找的目标 node id
     pub target: Id,
+
+    /// BEP32 希望返回的节点地址族
+    pub want: Option<Want>,
 }
 
 impl FindNode {
@@ -41,6 +44,7 @@ impl FindNode {
         Self {
             id,
             target,
+            want: None,
             t,
             v,
             ip,
@@ -85,6 +89,8 @@ impl TryFrom<Frame> for FindNode {
             .to_owned()
             .try_into()?;
 
+        let want = Want::extract(a)?;
+
         Ok(FindNode {
             t,
             v,
@@ -92,6 +98,7 @@ impl TryFrom<Frame> for FindNode {
             ro,
             id,
             target,
+            want,
         })
     }
 }
@@ -107,6 +114,9 @@ impl From<FindNode> for Frame {
         let mut a: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
         a.insert("id".into(), value.id.get_bytes().into());
         a.insert("target".into(), value.target.get_bytes().into());
+        if let Some(want) = value.want {
+            a.insert("want".into(), want.into());
+        }
 
         rst.insert("a".into(), a.into());
 
@@ -138,4 +148,25 @@ mod tests {
         let rst: FindNode = Frame::try_from(data).unwrap().try_into().unwrap();
         assert_eq!(af, rst);
     }
+
+    #[test]
+    fn test_want() {
+        let mut af = FindNode::new(
+            "id000000000000000001".try_into().unwrap(),
+            "info0000000000000001".try_into().unwrap(),
+            "t1".into(),
+            None,
+            None,
+            None,
+        );
+        af.want = Some(Want::new(true, true));
+        let rst: Frame = af.clone().into();
+
+        let data = b"d1:ad2:id20:id0000000000000000016:target20:info00000000000000014:wantl2:n42:n6ee1:q9:find_node1:t2:t11:y1:qe";
+        let data = decode(data.as_slice().into()).unwrap();
+        assert_eq!(data, rst.into());
+
+        let rst: FindNode = Frame::try_from(data).unwrap().try_into().unwrap();
+        assert_eq!(af, rst);
+    }
 }
@@ -6,7 +6,7 @@ use yiilian_core::{common::error::Error, data::BencodeData};
 use crate::{
     common::{
         {Id, ID_SIZE},
-        bytes_to_nodes4,
+        bytes_to_nodes4, bytes_to_nodes6,
     },
     gen_frame_common_field, merge_node_bytes,
     routing_table::Node,
@@ -36,6 +36,9 @@ pub struct FindNodeReply {
     /// feedback params
     // pub nodes: Vec<Bytes>,
     pub nodes: Vec<Node>,
+
+    /// BEP32 IPv6 节点
+    pub nodes6: Vec<Node>,
 }
 
 impl FindNodeReply {
@@ -50,6 +53,7 @@ impl FindNodeReply {
         Self {
             id,
             nodes,
+            nodes6: vec![],
             t,
             v,
             ip,
@@ -85,19 +89,29 @@ impl TryFrom<Frame> for FindNodeReply {
             .to_owned()
             .try_into()?;
 
-        let nodes = {
-            if let Some(node_bytes) = r.get_dict_item("nodes") {
-                let node_bytes = node_bytes.as_bstr()?;
-                bytes_to_nodes4(node_bytes, ID_SIZE)?
-            } else {
-                Err(Error::new_frame(
-                    None,
-                    Some(format!("Invalid frame for FindNodeReply, frame: {frame}")),
-                ))?
-            }
+        let nodes = if let Some(node_bytes) = r.get_dict_item("nodes") {
+            bytes_to_nodes4(node_bytes.as_bstr()?, ID_SIZE)?
+        } else {
+            vec![]
+        };
+
+        let nodes6 = if let Some(node_bytes) = r.get_dict_item("nodes6") {
+            bytes_to_nodes6(node_bytes.as_bstr()?, ID_SIZE)?
+        } else {
+            vec![]
         };
 
-        Ok(FindNodeReply::new(id, nodes, t, v, ip, ro))
+        if !r.has_key("nodes") && !r.has_key("nodes6") {
+            Err(Error::new_frame(
+                None,
+                Some(format!("Invalid frame for FindNodeReply, frame: {frame}")),
+            ))?
+        }
+
+        let mut rst = FindNodeReply::new(id, nodes, t, v, ip, ro);
+        rst.nodes6 = nodes6;
+
+        Ok(rst)
     }
 }
 
@@ -111,9 +125,16 @@ impl From<FindNodeReply> for Frame {
         let mut r: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
         r.insert("id".into(), value.id.get_bytes().into());
 
-        let nodes = merge_node_bytes!(&value.nodes, ID_SIZE);
+        // 只有 nodes6 时，不需要携带空的 nodes
+        if !value.nodes.is_empty() || value.nodes6.is_empty() {
+            let nodes = merge_node_bytes!(&value.nodes, ID_SIZE);
+            r.insert("nodes".into(), nodes.into());
+        }
 
-        r.insert("nodes".into(), nodes.into());
+        if !value.nodes6.is_empty() {
+            let nodes6 = merge_node_bytes!(&value.nodes6, ID_SIZE);
+            r.insert("nodes6".into(), nodes6.into());
+        }
 
         rst.insert("r".into(), r.into());
 
@@ -152,4 +173,29 @@ mod tests {
         let rst: FindNodeReply = Frame::try_from(data).unwrap().try_into().unwrap();
         assert_eq!(af, rst);
     }
+
+    #[test]
+    fn test_nodes6() {
+        let id1 = Id::from_bytes(b"node0000000000000001").unwrap();
+        let addr: SocketAddr = "[::1]:1".parse().unwrap();
+        let mut af = FindNodeReply::new(
+            "id000000000000000001".try_into().unwrap(),
+            vec![],
+            "t1".into(),
+            None,
+            None,
+            None,
+        );
+        af.nodes6 = vec![Node::new(id1, addr)];
+
+        let rst: Frame = af.clone().into();
+
+        let data = b"d1:rd2:id20:id0000000000000000016:nodes638:node0000000000000001\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x01\0\x01e1:t2:t11:y1:re";
+
+        let data = decode(data.as_slice().into()).unwrap();
+        assert_eq!(data, rst.into());
+
+        let rst: FindNodeReply = Frame::try_from(data).unwrap().try_into().unwrap();
+        assert_eq!(af, rst);
+    }
 }
@@ -5,7 +5,7 @@ use bytes::Bytes;
 use crate::{common::Id, gen_frame_common_field, transaction::TransactionId};
 use yiilian_core::{common::error::Error, data::BencodeData};
 
-use super::{frame::Frame, util::extract_frame_common_field};
+use super::{frame::Frame, util::{extract_frame_common_field, Want}};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GetPeers {
@@ -26,6 +26,9 @@ pub struct GetPeers {
     pub id: Id,
 
     pub info_hash: Id,
+
+    /// BEP32 希望返回的节点地址族
+    pub want: Option<Want>,
 }
 
 impl GetPeers {
@@ -40,6 +43,7 @@ impl GetPeers {
         Self {
             id,
             info_hash,
+            want: None,
             t,
             v,
             ip,
@@ -85,7 +89,10 @@ impl TryFrom<Frame> for GetPeers {
             .to_owned()
             .try_into()?;
 
-        Ok(GetPeers::new(id, info_hash, t, v, ip, ro))
+        let mut rst = GetPeers::new(id, info_hash, t, v, ip, ro);
+        rst.want = Want::extract(a)?;
+
+        Ok(rst)
     }
 }
 
@@ -100,6 +107,9 @@ impl From<GetPeers> for Frame {
         let mut a: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
         a.insert("id".into(), value.id.get_bytes().into());
         a.insert("info_hash".into(), value.info_hash.get_bytes().into());
+        if let Some(want) = value.want {
+            a.insert("want".into(), want.into());
+        }
 
         rst.insert("a".into(), a.into());
 
@@ -133,4 +143,25 @@ mod tests {
         let rst: GetPeers = Frame::try_from(data).unwrap().try_into().unwrap();
         assert_eq!(af, rst);
     }
+
+    #[test]
+    fn test_want() {
+        let mut af = GetPeers::new(
+            "id000000000000000001".try_into().unwrap(),
+            "info0000000000000001".try_into().unwrap(),
+            "t1".into(),
+            None,
+            None,
+            None,
+        );
+        af.want = Some(Want::new(false, true));
+        let rst: Frame = af.clone().into();
+
+        let data = b"d1:ad2:id20:id0000000000000000019:info_hash20:info00000000000000014:wantl2:n6ee1:q9:get_peers1:t2:t11:y1:qe";
+        let data = decode(data.as_slice().into()).unwrap();
+        assert_eq!(data, rst.into());
+
+        let rst: GetPeers = Frame::try_from(data).unwrap().try_into().unwrap();
+        assert_eq!(af, rst);
+    }
 }
@@ -9,7 +9,7 @@ use yiilian_core::{common::{
 use crate::{
     common::{
         {Id, ID_SIZE},
-        bytes_to_nodes4,
+        bytes_to_nodes4, bytes_to_nodes6,
     },
     gen_frame_common_field, merge_node_bytes,
     routing_table::Node,
@@ -42,7 +42,10 @@ pub struct GetPeersReply {
     /// reply nodes
     pub nodes: Vec<Node>,
 
-    /// reply values
+    /// BEP32 IPv6 节点
+    pub nodes6: Vec<Node>,
+
+    /// reply values，IPv6 的 peer 为 18 字节的紧凑格式
     pub values: Vec<SocketAddr>,
 }
 
@@ -61,6 +64,7 @@ impl GetPeersReply {
             id,
             token,
             nodes,
+            nodes6: vec![],
             values,
             t,
             v,
@@ -116,6 +120,12 @@ impl TryFrom<Frame> for GetPeersReply {
             vec![]
         };
 
+        let nodes6 = if let Some(node_bytes) = r.get_dict_item("nodes6") {
+            bytes_to_nodes6(node_bytes.as_bstr()?, ID_SIZE)?
+        } else {
+            vec![]
+        };
+
         let values = if let Some(value_bytes) = r.get_dict_item("values") {
             let vb = value_bytes.as_list()?;
             let mut values = vec![];
@@ -128,7 +138,10 @@ impl TryFrom<Frame> for GetPeersReply {
             vec![]
         };
 
-        Ok(GetPeersReply::new(id, token, nodes, values, t, v, ip, ro))
+        let mut rst = GetPeersReply::new(id, token, nodes, values, t, v, ip, ro);
+        rst.nodes6 = nodes6;
+
+        Ok(rst)
     }
 }
 
@@ -143,10 +156,18 @@ impl From<GetPeersReply> for Frame {
         r.insert("id".into(), value.id.get_bytes().into());
         r.insert("token".into(), value.token.clone().into());
 
-        r.insert(
-            "nodes".into(),
-            merge_node_bytes!(&value.nodes, ID_SIZE).into(),
-        );
+        if !value.nodes.is_empty() || value.nodes6.is_empty() {
+            r.insert(
+                "nodes".into(),
+                merge_node_bytes!(&value.nodes, ID_SIZE).into(),
+            );
+        }
+        if !value.nodes6.is_empty() {
+            r.insert(
+                "nodes6".into(),
+                merge_node_bytes!(&value.nodes6, ID_SIZE).into(),
+            );
+        }
         // r.insert("values".into(), merge_socket_addr_bytes!(&value.values).into());
         let mut values = vec![];
         for item in &value.values {
@@ -190,6 +211,31 @@ mod tests {
         assert_eq!(af, rst);
     }
 
+    #[test]
+    fn test_ipv6() {
+        let id1 = Id::from_bytes(b"node0000000000000001").unwrap();
+        let addr: SocketAddr = "[::1]:80".parse().unwrap();
+        let mut af = GetPeersReply::new(
+            "id000000000000000001".try_into().unwrap(),
+            "token01".into(),
+            vec![],
+            vec![addr.clone()],
+            "t1".into(),
+            None,
+            None,
+            None,
+        );
+        af.nodes6 = vec![Node::new(id1, addr.clone())];
+        let rst: Frame = af.clone().into();
+
+        let data = b"d1:rd2:id20:id0000000000000000016:nodes638:node0000000000000001\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x01\0P5:token7:token016:valuesl18:\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x01\0Pee1:t2:t11:y1:re";
+        let data = decode(data).unwrap();
+        assert_eq!(data, rst.into());
+
+        let rst: GetPeersReply = Frame::try_from(data).unwrap().try_into().unwrap();
+        assert_eq!(af, rst);
+    }
+
     #[test]
     fn test_decode() {
         let data = b"d2:ip6:e];\x99\x11\xae1:rd2:id20:d\x8d\x89W\xe3\xa9D\x1cF\xa4'7\xf0\xfbf\xf6\x81\x1d\xbd\xd95:nodes208:dD0\xf5x-E\x84\xa1l\x9a\x90\x9dU\x804\xeb\0\x03`t\xe9\xd6\xad4\xa0d[\xa0\x86*\xeb\x8c*@\xdeR?\r\x93!D\xe4\x9c\x95z\x01\xa0\xd7\x0e[(de\x9fM36\xf7\xa6Y\xdb\x83\xd7o\xe9\xed\x0b\x861\xf5h\xc9)\xce\xfa\x958d~D\x1aO\x01-O\xa4i\0\xf6\x98\x13\xaa<3<\x87\xca\xd2\xc3\xe0\xffK\x16d\x01\xe1\xf6\xf9\xd9=I\x85L\xca\xd5h\x8d\xdbuC\xce\xfd1R+\xf7\x0e\xe63d\x19\xae\xfeV*\x07\x91\xfcTu\xc6(\xaf\0\x8d\xd6\xdd\x15\xb5t\x11f\x82^\x1dd(\xd6nZ@\x1c\xf7A\x8cK\x97W\x8b\xfc\x12\xfc\xc5\x1f\xa5ZOA\x07\x1a\xe1d:\xc0\xb9\xfb\x83\xdb<\x1a\xdf;Pd\xb8\xc7aGE\xbe\xc8Y\x85\xa0g?T5:token20:\xb3\xfa\xbaA\xc0~b\x08\x8cz\xa6\xa1\xdf\x87\x9aP\xc9\x88K\xd56:valuesl6:\xa8w$\xaeV\xcfee1:t2:\x94\x881:v4:UT\xb7`1:y1:re";
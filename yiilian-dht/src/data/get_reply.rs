@@ -0,0 +1,249 @@
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use bytes::Bytes;
+use yiilian_core::{common::error::Error, data::BencodeData};
+
+use crate::{
+    common::{bytes_to_nodes4, Id, ID_SIZE},
+    gen_frame_common_field,
+    item::{Item, MutableItem},
+    merge_node_bytes,
+    routing_table::Node,
+    transaction::TransactionId,
+};
+
+use super::{frame::Frame, util::extract_frame_common_field};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetReply {
+    /// transaction_id
+    pub t: TransactionId,
+
+    /// version
+    pub v: Option<Bytes>,
+
+    /// 对方看到的我们的外网 IP
+    pub ip: Option<SocketAddr>,
+
+    /// readonly
+    pub ro: Option<u8>,
+
+    // ----------------------------
+    /// sender node id
+    pub id: Id,
+
+    /// 对方在 put 请求中需要回传该 token
+    pub token: Bytes,
+
+    /// reply nodes
+    pub nodes: Vec<Node>,
+
+    /// 数据项的值
+    pub value: Option<BencodeData>,
+
+    /// 可变数据项的公钥
+    pub k: Option<Bytes>,
+
+    /// 可变数据项的序列号
+    pub seq: Option<i64>,
+
+    /// 可变数据项的签名
+    pub sig: Option<Bytes>,
+}
+
+impl GetReply {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: Id,
+        token: Bytes,
+        nodes: Vec<Node>,
+        value: Option<BencodeData>,
+        k: Option<Bytes>,
+        seq: Option<i64>,
+        sig: Option<Bytes>,
+        t: TransactionId,
+        v: Option<Bytes>,
+        ip: Option<SocketAddr>,
+        ro: Option<u8>,
+    ) -> Self {
+        Self {
+            id,
+            token,
+            nodes,
+            value,
+            k,
+            seq,
+            sig,
+            t,
+            v,
+            ip,
+            ro,
+        }
+    }
+
+    /// 设置 reply 中携带的数据项
+    pub fn set_item(&mut self, item: Item) {
+        match item {
+            Item::Immutable(value) => {
+                self.value = Some(value);
+            }
+            Item::Mutable(item) => {
+                self.value = Some(item.value);
+                self.k = Some(item.k);
+                self.seq = Some(item.seq);
+                self.sig = Some(item.sig);
+            }
+        }
+    }
+
+    /// 取出 reply 中携带的数据项，可变数据项需要提供 put 时使用的 salt
+    pub fn get_item(&self, salt: Option<Bytes>) -> Option<Item> {
+        let value = self.value.clone()?;
+
+        match (&self.k, self.seq, &self.sig) {
+            (Some(k), Some(seq), Some(sig)) => Some(Item::Mutable(MutableItem::new(
+                k.clone(),
+                salt,
+                seq,
+                sig.clone(),
+                value,
+            ))),
+            _ => Some(Item::Immutable(value)),
+        }
+    }
+}
+
+impl TryFrom<Frame> for GetReply {
+    type Error = Error;
+
+    fn try_from(frame: Frame) -> Result<Self, Self::Error> {
+        let (t, v, ip, ro) = extract_frame_common_field(&frame)?;
+        if !frame.is_exist_items(&[("y", "r")]) {
+            return Err(Error::new_frame(
+                None,
+                Some(format!("Invalid frame for GetReply, frame: {frame}")),
+            ));
+        }
+
+        let r = frame.get("r").ok_or(Error::new_frame(
+            None,
+            Some(format!("Field 'r' not found in frame: {frame}")),
+        ))?;
+
+        let id: Id = r
+            .get_dict_item("id")
+            .ok_or(Error::new_frame(
+                None,
+                Some(format!("Field 'id' not found in frame: {frame}")),
+            ))?
+            .as_bstr()?
+            .to_owned()
+            .try_into()?;
+
+        let token: Bytes = r
+            .get_dict_item("token")
+            .ok_or(Error::new_frame(
+                None,
+                Some(format!("Field 'token' not found in frame: {frame}")),
+            ))?
+            .to_owned()
+            .try_into()?;
+
+        let nodes = if let Some(node_bytes) = r.get_dict_item("nodes") {
+            let node_bytes = node_bytes.as_bstr()?;
+            bytes_to_nodes4(node_bytes, ID_SIZE)?
+        } else {
+            vec![]
+        };
+
+        let value = r.get_dict_item("v").map(|val| val.to_owned());
+
+        let k = match r.get_dict_item("k") {
+            Some(k) => Some(k.as_bstr()?.to_owned()),
+            None => None,
+        };
+
+        let seq = match r.get_dict_item("seq") {
+            Some(seq) => Some(seq.as_int()?),
+            None => None,
+        };
+
+        let sig = match r.get_dict_item("sig") {
+            Some(sig) => Some(sig.as_bstr()?.to_owned()),
+            None => None,
+        };
+
+        Ok(GetReply::new(
+            id, token, nodes, value, k, seq, sig, t, v, ip, ro,
+        ))
+    }
+}
+
+impl From<GetReply> for Frame {
+    fn from(value: GetReply) -> Self {
+        let mut rst: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
+        gen_frame_common_field!(rst, value);
+
+        rst.insert("y".into(), "r".into());
+
+        let mut r: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
+        r.insert("id".into(), value.id.get_bytes().into());
+        r.insert("token".into(), value.token.clone().into());
+        r.insert(
+            "nodes".into(),
+            merge_node_bytes!(&value.nodes, ID_SIZE).into(),
+        );
+        if let Some(v) = value.value {
+            r.insert("v".into(), v);
+        }
+        if let Some(k) = value.k {
+            r.insert("k".into(), k.into());
+        }
+        if let Some(seq) = value.seq {
+            r.insert("seq".into(), seq.into());
+        }
+        if let Some(sig) = value.sig {
+            r.insert("sig".into(), sig.into());
+        }
+
+        rst.insert("r".into(), r.into());
+
+        Frame(rst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use yiilian_core::data::decode;
+
+    use super::*;
+
+    #[test]
+    fn test() {
+        let af = GetReply::new(
+            "id000000000000000001".try_into().unwrap(),
+            "token01".into(),
+            vec![],
+            Some("value1".into()),
+            None,
+            None,
+            None,
+            "t1".into(),
+            Some("v1".into()),
+            Some("127.0.0.1:80".parse().unwrap()),
+            Some(1),
+        );
+        let rst: Frame = af.clone().into();
+
+        let data = b"d2:ip6:\x7f\0\0\x01\0P1:rd2:id20:id0000000000000000015:nodes0:5:token7:token011:v6:value1e2:roi1e1:t2:t11:v2:v11:y1:re";
+        let data = decode(data).unwrap();
+        assert_eq!(data, rst.into());
+
+        let rst: GetReply = Frame::try_from(data).unwrap().try_into().unwrap();
+        assert_eq!(af, rst);
+        assert_eq!(
+            Some(Item::Immutable("value1".into())),
+            rst.get_item(None)
+        );
+    }
+}
@@ -4,6 +4,11 @@ pub mod find_node;
 pub mod find_node_reply;
 pub mod get_peers;
 pub mod get_peers_reply;
+pub mod get;
+pub mod get_reply;
+pub mod put;
+pub mod sample_infohashes;
+pub mod sample_infohashes_reply;
 pub mod ping;
 pub mod ping_announce_replay;
 pub mod error;
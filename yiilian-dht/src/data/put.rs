@@ -0,0 +1,218 @@
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use bytes::Bytes;
+use yiilian_core::{common::error::Error, data::BencodeData};
+
+use crate::{
+    common::Id,
+    gen_frame_common_field,
+    item::{Item, MutableItem},
+    transaction::TransactionId,
+};
+
+use super::{frame::Frame, util::extract_frame_common_field};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Put {
+    /// transaction_id
+    pub t: TransactionId,
+
+    /// version
+    pub v: Option<Bytes>,
+
+    /// 对方看到的我们的外网 IP
+    pub ip: Option<SocketAddr>,
+
+    /// readonly
+    pub ro: Option<u8>,
+
+    // ----------------------------
+    /// sender node id
+    pub id: Id,
+
+    /// get 请求时从对方获取到的 token
+    pub token: Bytes,
+
+    /// 写入的数据项
+    pub item: Item,
+
+    /// compare and swap，只有对方存储的 seq 等于该值时才会覆盖
+    pub cas: Option<i64>,
+}
+
+impl Put {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: Id,
+        token: Bytes,
+        item: Item,
+        cas: Option<i64>,
+        t: TransactionId,
+        v: Option<Bytes>,
+        ip: Option<SocketAddr>,
+        ro: Option<u8>,
+    ) -> Self {
+        Self {
+            id,
+            token,
+            item,
+            cas,
+            t,
+            v,
+            ip,
+            ro,
+        }
+    }
+}
+
+impl TryFrom<Frame> for Put {
+    type Error = Error;
+
+    fn try_from(frame: Frame) -> Result<Self, Self::Error> {
+        let (t, v, ip, ro) = extract_frame_common_field(&frame)?;
+        if !frame.is_exist_items(&[("y", "q"), ("q", "put")]) {
+            Err(Error::new_frame(
+                None,
+                Some(format!("Invalid frame for Put, frame: {frame}")),
+            ))?
+        }
+
+        let a = frame.get("a").ok_or(Error::new_frame(
+            None,
+            Some(format!("Field 'a' not found in frame: {frame}")),
+        ))?;
+        let id: Id = a
+            .get_dict_item("id")
+            .ok_or(Error::new_frame(
+                None,
+                Some(format!("Field 'id' not found in frame: {frame}")),
+            ))?
+            .as_bstr()?
+            .to_owned()
+            .try_into()?;
+
+        let token: Bytes = a
+            .get_dict_item("token")
+            .ok_or(Error::new_frame(
+                None,
+                Some(format!("Field 'token' not found in frame: {frame}")),
+            ))?
+            .to_owned()
+            .try_into()?;
+
+        let value = a
+            .get_dict_item("v")
+            .ok_or(Error::new_frame(
+                None,
+                Some(format!("Field 'v' not found in frame: {frame}")),
+            ))?
+            .to_owned();
+
+        let item = if let Some(k) = a.get_dict_item("k") {
+            let k = k.as_bstr()?.to_owned();
+            let salt = match a.get_dict_item("salt") {
+                Some(salt) => Some(salt.as_bstr()?.to_owned()),
+                None => None,
+            };
+            let seq = a
+                .get_dict_item("seq")
+                .ok_or(Error::new_frame(
+                    None,
+                    Some(format!("Field 'seq' not found in frame: {frame}")),
+                ))?
+                .as_int()?;
+            let sig = a
+                .get_dict_item("sig")
+                .ok_or(Error::new_frame(
+                    None,
+                    Some(format!("Field 'sig' not found in frame: {frame}")),
+                ))?
+                .as_bstr()?
+                .to_owned();
+
+            Item::Mutable(MutableItem::new(k, salt, seq, sig, value))
+        } else {
+            Item::Immutable(value)
+        };
+
+        let cas = if let Some(cas) = a.get_dict_item("cas") {
+            Some(cas.as_int()?)
+        } else {
+            None
+        };
+
+        Ok(Put::new(id, token, item, cas, t, v, ip, ro))
+    }
+}
+
+impl From<Put> for Frame {
+    fn from(value: Put) -> Self {
+        let mut rst: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
+        gen_frame_common_field!(rst, value);
+
+        rst.insert("y".into(), "q".into());
+        rst.insert("q".into(), "put".into());
+
+        let mut a: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
+        a.insert("id".into(), value.id.get_bytes().into());
+        a.insert("token".into(), value.token.clone().into());
+        if let Some(cas) = value.cas {
+            a.insert("cas".into(), cas.into());
+        }
+
+        match value.item {
+            Item::Immutable(v) => {
+                a.insert("v".into(), v);
+            }
+            Item::Mutable(item) => {
+                a.insert("k".into(), item.k.into());
+                if let Some(salt) = item.salt {
+                    a.insert("salt".into(), salt.into());
+                }
+                a.insert("seq".into(), item.seq.into());
+                a.insert("sig".into(), item.sig.into());
+                a.insert("v".into(), item.value);
+            }
+        }
+
+        rst.insert("a".into(), a.into());
+
+        Frame(rst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use yiilian_core::data::decode;
+
+    use super::*;
+
+    #[test]
+    fn test() {
+        let af = Put::new(
+            "id000000000000000001".try_into().unwrap(),
+            "01".into(),
+            Item::Mutable(MutableItem::new(
+                "k0000000000000000000000000000001".into(),
+                Some("s1".into()),
+                1,
+                "sig".into(),
+                "v1".into(),
+            )),
+            Some(0),
+            "t1".into(),
+            None,
+            None,
+            None,
+        );
+        let rst: Frame = af.clone().into();
+
+        let data = b"d1:ad3:casi0e2:id20:id0000000000000000011:k32:k00000000000000000000000000000014:salt2:s13:seqi1e3:sig3:sig5:token2:011:v2:v1e1:q3:put1:t2:t11:y1:qe";
+        let data = decode(data.as_slice().into()).unwrap();
+        assert_eq!(data, rst.into());
+
+        let rst: Put = Frame::try_from(data).unwrap().try_into().unwrap();
+        assert_eq!(af, rst);
+    }
+}
@@ -0,0 +1,227 @@
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use bytes::Bytes;
+use yiilian_core::{common::error::Error, data::BencodeData};
+
+use crate::{
+    common::{bytes_to_nodes4, bytes_to_nodes6, Id, ID_SIZE},
+    gen_frame_common_field, merge_node_bytes,
+    routing_table::Node,
+    transaction::TransactionId,
+};
+
+use super::{frame::Frame, util::extract_frame_common_field};
+
+/// BEP51 sample_infohashes 响应
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleInfoHashesReply {
+    /// transaction_id
+    pub t: TransactionId,
+
+    /// version
+    pub v: Option<Bytes>,
+
+    /// 对方看到的我们的外网 IP
+    pub ip: Option<SocketAddr>,
+
+    /// readonly
+    pub ro: Option<u8>,
+
+    // ----------------------------
+    /// sender node id
+    pub id: Id,
+
+    /// 对方要求再次向其发送 sample_infohashes 请求的最小间隔（秒）
+    pub interval: i64,
+
+    /// reply nodes
+    pub nodes: Vec<Node>,
+
+    /// BEP32 IPv6 节点
+    pub nodes6: Vec<Node>,
+
+    /// 对方存储的 info_hash 总数
+    pub num: i64,
+
+    /// 对方存储的 info_hash 的随机样本
+    pub samples: Vec<Id>,
+}
+
+impl SampleInfoHashesReply {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: Id,
+        interval: i64,
+        nodes: Vec<Node>,
+        num: i64,
+        samples: Vec<Id>,
+        t: TransactionId,
+        v: Option<Bytes>,
+        ip: Option<SocketAddr>,
+        ro: Option<u8>,
+    ) -> Self {
+        Self {
+            id,
+            interval,
+            nodes,
+            nodes6: vec![],
+            num,
+            samples,
+            t,
+            v,
+            ip,
+            ro,
+        }
+    }
+}
+
+impl TryFrom<Frame> for SampleInfoHashesReply {
+    type Error = Error;
+
+    fn try_from(frame: Frame) -> Result<Self, Self::Error> {
+        let (t, v, ip, ro) = extract_frame_common_field(&frame)?;
+        if !frame.is_exist_items(&[("y", "r")]) {
+            return Err(Error::new_frame(
+                None,
+                Some(format!("Invalid frame for SampleInfoHashesReply, frame: {frame}")),
+            ));
+        }
+
+        let r = frame.get("r").ok_or(Error::new_frame(
+            None,
+            Some(format!("Field 'r' not found in frame: {frame}")),
+        ))?;
+
+        let id: Id = r
+            .get_dict_item("id")
+            .ok_or(Error::new_frame(
+                None,
+                Some(format!("Field 'id' not found in frame: {frame}")),
+            ))?
+            .as_bstr()?
+            .to_owned()
+            .try_into()?;
+
+        let interval = match r.get_dict_item("interval") {
+            Some(val) => val.as_int()?,
+            None => 0,
+        };
+
+        let num = match r.get_dict_item("num") {
+            Some(val) => val.as_int()?,
+            None => 0,
+        };
+
+        let samples = {
+            let sample_bytes = r
+                .get_dict_item("samples")
+                .ok_or(Error::new_frame(
+                    None,
+                    Some(format!("Field 'samples' not found in frame: {frame}")),
+                ))?
+                .as_bstr()?;
+
+            if sample_bytes.len() % ID_SIZE != 0 {
+                Err(Error::new_frame(
+                    None,
+                    Some(format!("Wrong number of bytes for samples ({})", sample_bytes.len())),
+                ))?
+            }
+
+            sample_bytes
+                .chunks(ID_SIZE)
+                .map(Id::from_bytes)
+                .collect::<Result<Vec<Id>, Error>>()?
+        };
+
+        let nodes = if let Some(node_bytes) = r.get_dict_item("nodes") {
+            bytes_to_nodes4(node_bytes.as_bstr()?, ID_SIZE)?
+        } else {
+            vec![]
+        };
+
+        let nodes6 = if let Some(node_bytes) = r.get_dict_item("nodes6") {
+            bytes_to_nodes6(node_bytes.as_bstr()?, ID_SIZE)?
+        } else {
+            vec![]
+        };
+
+        let mut rst = SampleInfoHashesReply::new(id, interval, nodes, num, samples, t, v, ip, ro);
+        rst.nodes6 = nodes6;
+
+        Ok(rst)
+    }
+}
+
+impl From<SampleInfoHashesReply> for Frame {
+    fn from(value: SampleInfoHashesReply) -> Self {
+        let mut rst: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
+        gen_frame_common_field!(rst, value);
+
+        rst.insert("y".into(), "r".into());
+
+        let mut r: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
+        r.insert("id".into(), value.id.get_bytes().into());
+        r.insert("interval".into(), value.interval.into());
+        r.insert("num".into(), value.num.into());
+
+        if !value.nodes.is_empty() || value.nodes6.is_empty() {
+            r.insert(
+                "nodes".into(),
+                merge_node_bytes!(&value.nodes, ID_SIZE).into(),
+            );
+        }
+        if !value.nodes6.is_empty() {
+            r.insert(
+                "nodes6".into(),
+                merge_node_bytes!(&value.nodes6, ID_SIZE).into(),
+            );
+        }
+
+        let mut samples = Vec::with_capacity(value.samples.len() * ID_SIZE);
+        for item in &value.samples {
+            samples.extend(item.to_vec());
+        }
+        let samples: Bytes = samples.into();
+        r.insert("samples".into(), samples.into());
+
+        rst.insert("r".into(), r.into());
+
+        Frame(rst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use yiilian_core::data::decode;
+
+    use super::*;
+
+    #[test]
+    fn test() {
+        let id1 = Id::from_bytes(b"node0000000000000001").unwrap();
+        let addr: SocketAddr = "192.168.0.1:1".parse().unwrap();
+        let af = SampleInfoHashesReply::new(
+            "id000000000000000001".try_into().unwrap(),
+            60,
+            vec![Node::new(id1, addr)],
+            2,
+            vec![
+                "info0000000000000001".try_into().unwrap(),
+                "info0000000000000002".try_into().unwrap(),
+            ],
+            "t1".into(),
+            None,
+            None,
+            None,
+        );
+        let rst: Frame = af.clone().into();
+
+        let data = b"d1:rd2:id20:id0000000000000000018:intervali60e5:nodes26:node0000000000000001\xc0\xa8\0\x01\0\x013:numi2e7:samples40:info0000000000000001info0000000000000002e1:t2:t11:y1:re";
+        let data = decode(data.as_slice().into()).unwrap();
+        assert_eq!(data, rst.into());
+
+        let rst: SampleInfoHashesReply = Frame::try_from(data).unwrap().try_into().unwrap();
+        assert_eq!(af, rst);
+    }
+}
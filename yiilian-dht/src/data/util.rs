@@ -1,7 +1,7 @@
 use std::net::SocketAddr;
 
 use bytes::Bytes;
-use yiilian_core::common::error::Error;
+use yiilian_core::{common::error::Error, data::BencodeData};
 
 use crate::transaction::TransactionId;
 
@@ -15,6 +15,8 @@ pub(crate) fn reply_matches_query(query: &Query, reply: &Reply) -> bool {
                 return true;
             } else if let Query::AnnouncePeer(_) = query {
                 return true;
+            } else if let Query::Put(_) = query {
+                return true;
             }
         }
         Reply::FindNode(_) => {
@@ -25,6 +27,19 @@ pub(crate) fn reply_matches_query(query: &Query, reply: &Reply) -> bool {
         Reply::GetPeers(_) => {
             if let Query::GetPeers(_) = query {
                 return true;
+            } else if let Query::Get(_) = query {
+                // 对方没有存储数据项时，get 响应和 get_peers 响应格式相同
+                return true;
+            }
+        }
+        Reply::Get(_) => {
+            if let Query::Get(_) = query {
+                return true;
+            }
+        }
+        Reply::SampleInfoHashes(_) => {
+            if let Query::SampleInfoHashes(_) = query {
+                return true;
             }
         }
     }
@@ -32,6 +47,63 @@ pub(crate) fn reply_matches_query(query: &Query, reply: &Reply) -> bool {
     false
 }
 
+/// BEP32 中 query 的 want 参数，表示请求方希望得到哪些地址族的节点
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Want {
+    /// 需要 IPv4 节点 (nodes)
+    pub n4: bool,
+
+    /// 需要 IPv6 节点 (nodes6)
+    pub n6: bool,
+}
+
+impl Want {
+    pub fn new(n4: bool, n6: bool) -> Self {
+        Want { n4, n6 }
+    }
+
+    /// 没有携带 want 参数时，按照请求方的地址族返回节点
+    pub fn from_addr(addr: &SocketAddr) -> Self {
+        match addr {
+            SocketAddr::V4(_) => Want::new(true, false),
+            SocketAddr::V6(_) => Want::new(false, true),
+        }
+    }
+
+    /// 从 query 的 a 参数中提取 want
+    pub(crate) fn extract(a: &BencodeData) -> Result<Option<Want>, Error> {
+        let list = match a.get_dict_item("want") {
+            Some(val) => val.as_list()?,
+            None => return Ok(None),
+        };
+
+        let mut want = Want::default();
+        for item in list {
+            match item.as_bstr()?.as_ref() {
+                b"n4" => want.n4 = true,
+                b"n6" => want.n6 = true,
+                _ => (),
+            }
+        }
+
+        Ok(Some(want))
+    }
+}
+
+impl From<Want> for BencodeData {
+    fn from(value: Want) -> Self {
+        let mut list: Vec<BencodeData> = vec![];
+        if value.n4 {
+            list.push("n4".into());
+        }
+        if value.n6 {
+            list.push("n6".into());
+        }
+
+        list.into()
+    }
+}
+
 /// 提取 frame 中的通用字段
 pub(crate) fn extract_frame_common_field(
     frame: &Frame,
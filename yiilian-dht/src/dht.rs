@@ -1,17 +1,18 @@
 mod dht_builder;
 pub use dht_builder::DhtBuilder;
 
+use bytes::Bytes;
 use chrono::Utc;
 use futures::{stream::FuturesUnordered, StreamExt};
 use std::{
     collections::HashSet,
-    fs::{self, File},
-    io::Write,
-    net::{IpAddr, SocketAddr},
+    fs,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
     path::PathBuf,
     sync::{Arc, Mutex, RwLock},
     time::Duration,
 };
+use socket2::{Domain, Protocol, Socket, Type};
 use tokio::{
     net::{lookup_host, UdpSocket}, sync::Semaphore, time::sleep
 };
@@ -22,24 +23,26 @@ use yiilian_core::{
         shutdown::ShutdownReceiver,
         util::random_bytes,
     },
+    data::BencodeData,
     net::block_list::{BlockAddr, BlockList},
 };
 
 use crate::{
     common::{
-        IPV4Consensus, Id, State,
-        {
-            dht_ctx_drop, dht_ctx_insert, dht_ctx_routing_tbl, dht_ctx_settings, dht_ctx_state,
-            dht_ctx_trans_mgr, Context,
-        },
+        IPV4Consensus, IPV6Consensus, Id, State, ID_SIZE,
+        Context, DhtHandle,
         Settings,
     },
-    data::body::{KrpcBody, Reply},
+    data::{
+        body::{KrpcBody, Reply},
+        sample_infohashes_reply::SampleInfoHashesReply,
+    },
     net::{Client, Server},
+    item::{Item, ItemManager, MutableItem, SigningKey},
     peer::PeerManager,
-    routing_table::{Node, Persist, RoutingTable},
-    service::KrpcService,
-    transaction::{GetPeersResult, TransactionManager},
+    routing_table::{Node, Persist, PersistNode, PersistPeer, RoutingTable, PERSIST_VERSION},
+    service::{KrpcService, RouterService},
+    transaction::{GetPeersResult, Lookup, LookupKind, TransactionManager},
 };
 
 #[derive(Debug, Clone)]
@@ -49,7 +52,8 @@ pub enum DhtMode {
 }
 
 pub struct Dht<S> {
-    ctx_index: u16,
+    /// 只有 Dht 持有 Context 的强引用，Dht 被 drop 后 Context 随之释放
+    ctx: Arc<Context>,
 
     pub local_addr: SocketAddr,
 
@@ -65,35 +69,28 @@ impl<S> Dht<S>
 where
     S: KrpcService<KrpcBody, ResBody = KrpcBody, Error = Error> + Clone + Send + 'static,
 {
-    pub fn init(
+    /// make_service 用本实例的 RouterService 生成处理请求的 service，
+    /// local_id 为 None 时根据 mode 生成节点 id
+    #[allow(clippy::too_many_arguments)]
+    pub fn init<F>(
         local_addr: SocketAddr,
-        service: S,
+        local_id: Option<Id>,
+        make_service: F,
         settings: Settings,
         node_block_list: Option<HashSet<BlockAddr>>,
         shutdown_rx: ShutdownReceiver,
         workers: Option<usize>,
         mode: DhtMode,
         home_dir: PathBuf,
-    ) -> Result<Self, Error> {
-        let local_id =  if let DhtMode::Crawler(_) = mode {
-            Id::from_random(&mut rand::thread_rng())
-        } else {
-            Id::from_ip(&local_addr.ip())
+    ) -> Result<Self, Error>
+    where
+        F: FnOnce(RouterService) -> S,
+    {
+        let local_id = match local_id {
+            Some(local_id) => local_id,
+            None if matches!(mode, DhtMode::Crawler(_)) => Id::from_random(&mut rand::thread_rng()),
+            None => Id::from_ip(&local_addr.ip()),
         };
-        
-        let ctx_index = local_addr.port();
-
-        let transaction_manager =
-            TransactionManager::new(local_addr.port(), local_addr, mode.clone());
-
-        let routing_table = build_routing_table(
-            ctx_index,
-            local_id,
-            settings.block_list_max_size,
-            settings.bucket_size,
-            node_block_list,
-            shutdown_rx.clone(),
-        );
 
         let state = build_state(local_id, settings.token_secret_size)?;
 
@@ -102,20 +99,42 @@ where
             settings.max_peers_per_resource,
         ));
 
-        let socket = build_socket(local_addr)?;
+        let item_manager = Mutex::new(ItemManager::new(settings.max_items));
+
+        let socket = build_socket(local_addr, settings.ipv6)?;
+        // 绑定端口 0 时使用系统分配的端口
+        let local_addr = SocketAddr::new(
+            local_addr.ip(),
+            socket.local_addr().map_err(|e| Error::new_bind(Some(Box::new(e))))?.port(),
+        );
         let socket = Arc::new(socket);
 
         let client = Client::new(socket.clone());
 
-        let ctx = Context::new(
-            settings,
-            state,
-            routing_table,
-            peer_manager,
-            transaction_manager,
-            client,
-        );
-        dht_ctx_insert(ctx_index, ctx);
+        // TransactionManager 和 RoutingTable 持有 Context 的 Weak
+        let ctx = Arc::new_cyclic(|weak_ctx| {
+            let transaction_manager =
+                TransactionManager::new(DhtHandle::new(weak_ctx.clone()), local_addr, mode.clone());
+
+            let routing_table = build_routing_table(
+                DhtHandle::new(weak_ctx.clone()),
+                local_id,
+                settings.block_list_max_size,
+                settings.bucket_size,
+                node_block_list,
+                shutdown_rx.clone(),
+            );
+
+            Context::new(
+                settings,
+                state,
+                routing_table,
+                peer_manager,
+                item_manager,
+                transaction_manager,
+                client,
+            )
+        });
 
         let workers = match workers {
             Some(val) => {
@@ -125,13 +144,17 @@ where
             None => None,
         };
 
+        let service = make_service(RouterService::new(DhtHandle::new(Arc::downgrade(&ctx))));
         let server = Server::new(socket.clone(), service, workers);
 
         let nodes_file = home_dir
-            .join(format!(".yiilian/dht/{}.txt", ctx_index));
+            .join(".yiilian/dht")
+            .join(nodes_file_name(&local_addr));
+
+        restore_persist(&ctx, &nodes_file);
 
         Ok(Dht {
-            ctx_index,
+            ctx,
             local_addr,
             server,
             nodes_file,
@@ -139,22 +162,12 @@ where
         })
     }
 
-    pub async fn run_loop(&self) {
-        let ctx_index = self.ctx_index;
-        // let shutdown_rx = self.shutdown_rx.clone();
-        // let nodes_file = self.nodes_file.clone();
-
-        // // graceful shutdown
-        // tokio::spawn(async move {
-        //     log::trace!(target: "yiilian_dht::dht::run_loop", "Task '{}' starting up", "persist nodes on exit");
-        //     tokio::select! {
-        //         _ = shutdown_rx.watch() => {
-        //             persist_nodes(ctx_index, nodes_file.clone()).await;
-        //             dht_ctx_drop(ctx_index);
-        //         },
-        //     }
-        // });
+    /// 可以在其它任务中使用的句柄，不会阻止 Dht 释放
+    pub fn handle(&self) -> DhtHandle {
+        DhtHandle::new(Arc::downgrade(&self.ctx))
+    }
 
+    pub async fn run_loop(&self) {
         // 各种周期性的 future
         // tokio::try_join! 全部完成或有一个 Err 时退出
         match tokio::try_join!(
@@ -165,44 +178,39 @@ where
             self.periodic_find_node(),
             self.periodic_ip4_maintenance(),
             self.periodic_token_rotation(),
-            dht_ctx_trans_mgr(self.ctx_index).request_cleanup(),
+            self.periodic_sample_infohashes(),
+            self.ctx.transaction_manager().request_cleanup(),
         ) {
             Ok(_) => (),
             Err(e) => {
-                log::debug!(target: "yiilian_dht::dht::run_loop", "[{}] Quit with error: {}", ctx_index, e);
+                log::debug!(target: "yiilian_dht::dht::run_loop", "[{}] Quit with error: {}", self.local_addr, e);
             }
         }
     }
 
-    /// Build and send a ping to a target. Doesn't wait for a response
-    /// 生成并发任务执行 ping 请求，并等待响应
+    /// 启动时路由表已从节点文件恢复，这里在后台重新 ping 文件中的节点进行验证
     async fn ping_persist_nodes_once(&self) -> Result<(), Error> {
-        match fs::read_to_string(&self.nodes_file) {
-            Ok(val) => {
-                if let Ok(persist) = serde_yaml::from_str::<Persist>(&val) {
-                    log::trace!(target: "yiilian_dht::dht::ping_persist_once", " [{}] Enter ping_persist_once", self.ctx_index);
+        let persist = match load_persist(&self.nodes_file) {
+            Some(persist) => persist,
+            None => return Ok(()),
+        };
+        log::trace!(target: "yiilian_dht::dht::ping_persist_once", " [{}] Enter ping_persist_once", self.local_addr);
 
-                    for node_addr in persist.node_addrs {
-                        let rst = dht_ctx_trans_mgr(self.ctx_index)
-                            .ping_no_wait(node_addr, None)
-                            .await;
+        // 版本 1 的文件只有地址，版本 2 带上节点 id
+        let targets = persist.node_addrs
+            .into_iter()
+            .map(|addr| (addr, None))
+            .chain(persist.nodes.iter().map(|node| (node.address, Id::from_hex(&node.id).ok())));
 
-                        match rst {
-                            Err(e) => {
-                                log::debug!(target: "yiilian_dht::dht::ping_persist_once", "[{}] ping_no_wait error: {}", self.ctx_index, e);
-                            }
-                            _ => (),
-                        }
-                        sleep(Duration::from_millis(10)).await;
-                    }
-                } else {
-                    log::debug!(target: "yiilian_dht::dht::ping_persist_once", "[{}] Parsing node file {:?} error", self.ctx_index, self.nodes_file.as_os_str());
-                }
-            }
-            Err(e) => {
-                // 第一次运行的时候肯定是不存在 node_file 的
-                log::debug!(target: "yiilian_dht::dht::ping_persist_once", "[{}] read node file error: {}", self.ctx_index, e);
+        for (node_addr, node_id) in targets {
+            let rst = self.ctx.transaction_manager()
+                .ping_no_wait(node_addr, node_id)
+                .await;
+
+            if let Err(e) = rst {
+                log::debug!(target: "yiilian_dht::dht::ping_persist_once", "[{}] ping_no_wait error: {}", self.local_addr, e);
             }
+            sleep(Duration::from_millis(10)).await;
         }
 
         Ok(())
@@ -212,23 +220,23 @@ where
     /// 同时，在 ping 反馈时，我们也会将对方加入 routing table
     async fn periodic_router_ping(&self) -> Result<(), Error> {
         loop {
-            let is_join_kad = dht_ctx_state(self.ctx_index)
+            let is_join_kad = self.ctx.state()
                 .read()
-                .expect_error("dht_ctx_state.read() failed")
+                .expect_error("state.read() failed")
                 .is_join_kad;
 
             let router_ping_interval_sec = {
                 if is_join_kad {
-                    dht_ctx_settings(self.ctx_index).router_ping_interval_secs
+                    self.ctx.settings().router_ping_interval_secs
                 } else {
-                    dht_ctx_settings(self.ctx_index).router_ping_if_not_join_interval_secs
+                    self.ctx.settings().router_ping_if_not_join_interval_secs
                 }
             };
 
             log::trace!(
                 target: "yiilian_dht::dht::periodic_router_ping",
                 "[{}] Enter periodic_router_ping, is_join_kad: {}, interval_sec: {}",
-                self.ctx_index, is_join_kad, router_ping_interval_sec
+                self.local_addr, is_join_kad, router_ping_interval_sec
             );
 
             self.ping_routers().await;
@@ -242,7 +250,7 @@ where
     async fn ping_routers(&self) {
         let mut futures = FuturesUnordered::new();
         // 入口 router
-        let routers = &dht_ctx_settings(self.ctx_index).routers;
+        let routers = &self.ctx.settings().routers;
 
         for hostname in routers {
             futures.push(self.ping_router(hostname.clone()));
@@ -251,15 +259,17 @@ where
         while let Some(rst) = futures.next().await {
             match rst {
                 Err(e) => {
-                    log::debug!(target:"yiilian_dht::dht::ping_routers", "[{}] error: {:?}", self.ctx_index, e);
+                    log::debug!(target:"yiilian_dht::dht::ping_routers", "[{}] error: {:?}", self.local_addr, e);
                 }
                 _ => (),
             }
         }
     }
 
-    /// 将 “域名:PORT” 解析为 “IPv4:PORT” ，并向对方发送 PING 请求，并等待响应
+    /// 将 “域名:PORT” 解析为 “IPv4:PORT” （开启 IPv6 时还有 “IPv6:PORT”），并向对方发送 PING 请求，并等待响应
     async fn ping_router(&self, hostname: String) -> Result<(), Error> {
+        let ipv6 = self.ctx.settings().ipv6;
+
         // 解析域名
         let resolve = lookup_host(&hostname).await;
 
@@ -275,19 +285,32 @@ where
                 ))?
             }
             Ok(val) => {
-                // 对解析出的 ip 地址，并发发送 ping 请求并处理其响应
+                // 对解析出的 ip 地址，每个地址族各 ping 一个
+                let mut pinged_v4 = false;
+                let mut pinged_v6 = !ipv6;
                 for socket_addr in val {
-                    if socket_addr.is_ipv4() {
-                        dht_ctx_routing_tbl(self.ctx_index)
-                            .lock()
-                            .expect_error("dht_ctx_routing_tbl.lock() failed")
-                            .white_list
-                            .insert(socket_addr.ip());
-
-                        // 生成并发任务执行 ping 请求，并等待响应
-                        dht_ctx_trans_mgr(self.ctx_index)
-                            .ping_no_wait(socket_addr, None)
-                            .await?;
+                    let pinged = if socket_addr.is_ipv4() {
+                        &mut pinged_v4
+                    } else {
+                        &mut pinged_v6
+                    };
+                    if *pinged {
+                        continue;
+                    }
+                    *pinged = true;
+
+                    self.ctx.routing_table()
+                        .lock()
+                        .expect_error("routing_table.lock() failed")
+                        .white_list
+                        .insert(socket_addr.ip());
+
+                    // 生成并发任务执行 ping 请求，并等待响应
+                    self.ctx.transaction_manager()
+                        .ping_no_wait(socket_addr, None)
+                        .await?;
+
+                    if pinged_v4 && pinged_v6 {
                         break;
                     }
                 }
@@ -300,40 +323,40 @@ where
     /// 周期性 ping 路由表中的节点
     async fn periodic_buddy_ping(&self) -> Result<(), Error> {
         // 每隔 10 秒做一次 ping 检查
-        let ping_check_interval_secs = dht_ctx_settings(self.ctx_index).ping_check_interval_secs;
+        let ping_check_interval_secs = self.ctx.settings().ping_check_interval_secs;
 
         loop {
             sleep(Duration::from_secs(ping_check_interval_secs)).await; // 由于有这个 sleep，在它挂起任务时，就有机会优雅退出
 
-            let is_join_kad = dht_ctx_state(self.ctx_index)
+            let is_join_kad = self.ctx.state()
                 .read()
-                .expect_error("dht_ctx_state.read() failed")
+                .expect_error("state.read() failed")
                 .is_join_kad;
 
             if !is_join_kad {
                 continue;
             }
 
-            log::trace!(target: "yiilian_dht::dht::periodic_buddy_ping", "[{}] Enter periodic_buddy_ping", self.ctx_index);
+            log::trace!(target: "yiilian_dht::dht::periodic_buddy_ping", "[{}] Enter periodic_buddy_ping", self.local_addr);
 
             // 将需要状态的东西打包到一个块中，这样 Rust 就不会抱怨 MutexGuard 跨 .await 了
             let reverify_interval_secs = {
                 let reverify_grace_period_secs =
-                    dht_ctx_settings(self.ctx_index).reverify_grace_period_secs;
+                    self.ctx.settings().reverify_grace_period_secs;
                 let verify_grace_period_secs =
-                    dht_ctx_settings(self.ctx_index).verify_grace_period_secs;
+                    self.ctx.settings().verify_grace_period_secs;
 
                 // 将过期没再次校验的节点从 buckets 中删除
-                dht_ctx_routing_tbl(self.ctx_index)
+                self.ctx.routing_table()
                     .lock()
-                    .expect_error("dht_ctx_routing_tbl.lock() failed")
+                    .expect_error("routing_table.lock() failed")
                     .prune(
                         Duration::from_secs(reverify_grace_period_secs), // 每隔 14 分钟一次
                         Duration::from_secs(verify_grace_period_secs),   // 每隔 1 分钟一次
                     );
 
                 // 验证的有效时间为 15 分钟
-                dht_ctx_settings(self.ctx_index).reverify_interval_secs
+                self.ctx.settings().reverify_interval_secs
             };
 
             // 到了 reverify_interval_secs 再次验证时间间隔，需要将所有的 node （已验证/未验证） 都 ping 一遍
@@ -341,13 +364,13 @@ where
             let ping_if_older_than = Utc::now() - Duration::from_secs(reverify_interval_secs);
 
             let (unverified, verified) = {
-                let unverified = dht_ctx_routing_tbl(self.ctx_index)
+                let unverified = self.ctx.routing_table()
                     .lock()
-                    .expect_error("dht_ctx_routing_tbl.lock() failed")
+                    .expect_error("routing_table.lock() failed")
                     .get_all_unverified();
-                let verified = dht_ctx_routing_tbl(self.ctx_index)
+                let verified = self.ctx.routing_table()
                     .lock()
-                    .expect_error("dht_ctx_routing_tbl.lock() failed")
+                    .expect_error("routing_table.lock() failed")
                     .get_all_verified();
 
                 (unverified, verified)
@@ -364,7 +387,7 @@ where
                 }
 
                 // 生成并发任务执行 ping 请求，并等待响应
-                let rst = dht_ctx_trans_mgr(self.ctx_index)
+                let rst = self.ctx.transaction_manager()
                     .ping_no_wait(node.address, Some(node.id))
                     .await;
 
@@ -372,7 +395,7 @@ where
                     Err(error) => match error.get_kind() {
                         Kind::Transatcion => (),
                         _ => {
-                            log::debug!(target:"yiilian_dht::dht::periodic_buddy_ping", "[{}] Error ping unverified: {:?}", self.ctx_index, error);
+                            log::debug!(target:"yiilian_dht::dht::periodic_buddy_ping", "[{}] Error ping unverified: {:?}", self.local_addr, error);
                         }
                     },
                     Ok(_) => {}
@@ -387,7 +410,7 @@ where
                     }
                 }
 
-                let rst = dht_ctx_trans_mgr(self.ctx_index)
+                let rst = self.ctx.transaction_manager()
                     .ping_no_wait(node.address, Some(node.id))
                     .await;
 
@@ -395,7 +418,7 @@ where
                     Err(error) => match error.get_kind() {
                         Kind::Transatcion => {}
                         _ => {
-                            log::debug!(target:"yiilian_dht::dht::periodic_buddy_ping", "[{}] Error ping verified: {:?}", self.ctx_index, error);
+                            log::debug!(target:"yiilian_dht::dht::periodic_buddy_ping", "[{}] Error ping verified: {:?}", self.local_addr, error);
                         }
                     },
                     _ => (),
@@ -406,22 +429,22 @@ where
 
     /// 周期性 find_node 一个随机生成的接近本机节点的 Node id
     async fn periodic_find_node(&self) -> Result<(), Error> {
-        let find_node_interval_secs = dht_ctx_settings(self.ctx_index).find_nodes_interval_secs; // 33 s
+        let find_node_interval_secs = self.ctx.settings().find_nodes_interval_secs; // 33 s
         loop {
             sleep(Duration::from_secs(find_node_interval_secs)).await;
-            let is_join_kad = dht_ctx_state(self.ctx_index)
+            let is_join_kad = self.ctx.state()
                 .read()
-                .expect_error("dht_ctx_state.read() failed")
+                .expect_error("state.read() failed")
                 .is_join_kad;
             if !is_join_kad {
                 continue;
             }
 
-            log::trace!(target: "yiilian_dht::dht::periodic_find_node", "[{}] Enter periodic_find_node", self.ctx_index);
+            log::trace!(target: "yiilian_dht::dht::periodic_find_node", "[{}] Enter periodic_find_node", self.local_addr);
 
-            let (count_unverified, count_verified) = dht_ctx_routing_tbl(self.ctx_index)
+            let (count_unverified, count_verified) = self.ctx.routing_table()
                 .lock()
-                .expect_error("dht_ctx_routing_tbl.lock() failed")
+                .expect_error("routing_table.lock() failed")
                 .count();
 
             // 如果路由表中没有 node ，则 ping 入口 router。
@@ -432,15 +455,15 @@ where
 
             // 有足够多的未验证节点，则不需要本次 find_node 了
             let id_near_us = {
-                let find_nodes_skip_count = dht_ctx_settings(self.ctx_index).find_nodes_skip_count;
+                let find_nodes_skip_count = self.ctx.settings().find_nodes_skip_count;
                 if count_unverified > find_nodes_skip_count {
                     continue;
                 }
 
                 // 生成一个和本机 ID 接近的新 ID （只有后 4 个字节不同）
-                let id_near = dht_ctx_state(self.ctx_index)
+                let id_near = self.ctx.state()
                     .read()
-                    .expect_error("dht_ctx_state.read() failed")
+                    .expect_error("state.read() failed")
                     .get_local_id()
                     .make_mutant(4);
                 let id_near = id_near.expect_error("id_near make_mutant() error");
@@ -449,79 +472,156 @@ where
             };
 
             // 向这些附近节点中发送 find_node 本机节点的请求
-            dht_ctx_trans_mgr(self.ctx_index)
+            self.ctx.transaction_manager()
                 .find_node(id_near_us)
                 .await;
         }
     }
 
-    /// 每隔 10 秒，周期性维护 IPv4 （使用本机的最佳外网IP地址生成本机节点 ID）
+    /// 每隔 10 秒，周期性维护 IPv4 和 IPv6 （使用本机的最佳外网IP地址生成本机节点 ID）
     async fn periodic_ip4_maintenance(&self) -> Result<(), Error> {
 
         let ip4_maintenance_interval_sec =
-            dht_ctx_settings(self.ctx_index).ip4_maintenance_interval_sec;
+            self.ctx.settings().ip4_maintenance_interval_sec;
 
         loop {
             sleep(Duration::from_secs(ip4_maintenance_interval_sec)).await;
             
-            log::trace!(target: "yiilian_dht::dht::periodic_ip4_maintenance", "[{}] Enter periodic_ip4_maintenance", self.ctx_index);
+            log::trace!(target: "yiilian_dht::dht::periodic_ip4_maintenance", "[{}] Enter periodic_ip4_maintenance", self.local_addr);
 
             if let DhtMode::Crawler(_) = self.mode {
                 continue;
             }
 
             // 每隔 10 秒，将各 ip 投票数 - 1
-            dht_ctx_state(self.ctx_index)
-                .write()
-                .expect_error("dht_ctx_state.write() failed")
-                .ip4_source
-                .decay();
+            {
+                let mut state = self.ctx.state()
+                    .write()
+                    .expect_error("state.write() failed");
+                state.ip4_source.decay();
+                state.ip6_source.decay();
+            }
 
-            let best_ipv4 = dht_ctx_state(self.ctx_index)
-                .read()
-                .expect_error("dht_ctx_state.read() failed")
-                .ip4_source
-                .get_best_ipv4();
-            if let Some(ip) = best_ipv4 {
-                // 取出被投票数最多的外网 ipv4 地址，如果获取的投票数没超过阈值，则返回 None
-                let ip = IpAddr::V4(ip);
-                let local_id = dht_ctx_state(self.ctx_index)
+            // 取出被投票数最多的外网 ip 地址，优先使用 ipv4，如果获取的投票数没超过阈值，则返回 None
+            let best_ip: Option<IpAddr> = {
+                let state = self.ctx.state()
+                    .read()
+                    .expect_error("state.read() failed");
+
+                state
+                    .ip4_source
+                    .get_best_ipv4()
+                    .map(IpAddr::V4)
+                    .or(state.ip6_source.get_best_ipv6().map(IpAddr::V6))
+            };
+            if let Some(ip) = best_ip {
+                let local_id = self.ctx.state()
                     .read()
-                    .expect_error("dht_ctx_state.read() failed")
+                    .expect_error("state.read() failed")
                     .get_local_id();
 
                 // 如果本机外网 ip 地址和 本机节点 id 没有有效匹配，则生成一个新的有效匹配的本机 node id
                 let is_not_valid = !local_id.is_valid_for_ip(
                     &ip,
-                    &dht_ctx_routing_tbl(self.ctx_index)
+                    &self.ctx.routing_table()
                         .lock()
-                        .expect_error("dht_ctx_routing_tbl.lock() failed")
+                        .expect_error("routing_table.lock() failed")
                         .white_list,
                 );
 
                 if is_not_valid {
                     let new_id = Id::from_ip(&ip);
-                    dht_ctx_state(self.ctx_index)
+                    self.ctx.state()
                         .write()
-                        .expect_error("dht_ctx_state.write() failed")
+                        .expect_error("state.write() failed")
                         .set_local_id(new_id);
 
-                    dht_ctx_routing_tbl(self.ctx_index)
+                    self.ctx.routing_table()
                         .lock()
-                        .expect_error("dht_ctx_routing_tbl.lock() failed")
+                        .expect_error("routing_table.lock() failed")
                         .set_id(new_id);
                 }
             }
         }
     }
 
+    /// 爬虫模式下，遍历 keyspace，周期性向 target 附近的节点发出 sample_infohashes 请求（BEP51）
+    ///
+    /// 对方响应中的 samples 会和其它 reply 一样经过 service 层，由上层（比如 EventLayer）收集
+    async fn periodic_sample_infohashes(&self) -> Result<(), Error> {
+        if let DhtMode::Normal = self.mode {
+            return Ok(());
+        }
+
+        let sample_infohashes_loop_interval_secs =
+            self.ctx.settings().sample_infohashes_loop_interval_secs;
+        let mut target = Id::from_random(&mut rand::thread_rng());
+
+        loop {
+            sleep(Duration::from_secs(sample_infohashes_loop_interval_secs)).await;
+
+            let is_join_kad = self.ctx.state()
+                .read()
+                .expect_error("state.read() failed")
+                .is_join_kad;
+            if !is_join_kad {
+                continue;
+            }
+
+            log::trace!(target: "yiilian_dht::dht::periodic_sample_infohashes", "[{}] Enter periodic_sample_infohashes, target: {}", self.local_addr, target);
+
+            let nearest = {
+                let routing_table = self.ctx.routing_table();
+                let routing_table = routing_table
+                    .lock()
+                    .expect_error("routing_table.lock() failed");
+
+                let mut nearest = routing_table.get_nearest_nodes(&target, None);
+                nearest.extend(routing_table.get_nearest_nodes6(&target, None));
+                nearest
+            };
+
+            let mut futures = FuturesUnordered::new();
+            for node in nearest {
+                futures.push(
+                    self.ctx.transaction_manager().sample_infohashes(node.address, Some(node.id), target),
+                );
+            }
+
+            while let Some(rst) = futures.next().await {
+                match rst {
+                    Ok(reply) => {
+                        // 将对方返回的节点加入路由表，以便遍历更多的节点
+                        for node in reply.nodes.into_iter().chain(reply.nodes6) {
+                            let mut routing_table = self.ctx.routing_table()
+                                .lock()
+                                .expect_error("routing_table.lock() failed");
+
+                            if node.id.is_valid_for_ip(&node.address.ip(), &routing_table.white_list) {
+                                routing_table.add_or_update(node, false).ok();
+                            }
+                        }
+                    }
+                    Err(error) => match error.get_kind() {
+                        Kind::General | Kind::Transatcion => (),
+                        _ => {
+                            log::trace!(target: "yiilian_dht::dht::periodic_sample_infohashes", "[{}] error: {:?}", self.local_addr, error);
+                        }
+                    },
+                }
+            }
+
+            target = next_sample_target(&target);
+        }
+    }
+
     /// 定期维护 token
     async fn periodic_token_rotation(&self) -> Result<(), Error> {
         let token_refresh_interval_sec =
-            dht_ctx_settings(self.ctx_index).token_refresh_interval_sec;
+            self.ctx.settings().token_refresh_interval_sec;
 
         loop {
-            log::trace!(target: "yiilian_dht::dht::periodic_token_rotation", "[{}] Enter periodic_token_rotation", self.ctx_index);
+            log::trace!(target: "yiilian_dht::dht::periodic_token_rotation", "[{}] Enter periodic_token_rotation", self.local_addr);
 
             sleep(Duration::from_secs(token_refresh_interval_sec)).await;
             self.rotate_token_secrets();
@@ -530,45 +630,104 @@ where
 
     /// 更新 token_secret
     fn rotate_token_secrets(&self) {
-        let new_token_secret = random_bytes(dht_ctx_settings(self.ctx_index).token_secret_size);
-        let old_token_secret = dht_ctx_state(self.ctx_index)
+        let new_token_secret = random_bytes(self.ctx.settings().token_secret_size);
+        let old_token_secret = self.ctx.state()
             .read()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .token_secret
             .clone();
 
-        dht_ctx_state(self.ctx_index)
+        self.ctx.state()
             .write()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .old_token_secret = old_token_secret;
 
-        dht_ctx_state(self.ctx_index)
+        self.ctx.state()
             .write()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .token_secret = new_token_secret;
     }
 
     pub async fn get_peers(&self, info_hash: Id) -> Result<GetPeersResult, Error> {
-        dht_ctx_trans_mgr(self.ctx_index)
-            .get_peers(info_hash, true)
+        self.ctx.transaction_manager()
+            .get_peers(info_hash)
             .await
     }
+
+    /// 查找 info_hash 的 peers，可以在查找过程中取出已收到的 peers
+    pub fn lookup_peers(&self, info_hash: Id) -> Result<Lookup, Error> {
+        Lookup::new(self.handle(), LookupKind::GetPeers, info_hash)
+    }
+
+    /// 向 info_hash 附近的节点宣告本节点提供该资源的下载，返回接受宣告的节点
+    ///
+    /// port 为 None 时对方使用 DHT 端口作为下载端口（implied_port）
+    pub async fn announce_peer(&self, info_hash: Id, port: Option<u16>) -> Result<Vec<Node>, Error> {
+        self.ctx.transaction_manager()
+            .announce_peer(info_hash, port)
+            .await
+    }
+
+    /// 向 DHT 写入不可变数据项（BEP44），返回数据项的 target
+    pub async fn put_immutable(&self, value: BencodeData) -> Result<Id, Error> {
+        Item::check_value_size(&value)?;
+
+        let item = Item::Immutable(value);
+        let target = item.target();
+
+        let nodes = self.ctx.transaction_manager()
+            .put_item(item, None)
+            .await?;
+        log::debug!(target: "yiilian_dht::dht::put_immutable", "[{}] Put item {} to {} nodes", self.local_addr, target, nodes.len());
+
+        Ok(target)
+    }
+
+    /// 向 DHT 写入由 signing_key 签名的可变数据项（BEP44），返回数据项的 target
+    ///
+    /// 同一个 signing_key + salt 的数据项，只有 seq 更大的才能覆盖已存储的数据项。
+    /// 如果提供了 cas，只有对方存储的 seq 等于 cas 时才会覆盖。
+    pub async fn put_mutable(
+        &self,
+        signing_key: &SigningKey,
+        value: BencodeData,
+        salt: Option<Bytes>,
+        seq: i64,
+        cas: Option<i64>,
+    ) -> Result<Id, Error> {
+        let item = Item::Mutable(MutableItem::sign(signing_key, value, salt, seq)?);
+        let target = item.target();
+
+        let nodes = self.ctx.transaction_manager()
+            .put_item(item, cas)
+            .await?;
+        log::debug!(target: "yiilian_dht::dht::put_mutable", "[{}] Put item {} to {} nodes", self.local_addr, target, nodes.len());
+
+        Ok(target)
+    }
+
+    /// 从 DHT 上获取 target 对应的数据项（BEP44），可变数据项需要提供 put 时使用的 salt
+    pub async fn get_item(&self, target: Id, salt: Option<Bytes>) -> Result<Option<Item>, Error> {
+        let rst = self.ctx.transaction_manager()
+            .get_item(target, salt)
+            .await?;
+
+        Ok(rst.item().cloned())
+    }
 }
 
 impl<S> Drop for Dht<S> {
     fn drop(&mut self) {
-        let ctx_index = self.ctx_index;
-        let nodes_file = self.nodes_file.clone();
-
         // save nodes
         log::trace!(target: "yiilian_dht::dht::run_loop", "Task '{}' starting up", "persist nodes on exit");
-        persist_nodes(ctx_index, nodes_file.clone());
-        dht_ctx_drop(ctx_index);
+        if let Err(e) = persist_nodes(&self.ctx, &self.nodes_file) {
+            log::error!(target: "yiilian_dht::dht::persist_nodes", "[{}] Persist nodes to {:?} error: {}", self.local_addr, self.nodes_file.as_os_str(), e);
+        }
     }
 }
 
 fn build_routing_table(
-    ctx_index: u16,
+    ctx: DhtHandle,
     local_id: Id,
     block_list_max_size: usize,
     bucket_size: usize,
@@ -576,24 +735,54 @@ fn build_routing_table(
     shutdown_rx: ShutdownReceiver,
 ) -> Mutex<RoutingTable> {
     let node_block_list = BlockList::new("node_block_list", block_list_max_size, node_block_list, shutdown_rx);
-    let routing_table = RoutingTable::new(ctx_index, bucket_size, node_block_list, local_id);
+    let routing_table = RoutingTable::new(ctx, bucket_size, node_block_list, local_id);
 
     Mutex::new(routing_table)
 }
 
+/// 绑定在指定 ip 上的实例使用不同的文件，绑定 0.0.0.0 时与原来的文件名相同
+fn nodes_file_name(local_addr: &SocketAddr) -> String {
+    if local_addr.ip().is_unspecified() {
+        format!("{}.txt", local_addr.port())
+    } else {
+        format!("{}_{}.txt", local_addr.ip(), local_addr.port())
+    }
+}
+
 fn build_state(local_id: Id, token_secret_size: usize) -> Result<RwLock<State>, Error> {
     let token_secret = random_bytes(token_secret_size);
 
     Ok(RwLock::new(State::new(
         local_id,
         IPV4Consensus::new(2, 10),
+        IPV6Consensus::new(2, 10),
         token_secret,
     )))
 }
 
-fn build_socket(socket_addr: SocketAddr) -> Result<UdpSocket, Error> {
-    let std_sock =
-        std::net::UdpSocket::bind(socket_addr).map_err(|e| Error::new_bind(Some(Box::new(e))))?;
+/// ipv6 为 true 时，绑定同时接收 IPv4 和 IPv6 消息的双栈 socket
+fn build_socket(socket_addr: SocketAddr, ipv6: bool) -> Result<UdpSocket, Error> {
+    let std_sock = if ipv6 {
+        // 0.0.0.0 需要换成 [::] 才能同时监听 IPv4 和 IPv6
+        let socket_addr = match socket_addr.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => {
+                SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), socket_addr.port())
+            }
+            IpAddr::V4(ip) => SocketAddr::new(ip.to_ipv6_mapped().into(), socket_addr.port()),
+            IpAddr::V6(_) => socket_addr,
+        };
+
+        let sock = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))
+            .map_err(|e| Error::new_bind(Some(Box::new(e))))?;
+        sock.set_only_v6(false)
+            .map_err(|e| Error::new_bind(Some(Box::new(e))))?;
+        sock.bind(&socket_addr.into())
+            .map_err(|e| Error::new_bind(Some(Box::new(e))))?;
+
+        std::net::UdpSocket::from(sock)
+    } else {
+        std::net::UdpSocket::bind(socket_addr).map_err(|e| Error::new_bind(Some(Box::new(e))))?
+    };
     std_sock
         .set_nonblocking(true)
         .map_err(|e| Error::new_bind(Some(Box::new(e))))?;
@@ -603,72 +792,267 @@ fn build_socket(socket_addr: SocketAddr) -> Result<UdpSocket, Error> {
     Ok(socket)
 }
 
-/// save nodes to file
-fn persist_nodes(ctx_index: u16, nodes_file: PathBuf) {
-    let mut nodes = dht_ctx_routing_tbl(ctx_index)
-        .lock()
-        .expect_error("dht_ctx_routing_tbl.lock() failed")
-        .get_all_verified();
-    nodes.extend(
-        dht_ctx_routing_tbl(ctx_index)
-            .lock()
-            .expect_error("dht_ctx_routing_tbl.lock() failed")
-            .get_all_unverified(),
-    );
+/// 生成下一个 sample_infohashes 的 target：前 2 个字节递增（按 1/65536 的步长遍历 keyspace），其余字节随机
+fn next_sample_target(target: &Id) -> Id {
+    let bytes = target.to_vec();
+    let prefix = u16::from_be_bytes([bytes[0], bytes[1]]).wrapping_add(1);
 
-    let node_addrs: Vec<SocketAddr> = nodes.into_iter().map(|node| node.address).collect();
-
-    let persist = Persist { node_addrs };
-
-    let persist = serde_yaml::to_string(&persist).expect_error("serde_yaml::to_string() failed");
-    let parent_path = nodes_file
-        .parent()
-        .expect_error("nodes_file.parent() is none");
+    let mut next = Id::from_random(&mut rand::thread_rng()).to_vec();
+    next[..2].copy_from_slice(&prefix.to_be_bytes());
 
-    match std::fs::create_dir_all(&parent_path) {
-        Ok(_) => {
-            let mut f = File::create(&nodes_file).expect_error("File::create() node file failed");
+    Id::from_bytes(&next[..ID_SIZE]).expect_error("Id::from_bytes() failed")
+}
 
-            f.write_all(persist.as_bytes())
-                .expect_error("f.write_all() nodes failed");
-        }
+/// 读取节点文件，文件不存在或解析失败时返回 None
+fn load_persist(nodes_file: &PathBuf) -> Option<Persist> {
+    match fs::read_to_string(nodes_file) {
+        Ok(val) => match serde_yaml::from_str::<Persist>(&val) {
+            // 更新版本写入的文件可能有不认识的字段，按默认值读取会丢失数据
+            Ok(persist) if persist.version > PERSIST_VERSION => {
+                log::warn!(target: "yiilian_dht::dht::load_persist", "Node file {:?} version {} is newer than {}, ignored", nodes_file.as_os_str(), persist.version, PERSIST_VERSION);
+                None
+            }
+            Ok(persist) => Some(persist),
+            Err(e) => {
+                log::debug!(target: "yiilian_dht::dht::load_persist", "Parsing node file {:?} error: {}", nodes_file.as_os_str(), e);
+                None
+            }
+        },
         Err(e) => {
-            log::error!(target:"yiilian_dht::routing_table::save_nodes", "Path create {:?} error: {}", parent_path, e);
+            // 第一次运行的时候肯定是不存在 node_file 的
+            log::debug!(target: "yiilian_dht::dht::load_persist", "Read node file {:?} error: {}", nodes_file.as_os_str(), e);
+            None
         }
     }
 }
 
-pub async fn ping(
-    ctx_index: u16,
-    target_addr: SocketAddr,
-    target_id: Option<Id>,
-) -> Result<Reply, Error> {
-    dht_ctx_trans_mgr(ctx_index)
-        .ping(target_addr, target_id)
-        .await
+/// 从节点文件直接恢复路由表和 peers，之后由 ping_persist_nodes_once 重新验证节点
+fn restore_persist(ctx: &Context, nodes_file: &PathBuf) {
+    let persist = match load_persist(nodes_file) {
+        Some(persist) => persist,
+        None => return,
+    };
+
+    {
+        let mut routing_table = ctx.routing_table().lock().expect_error("routing_table.lock() failed");
+        for node in &persist.nodes {
+            match Node::try_from(node) {
+                Ok(restored) => {
+                    if let Err(e) = routing_table.restore(restored) {
+                        log::debug!(target: "yiilian_dht::dht::restore_persist", "Restore node {} error: {}", node.address, e);
+                    }
+                }
+                Err(e) => {
+                    log::debug!(target: "yiilian_dht::dht::restore_persist", "Parsing node {} error: {}", node.address, e);
+                }
+            }
+        }
+    }
+
+    let mut peer_manager = ctx.peer_manager().lock().expect_error("peer_manager.lock() failed");
+    for peer in &persist.peers {
+        match peer.to_peer() {
+            Ok((info_hash, peer)) => peer_manager.restore_peer(info_hash, peer),
+            Err(e) => {
+                log::debug!(target: "yiilian_dht::dht::restore_persist", "Parsing peer {} error: {}", peer.addr, e);
+            }
+        }
+    }
 }
 
-pub async fn find_node(ctx_index: u16, target_id: Id) -> Result<Vec<Node>, Error> {
-    let rst = dht_ctx_trans_mgr(ctx_index).find_node(target_id).await;
-    Ok(rst)
+/// save nodes and peers to file
+fn persist_nodes(ctx: &Context, nodes_file: &PathBuf) -> Result<(), Error> {
+    let nodes: Vec<PersistNode> = {
+        let routing_table = ctx.routing_table().lock().expect_error("routing_table.lock() failed");
+        routing_table
+            .get_all_verified()
+            .iter()
+            .chain(routing_table.get_all_unverified().iter())
+            .map(PersistNode::from)
+            .collect()
+    };
+
+    let peers: Vec<PersistPeer> = ctx
+        .peer_manager()
+        .lock()
+        .expect_error("peer_manager.lock() failed")
+        .get_all_peers()
+        .iter()
+        .map(|(info_hash, peer)| PersistPeer::new(info_hash, peer))
+        .collect();
+
+    let persist = Persist::new(nodes, peers);
+    let persist = serde_yaml::to_string(&persist).map_err(|e| Error::new_file(Some(e.into()), None))?;
+
+    let parent_path = nodes_file
+        .parent()
+        .ok_or_else(|| Error::new_general(&format!("Node file {:?} has no parent", nodes_file)))?;
+    fs::create_dir_all(parent_path).map_err(|e| Error::new_file(Some(e.into()), None))?;
+
+    // 先写临时文件再改名，写入中断时保留原来的节点文件
+    let tmp_file = nodes_file.with_extension("tmp");
+    fs::write(&tmp_file, persist.as_bytes()).map_err(|e| Error::new_file(Some(e.into()), None))?;
+    fs::rename(&tmp_file, nodes_file).map_err(|e| Error::new_file(Some(e.into()), None))?;
+
+    Ok(())
 }
 
-pub async fn get_peers(
-    ctx_index: u16,
-    info_hash: Id,
-    quick_mode: bool,
-) -> Result<GetPeersResult, Error> {
-    dht_ctx_trans_mgr(ctx_index)
-        .get_peers(info_hash, quick_mode)
-        .await
+impl DhtHandle {
+    pub async fn ping(&self, target_addr: SocketAddr, target_id: Option<Id>) -> Result<Reply, Error> {
+        self.ctx()?
+            .transaction_manager()
+            .ping(target_addr, target_id)
+            .await
+    }
+
+    pub async fn find_node(&self, target_id: Id) -> Result<Vec<Node>, Error> {
+        let rst = self.ctx()?.transaction_manager().find_node(target_id).await;
+        Ok(rst)
+    }
+
+    pub async fn get_peers(&self, info_hash: Id) -> Result<GetPeersResult, Error> {
+        self.ctx()?
+            .transaction_manager()
+            .get_peers(info_hash)
+            .await
+    }
+
+    pub fn lookup_peers(&self, info_hash: Id) -> Result<Lookup, Error> {
+        Lookup::new(self.clone(), LookupKind::GetPeers, info_hash)
+    }
+
+    pub async fn sample_infohashes(
+        &self,
+        target_addr: SocketAddr,
+        target_id: Option<Id>,
+        target: Id,
+    ) -> Result<SampleInfoHashesReply, Error> {
+        self.ctx()?
+            .transaction_manager()
+            .sample_infohashes(target_addr, target_id, target)
+            .await
+    }
+
+    pub async fn announce_peer(&self, local_addr: SocketAddr, info_hash: Id) -> Result<Vec<Node>, Error> {
+        self.ctx()?
+            .transaction_manager()
+            .announce_peer(info_hash, Some(local_addr.port()))
+            .await
+    }
 }
 
-pub async fn announce_peer(
-    ctx_index: u16,
-    local_addr: SocketAddr,
-    info_hash: Id,
-) -> Result<Vec<Node>, Error> {
-    dht_ctx_trans_mgr(ctx_index)
-        .announce_peer(info_hash, Some(local_addr.port()))
-        .await
+#[cfg(test)]
+mod tests {
+    use yiilian_core::common::shutdown::create_shutdown;
+
+    use crate::common::SettingsBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_next_sample_target() {
+        let target = Id::from_hex("00ff000000000000000000000000000000000000").unwrap();
+        let next = next_sample_target(&target);
+        assert_eq!(&[0x01, 0x00], &next.to_vec()[..2]);
+
+        let target = Id::from_hex("ffff000000000000000000000000000000000000").unwrap();
+        let next = next_sample_target(&target);
+        assert_eq!(&[0x00, 0x00], &next.to_vec()[..2]);
+    }
+
+    #[tokio::test]
+    async fn test_dht_handle() {
+        let (_shutdown_tx, shutdown_rx) = create_shutdown();
+        let home_dir = std::env::temp_dir().join("yiilian_test_dht_handle");
+        let settings = SettingsBuilder::new().routers(&Some(vec![])).build();
+
+        // 同一个端口的两个实例绑定在不同的地址上，各自使用独立的 Context
+        let addr1: SocketAddr = "127.0.0.1:47931".parse().unwrap();
+        let addr2: SocketAddr = "127.0.0.2:47931".parse().unwrap();
+        let dht1 = DhtBuilder::new(addr1, shutdown_rx.clone(), None, home_dir.clone())
+            .settings(Some(settings.clone()))
+            .build()
+            .unwrap();
+        let dht2 = DhtBuilder::new(addr2, shutdown_rx.clone(), None, home_dir.clone())
+            .settings(Some(settings.clone()))
+            .build()
+            .unwrap();
+
+        let handle1 = dht1.handle();
+        tokio::select! {
+            _ = dht1.run_loop() => panic!("dht1 quit"),
+            _ = dht2.run_loop() => panic!("dht2 quit"),
+            rst = handle1.ping(addr2, None) => assert!(rst.is_ok()),
+        }
+
+        // drop 后 Context 随之释放，可以重新绑定同一个地址
+        drop(dht1);
+        assert!(handle1.is_closed());
+        assert!(handle1.ping(addr2, None).await.is_err());
+
+        sleep(Duration::from_millis(100)).await;
+        let dht1 = DhtBuilder::new(addr1, shutdown_rx.clone(), None, home_dir.clone())
+            .settings(Some(settings))
+            .build()
+            .unwrap();
+        assert!(!dht1.handle().is_closed());
+
+        drop(dht1);
+        drop(dht2);
+        fs::remove_dir_all(home_dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_persist_restore() {
+        let (_shutdown_tx, shutdown_rx) = create_shutdown();
+        let home_dir = std::env::temp_dir().join("yiilian_test_persist_restore");
+        let settings = SettingsBuilder::new().routers(&Some(vec![])).build();
+        let local_addr: SocketAddr = "127.0.0.1:48500".parse().unwrap();
+
+        let node_id = Id::from_hex("88ffb73943354a00dc2dadd14c54d28020a513c8").unwrap();
+        let node_addr: SocketAddr = "127.0.0.2:6881".parse().unwrap();
+        let peer_addr: SocketAddr = "127.0.0.3:6882".parse().unwrap();
+
+        let dht = DhtBuilder::new(local_addr, shutdown_rx.clone(), None, home_dir.clone())
+            .settings(Some(settings.clone()))
+            .build()
+            .unwrap();
+        dht.ctx.routing_table().lock().unwrap()
+            .add_or_update(Node::new(node_id, node_addr), true)
+            .unwrap();
+        dht.ctx.peer_manager().lock().unwrap().announce_peer(node_id, peer_addr);
+        let last_seen = dht.ctx.routing_table().lock().unwrap().get_all_verified()[0].last_seen;
+        drop(dht);
+
+        // 重启后不需要 ping，路由表和 peers 直接从节点文件恢复
+        sleep(Duration::from_millis(100)).await;
+        let dht = DhtBuilder::new(local_addr, shutdown_rx.clone(), None, home_dir.clone())
+            .settings(Some(settings))
+            .build()
+            .unwrap();
+
+        let nodes = dht.ctx.routing_table().lock().unwrap().get_all_verified();
+        assert_eq!(1, nodes.len());
+        assert_eq!(node_id, nodes[0].id);
+        assert_eq!(node_addr, nodes[0].address);
+        assert_eq!(last_seen.timestamp_millis(), nodes[0].last_seen.timestamp_millis());
+        assert_eq!(vec![peer_addr], dht.ctx.peer_manager().lock().unwrap().get_peers(&node_id, None));
+
+        drop(dht);
+        fs::remove_dir_all(home_dir).ok();
+    }
+
+    #[test]
+    fn test_load_persist_version() {
+        let nodes_file = std::env::temp_dir().join("yiilian_test_load_persist_version.txt");
+
+        fs::write(&nodes_file, "node_addrs:\n- 127.0.0.1:6881\n").unwrap();
+        assert_eq!(1, load_persist(&nodes_file).unwrap().node_addrs.len());
+
+        // 不认识的更新版本不读取
+        fs::write(&nodes_file, format!("version: {}\nnodes: []\npeers: []\n", PERSIST_VERSION + 1)).unwrap();
+        assert!(load_persist(&nodes_file).is_none());
+
+        fs::remove_file(nodes_file).ok();
+    }
 }
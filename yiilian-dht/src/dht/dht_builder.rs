@@ -7,17 +7,17 @@ use yiilian_core::{
 };
 
 use crate::{
-    common::{Settings, SettingsBuilder},
+    common::{Id, Settings, SettingsBuilder},
     data::body::KrpcBody,
     service::{KrpcService, RouterService},
 };
 
 use super::{Dht, DhtMode};
 
-pub struct DhtBuilder<L, S> {
+pub struct DhtBuilder<L> {
     local_addr: SocketAddr,
+    local_id: Option<Id>,
     service_builder: ServiceBuilder<L>,
-    router_service: S,
     settings: Option<Settings>,
     block_list: Option<HashSet<BlockAddr>>,
     shutdown_rx: ShutdownReceiver,
@@ -26,13 +26,12 @@ pub struct DhtBuilder<L, S> {
     home_dir: PathBuf,
 }
 
-impl DhtBuilder<Identity, RouterService> {
+impl DhtBuilder<Identity> {
     pub fn new(local_addr: SocketAddr, shutdown_rx: ShutdownReceiver, workers: Option<usize>, home_dir: PathBuf) -> Self {
-        let router_service = RouterService::new(local_addr);
         Self {
             local_addr,
+            local_id: None,
             service_builder: ServiceBuilder::new(),
-            router_service,
             settings: None,
             block_list: None,
             shutdown_rx,
@@ -56,18 +55,24 @@ impl DhtBuilder<Identity, RouterService> {
         self.mode = mode;
         self
     }
+
+    /// 指定节点 id，不指定时根据 mode 生成
+    pub fn local_id(mut self, local_id: Option<Id>) -> Self {
+        self.local_id = local_id;
+        self
+    }
 }
 
-impl<L, S> DhtBuilder<L, S> {
-    pub fn layer<T>(self, layer: T) -> DhtBuilder<Stack<T, L>, S> {
+impl<L> DhtBuilder<L> {
+    pub fn layer<T>(self, layer: T) -> DhtBuilder<Stack<T, L>> {
         let service_builder = ServiceBuilder {
             layer: Stack::new(layer, self.service_builder.layer),
         };
 
         DhtBuilder {
             local_addr: self.local_addr,
+            local_id: self.local_id,
             service_builder,
-            router_service: self.router_service,
             settings: self.settings,
             block_list: self.block_list,
             shutdown_rx: self.shutdown_rx,
@@ -79,14 +84,15 @@ impl<L, S> DhtBuilder<L, S> {
 
     pub fn build(self) -> Result<Dht<L::Service>, Error>
     where
-        L: Layer<S>,
+        L: Layer<RouterService>,
         L::Service:
             KrpcService<KrpcBody, ResBody = KrpcBody, Error = Error> + Clone + Send + 'static,
     {
-        let service = self.service_builder.service(self.router_service);
+        let service_builder = self.service_builder;
         let dht = Dht::init(
             self.local_addr,
-            service,
+            self.local_id,
+            |router_service| service_builder.service(router_service),
             self.settings.unwrap_or(SettingsBuilder::new().build()),
             self.block_list,
             self.shutdown_rx,
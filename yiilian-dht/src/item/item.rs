@@ -0,0 +1,230 @@
+use bytes::Bytes;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha1::{Digest, Sha1};
+use yiilian_core::{
+    common::error::Error,
+    data::{BencodeData, Encode},
+};
+
+use crate::common::Id;
+
+/// bencode 编码后的 v 的最大长度
+pub const MAX_ITEM_VALUE_SIZE: usize = 1000;
+
+/// salt 的最大长度
+pub const MAX_SALT_SIZE: usize = 64;
+
+/// BEP44 中存放在 DHT 上的数据项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// 不可变数据项，target = SHA1(bencode(v))
+    Immutable(BencodeData),
+
+    /// 可变数据项，target = SHA1(k + salt)
+    Mutable(MutableItem),
+}
+
+impl Item {
+    /// 计算数据项在 DHT 上的 target
+    pub fn target(&self) -> Id {
+        match self {
+            Item::Immutable(value) => Item::immutable_target(value),
+            Item::Mutable(item) => item.target(),
+        }
+    }
+
+    pub fn value(&self) -> &BencodeData {
+        match self {
+            Item::Immutable(value) => value,
+            Item::Mutable(item) => &item.value,
+        }
+    }
+
+    pub fn immutable_target(value: &BencodeData) -> Id {
+        sha1_id(&value.encode())
+    }
+
+    pub fn mutable_target(k: &[u8], salt: Option<&Bytes>) -> Id {
+        let mut data = k.to_vec();
+        if let Some(salt) = salt {
+            data.extend(salt);
+        }
+
+        sha1_id(&data)
+    }
+
+    /// 校验数据项是否和 target 匹配，可变数据项还需要校验签名
+    pub fn is_valid_for(&self, target: &Id) -> bool {
+        match self {
+            Item::Immutable(_) => self.target() == *target,
+            Item::Mutable(item) => item.target() == *target && item.verify().is_ok(),
+        }
+    }
+
+    /// 校验 v 的长度是否合法
+    pub fn check_value_size(value: &BencodeData) -> Result<(), Error> {
+        let size = value.encode().len();
+        if size > MAX_ITEM_VALUE_SIZE {
+            Err(Error::new_general(&format!(
+                "Item value is too big: {} bytes",
+                size
+            )))?
+        }
+
+        Ok(())
+    }
+}
+
+/// 可变数据项，由 k 对应的私钥签名
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MutableItem {
+    /// ed25519 公钥
+    pub k: Bytes,
+
+    pub salt: Option<Bytes>,
+
+    /// 序列号，只有更大的 seq 才能覆盖已存储的数据项
+    pub seq: i64,
+
+    /// ed25519 签名
+    pub sig: Bytes,
+
+    pub value: BencodeData,
+}
+
+impl MutableItem {
+    pub fn new(k: Bytes, salt: Option<Bytes>, seq: i64, sig: Bytes, value: BencodeData) -> Self {
+        MutableItem {
+            k,
+            salt,
+            seq,
+            sig,
+            value,
+        }
+    }
+
+    /// 用私钥对数据项签名，生成可变数据项
+    pub fn sign(
+        signing_key: &SigningKey,
+        value: BencodeData,
+        salt: Option<Bytes>,
+        seq: i64,
+    ) -> Result<Self, Error> {
+        Item::check_value_size(&value)?;
+        check_salt_size(&salt)?;
+
+        let signable = signable_bytes(&value, salt.as_ref(), seq);
+        let sig = signing_key.sign(&signable);
+
+        Ok(MutableItem {
+            k: Bytes::copy_from_slice(signing_key.verifying_key().as_bytes()),
+            salt,
+            seq,
+            sig: Bytes::copy_from_slice(&sig.to_bytes()),
+            value,
+        })
+    }
+
+    pub fn target(&self) -> Id {
+        Item::mutable_target(&self.k, self.salt.as_ref())
+    }
+
+    /// 校验数据项的签名
+    pub fn verify(&self) -> Result<(), Error> {
+        Item::check_value_size(&self.value)?;
+        check_salt_size(&self.salt)?;
+
+        let k: [u8; 32] = self.k.as_ref().try_into().map_err(|_| {
+            Error::new_general(&format!("Invalid public key size: {}", self.k.len()))
+        })?;
+        let sig: [u8; 64] = self.sig.as_ref().try_into().map_err(|_| {
+            Error::new_general(&format!("Invalid signature size: {}", self.sig.len()))
+        })?;
+
+        let verifying_key = VerifyingKey::from_bytes(&k)
+            .map_err(|e| Error::new_general(&format!("Invalid public key: {}", e)))?;
+        let signable = signable_bytes(&self.value, self.salt.as_ref(), self.seq);
+
+        verifying_key
+            .verify(&signable, &Signature::from_bytes(&sig))
+            .map_err(|_| Error::new_general("Invalid signature"))
+    }
+}
+
+fn check_salt_size(salt: &Option<Bytes>) -> Result<(), Error> {
+    if let Some(salt) = salt {
+        if salt.len() > MAX_SALT_SIZE {
+            Err(Error::new_general(&format!(
+                "Item salt is too big: {} bytes",
+                salt.len()
+            )))?
+        }
+    }
+
+    Ok(())
+}
+
+/// 生成签名的原文： 4:salt<len>:<salt>3:seqi<seq>e1:v<bencoded v>
+fn signable_bytes(value: &BencodeData, salt: Option<&Bytes>, seq: i64) -> Vec<u8> {
+    let mut rst = vec![];
+    if let Some(salt) = salt {
+        if !salt.is_empty() {
+            rst.extend(b"4:salt");
+            rst.extend(salt.encode());
+        }
+    }
+    rst.extend(b"3:seq");
+    rst.extend(seq.encode());
+    rst.extend(b"1:v");
+    rst.extend(value.encode());
+
+    rst
+}
+
+fn sha1_id(data: &[u8]) -> Id {
+    let mut hasher = Sha1::new();
+    hasher.update(data);
+    let hash = hasher.finalize();
+
+    Id::from_bytes(&hash).expect("SHA1 digest is 20 bytes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_immutable_target() {
+        // BEP44 test vector
+        let value = BencodeData::from("Hello World!");
+        let target = Item::immutable_target(&value);
+
+        assert_eq!(
+            Id::from_hex("e5f96f6f38320f0f33959cb4d3d656452117aadb").unwrap(),
+            target
+        );
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let value = BencodeData::from("Hello World!");
+        let salt: Option<Bytes> = Some("foobar".into());
+
+        let mut item = MutableItem::sign(&signing_key, value, salt.clone(), 1).unwrap();
+        assert!(item.verify().is_ok());
+        assert_eq!(Item::mutable_target(&item.k, salt.as_ref()), item.target());
+
+        item.seq = 2;
+        assert!(item.verify().is_err());
+    }
+
+    #[test]
+    fn test_signable_bytes() {
+        let value = BencodeData::from("Hello World!");
+        let salt: Bytes = "foobar".into();
+        let rst = signable_bytes(&value, Some(&salt), 1);
+
+        assert_eq!(b"4:salt6:foobar3:seqi1e1:v12:Hello World!".to_vec(), rst);
+    }
+}
@@ -0,0 +1,99 @@
+use std::num::NonZeroUsize;
+
+use chrono::{DateTime, Utc};
+use lru::LruCache;
+use yiilian_core::common::expect_log::ExpectLog;
+
+use crate::common::Id;
+
+use super::Item;
+
+#[derive(Debug)]
+struct StoredItem {
+    item: Item,
+    last_updated: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+/// 存放 BEP44 put 请求写入的数据项
+pub struct ItemManager {
+    /// LruCache 最近最少使用缓存： key = target, value = 数据项
+    items: LruCache<Id, StoredItem>,
+}
+
+impl ItemManager {
+    pub fn new(max_items: usize) -> ItemManager {
+        ItemManager {
+            items: LruCache::new(
+                NonZeroUsize::new(max_items).expect_error("ItemManager NonZeroUsize create failed"),
+            ),
+        }
+    }
+
+    /// 保存数据项，已存在的同一 target 数据项会被替换
+    pub fn put(&mut self, target: Id, item: Item) {
+        self.items.put(
+            target,
+            StoredItem {
+                item,
+                last_updated: Utc::now(),
+            },
+        );
+    }
+
+    /// 返回 最后更新时间 > newer_than 的数据项
+    pub fn get(&mut self, target: &Id, newer_than: Option<DateTime<Utc>>) -> Option<Item> {
+        match self.items.get(target) {
+            Some(stored) => match newer_than {
+                Some(newer_than) if stored.last_updated <= newer_than => None,
+                _ => Some(stored.item.clone()),
+            },
+            None => None,
+        }
+    }
+
+    /// 返回 target 对应的可变数据项的 seq
+    pub fn get_seq(&mut self, target: &Id) -> Option<i64> {
+        match self.items.get(target) {
+            Some(StoredItem {
+                item: Item::Mutable(item),
+                ..
+            }) => Some(item.seq),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use yiilian_core::data::BencodeData;
+
+    use super::*;
+
+    #[test]
+    fn test_put_get() {
+        let mut mgr = ItemManager::new(1);
+        let item1 = Item::Immutable(BencodeData::from("value1"));
+        let item2 = Item::Immutable(BencodeData::from("value2"));
+        let target1 = item1.target();
+        let target2 = item2.target();
+
+        mgr.put(target1, item1.clone());
+        assert_eq!(Some(item1), mgr.get(&target1, None));
+
+        // 超出容量时，最早的数据项被淘汰
+        mgr.put(target2, item2.clone());
+        assert_eq!(None, mgr.get(&target1, None));
+        assert_eq!(Some(item2), mgr.get(&target2, None));
+        assert_eq!(None, mgr.get_seq(&target2));
+        assert_eq!(1, mgr.len());
+    }
+}
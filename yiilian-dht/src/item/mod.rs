@@ -0,0 +1,7 @@
+#[allow(clippy::module_inception)]
+mod item;
+mod item_manager;
+
+pub use item::{Item, MutableItem, MAX_ITEM_VALUE_SIZE, MAX_SALT_SIZE};
+pub use item_manager::ItemManager;
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
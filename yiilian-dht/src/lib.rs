@@ -4,8 +4,10 @@ pub mod common;
 pub mod routing_table;
 pub mod transaction;
 pub mod peer;
+pub mod item;
 pub mod net;
 pub mod data;
 pub mod service;
 pub mod dht;
 
+pub mod testing;
@@ -5,19 +5,29 @@ use yiilian_core::{common::error::Error, data::{Body, Request}, net::udp::send_t
 
 use crate::data::body::KrpcBody;
 
+use super::mapped_addr;
+
 pub struct Client {
     socket: Arc<UdpSocket>,
+    /// 是否为双栈 socket
+    is_ipv6: bool,
 }
 
 impl Client {
     pub fn new(socket: Arc<UdpSocket>) -> Self {
+        let is_ipv6 = socket
+            .local_addr()
+            .map(|addr| addr.is_ipv6())
+            .unwrap_or(false);
+
         Client {
-            socket
+            socket,
+            is_ipv6,
         }
     }
 
     pub async fn send(&self, mut req: Request<KrpcBody>) -> Result<usize, Error> {
-        let dest = req.remote_addr;
+        let dest = mapped_addr(req.remote_addr, self.is_ipv6);
         let data = req.get_data();
         
         send_to(&self.socket, &data, dest).await
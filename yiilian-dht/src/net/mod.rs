@@ -1,5 +1,41 @@
 mod client;
 mod server;
 
+use std::net::{IpAddr, SocketAddr};
+
 pub use client::Client;
-pub use server::Server;
\ No newline at end of file
+pub use server::Server;
+
+/// 双栈 socket 收到的 IPv4 地址是 IPv4-mapped 的 IPv6 地址 (::ffff:a.b.c.d)，需要还原成 IPv4 地址
+pub(crate) fn canonical_addr(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
+
+/// 通过双栈 socket 向 IPv4 地址发送消息时，需要转换成 IPv4-mapped 的 IPv6 地址
+pub(crate) fn mapped_addr(addr: SocketAddr, is_ipv6_socket: bool) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if is_ipv6_socket => {
+            SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), addr.port())
+        }
+        _ => addr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapped_addr() {
+        let v4: SocketAddr = "1.2.3.4:80".parse().unwrap();
+        let mapped: SocketAddr = "[::ffff:1.2.3.4]:80".parse().unwrap();
+
+        assert_eq!(mapped, mapped_addr(v4, true));
+        assert_eq!(v4, mapped_addr(v4, false));
+        assert_eq!(v4, canonical_addr(mapped));
+
+        let v6: SocketAddr = "[2001:db8::1]:80".parse().unwrap();
+        assert_eq!(v6, mapped_addr(v6, true));
+        assert_eq!(v6, canonical_addr(v6));
+    }
+}
@@ -14,6 +14,8 @@ use crate::data::body::{BodyKind, KrpcBody};
 
 use crate::service::KrpcService;
 
+use super::{canonical_addr, mapped_addr};
+
 pub struct Server<S> {
     socket: Arc<UdpSocket>,
     local_addr: SocketAddr,
@@ -59,7 +61,7 @@ where
             };
 
             let (data, remote_addr) = match recv_from(&self.socket).await {
-                Ok(val) => val,
+                Ok((data, remote_addr)) => (data, canonical_addr(remote_addr)),
                 Err(error) => {
                     log::debug!(
                         target: "yiilian_dht::net::server",
@@ -106,8 +108,9 @@ where
                         match res.body.get_kind() {
                             BodyKind::Empty => {} // response body 为空则不需要 send_to
                             _ => {
+                                let dest = mapped_addr(res.remote_addr, local_addr.is_ipv6());
                                 if let Err(error) =
-                                    send_to(&socket, &res.get_data(), res.remote_addr).await
+                                    send_to(&socket, &res.get_data(), dest).await
                                 {
                                     log::error!(
                                         target: "yiilian_dht::net::server",
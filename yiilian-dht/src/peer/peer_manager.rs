@@ -26,10 +26,16 @@ impl PeerManager {
     }
 
     pub fn announce_peer(&mut self, info_hash: Id, peer_addr: SocketAddr) {
+        self.restore_peer(info_hash, Peer::new(peer_addr));
+        // log::debug!(target: "yiilian_dht::PeerManager", "{} is in swarm with info_hash {}", peer_addr, info_hash);
+    }
+
+    /// 加入 peer 并保留其 last_updated，用于从节点文件恢复
+    pub fn restore_peer(&mut self, info_hash: Id, peer: Peer) {
         let peers = &mut self.peers;
         match peers.get_mut(&info_hash) {
             Some(swarm_lru) => {
-                swarm_lru.put(peer_addr, Peer::new(peer_addr));
+                swarm_lru.put(peer.addr, peer);
             }
 
             None => {
@@ -37,14 +43,24 @@ impl PeerManager {
                     NonZeroUsize::new(self.max_peers_per_resource)
                         .expect_error("PeerManager NonZeroUsize create failed"),
                 );
-                swarm_lru.put(peer_addr, Peer::new(peer_addr));
-                peers.put(info_hash.clone(), swarm_lru);
+                swarm_lru.put(peer.addr, peer);
+                peers.put(info_hash, swarm_lru);
             }
         }
-        // log::debug!(target: "yiilian_dht::PeerManager", "{} is in swarm with info_hash {}", peer_addr, info_hash);
     }
 
-    /// 返回 最后更新时间 > newer_than 的 IPv4 的 peers 的 IP 地址列表
+    /// 返回所有资源的 (info_hash, peer)，按最近最少使用的顺序，恢复时可以保持 LRU 顺序
+    pub fn get_all_peers(&self) -> Vec<(Id, Peer)> {
+        self.peers
+            .iter()
+            .rev()
+            .flat_map(|(info_hash, swarm_lru)| {
+                swarm_lru.iter().rev().map(move |(_, peer)| (*info_hash, *peer))
+            })
+            .collect()
+    }
+
+    /// 返回 最后更新时间 > newer_than 的 peers 的 IP 地址列表
     pub fn get_peers(
         &mut self,
         info_hash: &Id,
@@ -54,7 +70,7 @@ impl PeerManager {
         infos.iter().map(|info| info.addr).collect()
     }
 
-    /// 返回 最后更新时间 > newer_than 的 IPv4 的 peers
+    /// 返回 最后更新时间 > newer_than 的 peers (包括 IPv4 和 IPv6)
     pub fn get_peers_info(
         &mut self,
         info_hash: &Id,
@@ -65,7 +81,6 @@ impl PeerManager {
         if let Some(swarm_lru) = peers.get(info_hash) {
             let mut tmp = swarm_lru
                 .iter()
-                .filter(|pi| pi.0.ip().is_ipv4()) // Only return IPv4 for now, you dog!
                 .filter(|pi| {
                     newer_than.is_none()
                         || pi.1.last_updated > newer_than.expect_error("newer_than is none")
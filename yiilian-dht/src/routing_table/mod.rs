@@ -6,4 +6,4 @@ mod persist;
 pub use routing_table::RoutingTable;
 pub use node::Node;
 pub use bucket::Buckets;
-pub use persist::Persist;
\ No newline at end of file
+pub use persist::{Persist, PersistNode, PersistPeer, PERSIST_VERSION};
\ No newline at end of file
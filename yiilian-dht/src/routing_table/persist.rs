@@ -1,8 +1,152 @@
 use std::net::SocketAddr;
 
+use chrono::{DateTime, TimeZone, Utc};
 use serde::{Serialize, Deserialize};
+use yiilian_core::common::error::Error;
 
+use crate::{common::Id, peer::Peer};
+
+use super::Node;
+
+/// 当前节点文件的格式版本
+pub const PERSIST_VERSION: u32 = 2;
+
+/// 节点文件，保存路由表中的节点和 PeerManager 中的 peers
 #[derive(Serialize, Deserialize)]
 pub struct Persist {
+    /// 旧格式（只有 node_addrs）没有该字段，视为版本 1
+    #[serde(default = "default_version")]
+    pub version: u32,
+
+    /// 版本 1 只保存了节点地址，启动时只能重新 ping 来获取节点 id
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub node_addrs: Vec<SocketAddr>,
+
+    #[serde(default)]
+    pub nodes: Vec<PersistNode>,
+
+    #[serde(default)]
+    pub peers: Vec<PersistPeer>,
+}
+
+impl Persist {
+    pub fn new(nodes: Vec<PersistNode>, peers: Vec<PersistPeer>) -> Persist {
+        Persist {
+            version: PERSIST_VERSION,
+            node_addrs: vec![],
+            nodes,
+            peers,
+        }
+    }
+}
+
+fn default_version() -> u32 {
+    1
+}
+
+/// 持久化的节点，时间使用毫秒时间戳
+#[derive(Serialize, Deserialize)]
+pub struct PersistNode {
+    pub id: String,
+    pub address: SocketAddr,
+    pub first_seen: i64,
+    pub last_seen: i64,
+    pub last_verified: Option<i64>,
+}
+
+impl From<&Node> for PersistNode {
+    fn from(node: &Node) -> Self {
+        PersistNode {
+            id: node.id.to_string(),
+            address: node.address,
+            first_seen: node.first_seen.timestamp_millis(),
+            last_seen: node.last_seen.timestamp_millis(),
+            last_verified: node.last_verified.map(|t| t.timestamp_millis()),
+        }
+    }
+}
+
+impl TryFrom<&PersistNode> for Node {
+    type Error = Error;
+
+    fn try_from(value: &PersistNode) -> Result<Self, Self::Error> {
+        let mut node = Node::new(Id::from_hex(&value.id)?, value.address);
+        node.first_seen = from_millis(value.first_seen)?;
+        node.last_seen = from_millis(value.last_seen)?;
+        node.last_verified = value.last_verified.map(from_millis).transpose()?;
+
+        Ok(node)
+    }
+}
+
+/// 持久化的 peer，时间使用毫秒时间戳
+#[derive(Serialize, Deserialize)]
+pub struct PersistPeer {
+    pub info_hash: String,
+    pub addr: SocketAddr,
+    pub last_updated: i64,
+}
+
+impl PersistPeer {
+    pub fn new(info_hash: &Id, peer: &Peer) -> PersistPeer {
+        PersistPeer {
+            info_hash: info_hash.to_string(),
+            addr: peer.addr,
+            last_updated: peer.last_updated.timestamp_millis(),
+        }
+    }
+
+    /// 返回 (info_hash, peer)
+    pub fn to_peer(&self) -> Result<(Id, Peer), Error> {
+        let info_hash = Id::from_hex(&self.info_hash)?;
+        let peer = Peer {
+            addr: self.addr,
+            last_updated: from_millis(self.last_updated)?,
+        };
+
+        Ok((info_hash, peer))
+    }
+}
+
+fn from_millis(millis: i64) -> Result<DateTime<Utc>, Error> {
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .ok_or_else(|| Error::new_decode(&format!("Invalid timestamp: {}", millis)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_v1_compatible() {
+        let persist: Persist = serde_yaml::from_str("node_addrs:\n- 127.0.0.1:6881\n").unwrap();
+
+        assert_eq!(1, persist.version);
+        assert_eq!(vec!["127.0.0.1:6881".parse::<SocketAddr>().unwrap()], persist.node_addrs);
+        assert!(persist.nodes.is_empty());
+        assert!(persist.peers.is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let id = Id::from_hex("88ffb73943354a00dc2dadd14c54d28020a513c8").unwrap();
+        let mut node = Node::new(id, "127.0.0.1:6881".parse().unwrap());
+        node.last_verified = Some(node.last_seen);
+        let peer = Peer::new("127.0.0.2:6882".parse().unwrap());
+
+        let persist = Persist::new(vec![(&node).into()], vec![PersistPeer::new(&id, &peer)]);
+        let persist: Persist = serde_yaml::from_str(&serde_yaml::to_string(&persist).unwrap()).unwrap();
+        assert_eq!(PERSIST_VERSION, persist.version);
+
+        let restored = Node::try_from(&persist.nodes[0]).unwrap();
+        assert_eq!(node, restored);
+        assert_eq!(node.last_seen.timestamp_millis(), restored.last_seen.timestamp_millis());
+        assert!(restored.last_verified.is_some());
+
+        let (info_hash, restored) = persist.peers[0].to_peer().unwrap();
+        assert_eq!(id, info_hash);
+        assert_eq!(peer.addr, restored.addr);
+        assert_eq!(peer.last_updated.timestamp_millis(), restored.last_updated.timestamp_millis());
+    }
 }
@@ -10,16 +10,21 @@ use yiilian_core::{
     common::{error::Error, expect_log::ExpectLog}, net::block_list::BlockList
 };
 
-use crate::common::{dht_ctx_state, Id};
+use crate::common::{DhtHandle, Id};
 
 use super::{Buckets, Node};
 
 #[derive(Debug)]
 pub struct RoutingTable {
-    ctx_index: u16,
+    /// 用于更新 State 中的 is_join_kad
+    ctx: DhtHandle,
     verified: Buckets,
     unverified: Buckets,
 
+    /// BEP32 IPv6 节点使用独立的路由表
+    verified6: Buckets,
+    unverified6: Buckets,
+
     /// IP block list
     pub block_list: BlockList,
 
@@ -29,7 +34,7 @@ pub struct RoutingTable {
 
 impl RoutingTable {
     pub fn new(
-        ctx_index: u16,
+        ctx: DhtHandle,
         k: usize,
         block_list: BlockList,
         local_id: Id,
@@ -38,9 +43,11 @@ impl RoutingTable {
         block_list.prune_loop();
 
         RoutingTable {
-            ctx_index,
+            ctx,
             verified: Buckets::new(k, local_id),
             unverified: Buckets::new(k, local_id),
+            verified6: Buckets::new(k, local_id),
+            unverified6: Buckets::new(k, local_id),
             block_list,
             white_list: HashSet::new(),
         }
@@ -87,23 +94,68 @@ impl RoutingTable {
             self.add_or_update_last_seen(node)?;
         }
 
-        dht_ctx_state(self.ctx_index).write().expect_error("Get writable state failed")
-            .is_join_kad = self.verified.count() > 0;
+        self.update_join_kad();
+
+        Ok(())
+    }
+
+    /// 从节点文件恢复节点，保留原有的时间戳，已验证过的节点放入 verified bucket
+    pub fn restore(&mut self, node: Node) -> Result<(), Error> {
+        if self.is_blocked(&node.address) {
+            Err(Error::new_block(&format!("{} is blocked", node.address)))?;
+        }
+
+        if node.address.port() == 0 {
+            Err(Error::new_block(&format!("The port of {} is invalid", node.address)))?;
+        }
+
+        let (verified, unverified) = self.buckets_mut(&node.address);
+        if verified.get_node_mut(&node.id).is_some() || unverified.get_node_mut(&node.id).is_some() {
+            return Ok(());
+        }
+
+        if node.last_verified.is_some() {
+            let mut chump_list = Vec::with_capacity(1);
+            verified.add(node, Some(&mut chump_list))?;
+
+            for item in chump_list {
+                unverified.add(item, None)?;
+            }
+        } else {
+            unverified.add(node, None)?;
+        }
+
+        self.update_join_kad();
 
         Ok(())
     }
 
+    /// 根据节点的地址族，返回对应的 (verified, unverified) 路由表
+    fn buckets_mut(&mut self, addr: &SocketAddr) -> (&mut Buckets, &mut Buckets) {
+        if addr.is_ipv6() {
+            (&mut self.verified6, &mut self.unverified6)
+        } else {
+            (&mut self.verified, &mut self.unverified)
+        }
+    }
+
+    fn verified_count(&self) -> usize {
+        self.verified.count() + self.verified6.count()
+    }
+
     /// last_seen 是指最近我们收到过该节点的消息，不管我们是否发出过请求
     fn add_or_update_last_seen(&mut self, node: Node) -> Result<(), Error> {
-        if let Some(existing) = self.verified.get_node_mut(&node.id) {
+        let (verified, unverified) = self.buckets_mut(&node.address);
+
+        if let Some(existing) = verified.get_node_mut(&node.id) {
             trace!(target: "yiilian_dht::routing_table", "Updating existing verified node( id: {}, addr: {:?} ) last seen", node.id, node.address);
             existing.last_seen = Utc::now();
-        } else if let Some(existing) = self.unverified.get_node_mut(&node.id) {
+        } else if let Some(existing) = unverified.get_node_mut(&node.id) {
             trace!(target: "yiilian_dht::routing_table", "Updating existing unverified node( id: {}, addr: {:?} ) last seen", node.id, node.address);
             existing.last_seen = Utc::now();
         } else {
             trace!(target: "yiilian_dht::routing_table", "Attempting to add unverified node( id: {}, addr: {:?} )", node.id, node.address);
-            self.unverified.add(node, None)?;
+            unverified.add(node, None)?;
         }
 
         Ok(())
@@ -112,25 +164,26 @@ impl RoutingTable {
     /// 将已验证节点加入 verified bucket，如果 verified bucket 已满，则加入 unverified bucket，如果还是满了，则抛弃
     fn add_or_update_verified(&mut self, mut node: Node) -> Result<(), Error> {
         let now = Utc::now();
+        let (verified, unverified) = self.buckets_mut(&node.address);
 
         // Already exists in unverified.
         // Remove it and try to add it to Verified.
         // If verified is full, add whatever overflows back to unverified (if it fits)
         // 尝试将已验证节点从 Unverified 移除并加入 Verified，如果已满则加入 Unverified
-        if let Some(mut item) = self.unverified.remove(&node.id) {
+        if let Some(mut item) = unverified.remove(&node.id) {
             trace!(target: "yiilian_dht::routing_table", "Attempting to move {:?} from unverified to verified", node);
             item.last_seen = now;
             item.last_verified = Some(now);
             let mut chump_list = Vec::with_capacity(1);
-            self.verified.add(item, Some(&mut chump_list))?;
+            verified.add(item, Some(&mut chump_list))?;
 
             for item in chump_list {
-                self.unverified.add(item, None)?;
+                unverified.add(item, None)?;
             }
         }
         // Already exists in verified.
         // Update it
-        else if let Some(node) = self.verified.get_node_mut(&node.id) {
+        else if let Some(node) = verified.get_node_mut(&node.id) {
             trace!(target: "yiilian_dht::routing_table", "Marking verified node (id: {:?}, address: {:?}) as verified again", node.id, node.address);
             node.last_verified = Some(node.last_seen);
             node.last_seen = now;
@@ -143,10 +196,10 @@ impl RoutingTable {
             node.last_verified = Some(now);
 
             let mut chump_list = Vec::with_capacity(1);
-            self.verified.add(node, Some(&mut chump_list))?;
+            verified.add(node, Some(&mut chump_list))?;
 
             for item in chump_list {
-                self.unverified.add(item, None)?; // unverified 如果满了，则 node 被抛弃
+                unverified.add(item, None)?; // unverified 如果满了，则 node 被抛弃
             }
         }
 
@@ -155,12 +208,22 @@ impl RoutingTable {
 
     /// 返回所有 dht verfied 的节点
     pub fn get_all_verified(&self) -> Vec<Node> {
-        self.verified.values().iter().copied().cloned().collect()
+        self.verified
+            .values()
+            .into_iter()
+            .chain(self.verified6.values())
+            .cloned()
+            .collect()
     }
 
     /// 返回所有 dht unverfied 的节点
     pub fn get_all_unverified(&self) -> Vec<Node> {
-        self.unverified.values().iter().copied().cloned().collect()
+        self.unverified
+            .values()
+            .into_iter()
+            .chain(self.unverified6.values())
+            .cloned()
+            .collect()
     }
 
     /// 移除节点
@@ -168,15 +231,14 @@ impl RoutingTable {
 
         let node_v = self.verified.remove(&node_id);
         let node_u = self.unverified.remove(&node_id);
+        let node_v6 = self.verified6.remove(node_id);
+        let node_u6 = self.unverified6.remove(node_id);
 
-        if node_v.is_some() {
-            return node_v;
-        } else if node_u.is_some() {
-            return node_u;
+        if let Some(node) = node_v.or(node_u).or(node_v6).or(node_u6) {
+            return Some(node);
         }
 
-        dht_ctx_state(self.ctx_index).write().expect_error("Get writable state failed")
-            .is_join_kad = self.verified.count() > 0;
+        self.update_join_kad();
 
         None
     }
@@ -210,8 +272,20 @@ impl RoutingTable {
             .collect()
     }
 
+    /// 获取距离 id 更近的 IPv6 节点，结果中要排除掉 exclude 节点。
+    pub fn get_nearest_nodes6(&self, id: &Id, exclude: Option<&Id>) -> Vec<Node> {
+        self.verified6
+            .get_nearest_nodes(id, exclude)
+            .iter()
+            .map(|node| (*node).clone())
+            .collect()
+    }
+
     pub fn count(&self) -> (usize, usize) {
-        (self.unverified.count(), self.verified.count())
+        (
+            self.unverified.count() + self.unverified6.count(),
+            self.verified_count(),
+        )
     }
 
     /// grace_period： 已验证节点的再次校验时间间隔，超过该时间间隔没有再次校验的节点将被删除
@@ -225,35 +299,49 @@ impl RoutingTable {
         let time = now - grace_period;
         let unverified_time = now - unverified_grace_period;
 
-        // 在 verified 中保留 当前时间 - grace_period = 截至时点，之后已被验证的节点
-        self.verified.retain(|node| {
-            if let Some(last_verified) = node.last_verified {
-                return last_verified >= time;
-            }
-            // trace!(target: "yiilian_dht::RoutingTable", "Verified {:?} hasn't verified recently. Removing.", node);
-            false
-        });
-
-        // 在 unverified 中保留 当前时间 - grace_period = 截至时点，之后已被验证或被 seen (我们没请求过，但收到过该节点的消息) 的节点
-        self.unverified.retain(|node| {
-            if let Some(last_verified) = node.last_verified {
-                if last_verified >= time {
+        for (verified, unverified) in [
+            (&mut self.verified, &mut self.unverified),
+            (&mut self.verified6, &mut self.unverified6),
+        ] {
+            // 在 verified 中保留 当前时间 - grace_period = 截至时点，之后已被验证的节点
+            verified.retain(|node| {
+                if let Some(last_verified) = node.last_verified {
+                    return last_verified >= time;
+                }
+                // trace!(target: "yiilian_dht::RoutingTable", "Verified {:?} hasn't verified recently. Removing.", node);
+                false
+            });
+
+            // 在 unverified 中保留 当前时间 - grace_period = 截至时点，之后已被验证或被 seen (我们没请求过，但收到过该节点的消息) 的节点
+            unverified.retain(|node| {
+                if let Some(last_verified) = node.last_verified {
+                    if last_verified >= time {
+                        return true;
+                    }
+                }
+                if node.last_seen >= time && node.last_seen >= unverified_time {
                     return true;
                 }
-            }
-            if node.last_seen >= time && node.last_seen >= unverified_time {
-                return true;
-            }
-            // trace!(target: "yiilian_dht::RoutingTable", "Unverified {:?} is dead. Removing", node);
-            false
-        });
+                // trace!(target: "yiilian_dht::RoutingTable", "Unverified {:?} is dead. Removing", node);
+                false
+            });
+        }
 
-        dht_ctx_state(self.ctx_index).write().expect_error("Get writable state failed")
-            .is_join_kad = self.verified.count() > 0;
+        self.update_join_kad();
+    }
+
+    /// 有已验证的节点即认为已加入 kad 网络，Context 已释放时不更新
+    fn update_join_kad(&self) {
+        if let Ok(ctx) = self.ctx.ctx() {
+            ctx.state().write().expect_error("Get writable state failed")
+                .is_join_kad = self.verified_count() > 0;
+        }
     }
 
     pub fn set_id(&mut self, new_id: Id) {
         self.verified.set_id(new_id);
         self.unverified.set_id(new_id);
+        self.verified6.set_id(new_id);
+        self.unverified6.set_id(new_id);
     }
 }
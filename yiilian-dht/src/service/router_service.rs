@@ -1,4 +1,4 @@
-use std::{net::SocketAddr, time::Duration};
+use std::time::Duration;
 
 use yiilian_core::{
     common::{error::Error, expect_log::ExpectLog},
@@ -7,19 +7,24 @@ use yiilian_core::{
 };
 
 use crate::{
-    common::{dht_ctx_routing_tbl, dht_ctx_settings, dht_ctx_trans_mgr},
+    common::DhtHandle,
     data::body::{BodyKind, KrpcBody, Query},
     routing_table::Node,
 };
 
+/// 处理 DHT 的 query 和 reply，Dht 被 drop 后的请求返回错误
 #[derive(Clone)]
 pub struct RouterService {
-    local_addr: SocketAddr,
+    handle: DhtHandle,
 }
 
 impl RouterService {
-    pub fn new(local_addr: SocketAddr) -> Self {
-        RouterService { local_addr }
+    pub fn new(handle: DhtHandle) -> Self {
+        RouterService { handle }
+    }
+
+    pub fn handle(&self) -> &DhtHandle {
+        &self.handle
     }
 }
 
@@ -29,7 +34,7 @@ impl Service<Request<KrpcBody>> for RouterService {
     type Error = Error;
 
     async fn call(&mut self, req: Request<KrpcBody>) -> Result<Self::Response, Self::Error> {
-        let ctx_index = self.local_addr.port();
+        let ctx = self.handle.ctx()?;
         let req_body = req.body.get_kind();
 
         if req.remote_addr.port() == 0 {
@@ -41,7 +46,7 @@ impl Service<Request<KrpcBody>> for RouterService {
 
         let res = match req_body {
             BodyKind::Query(query) => {
-                let local_read_only = dht_ctx_settings(ctx_index).read_only;
+                let local_read_only = ctx.settings().read_only;
 
                 // 本地是只读节点，不处理对方 query
                 if !local_read_only {
@@ -50,7 +55,7 @@ impl Service<Request<KrpcBody>> for RouterService {
                     let is_id_valid = {
                         sender_id.is_valid_for_ip(
                             &req.remote_addr.ip(),
-                            &dht_ctx_routing_tbl(ctx_index)
+                            &ctx.routing_table()
                                 .lock()
                                 .expect_error("Lock context routing_table failed")
                                 .white_list,
@@ -61,7 +66,7 @@ impl Service<Request<KrpcBody>> for RouterService {
 
                     // 有效，且对方不是 readonly （允许加入到我方的路由表的未验证 bucket 中）
                     if is_id_valid && !remote_read_only {
-                        dht_ctx_routing_tbl(ctx_index)
+                        ctx.routing_table()
                             .lock()
                             .expect_error("Lock context routing_table failed")
                             .add_or_update(Node::new(sender_id, req.remote_addr.clone()), false)?;
@@ -69,25 +74,43 @@ impl Service<Request<KrpcBody>> for RouterService {
 
                     let (reply, _) = match query {
                         Query::Ping(query) => {
-                            dht_ctx_trans_mgr(ctx_index)
+                            ctx.transaction_manager()
                                 .handle_ping(query, &req.remote_addr)
                                 .await?
                         }
                         Query::FindNode(query) => {
-                            dht_ctx_trans_mgr(ctx_index)
+                            ctx.transaction_manager()
                                 .handle_find_node(query, &req.remote_addr)
                                 .await?
                         }
                         Query::GetPeers(query) => {
-                            dht_ctx_trans_mgr(ctx_index)
+                            ctx.transaction_manager()
                                 .handle_get_peers(query, &req.remote_addr)
                                 .await?
                         }
                         Query::AnnouncePeer(query) => {
-                            dht_ctx_trans_mgr(ctx_index)
+                            ctx.transaction_manager()
                                 .handle_announce_peer(query, &req.remote_addr)
                                 .await?
                         }
+                        Query::Get(query) => {
+                            ctx.transaction_manager()
+                                .handle_get(query, &req.remote_addr)
+                                .await?
+                        }
+                        Query::Put(query) => {
+                            // put 校验失败时回复的是错误消息，而不是 reply
+                            let (res_body, _) = ctx.transaction_manager()
+                                .handle_put(query, &req.remote_addr)
+                                .await?;
+
+                            return Ok(Response::new(KrpcBody::new(res_body), req.remote_addr, req.local_addr));
+                        }
+                        Query::SampleInfoHashes(query) => {
+                            ctx.transaction_manager()
+                                .handle_sample_infohashes(query, &req.remote_addr)
+                                .await?
+                        }
                     };
 
                     let res_body = KrpcBody::new(BodyKind::Reply(reply));
@@ -103,7 +126,7 @@ impl Service<Request<KrpcBody>> for RouterService {
                 }
             }
             BodyKind::Reply(reply) => {
-                dht_ctx_trans_mgr(ctx_index)
+                ctx.transaction_manager()
                     .handle_reply(reply, &req.remote_addr)
                     .await?;
                 Response::new(
@@ -114,8 +137,8 @@ impl Service<Request<KrpcBody>> for RouterService {
             }
             BodyKind::RError(_) => {
                 let reply_error_block_duration_sec =
-                    dht_ctx_settings(ctx_index).reply_error_block_duration_sec;
-                dht_ctx_routing_tbl(ctx_index)
+                    ctx.settings().reply_error_block_duration_sec;
+                ctx.routing_table()
                     .lock()
                     .expect_error("Lock context routing_table failed")
                     .add_block_list(
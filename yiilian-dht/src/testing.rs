@@ -0,0 +1,739 @@
+//! 在回环地址上启动一组 Dht 节点组成的模拟网络，可以注入延迟、丢包和恶意节点，
+//! 用于测试 find_node / get_peers / announce_peer 的结果以及路由表的状态。
+//! 节点绑定系统分配的端口，检查结果时用 wait_until 等待网络收敛，而不是固定的等待时间
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    fs,
+    future::Future,
+    hash::{Hash, Hasher},
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{future::join_all, stream::FuturesUnordered, StreamExt};
+use rand::{rngs::StdRng, SeedableRng};
+use tokio::time::{sleep, timeout};
+use yiilian_core::{
+    common::{
+        error::Error,
+        expect_log::ExpectLog,
+        shutdown::{create_shutdown, ShutdownSender},
+    },
+    data::{Request, Response},
+    service::{Layer, Service},
+};
+
+use crate::{
+    common::{DhtHandle, Id, Settings, SettingsBuilder, ID_SIZE},
+    data::{
+        body::{BodyKind, KrpcBody, Query, Reply},
+        find_node_reply::FindNodeReply,
+        get_peers_reply::GetPeersReply,
+    },
+    dht::{Dht, DhtBuilder},
+    routing_table::Node,
+    service::RouterService,
+};
+
+/// 恶意节点回复的伪造地址个数，这些地址绑定的 socket 从不回复
+pub const FAKE_ADDR_COUNT: usize = 8;
+
+/// wait_until 默认的最长等待时间
+pub const SIM_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// wait_until 检查条件的间隔
+const SIM_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// 恶意节点的行为
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Malice {
+    /// 不回复任何 query
+    Silent,
+    /// find_node / get_peers 回复离 target 很近、但不存在的节点
+    FakeNodes,
+    /// get_peers 回复伪造的 peers 和无效的 token
+    FakePeers,
+}
+
+/// 网络状况，延迟和丢包作用在节点收到的每个 query 和 reply 上
+#[derive(Debug, Clone)]
+pub struct SimConditions {
+    /// 丢包和延迟抖动只由 seed 和数据包决定，与任务调度无关
+    pub seed: u64,
+    pub latency: Duration,
+    /// 在 latency 上增加 0 到 jitter 的延迟
+    pub jitter: Duration,
+    /// 丢包率，0.0 ~ 1.0
+    pub loss: f64,
+    /// 伪造的地址，由 SimNetwork 持有的 socket 占用，不会被其它测试绑定
+    fake_addrs: Arc<Vec<SocketAddr>>,
+}
+
+impl SimConditions {
+    /// 伪造的地址，发往这些地址的包不会有回复
+    pub fn fake_addrs(&self) -> Vec<SocketAddr> {
+        self.fake_addrs.to_vec()
+    }
+}
+
+/// 在 RouterService 外注入网络状况，并让恶意节点回复伪造的内容
+#[derive(Clone)]
+pub struct SimService {
+    inner: RouterService,
+    conditions: SimConditions,
+    malice: Option<Malice>,
+}
+
+impl SimService {
+    pub fn new(inner: RouterService, conditions: SimConditions, malice: Option<Malice>) -> Self {
+        SimService {
+            inner,
+            conditions,
+            malice,
+        }
+    }
+
+    /// 恶意节点不处理该 query 时返回 None
+    fn malicious_reply(
+        &self,
+        malice: Malice,
+        query: &Query,
+        req: &Request<KrpcBody>,
+    ) -> Result<Option<Response<KrpcBody>>, Error> {
+        let local_id = self
+            .inner
+            .handle()
+            .ctx()?
+            .state()
+            .read()
+            .expect_error("state.read() failed")
+            .get_local_id();
+        let fake_token = Bytes::from_static(b"fake");
+
+        let reply = match (malice, query) {
+            (Malice::Silent, _) => return Ok(Some(empty_response(req))),
+            (Malice::FakeNodes, Query::FindNode(query)) => Reply::FindNode(FindNodeReply::new(
+                local_id,
+                self.fake_nodes(&query.target),
+                query.t.clone(),
+                None,
+                Some(req.remote_addr),
+                None,
+            )),
+            (Malice::FakeNodes, Query::GetPeers(query)) => Reply::GetPeers(GetPeersReply::new(
+                local_id,
+                fake_token,
+                self.fake_nodes(&query.info_hash),
+                vec![],
+                query.t.clone(),
+                None,
+                Some(req.remote_addr),
+                None,
+            )),
+            (Malice::FakePeers, Query::GetPeers(query)) => Reply::GetPeers(GetPeersReply::new(
+                local_id,
+                fake_token,
+                vec![],
+                self.conditions.fake_addrs(),
+                query.t.clone(),
+                None,
+                Some(req.remote_addr),
+                None,
+            )),
+            _ => return Ok(None),
+        };
+
+        let res = Response::new(
+            KrpcBody::new(BodyKind::Reply(reply)),
+            req.remote_addr,
+            req.local_addr,
+        );
+
+        Ok(Some(res))
+    }
+
+    /// 只有最后一个字节和 target 不同的节点，比网络中任何真实节点都更近
+    fn fake_nodes(&self, target: &Id) -> Vec<Node> {
+        self.conditions
+            .fake_addrs()
+            .into_iter()
+            .enumerate()
+            .map(|(index, address)| {
+                let mut bytes = target.to_vec();
+                bytes[ID_SIZE - 1] ^= index as u8 + 1;
+                let id = Id::from_bytes(&bytes).expect_error("Id::from_bytes() failed");
+
+                Node::new(id, address)
+            })
+            .collect()
+    }
+}
+
+impl Service<Request<KrpcBody>> for SimService {
+    type Response = Response<KrpcBody>;
+
+    type Error = Error;
+
+    async fn call(&mut self, req: Request<KrpcBody>) -> Result<Self::Response, Self::Error> {
+        let tid = match req.body.get_kind() {
+            BodyKind::Query(query) => Some(query.get_tid()),
+            BodyKind::Reply(reply) => Some(reply.get_tid()),
+            _ => None,
+        };
+
+        if let Some(tid) = tid {
+            let fate = packet_fate(
+                self.conditions.seed,
+                &req.local_addr,
+                &req.remote_addr,
+                &tid.get_bytes(),
+            );
+
+            if fate_ratio(fate) < self.conditions.loss {
+                return Ok(empty_response(&req));
+            }
+
+            let delay = self.conditions.latency + self.conditions.jitter.mul_f64(fate_ratio(fate >> 32));
+            if !delay.is_zero() {
+                sleep(delay).await;
+            }
+        }
+
+        if let (Some(malice), BodyKind::Query(query)) = (self.malice, req.body.get_kind()) {
+            if let Some(res) = self.malicious_reply(malice, query, &req)? {
+                return Ok(res);
+            }
+        }
+
+        self.inner.call(req).await
+    }
+}
+
+pub struct SimLayer {
+    conditions: SimConditions,
+    malice: Option<Malice>,
+}
+
+impl SimLayer {
+    pub fn new(conditions: SimConditions, malice: Option<Malice>) -> Self {
+        SimLayer { conditions, malice }
+    }
+}
+
+impl Layer<RouterService> for SimLayer {
+    type Service = SimService;
+
+    fn layer(&self, inner: RouterService) -> Self::Service {
+        SimService::new(inner, self.conditions.clone(), self.malice)
+    }
+}
+
+/// 模拟网络中的一个节点
+pub struct SimNode {
+    dht: Dht<SimService>,
+    id: Id,
+    malice: Option<Malice>,
+}
+
+impl SimNode {
+    pub fn dht(&self) -> &Dht<SimService> {
+        &self.dht
+    }
+
+    pub fn handle(&self) -> DhtHandle {
+        self.dht.handle()
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.dht.local_addr
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    pub fn malice(&self) -> Option<Malice> {
+        self.malice
+    }
+
+    pub fn is_honest(&self) -> bool {
+        self.malice.is_none()
+    }
+}
+
+/// 诚实节点路由表的统计
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutingHealth {
+    /// 统计的诚实节点数
+    pub nodes: usize,
+    /// 已验证节点数的最小值
+    pub min_verified: usize,
+    /// 已验证节点数的平均值
+    pub avg_verified: f64,
+    /// 路由表中没有已验证节点的诚实节点数
+    pub isolated: usize,
+    /// 路由表中指向恶意节点或不存在的节点的条目数
+    pub bad_entries: usize,
+}
+
+pub struct SimNetworkBuilder {
+    node_count: usize,
+    seed: u64,
+    latency: Duration,
+    jitter: Duration,
+    loss: f64,
+    malicious: Vec<Malice>,
+    settings: Settings,
+}
+
+impl SimNetworkBuilder {
+    /// 每个节点绑定 127.0.0.1 上系统分配的端口
+    pub fn new(node_count: usize) -> Self {
+        SimNetworkBuilder {
+            node_count,
+            seed: 0,
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            malicious: vec![],
+            settings: sim_settings(),
+        }
+    }
+
+    /// 决定节点 id、丢包和延迟抖动
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn latency(mut self, latency: Duration, jitter: Duration) -> Self {
+        self.latency = latency;
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn loss(mut self, loss: f64) -> Self {
+        self.loss = loss;
+        self
+    }
+
+    /// 增加 count 个恶意节点，恶意节点排在网络的最后
+    pub fn malicious(mut self, malice: Malice, count: usize) -> Self {
+        self.malicious.extend(std::iter::repeat_n(malice, count));
+        self
+    }
+
+    /// 默认使用 sim_settings()
+    pub fn settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn build(self) -> Result<SimNetwork, Error> {
+        if self.node_count == 0 || self.malicious.len() >= self.node_count {
+            Err(Error::new_general("Simulated network needs at least one honest node"))?
+        }
+
+        let fake_sockets = (0..FAKE_ADDR_COUNT)
+            .map(|_| UdpSocket::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::new_bind(Some(Box::new(e))))?;
+        let fake_addrs = fake_sockets
+            .iter()
+            .map(|socket| socket.local_addr())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::new_bind(Some(Box::new(e))))?;
+
+        let (shutdown_tx, shutdown_rx) = create_shutdown();
+        // 伪造地址的端口在网络存活期间被占用，可以用来区分不同的网络
+        let home_dir = std::env::temp_dir().join(format!("yiilian_sim_{}", fake_addrs[0].port()));
+        let conditions = SimConditions {
+            seed: self.seed,
+            latency: self.latency,
+            jitter: self.jitter,
+            loss: self.loss,
+            fake_addrs: Arc::new(fake_addrs),
+        };
+
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let honest_count = self.node_count - self.malicious.len();
+        let mut nodes = Vec::with_capacity(self.node_count);
+
+        for index in 0..self.node_count {
+            let local_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0);
+            let id = Id::from_random(&mut rng);
+            let malice = index
+                .checked_sub(honest_count)
+                .map(|malicious_index| self.malicious[malicious_index]);
+
+            let dht = DhtBuilder::new(local_addr, shutdown_rx.clone(), None, home_dir.clone())
+                .local_id(Some(id))
+                .settings(Some(self.settings.clone()))
+                .layer(SimLayer::new(conditions.clone(), malice))
+                .build()?;
+
+            nodes.push(SimNode { dht, id, malice });
+        }
+
+        Ok(SimNetwork {
+            nodes,
+            conditions,
+            home_dir,
+            _fake_sockets: fake_sockets,
+            _shutdown_tx: shutdown_tx,
+        })
+    }
+}
+
+/// 模拟网络，drop 时释放所有节点并删除节点文件
+pub struct SimNetwork {
+    nodes: Vec<SimNode>,
+    conditions: SimConditions,
+    home_dir: PathBuf,
+    /// 占用伪造地址，收到的包从不读取
+    _fake_sockets: Vec<UdpSocket>,
+    _shutdown_tx: ShutdownSender,
+}
+
+impl SimNetwork {
+    pub fn nodes(&self) -> &[SimNode] {
+        &self.nodes
+    }
+
+    pub fn node(&self, index: usize) -> &SimNode {
+        &self.nodes[index]
+    }
+
+    pub fn honest_nodes(&self) -> impl Iterator<Item = &SimNode> {
+        self.nodes.iter().filter(|node| node.is_honest())
+    }
+
+    pub fn conditions(&self) -> &SimConditions {
+        &self.conditions
+    }
+
+    /// 运行所有节点，节点都退出时返回
+    pub async fn run_loop(&self) {
+        let mut runs: FuturesUnordered<_> = self.nodes.iter().map(|node| node.dht.run_loop()).collect();
+
+        while runs.next().await.is_some() {}
+    }
+
+    /// 在网络运行期间执行 fut
+    pub async fn run<F: Future>(&self, fut: F) -> F::Output {
+        tokio::pin!(fut);
+
+        tokio::select! {
+            output = &mut fut => output,
+            _ = self.run_loop() => fut.await,
+        }
+    }
+
+    /// 第一个节点作为入口，各节点和入口节点及前两个节点互相 ping 后，查找自己的 id 来填充路由表，
+    /// 需要在 run 中调用。只认识一个节点时，丢一个包就会使它被移出路由表
+    pub async fn bootstrap(&self) -> Result<(), Error> {
+        let pings = self.nodes.iter().enumerate().skip(1).map(|(index, node)| async move {
+            let mut known: Vec<usize> = vec![0, index.saturating_sub(1), index.saturating_sub(2)];
+            known.dedup();
+
+            for known in known {
+                let known = &self.nodes[known];
+                self.ping_node(node, known).await?;
+                // 入口节点只会把已验证的节点告诉其它节点
+                self.ping_node(known, node).await?;
+            }
+
+            Ok::<_, Error>(())
+        });
+        for rst in join_all(pings).await {
+            rst?;
+        }
+
+        let lookups = self
+            .nodes
+            .iter()
+            .map(|node| async move { node.handle().find_node(node.id).await });
+        for rst in join_all(lookups).await {
+            rst?;
+        }
+
+        // 丢包时查询超时的节点会被移出路由表，让没有已验证节点的诚实节点重新 ping 入口节点
+        let entry = &self.nodes[0];
+        self.wait_until(|| async {
+            let mut isolated = vec![];
+            for node in self.honest_nodes().skip(1) {
+                match node.handle().ctx() {
+                    Ok(ctx) => {
+                        let (_, verified) = ctx.routing_table().lock().expect_error("routing_table.lock() failed").count();
+                        if verified == 0 {
+                            isolated.push(node);
+                        }
+                    }
+                    Err(_) => return false,
+                }
+            }
+
+            for node in &isolated {
+                node.handle().ping(entry.addr(), None).await.ok();
+                entry.handle().ping(node.addr(), None).await.ok();
+            }
+
+            isolated.is_empty()
+        })
+        .await
+    }
+
+    /// 每隔 SIM_POLL_INTERVAL 检查一次 cond，直到返回 true。超过 SIM_WAIT_TIMEOUT 返回超时错误，
+    /// 需要在 run 中调用
+    pub async fn wait_until<F, Fut>(&self, mut cond: F) -> Result<(), Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = bool>,
+    {
+        let wait = async {
+            while !cond().await {
+                sleep(SIM_POLL_INTERVAL).await;
+            }
+        };
+
+        timeout(SIM_WAIT_TIMEOUT, wait)
+            .await
+            .map_err(|_| Error::new_timeout("Simulated network condition is not met"))
+    }
+
+    /// 诚实节点必须回复，丢包后等对方从黑名单中移除再重试。不回复的恶意节点无法验证，忽略错误
+    async fn ping_node(&self, node: &SimNode, target: &SimNode) -> Result<(), Error> {
+        let handle = node.handle();
+        if !target.is_honest() {
+            handle.ping(target.addr(), None).await.ok();
+            return Ok(());
+        }
+
+        let ctx = handle.ctx()?;
+        self.wait_until(|| async {
+            let is_blocked = ctx
+                .routing_table()
+                .lock()
+                .expect_error("routing_table.lock() failed")
+                .is_blocked(&target.addr());
+
+            !is_blocked && handle.ping(target.addr(), None).await.is_ok()
+        })
+        .await
+    }
+
+    /// 诚实节点中离 target 最近的 count 个节点，用于检查查找的结果
+    pub fn closest_honest(&self, target: &Id, count: usize) -> Vec<Node> {
+        let mut nodes: Vec<Node> = self
+            .honest_nodes()
+            .map(|node| Node::new(node.id, node.addr()))
+            .collect();
+        nodes.sort_by_key(|node| node.id.xor(target).to_vec());
+        nodes.truncate(count);
+
+        nodes
+    }
+
+    pub fn routing_health(&self) -> Result<RoutingHealth, Error> {
+        let honest_addrs: HashSet<SocketAddr> = self.honest_nodes().map(|node| node.addr()).collect();
+        let mut verified_counts = vec![];
+        let mut bad_entries = 0;
+
+        for node in self.honest_nodes() {
+            let ctx = node.handle().ctx()?;
+            let routing_table = ctx
+                .routing_table()
+                .lock()
+                .expect_error("routing_table.lock() failed");
+
+            let verified = routing_table.get_all_verified();
+            bad_entries += verified
+                .iter()
+                .chain(routing_table.get_all_unverified().iter())
+                .filter(|node| !honest_addrs.contains(&node.address))
+                .count();
+            verified_counts.push(verified.len());
+        }
+
+        Ok(RoutingHealth {
+            nodes: verified_counts.len(),
+            min_verified: verified_counts.iter().copied().min().unwrap_or(0),
+            avg_verified: verified_counts.iter().sum::<usize>() as f64 / verified_counts.len().max(1) as f64,
+            isolated: verified_counts.iter().filter(|count| **count == 0).count(),
+            bad_entries,
+        })
+    }
+}
+
+impl Drop for SimNetwork {
+    fn drop(&mut self) {
+        // 节点 drop 时会写入节点文件，之后才能删除目录
+        self.nodes.clear();
+        fs::remove_dir_all(&self.home_dir).ok();
+    }
+}
+
+/// 没有路由器，查找的间隔和超时都很短的设置
+pub fn sim_settings() -> Settings {
+    SettingsBuilder::new()
+        .routers(&Some(vec![]))
+        .send_next_query_interval_sec(0)
+        .send_query_timeout_sec(1)
+        .lookup_query_timeout_sec(1)
+        .timeout_block_duration_sec(1)
+        .reply_error_block_duration_sec(1)
+        .build()
+}
+
+fn packet_fate(seed: u64, local_addr: &SocketAddr, remote_addr: &SocketAddr, tid: &Bytes) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (seed, local_addr, remote_addr, tid).hash(&mut hasher);
+
+    hasher.finish()
+}
+
+fn fate_ratio(fate: u64) -> f64 {
+    (fate % 10_000) as f64 / 10_000.0
+}
+
+fn empty_response(req: &Request<KrpcBody>) -> Response<KrpcBody> {
+    Response::new(
+        KrpcBody::new(BodyKind::Empty),
+        req.remote_addr,
+        req.local_addr,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sim_network() {
+        let network = SimNetworkBuilder::new(48)
+            .seed(7)
+            .latency(Duration::from_millis(2), Duration::from_millis(3))
+            .loss(0.01)
+            .build()
+            .unwrap();
+
+        network
+            .run(async {
+                network.bootstrap().await.unwrap();
+
+                let health = network.routing_health().unwrap();
+                assert_eq!(48, health.nodes);
+                assert_eq!(0, health.isolated);
+                assert_eq!(0, health.bad_entries);
+
+                // 丢包时单次查找可能错过目标，等待查找结果收敛
+                let target = network.node(40).id();
+                let expected = network.closest_honest(&target, 1);
+                network
+                    .wait_until(|| async {
+                        let nodes = network.node(3).handle().find_node(target).await.unwrap();
+                        nodes.first() == expected.first()
+                    })
+                    .await
+                    .unwrap();
+
+                let info_hash = Id::from_random(&mut StdRng::seed_from_u64(1));
+                let announcer = network.node(5);
+                network
+                    .wait_until(|| async {
+                        let accepted = announcer.handle().announce_peer(announcer.addr(), info_hash).await;
+                        accepted.map(|nodes| !nodes.is_empty()).unwrap_or(false)
+                    })
+                    .await
+                    .unwrap();
+
+                network
+                    .wait_until(|| async {
+                        let rst = network.node(20).handle().get_peers(info_hash).await.unwrap();
+                        rst.peers().contains(&announcer.addr())
+                    })
+                    .await
+                    .unwrap();
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_sim_malicious() {
+        let network = SimNetworkBuilder::new(24)
+            .seed(11)
+            .malicious(Malice::Silent, 2)
+            .malicious(Malice::FakeNodes, 2)
+            .malicious(Malice::FakePeers, 2)
+            .build()
+            .unwrap();
+        assert_eq!(18, network.honest_nodes().count());
+        assert_eq!(Some(Malice::FakePeers), network.node(23).malice());
+
+        network
+            .run(async {
+                network.bootstrap().await.unwrap();
+                assert_eq!(0, network.routing_health().unwrap().isolated);
+
+                // 不回复的节点和伪造的节点超时后被跳过
+                let target = network.node(10).id();
+                let expected = network.closest_honest(&target, 1);
+                let fake_addrs = network.conditions().fake_addrs();
+                network
+                    .wait_until(|| async {
+                        let nodes = network.node(2).handle().find_node(target).await.unwrap();
+                        assert!(nodes.iter().all(|node| !fake_addrs.contains(&node.address)));
+                        nodes.first() == expected.first()
+                    })
+                    .await
+                    .unwrap();
+
+                let info_hash = Id::from_random(&mut StdRng::seed_from_u64(2));
+                let announcer = network.node(5);
+                announcer.handle().announce_peer(announcer.addr(), info_hash).await.unwrap();
+
+                // 伪造的 peers 混在结果中，但仍能找到真实的 peer
+                network
+                    .wait_until(|| async {
+                        let rst = network.node(12).handle().get_peers(info_hash).await.unwrap();
+                        rst.peers().contains(&announcer.addr())
+                    })
+                    .await
+                    .unwrap();
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_sim_fake_nodes() {
+        let network = SimNetworkBuilder::new(4)
+            .malicious(Malice::FakeNodes, 1)
+            .build()
+            .unwrap();
+
+        network
+            .run(async {
+                let node = network.node(0).handle();
+                for index in 1..4 {
+                    node.ping(network.node(index).addr(), None).await.unwrap();
+                }
+                // 恶意节点本身
+                assert_eq!(1, network.routing_health().unwrap().bad_entries);
+
+                // 伪造的节点查询超时后被加入黑名单并移出路由表
+                let target = network.node(1).id();
+                let nodes = node.find_node(target).await.unwrap();
+                assert_eq!(target, nodes[0].id);
+                assert_eq!(1, network.routing_health().unwrap().bad_entries);
+
+                let ctx = node.ctx().unwrap();
+                let routing_table = ctx.routing_table().lock().unwrap();
+                assert!(network.conditions().fake_addrs().iter().any(|addr| routing_table.is_blocked(addr)));
+            })
+            .await;
+    }
+}
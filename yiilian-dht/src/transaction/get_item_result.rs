@@ -0,0 +1,53 @@
+use yiilian_core::common::expect_log::ExpectLog;
+
+use crate::{common::Id, item::Item};
+
+use super::GetPeersResponder;
+
+/// Represents the results of a BEP44 get operation
+#[derive(Debug)]
+pub struct GetItemResult {
+    target: Id,
+    item: Option<Item>,
+    responders: Vec<GetPeersResponder>,
+}
+
+impl GetItemResult {
+    pub fn new(
+        target: Id,
+        item: Option<Item>,
+        mut responders: Vec<GetPeersResponder>,
+    ) -> GetItemResult {
+        responders.sort_unstable_by(|a, b| {
+            let a_dist = a.node().id.xor(&target);
+            let b_dist = b.node().id.xor(&target);
+
+            a_dist
+                .partial_cmp(&b_dist)
+                .expect_error("GetItemResult distance compare failed")
+        });
+        GetItemResult {
+            target,
+            item,
+            responders,
+        }
+    }
+
+    /// The target of the item that get was attempting to get
+    pub fn target(&self) -> Id {
+        self.target
+    }
+
+    /// The verified item with the highest seq that was found for the target
+    pub fn item(&self) -> Option<&Item> {
+        self.item.as_ref()
+    }
+
+    /// Vector of information about the DHT nodes that responded to get
+    ///
+    /// This is sorted by distance of the Node to the target, from nearest to farthest.
+    /// The tokens are needed by the following put requests.
+    pub fn responders(&self) -> &Vec<GetPeersResponder> {
+        &self.responders
+    }
+}
@@ -0,0 +1,488 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{future::BoxFuture, stream::FuturesUnordered, Stream, StreamExt};
+use tokio::time::{timeout_at, Instant};
+use yiilian_core::common::{error::Error, expect_log::ExpectLog};
+
+use crate::{
+    common::{Context, DhtHandle, Id},
+    data::{
+        body::{Query, Reply},
+        find_node::FindNode,
+        get_peers::GetPeers,
+        util::Want,
+    },
+    routing_table::Node,
+};
+
+use super::{GetPeersResponder, GetPeersResult, TransactionId};
+
+/// 一次查找的总时长上限
+pub const LOOKUP_TIMEOUT_SECS: u64 = 3 * 60;
+
+/// 查找时发送的 query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupKind {
+    FindNode,
+    GetPeers,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CandidateState {
+    Waiting,
+    InFlight,
+    Replied,
+    Failed,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    node: Node,
+    distance: Id,
+    state: CandidateState,
+    /// get_peers 回复中的 token
+    token: Option<Bytes>,
+}
+
+type QueryFuture = BoxFuture<'static, (Node, Result<Reply, Error>)>;
+
+/// Kademlia 迭代查找。
+///
+/// 候选节点按到 target 的 XOR 距离排序，同时最多有 alpha 个 query 在途，
+/// 离 target 最近的 k 个（未失败的）候选节点都回复后结束。
+/// 查找由调用方驱动，get_peers 查找中的 peers 在收到时即可取出
+pub struct Lookup {
+    handle: DhtHandle,
+    kind: LookupKind,
+    target: Id,
+    local_id: Id,
+    alpha: usize,
+    k: usize,
+    query_timeout: Duration,
+    read_only: bool,
+    want: Option<Want>,
+    deadline: Instant,
+
+    /// 按距离由近到远排序
+    shortlist: Vec<Candidate>,
+    in_flight: FuturesUnordered<QueryFuture>,
+    peers: Vec<SocketAddr>,
+    seen_peers: HashSet<SocketAddr>,
+    /// 还没有被 next_peer 取出的 peers
+    new_peers: VecDeque<SocketAddr>,
+    finished: bool,
+}
+
+impl Lookup {
+    /// 用路由表中已验证的节点作为初始候选节点
+    pub fn new(handle: DhtHandle, kind: LookupKind, target: Id) -> Result<Self, Error> {
+        let ctx = handle.ctx()?;
+        let settings = ctx.settings();
+        let local_id = ctx
+            .state()
+            .read()
+            .expect_error("state.read() failed")
+            .get_local_id();
+
+        let mut lookup = Lookup {
+            kind,
+            target,
+            local_id,
+            alpha: settings.lookup_alpha.max(1),
+            k: settings.bucket_size,
+            query_timeout: Duration::from_secs(settings.lookup_query_timeout_sec),
+            read_only: settings.read_only,
+            want: ctx.transaction_manager().lookup_want(),
+            deadline: Instant::now() + Duration::from_secs(LOOKUP_TIMEOUT_SECS),
+            shortlist: vec![],
+            in_flight: FuturesUnordered::new(),
+            peers: vec![],
+            seen_peers: HashSet::new(),
+            new_peers: VecDeque::new(),
+            finished: false,
+            handle,
+        };
+
+        let verified = ctx
+            .routing_table()
+            .lock()
+            .expect_error("routing_table.lock() failed")
+            .get_all_verified();
+        for node in verified {
+            lookup.add_candidate(&ctx, node);
+        }
+
+        Ok(lookup)
+    }
+
+    pub fn alpha(mut self, alpha: usize) -> Self {
+        self.alpha = alpha.max(1);
+        self
+    }
+
+    pub fn target(&self) -> Id {
+        self.target
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// 推进查找直到收到一个新的 peer，查找结束后返回 None
+    pub async fn next_peer(&mut self) -> Option<SocketAddr> {
+        loop {
+            if let Some(peer) = self.new_peers.pop_front() {
+                return Some(peer);
+            }
+
+            if !self.step().await {
+                return None;
+            }
+        }
+    }
+
+    /// 查找过程中收到的 peers，不重复
+    pub fn peers(&mut self) -> impl Stream<Item = SocketAddr> + '_ {
+        futures::stream::unfold(self, |lookup| async move {
+            let peer = lookup.next_peer().await?;
+            Some((peer, lookup))
+        })
+    }
+
+    /// 一直推进到查找结束
+    pub async fn run(&mut self) {
+        while self.step().await {}
+        self.new_peers.clear();
+    }
+
+    /// 已回复的节点中离 target 最近的 k 个
+    pub fn closest(&self) -> Vec<Node> {
+        self.replied().take(self.k).map(|candidate| candidate.node.clone()).collect()
+    }
+
+    pub fn into_get_peers_result(self) -> GetPeersResult {
+        let responders = self
+            .replied()
+            .filter_map(|candidate| {
+                let token = candidate.token.clone()?;
+                Some(GetPeersResponder::new(candidate.node.clone(), token))
+            })
+            .collect();
+
+        GetPeersResult::new(self.target, self.peers, responders)
+    }
+
+    fn replied(&self) -> impl Iterator<Item = &Candidate> {
+        self.shortlist
+            .iter()
+            .filter(|candidate| candidate.state == CandidateState::Replied)
+    }
+
+    /// 发出新的 query 并处理一个回复，查找结束时返回 false
+    async fn step(&mut self) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        self.send_queries();
+        if self.in_flight.is_empty() {
+            self.finished = true;
+            return false;
+        }
+
+        match timeout_at(self.deadline, self.in_flight.next()).await {
+            Ok(Some((node, rst))) => match self.handle.ctx() {
+                Ok(ctx) => {
+                    self.handle_result(&ctx, node, rst);
+                    true
+                }
+                Err(_) => {
+                    self.finish();
+                    false
+                }
+            },
+            Ok(None) => true,
+            Err(_) => {
+                log::debug!(
+                    target: "yiilian_dht::transaction::lookup",
+                    "{:?} {} timed out after {} sec",
+                    self.kind, self.target, LOOKUP_TIMEOUT_SECS
+                );
+                self.finish();
+                false
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        self.in_flight = FuturesUnordered::new();
+    }
+
+    /// 向最近的 k 个未失败候选节点中还没有查询过的节点发出 query，在途的 query 不超过 alpha 个
+    fn send_queries(&mut self) {
+        let mut nodes = vec![];
+        let mut in_flight = self.in_flight.len();
+
+        for candidate in self
+            .shortlist
+            .iter_mut()
+            .filter(|candidate| candidate.state != CandidateState::Failed)
+            .take(self.k)
+        {
+            if in_flight >= self.alpha {
+                break;
+            }
+
+            if candidate.state == CandidateState::Waiting {
+                candidate.state = CandidateState::InFlight;
+                nodes.push(candidate.node.clone());
+                in_flight += 1;
+            }
+        }
+
+        for node in nodes {
+            let query = self.build_query();
+            self.in_flight.push(send_query(self.handle.clone(), query, node, self.query_timeout));
+        }
+    }
+
+    fn build_query(&self) -> Query {
+        let ro = if self.read_only { Some(1) } else { None };
+
+        match self.kind {
+            LookupKind::FindNode => Query::FindNode(FindNode {
+                t: TransactionId::from_random(),
+                v: None,
+                ip: None,
+                ro,
+                id: self.local_id,
+                target: self.target,
+                want: self.want,
+            }),
+            LookupKind::GetPeers => Query::GetPeers(GetPeers {
+                t: TransactionId::from_random(),
+                v: None,
+                ip: None,
+                ro,
+                id: self.local_id,
+                info_hash: self.target,
+                want: self.want,
+            }),
+        }
+    }
+
+    fn handle_result(&mut self, ctx: &Context, node: Node, rst: Result<Reply, Error>) {
+        let (nodes, token, values) = match (self.kind, rst) {
+            (LookupKind::FindNode, Ok(Reply::FindNode(reply))) => {
+                (reply.nodes.into_iter().chain(reply.nodes6).collect::<Vec<Node>>(), None, vec![])
+            }
+            (LookupKind::GetPeers, Ok(Reply::GetPeers(reply))) => (
+                reply.nodes.into_iter().chain(reply.nodes6).collect(),
+                Some(reply.token),
+                reply.values,
+            ),
+            (_, Ok(reply)) => {
+                log::trace!(
+                    target: "yiilian_dht::transaction::lookup",
+                    "Address {:?} got wrong packet type back: {:?}",
+                    node.address, reply
+                );
+
+                let reply_error_block_duration_sec = ctx.settings().reply_error_block_duration_sec;
+                ctx.routing_table()
+                    .lock()
+                    .expect_error("routing_table.lock() failed")
+                    .add_block_list(
+                        node.address,
+                        Some(node.id),
+                        Some(Duration::from_secs(reply_error_block_duration_sec)),
+                    );
+                self.set_state(&node.id, CandidateState::Failed, None);
+
+                return;
+            }
+            (_, Err(error)) => {
+                // 已在 send_query() 中加入了黑名单
+                log::trace!(
+                    target: "yiilian_dht::transaction::lookup",
+                    "{:?} {:?} error: {}",
+                    self.kind, node.address, error
+                );
+                self.set_state(&node.id, CandidateState::Failed, None);
+
+                return;
+            }
+        };
+
+        self.set_state(&node.id, CandidateState::Replied, token);
+
+        for node in nodes {
+            // 将返回的 nodes 加入到路由表的未验证 buckets 中
+            let id_is_valid = node.id.is_valid_for_ip(
+                &node.address.ip(),
+                &ctx.routing_table()
+                    .lock()
+                    .expect_error("routing_table.lock() failed")
+                    .white_list,
+            );
+
+            if id_is_valid && node.address.port() > 0 {
+                if let Err(e) = ctx
+                    .routing_table()
+                    .lock()
+                    .expect_error("routing_table.lock() failed")
+                    .add_or_update(node.clone(), false)
+                {
+                    log::trace!(
+                        target: "yiilian_dht::transaction::lookup",
+                        "Add node {:?} to buckets failed, error: {}",
+                        node, e
+                    );
+                }
+            }
+
+            self.add_candidate(ctx, node);
+        }
+
+        for peer in values {
+            if self.seen_peers.insert(peer) {
+                self.peers.push(peer);
+                self.new_peers.push_back(peer);
+            }
+        }
+    }
+
+    fn set_state(&mut self, id: &Id, state: CandidateState, token: Option<Bytes>) {
+        if let Some(candidate) = self.shortlist.iter_mut().find(|candidate| &candidate.node.id == id) {
+            candidate.state = state;
+            candidate.token = token;
+        }
+    }
+
+    /// 已经在候选列表中（包括已失败的）或在黑名单中的节点不会再加入
+    fn add_candidate(&mut self, ctx: &Context, node: Node) {
+        if node.id == self.local_id || node.address.port() == 0 {
+            return;
+        }
+
+        if self.shortlist.iter().any(|candidate| candidate.node.id == node.id) {
+            return;
+        }
+
+        if ctx
+            .routing_table()
+            .lock()
+            .expect_error("routing_table.lock() failed")
+            .is_blocked(&node.address)
+        {
+            return;
+        }
+
+        let distance = node.id.xor(&self.target);
+        let pos = self.shortlist.partition_point(|candidate| candidate.distance < distance);
+
+        self.shortlist.insert(
+            pos,
+            Candidate {
+                node,
+                distance,
+                state: CandidateState::Waiting,
+                token: None,
+            },
+        );
+    }
+}
+
+fn send_query(handle: DhtHandle, query: Query, node: Node, timeout: Duration) -> QueryFuture {
+    Box::pin(async move {
+        let rst = match handle.ctx() {
+            Ok(ctx) => {
+                ctx.transaction_manager()
+                    .send_query(query, &node.address, Some(node.id), Some(timeout))
+                    .await
+            }
+            Err(error) => Err(error),
+        };
+
+        (node, rst)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::testing::SimNetworkBuilder;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lookup() {
+        let network = SimNetworkBuilder::new(32)
+            .seed(3)
+            .latency(Duration::from_millis(2), Duration::from_millis(2))
+            .build()
+            .unwrap();
+
+        network
+            .run(async {
+                network.bootstrap().await.unwrap();
+
+                let info_hash = Id::from_random(&mut StdRng::seed_from_u64(4));
+                let announcers: HashSet<SocketAddr> = [3, 4, 5]
+                    .iter()
+                    .map(|index| network.node(*index).addr())
+                    .collect();
+                for index in [3, 4, 5] {
+                    let node = network.node(index);
+                    node.handle().announce_peer(node.addr(), info_hash).await.unwrap();
+                }
+
+                // peers 在查找过程中逐个取出
+                network
+                    .wait_until(|| async {
+                        let mut lookup = network.node(20).handle().lookup_peers(info_hash).unwrap();
+                        let peers: HashSet<SocketAddr> = lookup.peers().collect().await;
+                        assert!(lookup.is_finished());
+
+                        let rst = lookup.into_get_peers_result();
+                        assert_eq!(peers.len(), rst.peers().len());
+                        peers == announcers && !rst.responders().is_empty()
+                    })
+                    .await
+                    .unwrap();
+
+                // 只有一个在途 query 时同样能找到目标
+                let target = network.node(30).id();
+                network
+                    .wait_until(|| async {
+                        let mut lookup = Lookup::new(network.node(1).handle(), LookupKind::FindNode, target)
+                            .unwrap()
+                            .alpha(1);
+                        lookup.run().await;
+                        lookup.closest().first().map(|node| node.id) == Some(target)
+                    })
+                    .await
+                    .unwrap();
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_lookup_without_nodes() {
+        let network = SimNetworkBuilder::new(1).build().unwrap();
+
+        let mut lookup = Lookup::new(network.node(0).handle(), LookupKind::FindNode, network.node(0).id()).unwrap();
+        lookup.run().await;
+        assert!(lookup.is_finished());
+        assert!(lookup.closest().is_empty());
+    }
+}
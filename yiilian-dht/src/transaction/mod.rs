@@ -1,7 +1,11 @@
 mod transaction_manager;
 mod transaction;
 mod get_peers_result;
+mod get_item_result;
+mod lookup;
 
 pub use transaction_manager::TransactionManager;
 pub use transaction::{Transaction, TransactionId};
-pub use get_peers_result::{GetPeersResponder, GetPeersResult};
\ No newline at end of file
+pub use get_peers_result::{GetPeersResponder, GetPeersResult};
+pub use get_item_result::GetItemResult;
+pub use lookup::{Lookup, LookupKind, LOOKUP_TIMEOUT_SECS};
\ No newline at end of file
@@ -1,11 +1,15 @@
 use std::{
     collections::{HashMap, HashSet},
     net::{IpAddr, SocketAddr},
-    sync::Mutex,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
+use bytes::Bytes;
 use chrono::Utc;
+use lru::LruCache;
+use rand::seq::SliceRandom;
 use tokio::{sync::oneshot, time::interval};
 use yiilian_core::{
     common::{error::Error, expect_log::ExpectLog},
@@ -14,49 +18,75 @@ use yiilian_core::{
 
 use crate::{
     common::{
-        calculate_token, dht_ctx_client, dht_ctx_peer_mgr, dht_ctx_routing_tbl, dht_ctx_settings,
-        dht_ctx_state, dht_ctx_trans_mgr, Id,
+        calculate_token, Context, DhtHandle, Id,
     }, data::{
         announce_peer::AnnouncePeer,
         body::{BodyKind, KrpcBody, Query, Reply},
+        error::{
+            RError, CAS_MISMATCH, INVALID_SIGNATURE, PROTOCOL_ERROR, SALT_TOO_BIG,
+            SEQ_LESS_THAN_CURRENT, VALUE_TOO_BIG,
+        },
         find_node::FindNode,
         find_node_reply::FindNodeReply,
+        get::Get,
         get_peers::GetPeers,
         get_peers_reply::GetPeersReply,
+        get_reply::GetReply,
         ping::Ping,
         ping_announce_replay::PingOrAnnounceReply,
-        util::reply_matches_query,
-    }, dht::DhtMode, routing_table::{Buckets, Node}
+        put::Put,
+        sample_infohashes::SampleInfoHashes,
+        sample_infohashes_reply::SampleInfoHashesReply,
+        util::{reply_matches_query, Want},
+    }, dht::DhtMode, item::{Item, MAX_SALT_SIZE}, routing_table::{Buckets, Node}
 };
 
-use super::{GetPeersResponder, GetPeersResult, Transaction, TransactionId};
+use super::{GetItemResult, GetPeersResponder, GetPeersResult, Lookup, LookupKind, Transaction, TransactionId};
+
+/// 最多记录多少个节点的 sample_infohashes interval
+const MAX_SAMPLE_INTERVALS: usize = 10_000;
+
+/// BEP51 规定 interval 最大为 6 小时
+const MAX_SAMPLE_INTERVAL_SECS: i64 = 6 * 60 * 60;
 
 #[derive(Debug)]
 /// 管理所有的事务性和非事务性的发送和接受的消息
 pub struct TransactionManager {
-    ctx_index: u16,
+    /// TransactionManager 属于 Context，通过 DhtHandle 访问其它状态
+    ctx: DhtHandle,
     local_addr: SocketAddr,
     /// 对外发送 query 的事务队列（只有主动发送 query 时才会产生事务）
     transactions: Mutex<HashMap<TransactionId, Transaction>>,
     mode: DhtMode,
+    /// 各节点允许再次发送 sample_infohashes 请求的时间点（BEP51 interval）
+    sample_intervals: Mutex<LruCache<SocketAddr, Instant>>,
 }
 
 impl TransactionManager {
     pub fn new(
-        ctx_index: u16,
+        ctx: DhtHandle,
         local_addr: SocketAddr,
         mode: DhtMode,
     ) -> Self {
         let transactions = Mutex::new(HashMap::new());
+        let sample_intervals = Mutex::new(LruCache::new(
+            NonZeroUsize::new(MAX_SAMPLE_INTERVALS).expect_error("NonZeroUsize::new() failed"),
+        ));
 
         Self {
-            ctx_index,
+            ctx,
             local_addr,
             transactions,
             mode,
+            sample_intervals,
         }
     }
 
+    /// Dht 已释放时返回错误，比如关闭过程中仍在运行的事务任务
+    fn ctx(&self) -> Result<Arc<Context>, Error> {
+        self.ctx.ctx()
+    }
+
     /// 清除早于 duration 的请求事务
     pub fn prune_older_than(&self, duration: Duration) {
         // 过期时间点 = 当前时间 - duration
@@ -147,9 +177,9 @@ impl TransactionManager {
                 self.remove_transcation(&tran_id);
                 // 并将目标节点加入 block_list，同时从 routing_table 中删除
                 let timeout_block_duration_sec =
-                    dht_ctx_settings(self.ctx_index).timeout_block_duration_sec;
+                    self.ctx()?.settings().timeout_block_duration_sec;
 
-                dht_ctx_routing_tbl(self.ctx_index)
+                self.ctx()?.routing_table()
                     .lock()
                     .expect_error("Lock context routing_table failed")
                     .add_block_list(
@@ -225,7 +255,7 @@ impl TransactionManager {
             self.local_addr,
         );
 
-        dht_ctx_client(self.ctx_index).send(req).await?;
+        self.ctx()?.client().send(req).await?;
 
         // 等待 transaction 上的 reply
         match notify_rx.await {
@@ -248,24 +278,72 @@ impl TransactionManager {
             self.local_addr,
         );
 
-        dht_ctx_client(self.ctx_index).send(req).await
+        self.ctx()?.client().send(req).await
     }
 
     /// Adds a 'vote' for whatever IP address the sender says we have.
     /// addr：对方 IP
-    /// requester_ip 是对方看到的本机的外网 IP
-    fn ip4_vote_helper(ctx_index: u16, addr: &SocketAddr, requester_ip: &Option<SocketAddr>) {
-        if let IpAddr::V4(their_ip) = addr.ip() {
-            if let Some(SocketAddr::V4(they_claim_our_sockaddr)) = &requester_ip {
-                dht_ctx_state(ctx_index)
+    /// requester_ip 是对方看到的本机的外网 IP，IPv4 和 IPv6 分别投票
+    fn ip_vote_helper(&self, addr: &SocketAddr, requester_ip: &Option<SocketAddr>) -> Result<(), Error> {
+        match (addr.ip(), requester_ip) {
+            (IpAddr::V4(their_ip), Some(SocketAddr::V4(they_claim_our_sockaddr))) => {
+                self.ctx()?.state()
                     .write()
-                    .expect_error("dht_ctx_state().write() failed")
+                    .expect_error("state().write() failed")
                     .ip4_source
                     .add_vote(their_ip, *they_claim_our_sockaddr.ip());
             }
+            (IpAddr::V6(their_ip), Some(SocketAddr::V6(they_claim_our_sockaddr))) => {
+                self.ctx()?.state()
+                    .write()
+                    .expect_error("state().write() failed")
+                    .ip6_source
+                    .add_vote(their_ip, *they_claim_our_sockaddr.ip());
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// 开启 IPv6 时，在 find_node / get_peers 请求中同时请求 IPv4 和 IPv6 节点
+    pub(crate) fn lookup_want(&self) -> Option<Want> {
+        if self.ctx().ok()?.settings().ipv6 {
+            Some(Want::new(true, true))
+        } else {
+            None
         }
     }
 
+    /// 根据请求中的 want（如果没有则根据请求方的地址族），返回距离 target 最近的 IPv4 和 IPv6 节点
+    fn nearest_nodes_for_want(
+        &self,
+        target: &Id,
+        requester_id: &Id,
+        want: Option<Want>,
+        remote_addr: &SocketAddr,
+    ) -> Result<(Vec<Node>, Vec<Node>), Error> {
+        let want = want.unwrap_or(Want::from_addr(remote_addr));
+        let ctx = self.ctx()?;
+        let routing_table = ctx
+            .routing_table()
+            .lock()
+            .expect_error("routing_table.lock() failed");
+
+        let nodes = if want.n4 {
+            routing_table.get_nearest_nodes(target, Some(requester_id))
+        } else {
+            vec![]
+        };
+        let nodes6 = if want.n6 {
+            routing_table.get_nearest_nodes6(target, Some(requester_id))
+        } else {
+            vec![]
+        };
+
+        Ok((nodes, nodes6))
+    }
+
     /// 添加事务
     pub(crate) fn add_transaction(&self, tran: Transaction) {
         self.transactions
@@ -282,11 +360,11 @@ impl TransactionManager {
     ) -> Result<(Reply, SocketAddr), Error> {
         // info!("Receive ping request from {:?}", sender);
 
-        let local_id = dht_ctx_state(self.ctx_index)
+        let local_id = self.ctx()?.state()
             .read()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .get_local_id();
-        let read_only = dht_ctx_settings(self.ctx_index).read_only;
+        let read_only = self.ctx()?.settings().read_only;
 
         let reply = PingOrAnnounceReply {
             t: query.t.clone(),
@@ -305,17 +383,15 @@ impl TransactionManager {
         query: &FindNode,
         remote_addr: &SocketAddr,
     ) -> Result<(Reply, SocketAddr), Error> {
-        let local_id = dht_ctx_state(self.ctx_index)
+        let local_id = self.ctx()?.state()
             .read()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .get_local_id();
-        let read_only = dht_ctx_settings(self.ctx_index).read_only;
+        let read_only = self.ctx()?.settings().read_only;
 
         //获取除 requester_id 外，距离 target 最近的节点
-        let nearest = dht_ctx_routing_tbl(self.ctx_index)
-            .lock()
-            .expect_error("dht_ctx_routing_tbl.lock() failed")
-            .get_nearest_nodes(&query.target, Some(&query.id));
+        let (nearest, nearest6) =
+            self.nearest_nodes_for_want(&query.target, &query.id, query.want, remote_addr)?;
 
         let reply = FindNodeReply {
             t: query.t.clone(),
@@ -324,6 +400,7 @@ impl TransactionManager {
             ro: if read_only { Some(1) } else { None },
             id: local_id,
             nodes: nearest,
+            nodes6: nearest6,
         };
 
         Ok((Reply::FindNode(reply), remote_addr.clone()))
@@ -335,34 +412,42 @@ impl TransactionManager {
         query: &GetPeers,
         remote_addr: &SocketAddr,
     ) -> Result<(Reply, SocketAddr), Error> {
-        let local_id = dht_ctx_state(self.ctx_index)
+        let local_id = self.ctx()?.state()
             .read()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .get_local_id();
-        let read_only = dht_ctx_settings(self.ctx_index).read_only;
+        let read_only = self.ctx()?.settings().read_only;
 
         let peers = {
             let get_peers_freshness_secs =
-                dht_ctx_settings(self.ctx_index).get_peers_freshness_secs;
-            let max_peers_response = dht_ctx_settings(self.ctx_index).max_peers_response;
+                self.ctx()?.settings().get_peers_freshness_secs;
+            let max_peers_response = self.ctx()?.settings().max_peers_response;
             let newer_than = Utc::now() - Duration::from_secs(get_peers_freshness_secs);
-            let mut peers = dht_ctx_peer_mgr(self.ctx_index)
+            let mut peers = self.ctx()?.peer_manager()
                 .lock()
-                .expect_error("dht_ctx_peer_mgr.lock() failed")
+                .expect_error("peer_manager.lock() failed")
                 .get_peers(&query.info_hash, Some(newer_than));
+            // 只返回和请求方地址族相同的 peers
+            peers.retain(|peer| peer.is_ipv6() == remote_addr.is_ipv6());
             peers.truncate(max_peers_response);
 
             if let DhtMode::Crawler(port) = self.mode {
-                let wan_addr = {            
-                    dht_ctx_state(self.ctx_index)
+                let wan_addr: Option<IpAddr> = {
+                    let ctx = self.ctx()?;
+                    let state = ctx
+                        .state()
                         .read()
-                        .expect("dht_ctx_state.read() failed")
-                        .ip4_source
-                        .get_best_ipv4()
+                        .expect("state.read() failed");
+
+                    if remote_addr.is_ipv6() {
+                        state.ip6_source.get_best_ipv6().map(|ip| ip.into())
+                    } else {
+                        state.ip4_source.get_best_ipv4().map(|ip| ip.into())
+                    }
                 };
 
                 if let Some(wan_addr) = wan_addr {
-                    let wan_addr = SocketAddr::new(wan_addr.into(), port);
+                    let wan_addr = SocketAddr::new(wan_addr, port);
     
                     peers.insert(0, wan_addr);
                 }
@@ -373,17 +458,15 @@ impl TransactionManager {
         };
 
         // 根据 token_secret 和对方 IP 生成 token，对方在向我方发出 announce 请求中需要带上该 token
-        let token_secret = dht_ctx_state(self.ctx_index)
+        let token_secret = self.ctx()?.state()
             .read()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .token_secret
             .clone();
         let token = calculate_token(&remote_addr, token_secret);
         let token = token.to_vec().into();
-        let nearest_nodes = dht_ctx_routing_tbl(self.ctx_index)
-            .lock()
-            .expect_error("dht_ctx_routing_tbl.lock() failed")
-            .get_nearest_nodes(&query.info_hash, Some(&query.id));
+        let (nearest_nodes, nearest_nodes6) =
+            self.nearest_nodes_for_want(&query.info_hash, &query.id, query.want, remote_addr)?;
         let reply = GetPeersReply {
             t: query.t.clone(),
             v: None,
@@ -392,6 +475,7 @@ impl TransactionManager {
             id: local_id,
             token: token,
             nodes: nearest_nodes,
+            nodes6: nearest_nodes6,
             values: peers,
         };
 
@@ -403,28 +487,28 @@ impl TransactionManager {
         query: &AnnouncePeer,
         remote_addr: &SocketAddr,
     ) -> Result<(Reply, SocketAddr), Error> {
-        let local_id = dht_ctx_state(self.ctx_index)
+        let local_id = self.ctx()?.state()
             .read()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .get_local_id();
-        let read_only = dht_ctx_settings(self.ctx_index).read_only;
+        let read_only = self.ctx()?.settings().read_only;
 
         // 根据 token_secret/old_token_secret 和 对方 ip 计算 token 是否合法（这样就不用缓存上次发出的 token）
-        let token_secret = dht_ctx_state(self.ctx_index)
+        let token_secret = self.ctx()?.state()
             .read()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .token_secret
             .clone();
-        let old_token_secret = dht_ctx_state(self.ctx_index)
+        let old_token_secret = self.ctx()?.state()
             .read()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .old_token_secret
             .clone();
         let is_token_valid = query.token == calculate_token(&remote_addr, token_secret).to_vec()
             || query.token == calculate_token(&remote_addr, old_token_secret).to_vec();
 
         // let reply_error_block_duration_sec =
-        //     dht_ctx_settings(self.ctx_index).reply_error_block_duration_sec;
+        //     self.ctx()?.settings().reply_error_block_duration_sec;
 
         if is_token_valid {
             // 如果有 implied_port，则使用请求方的 ip+port; 如果没有 implied_port，则使用请求消息中的 port
@@ -438,9 +522,9 @@ impl TransactionManager {
             };
 
             // 将对方 address 加入到 announce 的 info_hash 对应的 peers 列表中
-            dht_ctx_peer_mgr(self.ctx_index)
+            self.ctx()?.peer_manager()
                 .lock()
-                .expect_error("dht_ctx_peer_mgr.lock() failed")
+                .expect_error("peer_manager.lock() failed")
                 .announce_peer(query.info_hash, sockaddr);
 
             let reply = PingOrAnnounceReply {
@@ -460,6 +544,185 @@ impl TransactionManager {
         }
     }
 
+    /// 处理对方 get 请求（BEP44）
+    pub(crate) async fn handle_get(
+        &self,
+        query: &Get,
+        remote_addr: &SocketAddr,
+    ) -> Result<(Reply, SocketAddr), Error> {
+        let local_id = self.ctx()?.state()
+            .read()
+            .expect_error("state.read() failed")
+            .get_local_id();
+        let read_only = self.ctx()?.settings().read_only;
+
+        // 根据 token_secret 和对方 IP 生成 token，对方在向我方发出 put 请求中需要带上该 token
+        let token_secret = self.ctx()?.state()
+            .read()
+            .expect_error("state.read() failed")
+            .token_secret
+            .clone();
+        let token = calculate_token(remote_addr, token_secret);
+        let token = token.to_vec().into();
+        let nearest_nodes = self.ctx()?.routing_table()
+            .lock()
+            .expect_error("routing_table.lock() failed")
+            .get_nearest_nodes(&query.target, Some(&query.id));
+
+        let item = {
+            let item_freshness_secs = self.ctx()?.settings().item_freshness_secs;
+            let newer_than = Utc::now() - Duration::from_secs(item_freshness_secs);
+            self.ctx()?.item_manager()
+                .lock()
+                .expect_error("item_manager.lock() failed")
+                .get(&query.target, Some(newer_than))
+        };
+
+        let mut reply = GetReply::new(
+            local_id,
+            token,
+            nearest_nodes,
+            None,
+            None,
+            None,
+            None,
+            query.t.clone(),
+            None,
+            Some(remote_addr.to_owned()),
+            if read_only { Some(1) } else { None },
+        );
+
+        if let Some(item) = item {
+            // 对方已经持有 seq 不小于我方的可变数据项时，不需要返回数据项
+            let is_outdated = match (&item, query.seq) {
+                (Item::Mutable(item), Some(seq)) => item.seq <= seq,
+                _ => false,
+            };
+
+            if !is_outdated {
+                reply.set_item(item);
+            }
+        }
+
+        Ok((Reply::Get(reply), *remote_addr))
+    }
+
+    /// 处理对方 put 请求（BEP44），校验 token、数据项及 seq/cas 后保存数据项。
+    /// 校验失败时返回 BEP44 规定错误码的错误消息
+    pub(crate) async fn handle_put(
+        &self,
+        query: &Put,
+        remote_addr: &SocketAddr,
+    ) -> Result<(BodyKind, SocketAddr), Error> {
+        let local_id = self.ctx()?.state()
+            .read()
+            .expect_error("state.read() failed")
+            .get_local_id();
+        let read_only = self.ctx()?.settings().read_only;
+
+        // 和 announce_peer 一样，根据 token_secret/old_token_secret 和 对方 ip 计算 token 是否合法
+        let token_secret = self.ctx()?.state()
+            .read()
+            .expect_error("state.read() failed")
+            .token_secret
+            .clone();
+        let old_token_secret = self.ctx()?.state()
+            .read()
+            .expect_error("state.read() failed")
+            .old_token_secret
+            .clone();
+        let is_token_valid = query.token == calculate_token(remote_addr, token_secret).to_vec()
+            || query.token == calculate_token(remote_addr, old_token_secret).to_vec();
+
+        let target = query.item.target();
+        let rst = if is_token_valid {
+            let stored = self.ctx()?.item_manager()
+                .lock()
+                .expect_error("item_manager.lock() failed")
+                .get(&target, None);
+
+            check_put_item(&query.item, query.cas, stored.as_ref())
+        } else {
+            Err((PROTOCOL_ERROR, format!("Invalid token: {:?}", query.token)))
+        };
+
+        if let Err((code, message)) = rst {
+            log::trace!(
+                target: "yiilian_dht::transaction::handle_put",
+                "[{}] Reject put from {}: {} {}",
+                self.local_addr.port(), remote_addr, code, message
+            );
+
+            let error = RError::new(
+                code,
+                message.into(),
+                query.t.clone(),
+                None,
+                Some(*remote_addr),
+                if read_only { Some(1) } else { None },
+            );
+
+            return Ok((BodyKind::RError(error), *remote_addr));
+        }
+
+        self.ctx()?.item_manager()
+            .lock()
+            .expect_error("item_manager.lock() failed")
+            .put(target, query.item.clone());
+
+        let reply = PingOrAnnounceReply {
+            t: query.t.clone(),
+            v: None,
+            ip: Some(*remote_addr),
+            ro: if read_only { Some(1) } else { None },
+            id: local_id,
+        };
+
+        Ok((BodyKind::Reply(Reply::PingOrAnnounce(reply)), *remote_addr))
+    }
+
+    /// 处理对方 sample_infohashes 请求（BEP51），从 PeerManager 中随机抽取 info_hash 返回
+    pub(crate) async fn handle_sample_infohashes(
+        &self,
+        query: &SampleInfoHashes,
+        remote_addr: &SocketAddr,
+    ) -> Result<(Reply, SocketAddr), Error> {
+        let local_id = self.ctx()?.state()
+            .read()
+            .expect_error("state.read() failed")
+            .get_local_id();
+        let read_only = self.ctx()?.settings().read_only;
+        let max_samples_response = self.ctx()?.settings().max_samples_response;
+        let interval = self.ctx()?.settings().sample_infohashes_interval_secs;
+
+        let info_hashes = self.ctx()?.peer_manager()
+            .lock()
+            .expect_error("peer_manager.lock() failed")
+            .get_info_hashes();
+        let samples: Vec<Id> = info_hashes
+            .choose_multiple(&mut rand::thread_rng(), max_samples_response)
+            .cloned()
+            .collect();
+
+        let (nearest, nearest6) =
+            self.nearest_nodes_for_want(&query.target, &query.id, query.want, remote_addr)?;
+
+        let mut reply = SampleInfoHashesReply::new(
+            local_id,
+            interval as i64,
+            nearest,
+            info_hashes.len() as i64,
+            samples,
+            query.t.clone(),
+            None,
+            Some(remote_addr.to_owned()),
+            if read_only { Some(1) } else { None },
+        );
+        reply.nodes6 = nearest6;
+
+        Ok((Reply::SampleInfoHashes(reply), *remote_addr))
+    }
+
     /// 处理对方的反馈（需要事务处理）
     pub(crate) async fn handle_reply(
         &self,
@@ -473,9 +736,9 @@ impl TransactionManager {
         let id_is_valid = {
             their_id.is_valid_for_ip(
                 &sender.ip(),
-                &dht_ctx_routing_tbl(self.ctx_index)
+                &self.ctx()?.routing_table()
                     .lock()
-                    .expect_error("dht_ctx_routing_tbl.lock() failed")
+                    .expect_error("routing_table.lock() failed")
                     .white_list,
             )
         };
@@ -483,22 +746,22 @@ impl TransactionManager {
 
         if id_is_valid && sender.port() > 0 {
             // 根据这次的reply，对我们的外网IP增加 vote，注意 reply.requester_ip 是对方认为我们的外网 IP
-            Self::ip4_vote_helper(self.ctx_index, &sender, &reply.get_ip());
+            self.ip_vote_helper(sender, &reply.get_ip())?;
 
             // 将对方节点及ipport，加入或更新 kbucket
             // 由于对方节点时响应我们的请求的，所以它就是 verified node, 因此 add_or_update(_, verified) 参数要传 true
-            dht_ctx_routing_tbl(self.ctx_index)
+            self.ctx()?.routing_table()
                 .lock()
-                .expect_error("dht_ctx_routing_tbl.lock() failed")
+                .expect_error("routing_table.lock() failed")
                 .add_or_update(Node::new(their_id, *sender), true)?;
         }
 
         // 如果不在黑名单中，且事务有回传 channel , 则通过该 channel 回传 reply
         // take_matching_transaction 会将匹配 reply 的 transaction 删除
         if let Some(transaction) = self.take_matching_transaction(&reply, sender) {
-            let in_block_list = dht_ctx_routing_tbl(self.ctx_index)
+            let in_block_list = self.ctx()?.routing_table()
                 .lock()
-                .expect_error("dht_ctx_routing_tbl.lock() failed")
+                .expect_error("routing_table.lock() failed")
                 .is_blocked(sender);
 
             if !in_block_list {
@@ -579,11 +842,11 @@ impl TransactionManager {
             )))?
         }
 
-        let local_id = dht_ctx_state(self.ctx_index)
+        let local_id = self.ctx()?.state()
             .read()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .get_local_id();
-        let read_only = dht_ctx_settings(self.ctx_index).read_only;
+        let read_only = self.ctx()?.settings().read_only;
 
         let ping_query = Ping {
             t: TransactionId::from_random(),
@@ -593,7 +856,7 @@ impl TransactionManager {
             id: local_id,
         };
 
-        let send_query_timeout_sec = dht_ctx_settings(self.ctx_index).send_query_timeout_sec;
+        let send_query_timeout_sec = self.ctx()?.settings().send_query_timeout_sec;
 
         self.send_query(
             Query::Ping(ping_query),
@@ -619,12 +882,12 @@ impl TransactionManager {
             )))?
         }
 
-        let local_id = dht_ctx_state(self.ctx_index)
+        let local_id = self.ctx()?.state()
             .read()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .get_local_id();
 
-        let read_only = dht_ctx_settings(self.ctx_index).read_only;
+        let read_only = self.ctx()?.settings().read_only;
         let ping_query = Ping {
             t: TransactionId::from_random(),
             v: None,
@@ -641,240 +904,163 @@ impl TransactionManager {
     }
 
     /// 找到离目标节点最近的节点集合
-    ///
-    /// 这个操作会一直迭代，直到没有更近的节点被发现或者超时后才结束
     pub(crate) async fn find_node(&self, target_id: Id) -> Vec<Node> {
-        // buckets 中存放的是 routing_table 中已验证的节点，以及本次 find_node 以来对方反馈的 nodes 节点
-        let local_id = dht_ctx_state(self.ctx_index)
-            .read()
-            .expect_error("dht_ctx_state.read() failed")
-            .get_local_id();
-        let bucket_size = dht_ctx_settings(self.ctx_index).bucket_size;
-
-        let mut buckets = Buckets::new(bucket_size, local_id);
-
-        let mut best_ids = Vec::<Id>::new();
-
-        let timeout_sec = Duration::from_secs(3 * 60);
-        let start  = Instant::now();
-
-        loop {
-
-            let elapsed = start.elapsed();
-            if elapsed >= timeout_sec {
-                break;
+        match Lookup::new(self.ctx.clone(), LookupKind::FindNode, target_id) {
+            Ok(mut lookup) => {
+                lookup.run().await;
+                lookup.closest()
             }
+            Err(_) => vec![],
+        }
+    }
 
-            // 每次循环都从 routing_table 中加载新的已验证的 node 到当前 buckets 中
-            let all_verifyied = dht_ctx_routing_tbl(self.ctx_index)
-                .lock()
-                .expect_error("dht_ctx_routing_tbl.lock() failed")
-                .get_all_verified();
-            for item in all_verifyied {
-                if !buckets.contains(&item.id)
-                    && !dht_ctx_routing_tbl(self.ctx_index)
-                        .lock()
-                        .expect_error("dht_ctx_routing_tbl.lock() failed")
-                        .block_list
-                        .contains(item.address.ip(), item.address.port())
-                {
-                    buckets.add(item, None).ok();
-                }
-            }
+    /// 获取 info_hash 对应的 peers，以及回复了 get_peers 的节点
+    pub(crate) async fn get_peers(&self, info_hash: Id) -> Result<GetPeersResult, Error> {
+        let mut lookup = Lookup::new(self.ctx.clone(), LookupKind::GetPeers, info_hash)?;
+        lookup.run().await;
 
-            // 在 buckets 中找到离 target_id 最近的节点，如果没找到任何节点，则稍后再尝试
-            let nearest = buckets.get_nearest_nodes(&target_id, None);
-            if nearest.is_empty() {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-                continue;
-            }
+        Ok(lookup.into_get_peers_result())
+    }
 
-            let best_ids_current: Vec<Id> = nearest.iter().map(|node| node.id.clone()).collect();
+    /// Announce that you are a peer for a specific info_hash, returning the nodes
+    /// that were successfully announced to.
+    ///
+    /// # Arguments
+    /// * `info_hash` - Id of the torrent
+    /// * `port` - optional port that other peers should use to contact your peer.
+    /// If omitted, `implied_port` will be set true on the announce messages and
+    /// * `timeout` - the maximum amount of time that will be spent searching for
+    /// peers close to `info_hash` before announcing to them. This means that this
+    /// function can actually take a bit longer than `timeout`, since it will take
+    /// a moment after `timeout` has elapsed to announce to the nodes.
+    pub(crate) async fn announce_peer(
+        &self,
+        info_hash: Id,
+        port: Option<u16>,
+    ) -> Result<Vec<Node>, Error> {
+        let mut to_ret = Vec::new();
 
-            if best_ids == best_ids_current {
-                // 直到找不到更近的节点，则退出循环
-                break;
-            }
-            best_ids = best_ids_current;
+        // Figure out which nodes we want to announce to
+        let get_peers_result = self.get_peers(info_hash).await?;
 
-            // 发送 find_node 请求给这些节点
-            let sender_id = local_id.clone().clone();
-            let read_only = dht_ctx_settings(self.ctx_index).read_only;
-
-            let todos = futures::stream::FuturesUnordered::new();
-            for node in nearest {
-                let read_only = if read_only { Some(1) } else { None };
-
-                let find_node_query = (
-                    Query::FindNode(FindNode {
-                        t: TransactionId::from_random(),
-                        v: None,
-                        ip: None,
-                        ro: read_only,
-                        id: sender_id.clone(),
-                        target: target_id.clone(),
-                    }),
-                    node.address,
-                    node.id.clone(),
-                );
+        log::trace!(
+            target:"yiilian_dht::transaction::announce_peer",
+            "[{}] {} nodes responded to get_peers",
+            self.local_addr.port(), get_peers_result.responders().len()
+        );
 
-                todos.push(find_node_query);
-            }
+        // Prepare to send packets to the nearest 8 node
+        let todos = futures::stream::FuturesUnordered::new();
+        let bucket_size = self.ctx()?.settings().bucket_size;
+        for responder in get_peers_result.responders().into_iter().take(bucket_size) {
+            let read_only = self.ctx()?.settings().read_only;
+            let announce_peer = (
+                Query::AnnouncePeer(AnnouncePeer {
+                    t: TransactionId::from_random(),
+                    v: None,
+                    ip: None,
+                    ro: if read_only { Some(1) } else { None },
+                    id: responder.node().id.clone(),
+                    info_hash: info_hash.clone(),
+                    implied_port: if let Some(_) = port { Some(0) } else { Some(1) },
+                    port: port.unwrap_or(0),
+                    token: responder.token().to_owned(),
+                }),
+                responder.node().to_owned(),
+            );
 
-            // 随机执行 todos 中的 future
-            for (query, dest_addr, dest_id) in todos {
-                let request_result = self
-                    .send_query(
-                        query,
-                        &dest_addr,
-                        Some(dest_id.clone()),
-                        Some(Duration::from_secs(5)),
-                    )
-                    .await;
+            todos.push(announce_peer);
+        }
 
-                match request_result {
-                    Ok(reply) => match reply {
-                        Reply::FindNode(val) => {
-                            log::trace!(target: "yiilian_dht::transaction::find_node", "[{}] Address {:?} got {} nodes", self.local_addr.port(), dest_addr, val.nodes.len());
-
-                            for node in val.nodes {
-                                // 将 find_node 返回的 nodes 加入到 routing_table 中
-                                let id_is_valid = {
-                                    node.id.is_valid_for_ip(
-                                        &node.address.ip(),
-                                        &dht_ctx_routing_tbl(self.ctx_index)
-                                            .lock()
-                                            .expect_error("dht_ctx_routing_tbl.lock() failed")
-                                            .white_list,
-                                    )
-                                };
-
-                                if id_is_valid && node.address.port() > 0 {
-                                    if let Err(e) = dht_ctx_routing_tbl(self.ctx_index)
-                                        .lock()
-                                        .expect_error("dht_ctx_routing_tbl.lock() failed")
-                                        .add_or_update(node.clone(), false)
-                                    {
-                                        log::trace!(
-                                            target: "yiilian_dht::transaction::find_node",
-                                            "[{}] Add node {:?} to buckets failed, error: {}",
-                                            self.local_addr.port(), node, e
-                                        );
-                                    }
-                                }
-                                if !buckets.contains(&node.id) {
-                                    log::trace!(target: "yiilian_dht::transaction::find_node",
-                                        "[{}] Node (id: {:?}, {:?}) is a candidate for buckets",
-                                        self.local_addr.port(), node.id, node.address
-                                    );
-
-                                    buckets.add(node.clone(), None).ok();
-                                }
-                            }
-                        }
-                        _ => {
-                            log::trace!(target: "yiilian_dht::transaction::find_node", "[{}] Address {:?} got wrong frame type back: {:?}", self.local_addr.port(), dest_addr, reply);
-
-                            let reply_error_block_duration_sec =
-                                dht_ctx_settings(self.ctx_index).reply_error_block_duration_sec;
-                            buckets.remove(&dest_id);
-                            dht_ctx_routing_tbl(self.ctx_index)
-                                .lock()
-                                .expect_error("dht_ctx_routing_tbl.lock() failed")
-                                .add_block_list(
-                                    dest_addr,
-                                    Some(dest_id),
-                                    Some(Duration::from_secs(reply_error_block_duration_sec)),
-                                );
-                        }
-                    },
-                    Err(error) => {
-                        // 已在 send_query() 中加入了黑名单
-                        buckets.remove(&dest_id);
+        // Execute the futures, handle their results
+        let send_query_timeout_sec = self.ctx()?.settings().send_query_timeout_sec;
+        for (query, dest_node) in todos {
+            let request_result = self
+                .send_query(
+                    query,
+                    &dest_node.address,
+                    Some(dest_node.id),
+                    Some(Duration::from_secs(send_query_timeout_sec)),
+                )
+                .await;
 
+            match request_result {
+                Ok(reply) => match reply {
+                    Reply::PingOrAnnounce(_) => {
+                        to_ret.push(dest_node);
+                    }
+                    _ => {
                         log::trace!(
-                            target: "yiilian_dht::transaction::find_node",
-                            "[{}] {:?} find_node error: {}",
-                            self.local_addr.port(), dest_addr, error
-                        );
+                            target: "yiilian_dht::transaction::announce_peer",
+                            "[{}] Got wrong packet type back: {:?}",
+                            self.local_addr.port(), reply
+                        )
                     }
+                },
+                Err(e) => {
+                    log::debug!(
+                        target: "yiilian_dht::transaction::announce_peer",
+                        "[{}] Error sending announce_peer: {}",
+                        self.local_addr.port(), e
+                    )
                 }
             }
-
-            // 确保我们下一次的发送至少间隔 1 秒
-            let send_next_query_interval_sec =
-                dht_ctx_settings(self.ctx_index).send_next_query_interval_sec;
-            tokio::time::sleep(Duration::from_secs(send_next_query_interval_sec)).await;
         }
 
-        // log::debug!(target: "yiilian_dht::transaction::find_node", "nearest nodes count: {}", buckets.len());
-
-        let nodes: Vec<Node> = buckets
-            .get_nearest_nodes(&target_id, None)
-            .into_iter()
-            .map(|node| node.clone())
-            .collect();
-
-        nodes
+        Ok(to_ret)
     }
 
-    /// Use the DHT to retrieve peers for the given info_hash.
+    /// 从 DHT 上获取 target 对应的数据项（BEP44）
     ///
-    /// Returns the all the results so far after `timeout` has elapsed
-    /// or the operation stops making progress (whichever happens first).
-    pub(crate) async fn get_peers(
+    /// 可变数据项需要提供 put 时使用的 salt，用于校验 target 和签名。
+    /// 对于可变数据项，返回所有已校验数据项中 seq 最大的那个。
+    pub(crate) async fn get_item(
         &self,
-        info_hash: Id,
-        quick_mode: bool,
-    ) -> Result<GetPeersResult, Error> {
-        let mut unique_peers = HashSet::new();
+        target: Id,
+        salt: Option<Bytes>,
+    ) -> Result<GetItemResult, Error> {
+        let mut item: Option<Item> = None;
         let mut responders = HashSet::new();
-        let local_id = dht_ctx_state(self.ctx_index)
+        let local_id = self.ctx()?.state()
             .read()
-            .expect_error("dht_ctx_state.read() failed")
+            .expect_error("state.read() failed")
             .get_local_id();
-        let bucket_size = dht_ctx_settings(self.ctx_index).bucket_size;
-        let read_only = dht_ctx_settings(self.ctx_index).read_only;
-        let send_query_timeout_sec = dht_ctx_settings(self.ctx_index).send_query_timeout_sec;
+        let bucket_size = self.ctx()?.settings().bucket_size;
+        let read_only = self.ctx()?.settings().read_only;
+        let send_query_timeout_sec = self.ctx()?.settings().send_query_timeout_sec;
 
         let mut buckets = Buckets::new(bucket_size, local_id);
 
-        // Hack to aid in bootstrapping
-        // self.find_node(info_hash).await?;
-
         let mut best_ids = Vec::new();
 
         let timeout_sec = Duration::from_secs(3 * 60);
-        let start  = Instant::now();
-        
-        loop {
+        let start = Instant::now();
 
+        loop {
             let elapsed = start.elapsed();
             if elapsed >= timeout_sec {
-                return Err(Error::new_timeout(&format!("get_peers loop timeout: {:?} sec", elapsed.as_secs())));
+                return Err(Error::new_timeout(&format!("get_item loop timeout: {:?} sec", elapsed.as_secs())));
             }
 
-            // Populate our buckets with the main buckets from the DHT
             // 从路由表中获取所有的 node
-            let all_verifyied = dht_ctx_routing_tbl(self.ctx_index)
+            let all_verifyied = self.ctx()?.routing_table()
                 .lock()
-                .expect_error("dht_ctx_routing_tbl.lock() failed")
+                .expect_error("routing_table.lock() failed")
                 .get_all_verified();
 
-            for item in all_verifyied {
-                if !buckets.contains(&item.id)
-                    && !dht_ctx_routing_tbl(self.ctx_index)
+            for node in all_verifyied {
+                if !buckets.contains(&node.id)
+                    && !self.ctx()?.routing_table()
                         .lock()
-                        .expect_error("dht_ctx_routing_tbl.lock() failed")
-                        .block_list
-                        .contains(item.address.ip(), item.address.port())
+                        .expect_error("routing_table.lock() failed")
+                        .is_blocked(&node.address)
                 {
-                    buckets.add(item, None).ok();
+                    buckets.add(node, None).ok();
                 }
             }
 
-            // 在 buckets 中找到离 target_id 最近的节点，如果没找到任何节点，则稍后再尝试
-            let mut nearest = buckets.get_nearest_nodes(&info_hash, None);
+            // 在 buckets 中找到离 target 最近的节点，如果没找到任何节点，则稍后再尝试
+            let mut nearest = buckets.get_nearest_nodes(&target, None);
 
             if nearest.is_empty() {
                 tokio::time::sleep(Duration::from_secs(1)).await;
@@ -887,34 +1073,31 @@ impl TransactionManager {
                 // 直到找不到更近的节点，则退出循环
                 break;
             }
-            
-            // 只在新增的附近节点中 get_peers
+
+            // 只在新增的附近节点中 get
             nearest.retain(|node| !best_ids.contains(&node.id));
 
             best_ids = best_ids_current;
 
-            // 发送 get_peers 请求给这些节点
-            let sender_id = local_id.clone();
-
-            let todos = futures::stream::FuturesUnordered::new();
-            for node in nearest {
-                let get_peers_query = (
-                    Query::GetPeers(GetPeers {
-                        t: TransactionId::from_random(),
-                        v: None,
-                        ip: None,
-                        ro: if read_only { Some(1) } else { None },
-                        id: sender_id.clone(),
-                        info_hash: info_hash.clone(),
-                    }),
-                    node.clone(),
-                );
-                todos.push(get_peers_query);
-            }
-            
-            // Send get_peers to nearest nodes, handle their responses
-            for (query, dest_node) in todos {
-                let request_result = dht_ctx_trans_mgr(self.ctx_index)
+            let nearest: Vec<Node> = nearest.into_iter().cloned().collect();
+            for dest_node in nearest {
+                // 已获得可变数据项时，只需要对方返回 seq 更大的数据项
+                let seq = match &item {
+                    Some(Item::Mutable(item)) => Some(item.seq),
+                    _ => None,
+                };
+
+                let query = Query::Get(Get::new(
+                    local_id,
+                    target,
+                    seq,
+                    TransactionId::from_random(),
+                    None,
+                    None,
+                    if read_only { Some(1) } else { None },
+                ));
+
+                let request_result = self
                     .send_query(
                         query,
                         &dest_node.address,
@@ -923,178 +1106,167 @@ impl TransactionManager {
                     )
                     .await;
 
-                match request_result {
-                    Ok(reply) => match reply {
-                        Reply::GetPeers(val) => {
-                            responders.insert(GetPeersResponder::new(dest_node.clone(), val.token));
-
-                            if val.nodes.len() > 0 {
-                                log::trace!(
-                                    target: "yiilian_dht::transaction::get_peers",
-                                    "[{}] Address {:?} got {} nodes",
-                                    self.local_addr.port(), dest_node.address, val.nodes.len()
-                                );
-
-                                for node in val.nodes {
-                                    // 将 find_node 返回的 nodes 加入到 routing_table 中
-                                    let id_is_valid = {
-                                        node.id.is_valid_for_ip(
-                                            &node.address.ip(),
-                                            &dht_ctx_routing_tbl(self.ctx_index)
-                                                .lock()
-                                                .expect_error("dht_ctx_routing_tbl.lock() failed")
-                                                .white_list,
-                                        )
-                                    };
-
-                                    if id_is_valid && node.address.port() > 0
-                                    // && !in_block_list
-                                    {
-                                        if quick_mode {
-                                            // 方法1： send ping no wait
-                                            let node_id = node.id;
-                                            let node_addr = node.address;
-
-                                            self.ping_no_wait(node_addr, Some(node_id)).await.ok();
-                                        } else {
-                                            // 方法2： 将获取的 nodes 加入到未验证 buckets 中
-                                            if let Err(e) = dht_ctx_routing_tbl(self.ctx_index)
-                                                .lock()
-                                                .expect_error("dht_ctx_routing_tbl.lock() failed")
-                                                .add_or_update(node.clone(), false)
-                                            {
-                                                log::trace!(
-                                                    target: "yiilian_dht::transaction::get_peers",
-                                                    "[{}] Add node {:?} to buckets failed, error: {}",
-                                                    self.local_addr.port(), node, e
-                                                );
-                                            }
-                                        }
-                                    }
-
-                                    let in_block_list = dht_ctx_routing_tbl(self.ctx_index)
-                                        .lock()
-                                        .expect_error("dht_ctx_routing_tbl.lock() failed")
-                                        .is_blocked(&node.address);
-
-                                    if !buckets.contains(&node.id) && !in_block_list {
-                                        log::trace!(
-                                            target: "yiilian_dht::transaction::get_peers",
-                                            "[{}] Node (id: {:?}, {:?}) is a candidate for buckets",
-                                            self.local_addr.port(), node.id, node.address
-                                        );
-                                        buckets.add(node.clone(), None).ok();
-                                    }
-                                }
-                            }
-
-                            if val.values.len() > 0 {
-                                for peer in val.values {
-                                    unique_peers.insert(peer);
-                                }
-                            }
+                let (token, nodes, received) = match request_result {
+                    Ok(Reply::Get(val)) => {
+                        let received = val.get_item(salt.clone());
+                        (val.token, val.nodes, received)
+                    }
+                    // 对方没有存储数据项
+                    Ok(Reply::GetPeers(val)) => (val.token, val.nodes, None),
+                    Ok(reply) => {
+                        log::trace!(
+                            target: "yiilian_dht::transaction::get_item",
+                            "[{}] Address {:?} got wrong packet type back: {:?}",
+                            self.local_addr.port(), dest_node.address, reply
+                        );
 
-                        }
-                        _ => {
-                            log::trace!(
-                                target: "yiilian_dht::transaction::get_peers",
-                                "[{}] Address {:?} got wrong packet type back: {:?}",
-                                self.local_addr.port(), dest_node.address, reply
+                        buckets.remove(&dest_node.id);
+                        let reply_error_block_duration_sec =
+                            self.ctx()?.settings().reply_error_block_duration_sec;
+
+                        self.ctx()?.routing_table()
+                            .lock()
+                            .expect_error("routing_table.lock() failed")
+                            .add_block_list(
+                                dest_node.address,
+                                Some(dest_node.id),
+                                Some(Duration::from_secs(reply_error_block_duration_sec)),
                             );
-
-                            buckets.remove(&dest_node.id);
-                            let reply_error_block_duration_sec =
-                                dht_ctx_settings(self.ctx_index).reply_error_block_duration_sec;
-
-                            dht_ctx_routing_tbl(self.ctx_index)
-                                .lock()
-                                .expect_error("dht_ctx_routing_tbl.lock() failed")
-                                .add_block_list(
-                                    dest_node.address,
-                                    Some(dest_node.id),
-                                    Some(Duration::from_secs(reply_error_block_duration_sec)),
-                                );
-                        }
-                    },
+                        continue;
+                    }
                     Err(error) => {
                         // 已在 send_query() 中加入了黑名单
                         buckets.remove(&dest_node.id);
                         log::trace!(
-                            target: "yiilian_dht::transaction::get_peers",
-                            "[{}] {:?} get_peers error: {}",
+                            target: "yiilian_dht::transaction::get_item",
+                            "[{}] {:?} get error: {}",
                             self.local_addr.port(), dest_node.address, error
                         );
+                        continue;
+                    }
+                };
+
+                responders.insert(GetPeersResponder::new(dest_node.clone(), token));
+
+                for node in nodes {
+                    let id_is_valid = node.id.is_valid_for_ip(
+                        &node.address.ip(),
+                        &self.ctx()?.routing_table()
+                            .lock()
+                            .expect_error("routing_table.lock() failed")
+                            .white_list,
+                    );
+
+                    if id_is_valid && node.address.port() > 0 {
+                        // 将获取的 nodes 加入到未验证 buckets 中
+                        self.ctx()?.routing_table()
+                            .lock()
+                            .expect_error("routing_table.lock() failed")
+                            .add_or_update(node.clone(), false)
+                            .ok();
+                    }
+
+                    let in_block_list = self.ctx()?.routing_table()
+                        .lock()
+                        .expect_error("routing_table.lock() failed")
+                        .is_blocked(&node.address);
+
+                    if !buckets.contains(&node.id) && !in_block_list {
+                        buckets.add(node, None).ok();
+                    }
+                }
+
+                if let Some(received) = received {
+                    if !received.is_valid_for(&target) {
+                        log::trace!(
+                            target: "yiilian_dht::transaction::get_item",
+                            "[{}] Address {:?} replied an invalid item for target {}",
+                            self.local_addr.port(), dest_node.address, target
+                        );
+                        continue;
+                    }
+
+                    let is_newer = match (&item, &received) {
+                        (Some(Item::Mutable(current)), Item::Mutable(received)) => {
+                            received.seq > current.seq
+                        }
+                        (Some(_), _) => false,
+                        (None, _) => true,
+                    };
+
+                    if is_newer {
+                        item = Some(received);
                     }
                 }
             }
 
             // 确保我们下一次的发送至少间隔 1 秒
             let send_next_query_interval_sec =
-                dht_ctx_settings(self.ctx_index).send_next_query_interval_sec;
+                self.ctx()?.settings().send_next_query_interval_sec;
             tokio::time::sleep(Duration::from_secs(send_next_query_interval_sec)).await;
         }
 
-        Ok(GetPeersResult::new(
-            info_hash,
-            unique_peers.into_iter().collect(),
+        Ok(GetItemResult::new(
+            target,
+            item,
             responders.into_iter().collect(),
         ))
     }
 
-    /// Announce that you are a peer for a specific info_hash, returning the nodes
-    /// that were successfully announced to.
+    /// 向离 target 最近的节点写入数据项（BEP44），返回写入成功的节点
     ///
     /// # Arguments
-    /// * `info_hash` - Id of the torrent
-    /// * `port` - optional port that other peers should use to contact your peer.
-    /// If omitted, `implied_port` will be set true on the announce messages and
-    /// * `timeout` - the maximum amount of time that will be spent searching for
-    /// peers close to `info_hash` before announcing to them. This means that this
-    /// function can actually take a bit longer than `timeout`, since it will take
-    /// a moment after `timeout` has elapsed to announce to the nodes.
-    pub(crate) async fn announce_peer(
+    /// * `item` - 写入的数据项，可变数据项需要已签名
+    /// * `cas` - 可选的 compare and swap 参数，只有对方存储的 seq 等于该值时才会覆盖
+    pub(crate) async fn put_item(
         &self,
-        info_hash: Id,
-        port: Option<u16>,
+        item: Item,
+        cas: Option<i64>,
     ) -> Result<Vec<Node>, Error> {
         let mut to_ret = Vec::new();
+        let target = item.target();
+        let salt = match &item {
+            Item::Mutable(item) => item.salt.clone(),
+            Item::Immutable(_) => None,
+        };
 
-        // Figure out which nodes we want to announce to
-        let get_peers_result = self.get_peers(info_hash.clone(), false).await?;
+        // 本机也保存一份
+        self.ctx()?.item_manager()
+            .lock()
+            .expect_error("item_manager.lock() failed")
+            .put(target, item.clone());
+
+        // 通过 get 找到需要写入的节点，并获取它们的 token
+        let get_item_result = self.get_item(target, salt).await?;
 
         log::trace!(
-            target:"yiilian_dht::transaction::announce_peer",
-            "[{}] {} nodes responded to get_peers",
-            self.local_addr.port(), get_peers_result.responders().len()
+            target:"yiilian_dht::transaction::put_item",
+            "[{}] {} nodes responded to get",
+            self.local_addr.port(), get_item_result.responders().len()
         );
 
-        // Prepare to send packets to the nearest 8 node
-        let todos = futures::stream::FuturesUnordered::new();
-        let bucket_size = dht_ctx_settings(self.ctx_index).bucket_size;
-        for responder in get_peers_result.responders().into_iter().take(bucket_size) {
-            let read_only = dht_ctx_settings(self.ctx_index).read_only;
-            let announce_peer = (
-                Query::AnnouncePeer(AnnouncePeer {
-                    t: TransactionId::from_random(),
-                    v: None,
-                    ip: None,
-                    ro: if read_only { Some(1) } else { None },
-                    id: responder.node().id.clone(),
-                    info_hash: info_hash.clone(),
-                    implied_port: if let Some(_) = port { Some(0) } else { Some(1) },
-                    port: port.unwrap_or(0),
-                    token: responder.token().to_owned(),
-                }),
-                responder.node().to_owned(),
-            );
-
-            todos.push(announce_peer);
-        }
-
-        // Execute the futures, handle their results
-        let send_query_timeout_sec = dht_ctx_settings(self.ctx_index).send_query_timeout_sec;
-        for (query, dest_node) in todos {
-            let request_result = dht_ctx_trans_mgr(self.ctx_index)
+        let local_id = self.ctx()?.state()
+            .read()
+            .expect_error("state.read() failed")
+            .get_local_id();
+        let bucket_size = self.ctx()?.settings().bucket_size;
+        let read_only = self.ctx()?.settings().read_only;
+        let send_query_timeout_sec = self.ctx()?.settings().send_query_timeout_sec;
+
+        for responder in get_item_result.responders().iter().take(bucket_size) {
+            let query = Query::Put(Put::new(
+                local_id,
+                responder.token().to_owned(),
+                item.clone(),
+                cas,
+                TransactionId::from_random(),
+                None,
+                None,
+                if read_only { Some(1) } else { None },
+            ));
+            let dest_node = responder.node().to_owned();
+
+            let request_result = self
                 .send_query(
                     query,
                     &dest_node.address,
@@ -1104,22 +1276,20 @@ impl TransactionManager {
                 .await;
 
             match request_result {
-                Ok(reply) => match reply {
-                    Reply::PingOrAnnounce(_) => {
-                        to_ret.push(dest_node);
-                    }
-                    _ => {
-                        log::trace!(
-                            target: "yiilian_dht::transaction::announce_peer",
-                            "[{}] Got wrong packet type back: {:?}",
-                            self.local_addr.port(), reply
-                        )
-                    }
-                },
+                Ok(Reply::PingOrAnnounce(_)) => {
+                    to_ret.push(dest_node);
+                }
+                Ok(reply) => {
+                    log::trace!(
+                        target: "yiilian_dht::transaction::put_item",
+                        "[{}] Got wrong packet type back: {:?}",
+                        self.local_addr.port(), reply
+                    )
+                }
                 Err(e) => {
                     log::debug!(
-                        target: "yiilian_dht::transaction::announce_peer",
-                        "[{}] Error sending announce_peer: {}",
+                        target: "yiilian_dht::transaction::put_item",
+                        "[{}] Error sending put: {}",
                         self.local_addr.port(), e
                     )
                 }
@@ -1129,10 +1299,85 @@ impl TransactionManager {
         Ok(to_ret)
     }
 
+    /// 向目标节点发出 sample_infohashes 请求（BEP51）
+    ///
+    /// 对方在响应中指定了再次请求的最小间隔 interval，间隔到期之前不会再向该节点发送请求
+    pub(crate) async fn sample_infohashes(
+        &self,
+        target_addr: SocketAddr,
+        target_id: Option<Id>,
+        target: Id,
+    ) -> Result<SampleInfoHashesReply, Error> {
+        let is_too_early = self
+            .sample_intervals
+            .lock()
+            .expect_error("sample_intervals.lock() failed")
+            .get(&target_addr)
+            .map(|next| *next > Instant::now())
+            .unwrap_or(false);
+        if is_too_early {
+            Err(Error::new_general(&format!(
+                "sample_infohashes interval of {} is not elapsed",
+                target_addr
+            )))?
+        }
+
+        let local_id = self.ctx()?.state()
+            .read()
+            .expect_error("state.read() failed")
+            .get_local_id();
+        let read_only = self.ctx()?.settings().read_only;
+        let send_query_timeout_sec = self.ctx()?.settings().send_query_timeout_sec;
+
+        let mut query = SampleInfoHashes::new(
+            local_id,
+            target,
+            TransactionId::from_random(),
+            None,
+            None,
+            if read_only { Some(1) } else { None },
+        );
+        query.want = self.lookup_want();
+
+        let reply = self
+            .send_query(
+                Query::SampleInfoHashes(query),
+                &target_addr,
+                target_id,
+                Some(Duration::from_secs(send_query_timeout_sec)),
+            )
+            .await?;
+
+        match reply {
+            Reply::SampleInfoHashes(reply) => {
+                let interval = reply.interval.clamp(0, MAX_SAMPLE_INTERVAL_SECS) as u64;
+                self.sample_intervals
+                    .lock()
+                    .expect_error("sample_intervals.lock() failed")
+                    .put(target_addr, Instant::now() + Duration::from_secs(interval));
+
+                log::trace!(
+                    target: "yiilian_dht::transaction::sample_infohashes",
+                    "[{}] Address {:?} got {} samples of {}, interval: {}",
+                    self.local_addr.port(), target_addr, reply.samples.len(), reply.num, interval
+                );
+
+                Ok(reply)
+            }
+            _ => Err(Error::new_frame(
+                None,
+                Some(format!(
+                    "Got wrong packet type back for sample_infohashes: {:?}",
+                    reply
+                )),
+            )),
+        }
+    }
+
     /// 每 10 秒清除一次创建时间在 10 秒前的请求事务
     pub async fn request_cleanup(&self) -> Result<(), Error> {
         let transaction_cleanup_interval_sec =
-            dht_ctx_settings(self.ctx_index).transaction_cleanup_interval_sec;
+            self.ctx()?.settings().transaction_cleanup_interval_sec;
         let mut interval = interval(Duration::from_secs(transaction_cleanup_interval_sec));
 
         loop {
@@ -1141,3 +1386,152 @@ impl TransactionManager {
         }
     }
 }
+
+/// 按 BEP44 校验 put 的数据项，stored 为本地已保存的同一 target 的数据项，
+/// 校验失败时返回 (错误码, 错误信息)
+fn check_put_item(item: &Item, cas: Option<i64>, stored: Option<&Item>) -> Result<(), (i64, String)> {
+    if Item::check_value_size(item.value()).is_err() {
+        return Err((VALUE_TOO_BIG, "Message (v field) too big".to_owned()));
+    }
+
+    let Item::Mutable(item) = item else {
+        return Ok(());
+    };
+
+    if item.salt.as_ref().is_some_and(|salt| salt.len() > MAX_SALT_SIZE) {
+        return Err((SALT_TOO_BIG, "Salt (salt field) too big".to_owned()));
+    }
+
+    if item.verify().is_err() {
+        return Err((INVALID_SIGNATURE, "Invalid signature".to_owned()));
+    }
+
+    let stored = match stored {
+        Some(Item::Mutable(stored)) => Some(stored),
+        _ => None,
+    };
+
+    // 没有已保存的数据项时，带 cas 的 put 同样视为不匹配
+    if let Some(cas) = cas {
+        if stored.map(|stored| stored.seq) != Some(cas) {
+            return Err((
+                CAS_MISMATCH,
+                format!("The CAS hash mismatched, cas: {}, current seq: {:?}", cas, stored.map(|stored| stored.seq)),
+            ));
+        }
+    }
+
+    if let Some(stored) = stored {
+        if item.seq < stored.seq || (item.seq == stored.seq && item.value != stored.value) {
+            return Err((
+                SEQ_LESS_THAN_CURRENT,
+                format!("Sequence number less than current, seq: {}, current seq: {}", item.seq, stored.seq),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Weak};
+
+    use yiilian_core::common::shutdown::create_shutdown;
+
+    use crate::{
+        common::SettingsBuilder,
+        dht::DhtBuilder,
+        item::{MutableItem, SigningKey},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ctx_dropped() {
+        // Context 已释放时返回错误而不是 panic
+        let transaction_manager = TransactionManager::new(
+            DhtHandle::new(Weak::new()),
+            "127.0.0.1:0".parse().unwrap(),
+            DhtMode::Normal,
+        );
+
+        assert!(transaction_manager.ping("127.0.0.1:6881".parse().unwrap(), None).await.is_err());
+        assert!(transaction_manager.ping_no_wait("127.0.0.1:6881".parse().unwrap(), None).await.is_err());
+        assert!(transaction_manager.request_cleanup().await.is_err());
+        assert!(transaction_manager.lookup_want().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handle_put() {
+        let (_shutdown_tx, shutdown_rx) = create_shutdown();
+        let home_dir = std::env::temp_dir().join("yiilian_test_handle_put");
+        fs::remove_dir_all(&home_dir).ok();
+        let settings = SettingsBuilder::new().routers(&Some(vec![])).build();
+        let dht = DhtBuilder::new("127.0.0.1:0".parse().unwrap(), shutdown_rx, None, home_dir.clone())
+            .settings(Some(settings))
+            .build()
+            .unwrap();
+        let ctx = dht.handle().ctx().unwrap();
+
+        let remote_addr: SocketAddr = "127.0.0.2:6881".parse().unwrap();
+        let token_secret = ctx.state().read().unwrap().token_secret.clone();
+        let token: Bytes = calculate_token(&remote_addr, token_secret).to_vec().into();
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let mutable = |value: &'static str, seq: i64| {
+            Item::Mutable(MutableItem::sign(&signing_key, value.into(), None, seq).unwrap())
+        };
+        let put = |token: &Bytes, item: Item, cas: Option<i64>| {
+            Put::new(Id::from_random(&mut rand::thread_rng()), token.clone(), item, cas, TransactionId::from_random(), None, None, None)
+        };
+        let error_code = |body: &BodyKind| match body {
+            BodyKind::RError(error) => Some(error.e.0),
+            _ => None,
+        };
+
+        let handle_put = |query: Put| {
+            let ctx = ctx.clone();
+            async move { ctx.transaction_manager().handle_put(&query, &remote_addr).await.unwrap().0 }
+        };
+
+        // token 错误
+        let body = handle_put(put(&Bytes::from_static(b"bad"), mutable("v1", 1), None)).await;
+        assert_eq!(Some(PROTOCOL_ERROR), error_code(&body));
+
+        // 还没有保存数据项时带 cas
+        let body = handle_put(put(&token, mutable("v1", 1), Some(1))).await;
+        assert_eq!(Some(CAS_MISMATCH), error_code(&body));
+
+        let body = handle_put(put(&token, mutable("v1", 1), None)).await;
+        assert_eq!(None, error_code(&body));
+
+        // seq 小于当前值
+        let body = handle_put(put(&token, mutable("v0", 0), None)).await;
+        assert_eq!(Some(SEQ_LESS_THAN_CURRENT), error_code(&body));
+
+        // seq 相同但 v 不同，相同的 v 则可以重复 put
+        let body = handle_put(put(&token, mutable("v2", 1), None)).await;
+        assert_eq!(Some(SEQ_LESS_THAN_CURRENT), error_code(&body));
+        let body = handle_put(put(&token, mutable("v1", 1), None)).await;
+        assert_eq!(None, error_code(&body));
+
+        // cas 与当前 seq 不符
+        let body = handle_put(put(&token, mutable("v2", 2), Some(0))).await;
+        assert_eq!(Some(CAS_MISMATCH), error_code(&body));
+        let body = handle_put(put(&token, mutable("v2", 2), Some(1))).await;
+        assert_eq!(None, error_code(&body));
+
+        // 签名无效
+        let mut item = MutableItem::sign(&signing_key, "v3".into(), None, 3).unwrap();
+        item.value = "v4".into();
+        let body = handle_put(put(&token, Item::Mutable(item), None)).await;
+        assert_eq!(Some(INVALID_SIGNATURE), error_code(&body));
+
+        let target = mutable("v2", 2).target();
+        assert_eq!(Some(2), ctx.item_manager().lock().unwrap().get_seq(&target));
+
+        drop(ctx);
+        drop(dht);
+        fs::remove_dir_all(home_dir).ok();
+    }
+}
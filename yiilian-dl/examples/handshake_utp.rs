@@ -1,12 +1,9 @@
-use std::io::Write;
 use std::net::SocketAddr;
 
-use bytes::Bytes;
 use rand::thread_rng;
-use utp::UtpStream;
 use yiilian_core::common::error::Error;
-use yiilian_core::data::{BtHandshake, MESSAGE_EXTENSION_ENABLE};
-use yiilian_dl::bt::net::utp::read_all;
+use yiilian_core::net::tcp::{read_bt_handshake, send_bt_handshake};
+use yiilian_dl::bt::net::utp::UtpSocket;
 use yiilian_dht::common::Id;
 
 #[tokio::main]
@@ -18,23 +15,17 @@ async fn main() {
         .unwrap();
 
     let peer_id = Id::from_random(&mut thread_rng()).get_bytes();
-    let hs = BtHandshake::new(&MESSAGE_EXTENSION_ENABLE, &info_hash, &peer_id);
-    let hs: Bytes = hs.into();
 
-    let mut stream = UtpStream::connect(peer_address)
-        .unwrap();
+    let socket = UtpSocket::bind("0.0.0.0:0".parse().unwrap()).await.unwrap();
+    let mut stream = socket.connect(peer_address).await.unwrap();
 
     println!("connected");
 
-    stream
-        .write(&hs)
-        .map_err(|error| Error::new_net(Some(error.into()), None, Some(peer_address)))
-        .unwrap();
-    stream.flush().unwrap();
+    send_bt_handshake(&mut stream, &info_hash, &peer_id).await.unwrap();
 
     println!("write handshake");
 
-    let rst = read_all(&mut stream).unwrap();
+    let rst = read_bt_handshake(&mut stream).await.unwrap();
 
     println!("read handshake");
 
@@ -1,21 +1,30 @@
-use std::collections::BTreeMap;
-use std::fs::File;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
 use std::io::Write;
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use crate::bt::common::BtConfig;
+use crate::bt::download::DownloadSession;
+use crate::bt::net::utp::UtpSocket;
+use crate::bt::net::Transport;
 use crate::bt::peer_wire::PeerWire;
+use crate::bt::tracker::{AnnounceRequest, TrackerClient};
+use crate::event::Event;
 use bytes::Bytes;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
 use hex::ToHex;
 use rand::thread_rng;
-use tokio::net::TcpStream;
-use tokio::time::timeout;
+use tokio::net::{lookup_host, TcpStream};
+use tokio::sync::{broadcast, OnceCell};
+use tokio::time::{interval, timeout};
 use yiilian_core::common::error::Error;
 use yiilian_core::common::shutdown::ShutdownReceiver;
 use yiilian_core::common::util::hash_it;
-use yiilian_core::data::{BencodeData, Encode};
+use yiilian_core::data::{BencodeData, BtTorrent, Encode, Magnet};
 use yiilian_core::service::{FirewallLayer, FirewallService};
 use yiilian_dht::common::{Id, SettingsBuilder, ID_SIZE};
 use yiilian_dht::dht::Dht;
@@ -23,12 +32,33 @@ use yiilian_dht::dht::DhtBuilder;
 use yiilian_dht::service::RouterService;
 
 pub const TCP_CONNECT_TIMEOUT_SEC: u64 = 10;
+pub const UTP_CONNECT_TIMEOUT_SEC: u64 = 10;
 const FOLDER_NUM: u64 = 1000;
+/// 单个种子同时连接的 peer 数
+pub const MAX_PEER_CONNECTIONS: usize = 30;
+const EVENT_CHANNEL_SIZE: usize = 1024;
+/// tit-for-tat 重新计算阻塞状态的间隔
+pub const CHOKE_INTERVAL_SEC: u64 = 10;
+/// 重新向 DHT 宣告做种资源的间隔
+pub const ANNOUNCE_INTERVAL_SEC: u64 = 30 * 60;
+/// 获取元数据时资源大小未知，向 tracker 报告非 0 的 left 以表明自己不是做种者
+const METADATA_LEFT: u64 = 1;
+/// 获取元数据时候选 peers（含 ut_pex 收到的）的上限
+const MAX_META_CANDIDATES: usize = 200;
+/// 做种会话的种子文件扩展名，保存在下载目录中，启动时恢复做种
+const SEED_FILE_EXT: &str = "torrent";
 
 pub struct BtDownloader {
     dht: Dht<FirewallService<RouterService>>,
     local_id: Bytes,
     download_dir: PathBuf,
+    download_port: u16,
+    event_tx: broadcast::Sender<Event>,
+    sessions: Mutex<HashMap<[u8; ID_SIZE], Arc<DownloadSession>>>,
+    trackers: Vec<String>,
+    tracker_client: TrackerClient,
+    /// TCP 连接失败时使用的 uTP socket，首次使用时绑定
+    utp_socket: OnceCell<UtpSocket>,
 }
 
 impl BtDownloader {
@@ -40,27 +70,210 @@ impl BtDownloader {
     ) -> Result<Self, Error> {
         let dht = create_dht(&config, shutdown_rx.clone(), home_dir)?;
         let local_id = Id::from_random(&mut thread_rng()).get_bytes();
+        let (event_tx, _) = broadcast::channel(EVENT_CHANNEL_SIZE);
         
-        Ok(BtDownloader {
+        let bt_downloader = BtDownloader {
             dht,
             local_id,
             download_dir,
-        })
+            download_port: config.download_port,
+            event_tx,
+            sessions: Mutex::new(HashMap::new()),
+            trackers: config.trackers.clone().unwrap_or_default(),
+            tracker_client: TrackerClient::default(),
+            utp_socket: OnceCell::new(),
+        };
+        bt_downloader.restore_seeds();
+
+        Ok(bt_downloader)
     }
 
     pub async fn run_loop(&self) {
-        self.dht.run_loop().await
+        tokio::join!(self.dht.run_loop(), self.choke_loop(), self.announce_loop());
     }
 
-    pub async fn fetch_meta_from_target(
+    /// 定期对所有会话执行 tit-for-tat（含乐观解除阻塞）
+    async fn choke_loop(&self) {
+        let mut choke_interval = interval(Duration::from_secs(CHOKE_INTERVAL_SEC));
+
+        loop {
+            choke_interval.tick().await;
+
+            for session in self.sessions() {
+                session.rechoke();
+            }
+        }
+    }
+
+    /// 定期向 DHT 重新宣告所有会话，启动时先宣告恢复的做种会话
+    async fn announce_loop(&self) {
+        let mut announce_interval = interval(Duration::from_secs(ANNOUNCE_INTERVAL_SEC));
+
+        loop {
+            announce_interval.tick().await;
+
+            for session in self.sessions() {
+                self.announce(session.info_hash()).await;
+            }
+        }
+    }
+
+    fn sessions(&self) -> Vec<Arc<DownloadSession>> {
+        self.sessions
+            .lock()
+            .expect("lock sessions")
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// 通过 DHT 宣告本节点在下载端口上提供该资源
+    async fn announce(&self, info_hash: &[u8; ID_SIZE]) {
+        match self
+            .dht
+            .announce_peer(Id::new(*info_hash), Some(self.download_port))
+            .await
+        {
+            Ok(nodes) => {
+                log::debug!(target:"yiilian_dl::bt::bt_downloader", "announce {} to {} nodes", info_hash.encode_hex::<String>(), nodes.len());
+            }
+            Err(error) => {
+                log::debug!(target:"yiilian_dl::bt::bt_downloader", "announce {} error: {}", info_hash.encode_hex::<String>(), error);
+            }
+        }
+    }
+
+    /// 获取或创建种子对应的会话，会话创建后一直保留用于做种
+    fn open_session(&self, torrent: &BtTorrent) -> Result<Arc<DownloadSession>, Error> {
+        let info_hash: [u8; ID_SIZE] = hex::decode(&torrent.info_hash)
+            .map_err(|error| Error::new_id(Some(error.into()), None))?
+            .try_into()
+            .map_err(|_| Error::new_id(None, Some(format!("invalid info_hash: {}", torrent.info_hash))))?;
+
+        let mut sessions = self.sessions.lock().expect("lock sessions");
+        if let Some(session) = sessions.get(&info_hash) {
+            return Ok(session.clone());
+        }
+
+        let session = Arc::new(DownloadSession::new(
+            &info_hash,
+            &torrent.info,
+            &self.download_dir,
+            Some(self.event_tx.clone()),
+        )?);
+        sessions.insert(info_hash, session.clone());
+
+        Ok(session)
+    }
+
+    /// 做种下载目录中已有的种子内容，只上传已校验的分片。内容完整时保存会话，重启后继续做种
+    pub async fn seed_torrent(&self, torrent: &BtTorrent) -> Result<(), Error> {
+        let session = self.open_session(torrent)?;
+        if session.is_complete() {
+            self.save_seed(torrent, session.info_hash())?;
+        }
+        self.announce(session.info_hash()).await;
+
+        Ok(())
+    }
+
+    /// 先写临时文件再改名，保存已完成会话的种子文件
+    fn save_seed(&self, torrent: &BtTorrent, info_hash: &[u8; ID_SIZE]) -> Result<(), Error> {
+        if torrent.raw.is_empty() {
+            Err(Error::new_general(&format!("torrent {} has no raw data", torrent.info_hash)))?
+        }
+
+        let seed_path = seed_file_path(&self.download_dir, info_hash);
+        let tmp_path = seed_path.with_extension(format!("{}.tmp", SEED_FILE_EXT));
+
+        fs::create_dir_all(&self.download_dir)
+            .map_err(|error| Error::new_file(Some(error.into()), None))?;
+        fs::write(&tmp_path, &torrent.raw)
+            .map_err(|error| Error::new_file(Some(error.into()), None))?;
+        fs::rename(&tmp_path, &seed_path)
+            .map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+        Ok(())
+    }
+
+    /// 恢复下载目录中保存的做种会话，会话在 run_loop 开始时被宣告
+    fn restore_seeds(&self) {
+        let entries = match fs::read_dir(&self.download_dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !is_seed_file(&path) {
+                continue;
+            }
+
+            let rst = fs::read(&path)
+                .map_err(|error| Error::new_file(Some(error.into()), None))
+                .and_then(|data| BtTorrent::try_from(&data[..]))
+                .and_then(|torrent| self.open_session(&torrent));
+
+            match rst {
+                Ok(session) => {
+                    log::debug!(target:"yiilian_dl::bt::bt_downloader", "restore seed {}, complete: {}", session.info_hash().encode_hex::<String>(), session.is_complete());
+                }
+                Err(error) => {
+                    log::debug!(target:"yiilian_dl::bt::bt_downloader", "restore seed {:?} error: {}", path, error);
+                }
+            }
+        }
+    }
+
+    pub fn has_session(&self, info_hash: &[u8; ID_SIZE]) -> bool {
+        self.sessions
+            .lock()
+            .expect("lock sessions")
+            .contains_key(info_hash)
+    }
+
+    /// 在后台任务中与已完成握手的入站 peer 交换分片
+    pub fn serve_peer<S: Transport>(&self, stream: S, info_hash: &[u8; ID_SIZE]) -> Result<(), Error> {
+        let session = match self.sessions.lock().expect("lock sessions").get(info_hash) {
+            Some(session) => session.clone(),
+            None => {
+                let info_str: String = info_hash.encode_hex();
+                Err(Error::new_not_found(&format!("not found session: {}", info_str)))?
+            }
+        };
+        let local_id = self.local_id.clone();
+
+        tokio::spawn(async move {
+            if let Err(error) = PeerWire::new()
+                .exchange_pieces(stream, &session, &local_id, true)
+                .await
+            {
+                log::trace!(target:"yiilian_dl::bt::bt_downloader", "serve_peer: {:?}", error);
+            }
+        });
+
+        Ok(())
+    }
+
+    pub async fn fetch_meta_from_target<S: Transport>(
         &self,
-        stream: TcpStream,
+        stream: S,
         info_hash: &[u8; ID_SIZE],
         is_hook: bool,
     ) -> Result<BTreeMap<Bytes, BencodeData>, Error> {
+        self.fetch_meta_with_pex(stream, info_hash, is_hook).await.0
+    }
+
+    /// 获取元数据，同时返回对方通过 ut_pex 告知的 peers
+    async fn fetch_meta_with_pex<S: Transport>(
+        &self,
+        stream: S,
+        info_hash: &[u8; ID_SIZE],
+        is_hook: bool,
+    ) -> (Result<BTreeMap<Bytes, BencodeData>, Error>, Vec<SocketAddr>) {
         let peer_wire = PeerWire::new();
 
-        match peer_wire
+        let rst = match peer_wire
             .fetch_info(stream, info_hash, &self.local_id, is_hook)
             .await
         {
@@ -69,7 +282,9 @@ impl BtDownloader {
                 log::trace!(target:"yiilian_dl::bt::bt_downloader", "{:?}", error);
                 Err(error)
             }
-        }
+        };
+
+        (rst, peer_wire.take_pex_peers())
     }
 
     pub async fn fetch_meta(
@@ -78,42 +293,98 @@ impl BtDownloader {
         blocked_addrs: &mut Vec<SocketAddr>,
         is_hook: bool,
     ) -> Result<BTreeMap<Bytes, BencodeData>, Error> {
-        let rst = self.dht.get_peers(Id::new(*info_hash)).await?;
-        
-        for peer in rst.peers() {
+        self.fetch_meta_from_peers(info_hash, &[], &[], blocked_addrs, is_hook).await
+    }
+
+    /// 先尝试 known_peers，再尝试从 DHT 和 tracker 获得的 peers。
+    /// 连接 peer 的同时 DHT 查找仍在进行，收到的 peers 依次加入候选列表
+    async fn fetch_meta_from_peers(
+        &self,
+        info_hash: &[u8; ID_SIZE],
+        known_peers: &[SocketAddr],
+        extra_trackers: &[String],
+        blocked_addrs: &mut Vec<SocketAddr>,
+        is_hook: bool,
+    ) -> Result<BTreeMap<Bytes, BencodeData>, Error> {
+        let mut peers: VecDeque<SocketAddr> = known_peers.iter().copied().collect();
+        let mut seen: HashSet<SocketAddr> = peers.iter().copied().collect();
+
+        let mut lookup = self.dht.lookup_peers(Id::new(*info_hash))?;
+        let tracker_peers = futures::stream::once(self.tracker_peers(info_hash, extra_trackers, METADATA_LEFT))
+            .flat_map(futures::stream::iter);
+        let mut found = Box::pin(futures::stream::select(lookup.peers(), tracker_peers));
+        let mut found_done = false;
+
+        loop {
+            let peer = match peers.pop_front() {
+                Some(peer) => peer,
+                None if found_done => break,
+                None => {
+                    match found.next().await {
+                        Some(peer) if seen.insert(peer) => peers.push_back(peer),
+                        Some(_) => {}
+                        None => found_done = true,
+                    }
+                    continue;
+                }
+            };
+
             if blocked_addrs.contains(&peer) {
-                
                 continue
             }
 
-            let stream = {
-                let tmp = timeout(Duration::from_secs(TCP_CONNECT_TIMEOUT_SEC), TcpStream::connect(peer)).await;
-                match tmp {
-                    Ok(val) => match val {
-                        Ok(stream) => stream,
-                        Err(error) => Err(Error::new_net(Some(error.into()), Some("Tcp connect in fetch_metdata".to_owned()), Some(*peer)))?,
+            let attempt = self.fetch_meta_from_peer(peer, info_hash, is_hook);
+            tokio::pin!(attempt);
+
+            let (rst, pex_peers) = loop {
+                tokio::select! {
+                    rst = &mut attempt => break rst,
+                    found_peer = found.next(), if !found_done => match found_peer {
+                        Some(found_peer) if seen.insert(found_peer) => peers.push_back(found_peer),
+                        Some(_) => {}
+                        None => found_done = true,
                     },
-                    Err(_) => Err(Error::new_timeout("Tcp connect timeout"))?,
                 }
             };
-            
-            match self.fetch_meta_from_target(stream, info_hash, is_hook).await {
+
+            // 将 ut_pex 收到的 peers 加入候选列表
+            for pex_peer in pex_peers {
+                if seen.len() >= MAX_META_CANDIDATES {
+                    break;
+                }
+                if seen.insert(pex_peer) {
+                    peers.push_back(pex_peer);
+                }
+            }
+
+            match rst {
                 Ok(rst) => return Ok(rst),
                 Err(_) => {
-                    blocked_addrs.push(*peer);
+                    blocked_addrs.push(peer);
                 },
             }
-            
-            // tokio::time::sleep(std::time::Duration::from_secs(1)).await;
         }
 
         let info_str: String =  info_hash.encode_hex();
         Err(Error::new_not_found(&format!("not found info_hash: {}", info_str)))
     }
 
-    pub async fn download_meta_from_target(
+    /// 连接 peer 并获取元数据，同时返回对方通过 ut_pex 告知的 peers
+    async fn fetch_meta_from_peer(
+        &self,
+        peer: SocketAddr,
+        info_hash: &[u8; ID_SIZE],
+        is_hook: bool,
+    ) -> (Result<BTreeMap<Bytes, BencodeData>, Error>, Vec<SocketAddr>) {
+        match self.connect_peer(peer).await {
+            Ok(stream) => self.fetch_meta_with_pex(stream, info_hash, is_hook).await,
+            Err(error) => (Err(error), vec![]),
+        }
+    }
+
+    pub async fn download_meta_from_target<S: Transport>(
         &self,
-        stream: TcpStream,
+        stream: S,
         info_hash: &[u8; ID_SIZE],
         is_hook: bool,
     ) -> Result<PathBuf, Error> {
@@ -188,11 +459,283 @@ impl BtDownloader {
         }
     }
 
+    /// 同时从 DHT 和 tracker 获取 peers，合并去重，DHT 的结果排在前面。
+    /// 只有在没有获得任何 peer 时才返回 DHT 的错误
+    pub async fn find_peers(
+        &self,
+        info_hash: &[u8; ID_SIZE],
+        extra_trackers: &[String],
+        left: u64,
+    ) -> Result<Vec<SocketAddr>, Error> {
+        let (dht_rst, tracker_peers) = tokio::join!(
+            self.dht.get_peers(Id::new(*info_hash)),
+            self.tracker_peers(info_hash, extra_trackers, left)
+        );
+
+        let mut peers: Vec<SocketAddr> = vec![];
+        let dht_error = match dht_rst {
+            Ok(rst) => {
+                peers.extend(rst.peers().iter());
+                None
+            }
+            Err(error) => Some(error),
+        };
+
+        for peer in tracker_peers {
+            if !peers.contains(&peer) {
+                peers.push(peer);
+            }
+        }
+
+        match dht_error {
+            Some(error) if peers.is_empty() => Err(error),
+            _ => Ok(peers),
+        }
+    }
+
+    /// 向 tracker（种子的 announce 或磁力链接的 tr，以及配置的公共 tracker）获取 peers，合并去重
+    async fn tracker_peers(
+        &self,
+        info_hash: &[u8; ID_SIZE],
+        extra_trackers: &[String],
+        left: u64,
+    ) -> Vec<SocketAddr> {
+        let mut trackers = self.trackers.clone();
+        for tracker in extra_trackers.iter().rev() {
+            if !tracker.is_empty() && !trackers.contains(tracker) {
+                trackers.insert(0, tracker.to_owned());
+            }
+        }
+
+        let peer_id: [u8; ID_SIZE] = self.local_id[..]
+            .try_into()
+            .expect("local_id len is invalid");
+        let req = AnnounceRequest::new(*info_hash, peer_id, self.download_port, left);
+        let tracker_tasks = trackers.iter().map(|tracker| {
+            // 总超时覆盖 BEP15 的全部重试
+            timeout(
+                self.tracker_client.max_duration(),
+                self.tracker_client.announce(tracker, &req),
+            )
+        });
+        let tracker_rsts = futures::future::join_all(tracker_tasks).await;
+
+        let mut peers: Vec<SocketAddr> = vec![];
+        for (tracker, rst) in trackers.iter().zip(tracker_rsts) {
+            match rst {
+                Ok(Ok(rsp)) => {
+                    for peer in rsp.peers {
+                        if !peers.contains(&peer) {
+                            peers.push(peer);
+                        }
+                    }
+                }
+                Ok(Err(error)) => {
+                    log::trace!(target:"yiilian_dl::bt::bt_downloader", "announce to {} error: {}", tracker, error);
+                }
+                Err(_) => {
+                    log::trace!(target:"yiilian_dl::bt::bt_downloader", "announce to {} timeout", tracker);
+                }
+            }
+        }
+
+        peers
+    }
+
+    /// 订阅下载进度事件
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.event_tx.subscribe()
+    }
+
+    /// 从 DHT 获取 peers 并下载种子内容到下载目录，已下载的分片会被跳过（断点续传）。
+    /// 下载完成后会向 DHT 宣告，会话继续保留用于做种
+    pub async fn download_torrent(
+        &self,
+        torrent: &BtTorrent,
+        blocked_addrs: &mut Vec<SocketAddr>,
+    ) -> Result<PathBuf, Error> {
+        self.download_torrent_from_peers(torrent, &[], std::slice::from_ref(&torrent.announce), blocked_addrs)
+            .await
+    }
+
+    /// 通过磁力链接下载：先获取元数据，再下载内容。
+    /// x.pe 中的 peers（域名先解析）最先尝试，tr 中的 tracker 与配置的公共 tracker 一起用于查找 peers
+    pub async fn download_from_magnet(
+        &self,
+        magnet: &Magnet,
+        blocked_addrs: &mut Vec<SocketAddr>,
+    ) -> Result<PathBuf, Error> {
+        let mut peers = magnet.peers.clone();
+        for peer_host in &magnet.peer_hosts {
+            match lookup_host(peer_host.as_str()).await {
+                Ok(addrs) => {
+                    for addr in addrs {
+                        if !peers.contains(&addr) {
+                            peers.push(addr);
+                        }
+                    }
+                }
+                Err(error) => {
+                    log::debug!(target:"yiilian_dl::bt::bt_downloader", "resolve x.pe {} error: {}", peer_host, error);
+                }
+            }
+        }
+
+        let info = self
+            .fetch_meta_from_peers(&magnet.info_hash, &peers, &magnet.trackers, blocked_addrs, false)
+            .await?;
+
+        let mut torrent: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
+        if let Some(announce) = magnet.trackers.first() {
+            torrent.insert("announce".into(), announce.as_bytes().to_owned().into());
+        }
+        torrent.insert("info".into(), info.into());
+        let torrent = BtTorrent::try_from(&torrent.encode()[..])?;
+
+        // 获取元数据失败的 peers 仍可能拥有分片
+        blocked_addrs.retain(|peer| !peers.contains(peer));
+
+        self.download_torrent_from_peers(&torrent, &peers, &magnet.trackers, blocked_addrs)
+            .await
+    }
+
+    async fn download_torrent_from_peers(
+        &self,
+        torrent: &BtTorrent,
+        known_peers: &[SocketAddr],
+        extra_trackers: &[String],
+        blocked_addrs: &mut Vec<SocketAddr>,
+    ) -> Result<PathBuf, Error> {
+        let session = self.open_session(torrent)?;
+        let info_hash = *session.info_hash();
+        let path = session.storage().root_path().to_path_buf();
+
+        if session.is_complete() {
+            self.register_seed(torrent, &info_hash);
+            return Ok(path);
+        }
+
+        let mut peers = known_peers.to_vec();
+        match self.find_peers(&info_hash, extra_trackers, session.left()).await {
+            Ok(rst) => {
+                for peer in rst {
+                    if !peers.contains(&peer) {
+                        peers.push(peer);
+                    }
+                }
+            }
+            Err(error) if peers.is_empty() => Err(error)?,
+            Err(_) => {}
+        }
+        let mut peers = peers
+            .iter()
+            .filter(|peer| !blocked_addrs.contains(peer))
+            .copied()
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        let mut tasks = FuturesUnordered::new();
+        loop {
+            while tasks.len() < MAX_PEER_CONNECTIONS {
+                match peers.next() {
+                    Some(peer) => tasks.push(self.download_from_target(peer, &session)),
+                    None => break,
+                }
+            }
+
+            match tasks.next().await {
+                Some((peer, Err(error))) => {
+                    log::trace!(target:"yiilian_dl::bt::bt_downloader", "{} {:?}", peer, error);
+                    blocked_addrs.push(peer);
+                }
+                Some((_, Ok(_))) => {}
+                None => break,
+            }
+
+            if session.is_complete() {
+                self.register_seed(torrent, &info_hash);
+                self.announce(&info_hash).await;
+                return Ok(path);
+            }
+        }
+
+        let info_str: String = info_hash.encode_hex();
+        Err(Error::new_not_found(&format!("download {} incomplete, no more peers", info_str)))
+    }
+
+    /// 保存失败不影响下载结果，只是重启后不再做种
+    fn register_seed(&self, torrent: &BtTorrent, info_hash: &[u8; ID_SIZE]) {
+        if let Err(error) = self.save_seed(torrent, info_hash) {
+            log::debug!(target:"yiilian_dl::bt::bt_downloader", "save seed {} error: {}", torrent.info_hash, error);
+        }
+    }
+
+    async fn download_from_target(
+        &self,
+        peer: SocketAddr,
+        session: &DownloadSession,
+    ) -> (SocketAddr, Result<(), Error>) {
+        let stream = match self.connect_peer(peer).await {
+            Ok(stream) => stream,
+            Err(error) => return (peer, Err(error)),
+        };
+
+        let rst = PeerWire::new()
+            .exchange_pieces(stream, session, &self.local_id, false)
+            .await;
+
+        (peer, rst)
+    }
+
+    async fn utp_socket(&self) -> Result<&UtpSocket, Error> {
+        self.utp_socket
+            .get_or_try_init(|| async {
+                // download_port 的 UDP 端口被占用时使用随机端口
+                match UtpSocket::bind(SocketAddr::from(([0, 0, 0, 0], self.download_port))).await {
+                    Ok(socket) => Ok(socket),
+                    Err(_) => UtpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 0))).await,
+                }
+            })
+            .await
+    }
+
+    /// 连接 peer，优先使用 TCP，失败后回退到 uTP
+    pub async fn connect_peer(&self, peer: SocketAddr) -> Result<Box<dyn Transport>, Error> {
+        let tcp_error = match timeout(Duration::from_secs(TCP_CONNECT_TIMEOUT_SEC), TcpStream::connect(peer)).await {
+            Ok(Ok(stream)) => return Ok(Box::new(stream)),
+            Ok(Err(error)) => Error::new_net(Some(error.into()), Some("Tcp connect".to_owned()), Some(peer)),
+            Err(_) => Error::new_timeout("Tcp connect timeout"),
+        };
+        log::trace!(target:"yiilian_dl::bt::bt_downloader", "{:?}, fallback to utp", tcp_error);
+
+        let utp_socket = self.utp_socket().await?;
+        match timeout(Duration::from_secs(UTP_CONNECT_TIMEOUT_SEC), utp_socket.connect(peer)).await {
+            Ok(Ok(stream)) => Ok(Box::new(stream)),
+            Ok(Err(error)) => Err(error),
+            Err(_) => Err(Error::new_timeout("Utp connect timeout")),
+        }
+    }
+
     pub fn local_id(&self) -> &Bytes {
         &self.local_id
     }
 }
 
+/// 与续传 bitfield 一样以 `.` 开头保存在下载目录中
+fn seed_file_path(download_dir: &Path, info_hash: &[u8; ID_SIZE]) -> PathBuf {
+    let info_str: String = info_hash.encode_hex();
+    download_dir.join(format!(".{}.{}", info_str, SEED_FILE_EXT))
+}
+
+fn is_seed_file(path: &Path) -> bool {
+    let is_hidden = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'));
+
+    is_hidden && path.extension().is_some_and(|ext| ext == SEED_FILE_EXT)
+}
+
 fn create_dht(
     config: &BtConfig,
     shutdown_rx: ShutdownReceiver,
@@ -237,3 +780,55 @@ fn create_dht(
 
     Ok(dht)
 }
+
+#[cfg(test)]
+mod tests {
+    use sha1::{Digest, Sha1};
+    use yiilian_core::common::shutdown::create_shutdown;
+
+    use crate::bt::common::DhtConfig;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_restore_seeds() {
+        let data: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let pieces: Vec<u8> = Sha1::digest(&data).to_vec();
+        let mut info: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
+        info.insert("length".into(), (data.len() as i64).into());
+        info.insert("name".into(), "seed".into());
+        info.insert("piece length".into(), 1024i64.into());
+        info.insert("pieces".into(), pieces.into());
+        let mut torrent: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
+        torrent.insert("info".into(), info.into());
+        let torrent = BtTorrent::try_from(&torrent.encode()[..]).unwrap();
+        let info_hash: [u8; ID_SIZE] = hex::decode(&torrent.info_hash).unwrap().try_into().unwrap();
+
+        let download_dir = std::env::temp_dir().join("yiilian_dl_test_restore_seeds");
+        let home_dir = download_dir.join("home");
+        fs::remove_dir_all(&download_dir).ok();
+
+        // 准备已下载完成的内容
+        let setup = DownloadSession::new(&info_hash, &torrent.info, &download_dir, None).unwrap();
+        let mut bitfield = setup.have();
+        setup.storage().write_piece(0, &data).unwrap();
+        bitfield.set(0);
+        setup.storage().save_bitfield(&bitfield).unwrap();
+
+        let (_shutdown_tx, shutdown_rx) = create_shutdown();
+        let config = BtConfig::new(DhtConfig { routers: Some(vec![]), ..Default::default() }, 0);
+
+        let bt_downloader = BtDownloader::new(&config, download_dir.clone(), shutdown_rx.clone(), home_dir.clone()).unwrap();
+        assert!(!bt_downloader.has_session(&info_hash));
+        bt_downloader.seed_torrent(&torrent).await.unwrap();
+        assert!(seed_file_path(&download_dir, &info_hash).exists());
+        drop(bt_downloader);
+
+        // 重启后恢复做种
+        let bt_downloader = BtDownloader::new(&config, download_dir.clone(), shutdown_rx, home_dir).unwrap();
+        assert!(bt_downloader.has_session(&info_hash));
+
+        drop(bt_downloader);
+        fs::remove_dir_all(download_dir).ok();
+    }
+}
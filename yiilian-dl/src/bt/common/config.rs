@@ -30,13 +30,16 @@ impl DlConfig {
 pub struct BtConfig {
     pub dht: DhtConfig,
     pub download_port: u16,
+    /// 公共 tracker 列表（http:// 或 udp://），与 DHT 一起用于查找 peers
+    pub trackers: Option<Vec<String>>,
 }
 
 impl BtConfig {
     pub fn new(dht: DhtConfig, download_port: u16) -> Self {
         BtConfig {
             dht,
-            download_port
+            download_port,
+            trackers: None,
         }
     }
 
@@ -1,5 +1,7 @@
 mod ut_metadata;
+mod ut_pex;
 pub use ut_metadata::*;
+pub use ut_pex::*;
 
 use std::{collections::BTreeMap, net::{IpAddr, Ipv4Addr, Ipv6Addr}};
 
@@ -45,6 +47,7 @@ impl ExtensionHeader {
     pub fn new_ut_metadata() -> Self {
         let m: BTreeMap<Bytes, BencodeData> = map! {
             UT_METADATA_NAME.into() => (UT_METADATA_ID as i64).into(),
+            UT_PEX_NAME.into() => (UT_PEX_ID as i64).into(),
         };
         let m = Some(m);
         
@@ -211,7 +214,7 @@ mod tests {
     use bytes::Bytes;
     use yiilian_core::{data::BencodeData, map};
 
-    use super::{ExtensionHeader, UT_METADATA_NAME};
+    use super::{ExtensionHeader, UT_METADATA_NAME, UT_PEX_ID, UT_PEX_NAME};
 
     #[test]
     fn test_codec() {
@@ -243,5 +246,8 @@ mod tests {
         let ut_metadata_id = eh.get_extension_id(UT_METADATA_NAME).unwrap();
 
         assert_eq!(ut_metadata_id, 1);
+
+        let eh = ExtensionHeader::new_ut_metadata();
+        assert_eq!(Some(UT_PEX_ID as i32), eh.get_extension_id(UT_PEX_NAME));
     }
 }
\ No newline at end of file
@@ -0,0 +1,184 @@
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use bytes::Bytes;
+use yiilian_core::{
+    common::{
+        error::Error,
+        util::{bytes_to_sockaddr, sockaddr_to_bytes},
+    },
+    data::{decode, BencodeData, Encode},
+};
+
+use crate::bt::data::frame::PeerMessage;
+
+pub const UT_PEX_NAME: &str = "ut_pex";
+pub const UT_PEX_ID: u8 = 2;
+/// 单条 ut_pex 消息中 added / dropped 最多包含的 peer 数
+pub const MAX_PEX_PEERS: usize = 50;
+
+const COMPACT_V4_SIZE: usize = 6;
+const COMPACT_V6_SIZE: usize = 18;
+
+/// added.f 中的标志位
+pub const PEX_FLAG_ENCRYPTION: u8 = 0x01;
+pub const PEX_FLAG_SEED: u8 = 0x02;
+pub const PEX_FLAG_UTP: u8 = 0x04;
+pub const PEX_FLAG_HOLEPUNCH: u8 = 0x08;
+pub const PEX_FLAG_OUTGOING: u8 = 0x10;
+
+/// Peer Exchange (BEP11) 消息
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UtPex {
+    /// 新增的 peer 及其标志位
+    pub added: Vec<(SocketAddr, u8)>,
+    pub dropped: Vec<SocketAddr>,
+}
+
+impl UtPex {
+    pub fn new(added: Vec<(SocketAddr, u8)>, dropped: Vec<SocketAddr>) -> Self {
+        UtPex { added, dropped }
+    }
+
+    /// 新增的 peer 地址
+    pub fn added_peers(&self) -> Vec<SocketAddr> {
+        self.added.iter().map(|(addr, _)| *addr).collect()
+    }
+
+    pub fn into_peer_message(self, message_id: u8) -> PeerMessage {
+        let payload: Bytes = self.into();
+
+        PeerMessage::Extended {
+            ext_msg_id: message_id,
+            payload,
+        }
+    }
+}
+
+impl TryFrom<Bytes> for UtPex {
+    type Error = Error;
+
+    fn try_from(value: Bytes) -> Result<Self, Self::Error> {
+        let message = decode(&value)?;
+        let message = message.as_map()?;
+
+        let mut added = vec![];
+        for (key, flag_key, addr_size) in [
+            (&b"added"[..], &b"added.f"[..], COMPACT_V4_SIZE),
+            (&b"added6"[..], &b"added6.f"[..], COMPACT_V6_SIZE),
+        ] {
+            let peers = get_compact_peers(message, key, addr_size)?;
+            let flags = match message.get(flag_key) {
+                Some(flags) => flags.as_bstr()?.to_owned(),
+                None => Bytes::new(),
+            };
+
+            for (i, addr) in peers.into_iter().enumerate() {
+                added.push((addr, flags.get(i).copied().unwrap_or(0)));
+            }
+        }
+
+        let mut dropped = get_compact_peers(message, b"dropped", COMPACT_V4_SIZE)?;
+        dropped.extend(get_compact_peers(message, b"dropped6", COMPACT_V6_SIZE)?);
+
+        Ok(UtPex { added, dropped })
+    }
+}
+
+fn get_compact_peers(
+    message: &BTreeMap<Bytes, BencodeData>,
+    key: &[u8],
+    addr_size: usize,
+) -> Result<Vec<SocketAddr>, Error> {
+    let bytes = match message.get(key) {
+        Some(val) => val.as_bstr()?,
+        None => return Ok(vec![]),
+    };
+
+    if bytes.len() % addr_size != 0 {
+        Err(Error::new_frame(
+            None,
+            Some(format!(
+                "ut_pex {} len {} is not multiple of {}",
+                String::from_utf8_lossy(key),
+                bytes.len(),
+                addr_size
+            )),
+        ))?
+    }
+
+    bytes.chunks(addr_size).map(bytes_to_sockaddr).collect()
+}
+
+impl From<UtPex> for Bytes {
+    fn from(value: UtPex) -> Self {
+        let mut added = vec![];
+        let mut added_f = vec![];
+        let mut added6 = vec![];
+        let mut added6_f = vec![];
+        for (addr, flag) in &value.added {
+            if addr.is_ipv4() {
+                added.extend(sockaddr_to_bytes(addr));
+                added_f.push(*flag);
+            } else {
+                added6.extend(sockaddr_to_bytes(addr));
+                added6_f.push(*flag);
+            }
+        }
+
+        let mut dropped = vec![];
+        let mut dropped6 = vec![];
+        for addr in &value.dropped {
+            if addr.is_ipv4() {
+                dropped.extend(sockaddr_to_bytes(addr));
+            } else {
+                dropped6.extend(sockaddr_to_bytes(addr));
+            }
+        }
+
+        let mut rst: BTreeMap<Bytes, BencodeData> = BTreeMap::new();
+        rst.insert("added".into(), added.into());
+        rst.insert("added.f".into(), added_f.into());
+        rst.insert("dropped".into(), dropped.into());
+        if !added6.is_empty() {
+            rst.insert("added6".into(), added6.into());
+            rst.insert("added6.f".into(), added6_f.into());
+        }
+        if !dropped6.is_empty() {
+            rst.insert("dropped6".into(), dropped6.into());
+        }
+
+        rst.encode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use bytes::Bytes;
+
+    use super::{UtPex, PEX_FLAG_SEED, PEX_FLAG_UTP};
+
+    #[test]
+    fn test_codec() {
+        let message = UtPex::new(
+            vec![
+                ("1.2.3.4:80".parse().unwrap(), PEX_FLAG_SEED),
+                ("[::1]:81".parse().unwrap(), PEX_FLAG_UTP),
+            ],
+            vec!["5.6.7.8:82".parse().unwrap()],
+        );
+
+        let data: Bytes = message.clone().into();
+        let rst: UtPex = data.try_into().unwrap();
+        assert_eq!(message, rst);
+
+        let data: Bytes = b"d5:added6:\x01\x02\x03\x04\x00\x507:dropped0:e"[..].into();
+        let rst: UtPex = data.try_into().unwrap();
+        assert_eq!(vec!["1.2.3.4:80".parse::<SocketAddr>().unwrap()], rst.added_peers());
+        assert!(rst.dropped.is_empty());
+
+        let data: Bytes = b"d5:added5:\x01\x02\x03\x04\x00e"[..].into();
+        assert!(TryInto::<UtPex>::try_into(data).is_err());
+    }
+}
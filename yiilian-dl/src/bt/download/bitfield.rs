@@ -0,0 +1,116 @@
+use yiilian_core::common::error::Error;
+
+/// 分片位图，第 0 个分片对应首字节的最高位
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitfield {
+    bits: Vec<u8>,
+    len: usize,
+}
+
+impl Bitfield {
+    pub fn new(len: usize) -> Self {
+        Bitfield {
+            bits: vec![0; len.div_ceil(8)],
+            len,
+        }
+    }
+
+    /// 从 bitfield 消息载荷中解析位图，校验字节长度及末尾多余位必须为 0
+    pub fn from_bytes(bytes: &[u8], len: usize) -> Result<Self, Error> {
+        if bytes.len() != len.div_ceil(8) {
+            Err(Error::new_frame(
+                None,
+                Some(format!(
+                    "bitfield len is invalid, expect {} bytes, got {}",
+                    len.div_ceil(8),
+                    bytes.len()
+                )),
+            ))?
+        }
+
+        if !len.is_multiple_of(8) {
+            let spare_mask = 0xffu8 >> (len % 8);
+            if bytes[bytes.len() - 1] & spare_mask != 0 {
+                Err(Error::new_frame(
+                    None,
+                    Some("bitfield spare bits are not cleared".to_owned()),
+                ))?
+            }
+        }
+
+        Ok(Bitfield {
+            bits: bytes.to_vec(),
+            len,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn has(&self, index: usize) -> bool {
+        if index >= self.len {
+            return false;
+        }
+
+        self.bits[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+
+    pub fn set(&mut self, index: usize) {
+        if index < self.len {
+            self.bits[index / 8] |= 0x80 >> (index % 8);
+        }
+    }
+
+    pub fn unset(&mut self, index: usize) {
+        if index < self.len {
+            self.bits[index / 8] &= !(0x80 >> (index % 8));
+        }
+    }
+
+    /// 已置位的分片数
+    pub fn count(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.count() == self.len
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bitfield;
+
+    #[test]
+    fn test_set_and_parse() {
+        let mut bf = Bitfield::new(10);
+        bf.set(0);
+        bf.set(9);
+        bf.set(10);
+
+        assert_eq!(&[0x80, 0x40], bf.as_bytes());
+        assert_eq!(2, bf.count());
+        assert!(bf.has(9));
+        assert!(!bf.has(10));
+
+        let parsed = Bitfield::from_bytes(bf.as_bytes(), 10).unwrap();
+        assert_eq!(bf, parsed);
+
+        bf.unset(0);
+        assert!(!bf.has(0));
+
+        // 多余位未清零
+        assert!(Bitfield::from_bytes(&[0x80, 0x20], 10).is_err());
+        // 长度不符
+        assert!(Bitfield::from_bytes(&[0x80], 10).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashSet;
+
+use rand::{seq::SliceRandom, thread_rng};
+
+/// 按速率解除阻塞的 peer 数（不含乐观解除阻塞）
+pub const UNCHOKE_SLOTS: usize = 3;
+/// 每隔多少轮更换一次乐观解除阻塞的 peer
+pub const OPTIMISTIC_UNCHOKE_ROUNDS: u32 = 3;
+
+/// 一轮 rechoke 中 peer 的统计
+#[derive(Debug, Clone)]
+pub struct PeerRate {
+    pub peer_id: u64,
+    pub interested: bool,
+    /// 本轮的传输字节数：下载中为从对方下载的字节数，做种时为上传给对方的字节数
+    pub rate: u64,
+}
+
+/// tit-for-tat 阻塞算法：对感兴趣的 peer 按速率解除阻塞前 UNCHOKE_SLOTS 个，
+/// 另外随机乐观解除阻塞一个，使新连接的 peer 有机会证明自己的速率
+#[derive(Debug, Default)]
+pub struct Choker {
+    round: u32,
+    optimistic: Option<u64>,
+}
+
+impl Choker {
+    pub fn new() -> Self {
+        Choker::default()
+    }
+
+    pub fn optimistic(&self) -> Option<u64> {
+        self.optimistic
+    }
+
+    /// 返回本轮解除阻塞的 peer_id 集合
+    pub fn rechoke(&mut self, peers: &[PeerRate]) -> HashSet<u64> {
+        let mut interested: Vec<&PeerRate> = peers.iter().filter(|p| p.interested).collect();
+        interested.sort_by(|a, b| b.rate.cmp(&a.rate).then(a.peer_id.cmp(&b.peer_id)));
+
+        let mut rst: HashSet<u64> = interested
+            .iter()
+            .take(UNCHOKE_SLOTS)
+            .map(|p| p.peer_id)
+            .collect();
+
+        let candidates: Vec<u64> = interested
+            .iter()
+            .map(|p| p.peer_id)
+            .filter(|peer_id| !rst.contains(peer_id))
+            .collect();
+
+        let keep_optimistic = !self.round.is_multiple_of(OPTIMISTIC_UNCHOKE_ROUNDS)
+            && self
+                .optimistic
+                .map(|peer_id| candidates.contains(&peer_id))
+                .unwrap_or(false);
+        if !keep_optimistic {
+            self.optimistic = candidates.choose(&mut thread_rng()).copied();
+        }
+
+        if let Some(peer_id) = self.optimistic {
+            rst.insert(peer_id);
+        }
+        self.round = self.round.wrapping_add(1);
+
+        rst
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bt::download::{Choker, PeerRate, UNCHOKE_SLOTS};
+
+    #[test]
+    fn test_rechoke() {
+        let mut peers: Vec<PeerRate> = (0..6u64)
+            .map(|peer_id| PeerRate {
+                peer_id,
+                interested: true,
+                rate: peer_id * 100,
+            })
+            .collect();
+        // 不感兴趣的 peer 不会被解除阻塞
+        peers.push(PeerRate {
+            peer_id: 100,
+            interested: false,
+            rate: 10_000,
+        });
+
+        let mut choker = Choker::new();
+        let rst = choker.rechoke(&peers);
+
+        assert_eq!(UNCHOKE_SLOTS + 1, rst.len());
+        assert!(rst.contains(&5) && rst.contains(&4) && rst.contains(&3));
+        assert!(!rst.contains(&100));
+
+        // 乐观解除阻塞的 peer 在若干轮内保持不变
+        let optimistic = choker.optimistic().unwrap();
+        assert!(optimistic < 3);
+        let rst = choker.rechoke(&peers);
+        assert!(rst.contains(&optimistic));
+        assert_eq!(Some(optimistic), choker.optimistic());
+    }
+}
@@ -0,0 +1,11 @@
+mod bitfield;
+mod choker;
+mod piece_picker;
+mod storage;
+mod session;
+
+pub use bitfield::*;
+pub use choker::*;
+pub use piece_picker::*;
+pub use storage::*;
+pub use session::*;
@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+
+use super::Bitfield;
+
+/// 单次请求的块大小 16KiB
+pub const BLOCK_SIZE: u32 = 16 * 1024;
+
+/// 一次 Request 消息请求的块
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockRequest {
+    pub index: u32,
+    pub begin: u32,
+    pub length: u32,
+}
+
+impl BlockRequest {
+    pub fn new(index: u32, begin: u32, length: u32) -> Self {
+        BlockRequest {
+            index,
+            begin,
+            length,
+        }
+    }
+}
+
+/// 收到块数据后的处理结果
+#[derive(Debug, PartialEq, Eq)]
+pub enum BlockResult {
+    /// 非预期的块（分片已完成、偏移或长度错误、重复块）
+    Ignored,
+    Accepted,
+    /// 分片的所有块都已收到，返回完整分片数据（尚未校验）
+    PieceCompleted(Bytes),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockState {
+    Missing,
+    /// 已请求，值为请求该块的 peer 数（endgame 阶段会大于 1）
+    Requested(u32),
+    Received,
+}
+
+#[derive(Debug)]
+struct PartialPiece {
+    blocks: Vec<BlockState>,
+    data: Vec<u8>,
+    received: usize,
+}
+
+/// 分片选择器：优先补全已开始的分片，其余按最稀有优先（rarest-first）选择，
+/// 所有块都已请求后进入 endgame，向其它 peer 重复请求未到达的块
+#[derive(Debug)]
+pub struct PiecePicker {
+    piece_length: u32,
+    total_length: u64,
+    have: Bitfield,
+    availability: Vec<u32>,
+    partial: BTreeMap<u32, PartialPiece>,
+}
+
+impl PiecePicker {
+    pub fn new(piece_length: u32, total_length: u64, have: Bitfield) -> Self {
+        let availability = vec![0; have.len()];
+
+        PiecePicker {
+            piece_length,
+            total_length,
+            have,
+            availability,
+            partial: BTreeMap::new(),
+        }
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.have.len()
+    }
+
+    pub fn piece_size(&self, index: u32) -> u32 {
+        let begin = index as u64 * self.piece_length as u64;
+        (self.total_length - begin).min(self.piece_length as u64) as u32
+    }
+
+    pub fn have(&self) -> &Bitfield {
+        &self.have
+    }
+
+    pub fn has_piece(&self, index: u32) -> bool {
+        self.have.has(index as usize)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.have.is_full()
+    }
+
+    /// 已校验通过的字节数
+    pub fn verified_bytes(&self) -> u64 {
+        (0..self.piece_count() as u32)
+            .filter(|index| self.has_piece(*index))
+            .map(|index| self.piece_size(index) as u64)
+            .sum()
+    }
+
+    pub fn add_peer(&mut self, bitfield: &Bitfield) {
+        for (index, count) in self.availability.iter_mut().enumerate() {
+            if bitfield.has(index) {
+                *count += 1;
+            }
+        }
+    }
+
+    pub fn remove_peer(&mut self, bitfield: &Bitfield) {
+        for (index, count) in self.availability.iter_mut().enumerate() {
+            if bitfield.has(index) {
+                *count = count.saturating_sub(1);
+            }
+        }
+    }
+
+    pub fn add_have(&mut self, index: u32) {
+        if let Some(count) = self.availability.get_mut(index as usize) {
+            *count += 1;
+        }
+    }
+
+    /// 分片校验通过后标记为已拥有
+    pub fn mark_have(&mut self, index: u32) {
+        self.partial.remove(&index);
+        self.have.set(index as usize);
+    }
+
+    /// 所有未完成的分片都已开始下载，且没有未请求的块
+    pub fn is_endgame(&self) -> bool {
+        let missing = self.piece_count() - self.have.count();
+
+        missing > 0
+            && missing == self.partial.len()
+            && self
+                .partial
+                .values()
+                .all(|p| p.blocks.iter().all(|b| *b != BlockState::Missing))
+    }
+
+    /// 为 peer 选择最多 max 个待请求的块，in_flight 是该 peer 已发出的请求
+    pub fn pick(
+        &mut self,
+        peer: &Bitfield,
+        in_flight: &[BlockRequest],
+        max: usize,
+    ) -> Vec<BlockRequest> {
+        let mut rst = vec![];
+        if max == 0 {
+            return rst;
+        }
+
+        // 先补全已开始的分片
+        let indexes: Vec<u32> = self
+            .partial
+            .keys()
+            .filter(|index| peer.has(**index as usize))
+            .copied()
+            .collect();
+        for index in indexes {
+            self.pick_from_piece(index, max, &mut rst);
+            if rst.len() >= max {
+                return rst;
+            }
+        }
+
+        // 再按最稀有优先开始新的分片
+        let mut candidates: Vec<u32> = (0..self.piece_count() as u32)
+            .filter(|index| {
+                peer.has(*index as usize)
+                    && !self.has_piece(*index)
+                    && !self.partial.contains_key(index)
+            })
+            .collect();
+        candidates.sort_by_key(|index| (self.availability[*index as usize], *index));
+
+        for index in candidates {
+            let piece_size = self.piece_size(index);
+            let block_num = piece_size.div_ceil(BLOCK_SIZE) as usize;
+            self.partial.insert(
+                index,
+                PartialPiece {
+                    blocks: vec![BlockState::Missing; block_num],
+                    data: vec![0; piece_size as usize],
+                    received: 0,
+                },
+            );
+
+            self.pick_from_piece(index, max, &mut rst);
+            if rst.len() >= max {
+                return rst;
+            }
+        }
+
+        // endgame：重复请求其它 peer 尚未返回的块
+        if rst.is_empty() && self.is_endgame() {
+            for (index, piece) in self.partial.iter_mut() {
+                if !peer.has(*index as usize) {
+                    continue;
+                }
+
+                for (block_index, state) in piece.blocks.iter_mut().enumerate() {
+                    if let BlockState::Requested(n) = state {
+                        let req = block_request(*index, block_index, piece.data.len() as u32);
+                        if !in_flight.contains(&req) {
+                            *n += 1;
+                            rst.push(req);
+                            if rst.len() >= max {
+                                return rst;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        rst
+    }
+
+    fn pick_from_piece(&mut self, index: u32, max: usize, rst: &mut Vec<BlockRequest>) {
+        if let Some(piece) = self.partial.get_mut(&index) {
+            let piece_size = piece.data.len() as u32;
+
+            for (block_index, state) in piece.blocks.iter_mut().enumerate() {
+                if rst.len() >= max {
+                    break;
+                }
+
+                if *state == BlockState::Missing {
+                    *state = BlockState::Requested(1);
+                    rst.push(block_request(index, block_index, piece_size));
+                }
+            }
+        }
+    }
+
+    /// 请求被取消（peer choke 或断开），块重新变为可请求
+    pub fn cancel(&mut self, req: &BlockRequest) {
+        if let Some(piece) = self.partial.get_mut(&req.index) {
+            let block_index = (req.begin / BLOCK_SIZE) as usize;
+            if let Some(state) = piece.blocks.get_mut(block_index) {
+                if let BlockState::Requested(n) = state {
+                    *state = if *n > 1 {
+                        BlockState::Requested(*n - 1)
+                    } else {
+                        BlockState::Missing
+                    };
+                }
+            }
+        }
+    }
+
+    pub fn on_block(&mut self, index: u32, begin: u32, block: &[u8]) -> BlockResult {
+        let piece = if let Some(piece) = self.partial.get_mut(&index) {
+            piece
+        } else {
+            return BlockResult::Ignored;
+        };
+
+        if !begin.is_multiple_of(BLOCK_SIZE) {
+            return BlockResult::Ignored;
+        }
+
+        let block_index = (begin / BLOCK_SIZE) as usize;
+        let piece_size = piece.data.len() as u32;
+        match piece.blocks.get(block_index) {
+            Some(BlockState::Received) | None => return BlockResult::Ignored,
+            _ => {}
+        }
+        if block_request(index, block_index, piece_size).length as usize != block.len() {
+            return BlockResult::Ignored;
+        }
+
+        let begin = begin as usize;
+        piece.data[begin..begin + block.len()].copy_from_slice(block);
+        piece.blocks[block_index] = BlockState::Received;
+        piece.received += 1;
+
+        if piece.received == piece.blocks.len() {
+            let piece = self.partial.remove(&index).expect("partial piece must exist");
+            BlockResult::PieceCompleted(piece.data.into())
+        } else {
+            BlockResult::Accepted
+        }
+    }
+}
+
+fn block_request(index: u32, block_index: usize, piece_size: u32) -> BlockRequest {
+    let begin = block_index as u32 * BLOCK_SIZE;
+    let length = (piece_size - begin).min(BLOCK_SIZE);
+
+    BlockRequest::new(index, begin, length)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bt::download::{Bitfield, BlockRequest, BlockResult, PiecePicker, BLOCK_SIZE};
+
+    fn full_bitfield(len: usize) -> Bitfield {
+        let mut bf = Bitfield::new(len);
+        (0..len).for_each(|i| bf.set(i));
+        bf
+    }
+
+    #[test]
+    fn test_rarest_first() {
+        let mut picker = PiecePicker::new(BLOCK_SIZE, BLOCK_SIZE as u64 * 3, Bitfield::new(3));
+
+        let peer1 = full_bitfield(3);
+        let mut peer2 = Bitfield::new(3);
+        peer2.set(0);
+        peer2.set(2);
+        picker.add_peer(&peer1);
+        picker.add_peer(&peer2);
+
+        // 分片 1 只有一个 peer 拥有，最稀有
+        let rst = picker.pick(&peer1, &[], 1);
+        assert_eq!(vec![BlockRequest::new(1, 0, BLOCK_SIZE)], rst);
+
+        // 已开始的分片优先于新分片，但分片 1 已无可请求的块
+        let rst = picker.pick(&peer1, &[], 2);
+        assert_eq!(
+            vec![BlockRequest::new(0, 0, BLOCK_SIZE), BlockRequest::new(2, 0, BLOCK_SIZE)],
+            rst
+        );
+    }
+
+    #[test]
+    fn test_blocks_and_endgame() {
+        let total = BLOCK_SIZE as u64 + 100;
+        let mut picker = PiecePicker::new(BLOCK_SIZE * 2, total, Bitfield::new(1));
+        let peer = full_bitfield(1);
+        picker.add_peer(&peer);
+
+        let rst = picker.pick(&peer, &[], 10);
+        assert_eq!(
+            vec![BlockRequest::new(0, 0, BLOCK_SIZE), BlockRequest::new(0, BLOCK_SIZE, 100)],
+            rst
+        );
+        assert!(picker.is_endgame());
+
+        // 已发出的请求不会重复请求给同一个 peer
+        assert!(picker.pick(&peer, &rst, 10).is_empty());
+        // 其它 peer 可以重复请求
+        assert_eq!(rst, picker.pick(&peer, &[], 10));
+
+        // 长度错误的块被忽略
+        assert_eq!(BlockResult::Ignored, picker.on_block(0, BLOCK_SIZE, &[1; 99]));
+        assert_eq!(BlockResult::Accepted, picker.on_block(0, BLOCK_SIZE, &[1; 100]));
+        assert_eq!(BlockResult::Ignored, picker.on_block(0, BLOCK_SIZE, &[1; 100]));
+
+        match picker.on_block(0, 0, &vec![2; BLOCK_SIZE as usize]) {
+            BlockResult::PieceCompleted(data) => {
+                assert_eq!(total as usize, data.len());
+                assert_eq!(2, data[0]);
+                assert_eq!(1, data[total as usize - 1]);
+            }
+            _ => panic!("piece should be completed"),
+        }
+
+        picker.mark_have(0);
+        assert!(picker.is_complete());
+        assert_eq!(total, picker.verified_bytes());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut picker = PiecePicker::new(BLOCK_SIZE, BLOCK_SIZE as u64, Bitfield::new(1));
+        let peer = full_bitfield(1);
+
+        let rst = picker.pick(&peer, &[], 1);
+        picker.cancel(&rst[0]);
+
+        assert!(!picker.is_endgame());
+        assert_eq!(rst, picker.pick(&peer, &[], 1));
+    }
+}
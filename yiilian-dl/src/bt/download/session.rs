@@ -0,0 +1,271 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use bytes::Bytes;
+use tokio::sync::{broadcast, watch};
+use yiilian_core::{common::error::Error, data::MetaInfo};
+use yiilian_dht::common::{Id, ID_SIZE};
+
+use crate::event::Event;
+
+use super::{Bitfield, BlockRequest, BlockResult, Choker, PeerRate, PiecePicker, Storage};
+
+const HAVE_CHANNEL_SIZE: usize = 256;
+
+/// 会话中已连接 peer 的统计，由 choker 使用
+#[derive(Debug)]
+struct PeerEntry {
+    interested: bool,
+    choked: bool,
+    downloaded: u64,
+    uploaded: u64,
+    choke_tx: watch::Sender<bool>,
+}
+
+/// 单个种子的下载 / 做种会话，由多个 peer 连接共享
+#[derive(Debug)]
+pub struct DownloadSession {
+    info_hash: [u8; ID_SIZE],
+    storage: Storage,
+    picker: Mutex<PiecePicker>,
+    peers: Mutex<HashMap<u64, PeerEntry>>,
+    next_peer_id: AtomicU64,
+    choker: Mutex<Choker>,
+    have_tx: broadcast::Sender<u32>,
+    event_tx: Option<broadcast::Sender<Event>>,
+}
+
+impl DownloadSession {
+    /// 创建会话，从下载目录中读取续传 bitfield
+    pub fn new(
+        info_hash: &[u8; ID_SIZE],
+        meta: &MetaInfo,
+        download_dir: &Path,
+        event_tx: Option<broadcast::Sender<Event>>,
+    ) -> Result<Self, Error> {
+        let storage = Storage::new(meta, download_dir, info_hash)?;
+        let have = storage.load_bitfield();
+        let picker = PiecePicker::new(storage.piece_length() as u32, storage.total_length(), have);
+        let (have_tx, _) = broadcast::channel(HAVE_CHANNEL_SIZE);
+
+        Ok(DownloadSession {
+            info_hash: *info_hash,
+            storage,
+            picker: Mutex::new(picker),
+            peers: Mutex::new(HashMap::new()),
+            next_peer_id: AtomicU64::new(0),
+            choker: Mutex::new(Choker::new()),
+            have_tx,
+            event_tx,
+        })
+    }
+
+    pub fn info_hash(&self) -> &[u8; ID_SIZE] {
+        &self.info_hash
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.storage.piece_count()
+    }
+
+    pub fn have(&self) -> Bitfield {
+        self.picker.lock().expect("lock failed").have().clone()
+    }
+
+    pub fn has_piece(&self, index: u32) -> bool {
+        self.picker.lock().expect("lock failed").has_piece(index)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.picker.lock().expect("lock failed").is_complete()
+    }
+
+    /// 剩余未下载的字节数
+    pub fn left(&self) -> u64 {
+        self.storage.total_length() - self.picker.lock().expect("lock failed").verified_bytes()
+    }
+
+    /// 登记新连接的 peer，返回 peer_id 及用于接收阻塞状态（true 为阻塞）的通道
+    pub fn register_peer(&self) -> (u64, watch::Receiver<bool>) {
+        let peer_id = self.next_peer_id.fetch_add(1, Ordering::Relaxed);
+        let (choke_tx, choke_rx) = watch::channel(true);
+
+        self.peers.lock().expect("lock failed").insert(
+            peer_id,
+            PeerEntry {
+                interested: false,
+                choked: true,
+                downloaded: 0,
+                uploaded: 0,
+                choke_tx,
+            },
+        );
+
+        (peer_id, choke_rx)
+    }
+
+    /// peer 断开时，撤销其可用性统计并释放其未完成的请求
+    pub fn unregister_peer(&self, peer_id: u64, bitfield: &Bitfield, in_flight: &[BlockRequest]) {
+        self.peers.lock().expect("lock failed").remove(&peer_id);
+
+        let mut picker = self.picker.lock().expect("lock failed");
+        picker.remove_peer(bitfield);
+        in_flight.iter().for_each(|req| picker.cancel(req));
+    }
+
+    pub fn peer_count(&self) -> usize {
+        self.peers.lock().expect("lock failed").len()
+    }
+
+    /// 订阅本地新完成的分片，用于向 peer 发送 Have 消息
+    pub fn subscribe_have(&self) -> broadcast::Receiver<u32> {
+        self.have_tx.subscribe()
+    }
+
+    pub fn add_peer(&self, bitfield: &Bitfield) {
+        self.picker.lock().expect("lock failed").add_peer(bitfield)
+    }
+
+    pub fn remove_peer(&self, bitfield: &Bitfield) {
+        self.picker.lock().expect("lock failed").remove_peer(bitfield)
+    }
+
+    pub fn add_have(&self, index: u32) {
+        self.picker.lock().expect("lock failed").add_have(index)
+    }
+
+    pub fn set_interested(&self, peer_id: u64, interested: bool) {
+        if let Some(peer) = self.peers.lock().expect("lock failed").get_mut(&peer_id) {
+            peer.interested = interested;
+        }
+    }
+
+    pub fn add_downloaded(&self, peer_id: u64, len: u64) {
+        if let Some(peer) = self.peers.lock().expect("lock failed").get_mut(&peer_id) {
+            peer.downloaded += len;
+        }
+    }
+
+    pub fn add_uploaded(&self, peer_id: u64, len: u64) {
+        if let Some(peer) = self.peers.lock().expect("lock failed").get_mut(&peer_id) {
+            peer.uploaded += len;
+        }
+    }
+
+    /// 读取已校验分片中的块，用于上传
+    pub fn read_block(&self, index: u32, begin: u32, length: u32) -> Result<Bytes, Error> {
+        if !self.has_piece(index) {
+            Err(Error::new_not_found(&format!("piece {} is not verified", index)))?
+        }
+
+        self.storage.read_block(index, begin, length)
+    }
+
+    /// 执行一轮 tit-for-tat：下载中按对方给我们的下载量排序，做种时按上传量排序，
+    /// 然后通知阻塞状态改变的 peer 并清零本轮统计
+    pub fn rechoke(&self) {
+        let is_complete = self.is_complete();
+        let mut peers = self.peers.lock().expect("lock failed");
+
+        let rates: Vec<PeerRate> = peers
+            .iter()
+            .map(|(peer_id, peer)| PeerRate {
+                peer_id: *peer_id,
+                interested: peer.interested,
+                rate: if is_complete { peer.uploaded } else { peer.downloaded },
+            })
+            .collect();
+        let unchoked = self.choker.lock().expect("lock failed").rechoke(&rates);
+
+        for (peer_id, peer) in peers.iter_mut() {
+            let choked = !unchoked.contains(peer_id);
+            if choked != peer.choked {
+                peer.choked = choked;
+                let _ = peer.choke_tx.send(choked);
+            }
+
+            peer.downloaded = 0;
+            peer.uploaded = 0;
+        }
+    }
+
+    pub fn cancel(&self, in_flight: &[BlockRequest]) {
+        let mut picker = self.picker.lock().expect("lock failed");
+        in_flight.iter().for_each(|req| picker.cancel(req));
+    }
+
+    pub fn pick(&self, peer: &Bitfield, in_flight: &[BlockRequest], max: usize) -> Vec<BlockRequest> {
+        self.picker
+            .lock()
+            .expect("lock failed")
+            .pick(peer, in_flight, max)
+    }
+
+    /// 处理收到的块，分片完整时校验并写盘，校验通过返回 true
+    pub fn on_block(&self, index: u32, begin: u32, block: &[u8]) -> Result<bool, Error> {
+        let rst = self
+            .picker
+            .lock()
+            .expect("lock failed")
+            .on_block(index, begin, block);
+
+        let data = match rst {
+            BlockResult::PieceCompleted(data) => data,
+            _ => return Ok(false),
+        };
+
+        if !self.storage.verify_piece(index, &data) {
+            log::debug!(target: "yiilian_dl::bt::download", "piece {} hash failed", index);
+            self.send_event(Event::PieceHashFailed {
+                info_hash: Id::new(self.info_hash),
+                index,
+            });
+
+            return Ok(false);
+        }
+
+        self.storage.write_piece(index, &data)?;
+
+        let (progress, is_complete) = {
+            let mut picker = self.picker.lock().expect("lock failed");
+            picker.mark_have(index);
+            self.storage.save_bitfield(picker.have())?;
+
+            let progress = Event::DownloadProgress {
+                info_hash: Id::new(self.info_hash),
+                verified_pieces: picker.have().count(),
+                total_pieces: picker.piece_count(),
+                verified_bytes: picker.verified_bytes(),
+                total_bytes: self.storage.total_length(),
+            };
+
+            (progress, picker.is_complete())
+        };
+
+        // 没有连接的 peer 时忽略
+        let _ = self.have_tx.send(index);
+        self.send_event(progress);
+        if is_complete {
+            self.send_event(Event::CompleteDownload(Id::new(self.info_hash)));
+        }
+
+        Ok(true)
+    }
+
+    fn send_event(&self, event: Event) {
+        if let Some(event_tx) = &self.event_tx {
+            // 没有订阅者时忽略
+            let _ = event_tx.send(event);
+        }
+    }
+}
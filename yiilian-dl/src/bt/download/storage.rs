@@ -0,0 +1,481 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use bytes::Bytes;
+use hex::ToHex;
+use sha1::{Digest, Sha1};
+use yiilian_core::{common::error::Error, data::MetaInfo};
+
+use super::Bitfield;
+
+const PIECE_HASH_LEN: usize = 20;
+const RESUME_FILE_EXT: &str = "bitfield";
+
+#[derive(Debug, Clone)]
+struct StorageFile {
+    path: PathBuf,
+    /// 文件在整个种子数据中的起始偏移
+    offset: u64,
+    length: u64,
+}
+
+/// 将分片映射到单文件或多文件布局并读写磁盘，同时维护用于续传的 bitfield 文件
+#[derive(Debug)]
+pub struct Storage {
+    files: Vec<StorageFile>,
+    root_path: PathBuf,
+    piece_length: u64,
+    total_length: u64,
+    pieces: Bytes,
+    resume_path: PathBuf,
+}
+
+impl Storage {
+    pub fn new(meta: &MetaInfo, download_dir: &Path, info_hash: &[u8]) -> Result<Self, Error> {
+        let (files, root_path, pieces, piece_length) = match meta {
+            MetaInfo::SingleFile {
+                length,
+                name,
+                pieces,
+                piece_length,
+            } => {
+                let path = safe_join(download_dir, name)?;
+                let file = StorageFile {
+                    path: path.clone(),
+                    offset: 0,
+                    length: *length as u64,
+                };
+
+                (vec![file], path, pieces, piece_length)
+            }
+            MetaInfo::MultiFile {
+                files,
+                name,
+                pieces,
+                piece_length,
+            } => {
+                let root_path = safe_join(download_dir, name)?;
+                let mut offset = 0;
+                let mut storage_files = vec![];
+
+                for item in files {
+                    let length = item.length as u64;
+                    storage_files.push(StorageFile {
+                        path: safe_join(&root_path, &item.path)?,
+                        offset,
+                        length,
+                    });
+                    offset += length;
+                }
+
+                (storage_files, root_path, pieces, piece_length)
+            }
+            // 混合种子按 v1 的布局下载，v1 中每个非空文件都从分片边界开始（BEP47 的 pad 文件不落盘）
+            MetaInfo::V2 {
+                files,
+                name,
+                piece_length,
+                v1_pieces: Some(pieces),
+                ..
+            } => {
+                if files.len() == 1 && files[0].path == *name {
+                    let path = safe_join(download_dir, name)?;
+                    let file = StorageFile {
+                        path: path.clone(),
+                        offset: 0,
+                        length: files[0].length as u64,
+                    };
+
+                    (vec![file], path, pieces, piece_length)
+                } else {
+                    let root_path = safe_join(download_dir, name)?;
+                    let mut offset: u64 = 0;
+                    let mut storage_files = vec![];
+
+                    for item in files {
+                        let length = item.length as u64;
+                        if length > 0 && *piece_length > 0 {
+                            offset = offset.next_multiple_of(*piece_length as u64);
+                        }
+                        storage_files.push(StorageFile {
+                            path: safe_join(&root_path, &item.path)?,
+                            offset,
+                            length,
+                        });
+                        offset += length;
+                    }
+
+                    (storage_files, root_path, pieces, piece_length)
+                }
+            }
+            // 纯 v2 按文件对齐分片并使用 merkle 树校验，目前只支持 v1 的分片布局
+            MetaInfo::V2 { name, .. } => Err(Error::new_general(&format!(
+                "v2 torrent is not supported for download: {}",
+                name
+            )))?,
+        };
+
+        let piece_length = *piece_length as u64;
+        // 混合种子的 pad 不对应文件，总长度取最后一个文件的结尾
+        let total_length: u64 = files.iter().map(|f| f.offset + f.length).max().unwrap_or(0);
+
+        if piece_length == 0 || pieces.len() % PIECE_HASH_LEN != 0 {
+            Err(Error::new_decode(&format!(
+                "invalid piece length {} or pieces len {}",
+                piece_length,
+                pieces.len()
+            )))?
+        }
+        let piece_count = total_length.div_ceil(piece_length);
+        if piece_count as usize != pieces.len() / PIECE_HASH_LEN {
+            Err(Error::new_decode(&format!(
+                "pieces num is {}, but expect {}",
+                pieces.len() / PIECE_HASH_LEN,
+                piece_count
+            )))?
+        }
+
+        let resume_path = {
+            let info_str: String = info_hash.encode_hex();
+            download_dir.join(format!(".{}.{}", info_str, RESUME_FILE_EXT))
+        };
+
+        Ok(Storage {
+            files,
+            root_path,
+            piece_length,
+            total_length,
+            pieces: pieces.clone(),
+            resume_path,
+        })
+    }
+
+    /// 单文件为文件路径，多文件为顶层目录
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
+    }
+
+    pub fn piece_length(&self) -> u64 {
+        self.piece_length
+    }
+
+    pub fn total_length(&self) -> u64 {
+        self.total_length
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.pieces.len() / PIECE_HASH_LEN
+    }
+
+    pub fn piece_size(&self, index: u32) -> u64 {
+        let begin = index as u64 * self.piece_length;
+        (self.total_length - begin).min(self.piece_length)
+    }
+
+    /// 用 MetaInfo 中的 SHA-1 校验分片数据
+    pub fn verify_piece(&self, index: u32, data: &[u8]) -> bool {
+        let begin = index as usize * PIECE_HASH_LEN;
+        let expect = match self.pieces.get(begin..begin + PIECE_HASH_LEN) {
+            Some(val) => val,
+            None => return false,
+        };
+
+        let mut hasher = Sha1::new();
+        hasher.update(data);
+
+        hasher.finalize()[..] == *expect
+    }
+
+    pub fn write_piece(&self, index: u32, data: &[u8]) -> Result<(), Error> {
+        if index as usize >= self.piece_count() || data.len() as u64 != self.piece_size(index) {
+            Err(Error::new_file(
+                None,
+                Some(format!("write piece {} with invalid len {}", index, data.len())),
+            ))?
+        }
+
+        let start = index as u64 * self.piece_length;
+        let end = start + data.len() as u64;
+
+        for file in &self.files {
+            // 空文件只需要创建
+            if file.length == 0 {
+                if file.offset >= start && file.offset <= end {
+                    open_file(&file.path, true)?;
+                }
+                continue;
+            }
+
+            let (file_begin, file_end) = (file.offset, file.offset + file.length);
+            if file_end <= start || file_begin >= end {
+                continue;
+            }
+
+            let write_begin = start.max(file_begin);
+            let write_end = end.min(file_end);
+
+            let mut f = open_file(&file.path, true)?;
+            f.seek(SeekFrom::Start(write_begin - file_begin))
+                .map_err(|error| Error::new_file(Some(error.into()), None))?;
+            f.write_all(&data[(write_begin - start) as usize..(write_end - start) as usize])
+                .map_err(|error| Error::new_file(Some(error.into()), None))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_piece(&self, index: u32) -> Result<Bytes, Error> {
+        if index as usize >= self.piece_count() {
+            Err(Error::new_file(None, Some(format!("read piece {} out of range", index))))?
+        }
+
+        self.read_range(index as u64 * self.piece_length, self.piece_size(index))
+    }
+
+    /// 读取分片内的一个块，用于响应 Request 消息
+    pub fn read_block(&self, index: u32, begin: u32, length: u32) -> Result<Bytes, Error> {
+        if index as usize >= self.piece_count()
+            || begin as u64 + length as u64 > self.piece_size(index)
+        {
+            Err(Error::new_file(
+                None,
+                Some(format!("read block {}:{}+{} out of range", index, begin, length)),
+            ))?
+        }
+
+        self.read_range(index as u64 * self.piece_length + begin as u64, length as u64)
+    }
+
+    fn read_range(&self, start: u64, length: u64) -> Result<Bytes, Error> {
+        let end = start + length;
+        let mut rst = vec![0; length as usize];
+
+        for file in &self.files {
+            let (file_begin, file_end) = (file.offset, file.offset + file.length);
+            if file_end <= start || file_begin >= end {
+                continue;
+            }
+
+            let read_begin = start.max(file_begin);
+            let read_end = end.min(file_end);
+
+            let mut f = open_file(&file.path, false)?;
+            f.seek(SeekFrom::Start(read_begin - file_begin))
+                .map_err(|error| Error::new_file(Some(error.into()), None))?;
+            f.read_exact(&mut rst[(read_begin - start) as usize..(read_end - start) as usize])
+                .map_err(|error| Error::new_file(Some(error.into()), None))?;
+        }
+
+        Ok(rst.into())
+    }
+
+    /// 读取续传 bitfield，文件不存在或无效时返回空位图
+    pub fn load_bitfield(&self) -> Bitfield {
+        if let Ok(data) = fs::read(&self.resume_path) {
+            if let Ok(bitfield) = Bitfield::from_bytes(&data, self.piece_count()) {
+                return bitfield;
+            }
+        }
+
+        Bitfield::new(self.piece_count())
+    }
+
+    /// 先写临时文件再改名，避免中途崩溃留下损坏的 bitfield
+    pub fn save_bitfield(&self, bitfield: &Bitfield) -> Result<(), Error> {
+        let tmp_path = self.resume_path.with_extension(format!("{}.tmp", RESUME_FILE_EXT));
+
+        fs::write(&tmp_path, bitfield.as_bytes())
+            .map_err(|error| Error::new_file(Some(error.into()), None))?;
+        fs::rename(&tmp_path, &self.resume_path)
+            .map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+        Ok(())
+    }
+}
+
+fn open_file(path: &Path, write: bool) -> Result<fs::File, Error> {
+    if write {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|error| Error::new_file(Some(error.into()), None))?;
+        }
+    }
+
+    OpenOptions::new()
+        .read(true)
+        .write(write)
+        .create(write)
+        .truncate(false)
+        .open(path)
+        .map_err(|error| {
+            Error::new_file(Some(error.into()), Some(format!("open {:?}", path)))
+        })
+}
+
+/// 拼接种子中的相对路径，拒绝绝对路径及 ".." 以防写到下载目录之外
+fn safe_join(base: &Path, path: &str) -> Result<PathBuf, Error> {
+    let mut rst = base.to_path_buf();
+    let mut is_empty = true;
+
+    for item in path.split('/') {
+        for component in Path::new(item).components() {
+            match component {
+                Component::Normal(val) => {
+                    rst.push(val);
+                    is_empty = false;
+                }
+                Component::CurDir => {}
+                _ => Err(Error::new_path(None, Some(format!("unsafe path in torrent: {}", path))))?,
+            }
+        }
+    }
+
+    if is_empty {
+        Err(Error::new_path(None, Some(format!("empty path in torrent: {:?}", path))))?
+    }
+
+    Ok(rst)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use bytes::{Bytes, BytesMut};
+    use sha1::{Digest, Sha1};
+    use yiilian_core::data::{FileInfo, FileInfoV2, MetaInfo};
+
+    use crate::bt::download::{Bitfield, Storage};
+
+    fn pieces_hash(data: &[u8], piece_length: usize) -> Bytes {
+        let mut rst = BytesMut::new();
+        for chunk in data.chunks(piece_length) {
+            let mut hasher = Sha1::new();
+            hasher.update(chunk);
+            rst.extend(hasher.finalize());
+        }
+
+        rst.into()
+    }
+
+    #[test]
+    fn test_multi_file() {
+        let download_dir = std::env::temp_dir().join("yiilian_dl_test_multi_file");
+        let _ = fs::remove_dir_all(&download_dir);
+
+        let data: Vec<u8> = (0..25u8).collect();
+        let meta = MetaInfo::MultiFile {
+            files: vec![
+                FileInfo { length: 7, path: "a.txt".to_owned() },
+                FileInfo { length: 0, path: "empty".to_owned() },
+                FileInfo { length: 18, path: "sub/b.txt".to_owned() },
+            ],
+            name: "root".to_owned(),
+            pieces: pieces_hash(&data, 10),
+            piece_length: 10,
+        };
+        let storage = Storage::new(&meta, &download_dir, &[1; 20]).unwrap();
+        assert_eq!(3, storage.piece_count());
+        assert_eq!(5, storage.piece_size(2));
+
+        assert!(!storage.verify_piece(0, &data[10..20]));
+        for (index, chunk) in data.chunks(10).enumerate() {
+            assert!(storage.verify_piece(index as u32, chunk));
+            storage.write_piece(index as u32, chunk).unwrap();
+        }
+
+        assert_eq!(data[..7], fs::read(download_dir.join("root/a.txt")).unwrap());
+        assert_eq!(0, fs::read(download_dir.join("root/empty")).unwrap().len());
+        assert_eq!(data[7..], fs::read(download_dir.join("root/sub/b.txt")).unwrap());
+        assert_eq!(data[10..20], storage.read_piece(1).unwrap());
+        assert_eq!(data[5..9], storage.read_block(0, 5, 4).unwrap());
+        assert!(storage.read_block(2, 4, 2).is_err());
+
+        let mut bitfield = storage.load_bitfield();
+        assert_eq!(Bitfield::new(3), bitfield);
+        bitfield.set(1);
+        storage.save_bitfield(&bitfield).unwrap();
+        assert_eq!(bitfield, storage.load_bitfield());
+
+        fs::remove_dir_all(&download_dir).unwrap();
+    }
+
+    #[test]
+    fn test_hybrid() {
+        let download_dir = std::env::temp_dir().join("yiilian_dl_test_hybrid");
+        let _ = fs::remove_dir_all(&download_dir);
+
+        let file = |length: i64, path: &str| FileInfoV2 {
+            length,
+            path: path.to_owned(),
+            pieces_root: None,
+        };
+
+        // v1 布局：a.txt(7) + pad(3) + empty + sub/b.txt(8)
+        let a: Vec<u8> = (0..7u8).collect();
+        let b: Vec<u8> = (10..18u8).collect();
+        let mut data = a.clone();
+        data.extend([0; 3]);
+        data.extend(&b);
+
+        let meta = MetaInfo::V2 {
+            files: vec![file(7, "a.txt"), file(0, "empty"), file(8, "sub/b.txt")],
+            name: "root".to_owned(),
+            piece_length: 10,
+            piece_layers: Default::default(),
+            v1_pieces: Some(pieces_hash(&data, 10)),
+        };
+        let storage = Storage::new(&meta, &download_dir, &[1; 20]).unwrap();
+        assert_eq!(18, storage.total_length());
+        assert_eq!(2, storage.piece_count());
+        assert_eq!(8, storage.piece_size(1));
+
+        for (index, chunk) in data.chunks(10).enumerate() {
+            assert!(storage.verify_piece(index as u32, chunk));
+            storage.write_piece(index as u32, chunk).unwrap();
+        }
+
+        assert_eq!(a, fs::read(download_dir.join("root/a.txt")).unwrap());
+        assert_eq!(0, fs::read(download_dir.join("root/empty")).unwrap().len());
+        assert_eq!(b, fs::read(download_dir.join("root/sub/b.txt")).unwrap());
+        assert_eq!(data[..10], storage.read_piece(0).unwrap());
+
+        // 单文件的混合种子
+        let meta = MetaInfo::V2 {
+            files: vec![file(7, "single.txt")],
+            name: "single.txt".to_owned(),
+            piece_length: 10,
+            piece_layers: Default::default(),
+            v1_pieces: Some(pieces_hash(&a, 10)),
+        };
+        let storage = Storage::new(&meta, &download_dir, &[2; 20]).unwrap();
+        storage.write_piece(0, &a).unwrap();
+        assert_eq!(a, fs::read(download_dir.join("single.txt")).unwrap());
+
+        // 纯 v2 种子仍不支持
+        let meta = MetaInfo::V2 {
+            files: vec![file(7, "a.txt")],
+            name: "root".to_owned(),
+            piece_length: 10,
+            piece_layers: Default::default(),
+            v1_pieces: None,
+        };
+        assert!(Storage::new(&meta, &download_dir, &[3; 20]).is_err());
+
+        fs::remove_dir_all(&download_dir).unwrap();
+    }
+
+    #[test]
+    fn test_unsafe_path() {
+        let meta = MetaInfo::MultiFile {
+            files: vec![FileInfo { length: 1, path: "../evil".to_owned() }],
+            name: "root".to_owned(),
+            pieces: pieces_hash(&[0], 10),
+            piece_length: 10,
+        };
+
+        assert!(Storage::new(&meta, &std::env::temp_dir(), &[1; 20]).is_err());
+    }
+}
@@ -3,3 +3,5 @@ pub mod data;
 pub mod peer_wire;
 pub mod net;
 pub mod bt_downloader;
+pub mod download;
+pub mod tracker;
@@ -1,2 +1,9 @@
 pub mod tcp;
-pub mod utp;
\ No newline at end of file
+pub mod utp;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// peer 连接所用的传输层，TCP 与 uTP 均实现了该 trait
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+impl<T> Transport for T where T: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
@@ -1,23 +1,55 @@
-use std::io::{Cursor, Seek, SeekFrom};
+use std::{io::{Cursor, Seek, SeekFrom}, time::Duration};
 
 use bytes::{Bytes, BytesMut};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::timeout,
 };
 use yiilian_core::{common::error::Error, net::tcp::read};
 
-use crate::bt::data::frame::MESSAGE_LEN_PREFIX;
+use crate::bt::{data::frame::MESSAGE_LEN_PREFIX, peer_wire::MAX_REQUEST_LEN};
 
-pub async fn read_message(stream: &mut TcpStream) -> Result<Bytes, Error> {
+/// 消息体（不含长度前缀）的最大长度：最大的 piece 消息为 1 字节 id + 4 字节 index + 4 字节 begin + 块数据
+pub const MAX_MESSAGE_LEN: usize = MAX_REQUEST_LEN as usize + 9;
+
+pub async fn read_message<S>(stream: &mut S) -> Result<Bytes, Error>
+where
+    S: AsyncRead + Unpin,
+{
     let mut buf: [u8; MESSAGE_LEN_PREFIX] = [0; MESSAGE_LEN_PREFIX];
     read(stream, &mut buf)
         .await
         .map_err(|error| Error::new_net(Some(error.into()), Some("read_message [u8; MESSAGE_LEN_PREFIX]".to_owned()), None))?;
+
+    read_message_body(stream, buf).await
+}
+
+/// 最多等待 idle 时长读取下一条消息，用于长时间保持的 peer 连接（对方每 2 分钟发送 keep-alive）
+pub async fn read_message_idle<S>(stream: &mut S, idle: Duration) -> Result<Bytes, Error>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buf: [u8; MESSAGE_LEN_PREFIX] = [0; MESSAGE_LEN_PREFIX];
+    match timeout(idle, stream.read_exact(&mut buf)).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(error)) => Err(Error::new_net(Some(error.into()), Some("read_message_idle [u8; MESSAGE_LEN_PREFIX]".to_owned()), None))?,
+        Err(_) => Err(Error::new_timeout("read_message_idle timeout"))?,
+    }
+
+    read_message_body(stream, buf).await
+}
+
+async fn read_message_body<S>(stream: &mut S, buf: [u8; MESSAGE_LEN_PREFIX]) -> Result<Bytes, Error>
+where
+    S: AsyncRead + Unpin,
+{
     let message_len_bytes = &buf[..];
-    let message_len =
-        u32::from_be_bytes(message_len_bytes.try_into().expect("bytes len is invalid")) as usize
-            + MESSAGE_LEN_PREFIX;
+    let body_len = u32::from_be_bytes(message_len_bytes.try_into().expect("bytes len is invalid")) as usize;
+    // 长度来自对方，分配内存前先检查，避免对方用一个长度前缀耗尽内存
+    if body_len > MAX_MESSAGE_LEN {
+        Err(Error::new_frame(None, Some(format!("message length {} exceeds {}", body_len, MAX_MESSAGE_LEN))))?
+    }
+    let message_len = body_len + MESSAGE_LEN_PREFIX;
 
     let buf: Vec<u8> = vec![0; message_len as usize];
     let mut buf = Cursor::new(buf);
@@ -35,7 +67,10 @@ pub async fn read_message(stream: &mut TcpStream) -> Result<Bytes, Error> {
     Ok(buf.into())
 }
 
-pub async fn send_message(stream: &mut TcpStream, data: &[u8]) -> Result<(), Error> {
+pub async fn send_message<S>(stream: &mut S, data: &[u8]) -> Result<(), Error>
+where
+    S: AsyncWrite + Unpin,
+{
     stream
         .write_all(data)
         .await
@@ -44,7 +79,7 @@ pub async fn send_message(stream: &mut TcpStream, data: &[u8]) -> Result<(), Err
     Ok(())
 }
 
-pub async fn read_all(stream: &mut TcpStream) -> Result<Bytes, Error> {
+pub async fn read_all<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Bytes, Error> {
     let mut rst = BytesMut::new();
     loop {
         let mut buf = [0; 4096];
@@ -60,3 +95,18 @@ pub async fn read_all(stream: &mut TcpStream) -> Result<Bytes, Error> {
 
     Ok(rst.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_message() {
+        let mut stream = &[0, 0, 0, 1, 2][..];
+        assert_eq!(&[0, 0, 0, 1, 2][..], &read_message(&mut stream).await.unwrap()[..]);
+
+        // 超长的长度前缀在分配内存前被拒绝
+        let mut stream = &[0xff, 0xff, 0xff, 0xff, 0][..];
+        assert!(read_message(&mut stream).await.is_err());
+    }
+}
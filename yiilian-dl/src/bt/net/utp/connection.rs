@@ -0,0 +1,544 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf},
+    sync::{mpsc, oneshot},
+    time::{sleep_until, Instant},
+};
+use yiilian_core::common::error::Error;
+
+use super::{
+    packet::{Packet, PacketType, HEADER_SIZE},
+    socket::SocketInner,
+};
+
+/// 单个包的最大载荷，避免 IP 分片
+pub(super) const MAX_PAYLOAD_SIZE: usize = 1400 - HEADER_SIZE;
+const MAX_PACKET_SIZE: usize = MAX_PAYLOAD_SIZE + HEADER_SIZE;
+const MIN_WINDOW: usize = MAX_PACKET_SIZE * 2;
+const INITIAL_WINDOW: usize = MAX_PACKET_SIZE * 4;
+const MAX_WINDOW: usize = 1024 * 1024;
+/// 对外通告的接收窗口
+const RECV_WINDOW: u32 = 1024 * 1024;
+/// LEDBAT 目标排队延迟 100ms
+const TARGET_DELAY_MICROS: i64 = 100_000;
+/// 每个 RTT 窗口最多增长的字节数
+const MAX_CWND_INCREASE_PER_RTT: f64 = 3000.0;
+/// base_delay 的刷新周期
+const BASE_DELAY_WINDOW: Duration = Duration::from_secs(120);
+const INITIAL_RTO: Duration = Duration::from_millis(1000);
+const MIN_RTO: Duration = Duration::from_millis(500);
+const MAX_RTO: Duration = Duration::from_secs(30);
+const MAX_SYN_RETRIES: u32 = 3;
+const MAX_RETRANSMISSIONS: u32 = 8;
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(29);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+/// 乱序缓存最多保留的包数
+const MAX_REORDER: u16 = 1024;
+const FAST_RETRANSMIT_DUP_ACKS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    SynSent,
+    Connected,
+}
+
+#[derive(Debug)]
+struct SentPacket {
+    packet: Packet,
+    sent_at: Instant,
+    transmissions: u32,
+}
+
+/// 一条 uTP 连接的状态机，运行在独立的任务中。
+/// 应用层通过 DuplexStream 的另一端读写数据，连接任务负责分包、确认、重传和拥塞控制
+pub(super) struct Connection {
+    socket: Arc<SocketInner>,
+    remote: SocketAddr,
+    state: State,
+    conn_id_recv: u16,
+    conn_id_send: u16,
+    /// 下一个要发送的序号
+    seq_nr: u16,
+    /// 已按序收到的最后一个序号
+    ack_nr: u16,
+    in_flight: VecDeque<SentPacket>,
+    /// 在途的字节数
+    cur_window: usize,
+    /// 拥塞窗口（LEDBAT）
+    max_window: usize,
+    peer_window: usize,
+    rtt: Option<Duration>,
+    rtt_var: Duration,
+    rto: Duration,
+    base_delay: Option<u32>,
+    base_delay_at: Instant,
+    /// 写入发出包头的 timestamp_diff
+    reply_micros: u32,
+    last_ack: u16,
+    dup_acks: u32,
+    /// 进入快速恢复时已发送的最大序号，确认到该序号前每个部分确认都立即重传下一个缺口
+    recovery_seq: Option<u16>,
+    reorder: HashMap<u16, Packet>,
+    /// 对方 FIN 包的序号
+    eof_seq: Option<u16>,
+    fin_sent: bool,
+    /// 对方数据已全部交付（或应用层已关闭读取端）
+    read_closed: bool,
+    app_write: WriteHalf<DuplexStream>,
+    epoch: Instant,
+    last_recv: Instant,
+    last_send: Instant,
+}
+
+impl Connection {
+    fn new(
+        socket: Arc<SocketInner>,
+        remote: SocketAddr,
+        conn_id_recv: u16,
+        conn_id_send: u16,
+        seq_nr: u16,
+        app_write: WriteHalf<DuplexStream>,
+        state: State,
+    ) -> Self {
+        let now = Instant::now();
+
+        Connection {
+            socket,
+            remote,
+            state,
+            conn_id_recv,
+            conn_id_send,
+            seq_nr,
+            ack_nr: 0,
+            in_flight: VecDeque::new(),
+            cur_window: 0,
+            max_window: INITIAL_WINDOW,
+            peer_window: RECV_WINDOW as usize,
+            rtt: None,
+            rtt_var: Duration::ZERO,
+            rto: INITIAL_RTO,
+            base_delay: None,
+            base_delay_at: now,
+            reply_micros: 0,
+            last_ack: 0,
+            dup_acks: 0,
+            recovery_seq: None,
+            reorder: HashMap::new(),
+            eof_seq: None,
+            fin_sent: false,
+            read_closed: false,
+            app_write,
+            epoch: now,
+            last_recv: now,
+            last_send: now,
+        }
+    }
+
+    /// 主动连接：发送 SYN，收到 STATE 后通过 connected_tx 通知
+    pub(super) async fn run_initiator(
+        socket: Arc<SocketInner>,
+        remote: SocketAddr,
+        conn_id_recv: u16,
+        app: DuplexStream,
+        packet_rx: mpsc::Receiver<Packet>,
+        connected_tx: oneshot::Sender<Result<(), Error>>,
+    ) {
+        let (app_read, app_write) = tokio::io::split(app);
+        let mut conn = Connection::new(
+            socket,
+            remote,
+            conn_id_recv,
+            conn_id_recv.wrapping_add(1),
+            1,
+            app_write,
+            State::SynSent,
+        );
+
+        let rst = match conn.send_packet(PacketType::Syn, Bytes::new()).await {
+            Ok(_) => conn.run(packet_rx, app_read, Some(connected_tx)).await,
+            Err(error) => {
+                let _ = connected_tx.send(Err(error));
+                Ok(())
+            }
+        };
+
+        conn.finish(rst);
+    }
+
+    /// 被动连接：回复收到的 SYN
+    pub(super) async fn run_acceptor(
+        socket: Arc<SocketInner>,
+        remote: SocketAddr,
+        syn: Packet,
+        app: DuplexStream,
+        packet_rx: mpsc::Receiver<Packet>,
+    ) {
+        let (app_read, app_write) = tokio::io::split(app);
+        let mut conn = Connection::new(
+            socket,
+            remote,
+            syn.connection_id.wrapping_add(1),
+            syn.connection_id,
+            rand::random(),
+            app_write,
+            State::Connected,
+        );
+        conn.ack_nr = syn.seq_nr;
+        conn.last_ack = conn.seq_nr.wrapping_sub(1);
+
+        let rst = match conn.send_packet(PacketType::State, Bytes::new()).await {
+            Ok(_) => conn.run(packet_rx, app_read, None).await,
+            Err(error) => Err(error),
+        };
+
+        conn.finish(rst);
+    }
+
+    fn finish(&mut self, rst: Result<(), Error>) {
+        if let Err(error) = rst {
+            log::trace!(target: "yiilian_dl::bt::net::utp", "connection to {} closed: {}", self.remote, error);
+        }
+
+        self.socket.remove_connection(&(self.remote, self.conn_id_recv));
+    }
+
+    async fn run(
+        &mut self,
+        mut packet_rx: mpsc::Receiver<Packet>,
+        mut app_read: ReadHalf<DuplexStream>,
+        mut connected_tx: Option<oneshot::Sender<Result<(), Error>>>,
+    ) -> Result<(), Error> {
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+        let mut app_eof = false;
+
+        loop {
+            if self.state == State::Connected {
+                if let Some(tx) = connected_tx.take() {
+                    let _ = tx.send(Ok(()));
+                }
+            }
+
+            if self.fin_sent && self.in_flight.is_empty() && self.read_closed {
+                return Ok(());
+            }
+
+            let can_send = self.state == State::Connected
+                && !app_eof
+                && (self.in_flight.is_empty() || self.cur_window + MAX_PACKET_SIZE <= self.window());
+            let deadline = self.next_deadline();
+
+            let rst = tokio::select! {
+                packet = packet_rx.recv() => {
+                    match packet {
+                        Some(packet) => self.on_packet(packet).await,
+                        None => Err(Error::new_net(None, Some("utp socket closed".to_owned()), Some(self.remote))),
+                    }
+                }
+                rst = app_read.read(&mut buf), if can_send => {
+                    match rst {
+                        Ok(0) | Err(_) => {
+                            app_eof = true;
+                            self.fin_sent = true;
+                            self.send_packet(PacketType::Fin, Bytes::new()).await
+                        }
+                        Ok(n) => self.send_packet(PacketType::Data, buf[..n].to_owned().into()).await,
+                    }
+                }
+                _ = sleep_until(deadline) => self.on_timeout().await,
+            };
+
+            if let Err(error) = rst {
+                if let Some(tx) = connected_tx.take() {
+                    let _ = tx.send(Err(error));
+                    return Ok(());
+                }
+
+                return Err(error);
+            }
+        }
+    }
+
+    fn window(&self) -> usize {
+        self.max_window.min(self.peer_window)
+    }
+
+    fn now_micros(&self) -> u32 {
+        self.epoch.elapsed().as_micros() as u32
+    }
+
+    fn next_deadline(&self) -> Instant {
+        let mut deadline = (self.last_recv + IDLE_TIMEOUT).min(self.last_send + KEEP_ALIVE_INTERVAL);
+        if let Some(sent) = self.in_flight.front() {
+            deadline = deadline.min(sent.sent_at + self.rto);
+        }
+
+        deadline
+    }
+
+    async fn on_timeout(&mut self) -> Result<(), Error> {
+        let now = Instant::now();
+
+        if now >= self.last_recv + IDLE_TIMEOUT {
+            Err(Error::new_timeout("utp connection idle timeout"))?
+        }
+
+        let is_expired = self
+            .in_flight
+            .front()
+            .map(|sent| now >= sent.sent_at + self.rto)
+            .unwrap_or(false);
+        if is_expired {
+            let transmissions = self.in_flight.front().map(|sent| sent.transmissions).unwrap_or(0);
+            if (self.state == State::SynSent && transmissions > MAX_SYN_RETRIES)
+                || transmissions > MAX_RETRANSMISSIONS
+            {
+                Err(Error::new_timeout("utp retransmit timeout"))?
+            }
+
+            // 超时说明网络严重拥塞，窗口降到最小
+            self.max_window = MIN_WINDOW;
+            self.rto = (self.rto * 2).min(MAX_RTO);
+            self.recovery_seq = Some(self.seq_nr.wrapping_sub(1));
+            self.retransmit_front().await?;
+        } else if self.state == State::Connected && now >= self.last_send + KEEP_ALIVE_INTERVAL {
+            self.send_packet(PacketType::State, Bytes::new()).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn on_packet(&mut self, packet: Packet) -> Result<(), Error> {
+        self.last_recv = Instant::now();
+        self.reply_micros = self.now_micros().wrapping_sub(packet.timestamp);
+        self.peer_window = packet.wnd_size as usize;
+
+        match packet.packet_type {
+            PacketType::Reset => {
+                Err(Error::new_net(None, Some("utp connection reset by peer".to_owned()), Some(self.remote)))?
+            }
+            PacketType::Syn => {
+                // 对方没有收到我们的 STATE，重新回复
+                if self.state == State::Connected && packet.seq_nr == self.ack_nr {
+                    self.send_packet(PacketType::State, Bytes::new()).await?;
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        if self.state == State::SynSent {
+            if packet.packet_type != PacketType::State {
+                return Ok(());
+            }
+
+            self.state = State::Connected;
+            self.ack_nr = packet.seq_nr.wrapping_sub(1);
+        }
+
+        self.on_ack(packet.ack_nr, packet.timestamp_diff).await?;
+
+        if packet.packet_type == PacketType::Data || packet.packet_type == PacketType::Fin {
+            self.on_data(packet).await?;
+            self.send_packet(PacketType::State, Bytes::new()).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn on_data(&mut self, packet: Packet) -> Result<(), Error> {
+        let expect = self.ack_nr.wrapping_add(1);
+        let distance = packet.seq_nr.wrapping_sub(expect);
+
+        if packet.packet_type == PacketType::Fin {
+            self.eof_seq = Some(packet.seq_nr);
+        }
+
+        if distance == 0 {
+            self.deliver(packet).await;
+
+            // 按序交付乱序缓存中的后续包
+            while let Some(packet) = self.reorder.remove(&self.ack_nr.wrapping_add(1)) {
+                self.deliver(packet).await;
+            }
+        } else if distance < MAX_REORDER {
+            self.reorder.insert(packet.seq_nr, packet);
+        }
+
+        if !self.read_closed && self.eof_seq == Some(self.ack_nr) {
+            self.read_closed = true;
+            let _ = self.app_write.shutdown().await;
+        }
+
+        Ok(())
+    }
+
+    async fn deliver(&mut self, packet: Packet) {
+        self.ack_nr = packet.seq_nr;
+
+        if !self.read_closed && !packet.payload.is_empty() {
+            // 应用层已关闭，丢弃后续数据
+            if self.app_write.write_all(&packet.payload).await.is_err() {
+                self.read_closed = true;
+            }
+        }
+    }
+
+    async fn on_ack(&mut self, ack_nr: u16, their_delay: u32) -> Result<(), Error> {
+        let now = Instant::now();
+        let mut bytes_acked = 0;
+        let mut rtt_sample = None;
+        let mut is_retransmitted = false;
+
+        while let Some(sent) = self.in_flight.front() {
+            // sent.seq_nr <= ack_nr（考虑回绕）
+            if seq_lt(ack_nr, sent.packet.seq_nr) {
+                break;
+            }
+
+            let sent = self.in_flight.pop_front().expect("in_flight front must exist");
+            bytes_acked += sent.packet.len();
+            self.cur_window = self.cur_window.saturating_sub(sent.packet.len());
+
+            is_retransmitted |= sent.transmissions > 1;
+            if sent.packet.seq_nr == ack_nr {
+                rtt_sample = Some(now - sent.sent_at);
+            }
+        }
+
+        if bytes_acked > 0 {
+            // Karn 算法：确认中包含重传包时不采样 RTT，被乱序缓存的包也会使采样偏大
+            if let (Some(sample), false) = (rtt_sample, is_retransmitted) {
+                self.update_rtt(sample);
+            }
+
+            self.dup_acks = 0;
+            self.update_window(bytes_acked, their_delay);
+
+            match self.recovery_seq {
+                Some(recovery_seq) if seq_lt(ack_nr, recovery_seq) => self.retransmit_front().await?,
+                _ => self.recovery_seq = None,
+            }
+        } else if ack_nr == self.last_ack && !self.in_flight.is_empty() {
+            self.dup_acks += 1;
+
+            if self.dup_acks == FAST_RETRANSMIT_DUP_ACKS && self.recovery_seq.is_none() {
+                self.max_window = (self.max_window / 2).max(MIN_WINDOW);
+                self.recovery_seq = Some(self.seq_nr.wrapping_sub(1));
+                self.retransmit_front().await?;
+            }
+        }
+        self.last_ack = ack_nr;
+
+        Ok(())
+    }
+
+    fn update_rtt(&mut self, sample: Duration) {
+        match self.rtt {
+            None => {
+                self.rtt = Some(sample);
+                self.rtt_var = sample / 2;
+            }
+            Some(rtt) => {
+                let delta = rtt.abs_diff(sample);
+                self.rtt_var = if delta > self.rtt_var {
+                    self.rtt_var + (delta - self.rtt_var) / 4
+                } else {
+                    self.rtt_var - (self.rtt_var - delta) / 4
+                };
+                self.rtt = Some(if sample > rtt {
+                    rtt + (sample - rtt) / 8
+                } else {
+                    rtt - (rtt - sample) / 8
+                });
+            }
+        }
+
+        let rtt = self.rtt.unwrap_or(INITIAL_RTO);
+        self.rto = (rtt + self.rtt_var * 4).clamp(MIN_RTO, MAX_RTO);
+    }
+
+    /// LEDBAT：排队延迟低于目标时增大窗口，高于目标时减小
+    fn update_window(&mut self, bytes_acked: usize, their_delay: u32) {
+        if self.base_delay_at.elapsed() > BASE_DELAY_WINDOW {
+            self.base_delay = None;
+            self.base_delay_at = Instant::now();
+        }
+
+        let base_delay = match self.base_delay {
+            Some(val) if val <= their_delay => val,
+            _ => {
+                self.base_delay = Some(their_delay);
+                their_delay
+            }
+        };
+        let our_delay = (their_delay - base_delay) as i64;
+
+        let off_target = (TARGET_DELAY_MICROS - our_delay) as f64 / TARGET_DELAY_MICROS as f64;
+        let window_factor = bytes_acked as f64 / self.max_window.max(bytes_acked) as f64;
+        let gain = MAX_CWND_INCREASE_PER_RTT * off_target * window_factor;
+
+        let max_window = (self.max_window as f64 + gain).clamp(MIN_WINDOW as f64, MAX_WINDOW as f64);
+        self.max_window = max_window as usize;
+    }
+
+    async fn send_packet(&mut self, packet_type: PacketType, payload: Bytes) -> Result<(), Error> {
+        let connection_id = if packet_type == PacketType::Syn {
+            self.conn_id_recv
+        } else {
+            self.conn_id_send
+        };
+
+        let mut packet = Packet::new(packet_type, connection_id, self.seq_nr, self.ack_nr);
+        packet.payload = payload;
+        self.send_to(&mut packet).await?;
+
+        if packet_type != PacketType::State {
+            self.seq_nr = self.seq_nr.wrapping_add(1);
+            self.cur_window += packet.len();
+            self.in_flight.push_back(SentPacket {
+                packet,
+                sent_at: Instant::now(),
+                transmissions: 1,
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn retransmit_front(&mut self) -> Result<(), Error> {
+        let mut sent = match self.in_flight.pop_front() {
+            Some(sent) => sent,
+            None => return Ok(()),
+        };
+
+        sent.packet.ack_nr = self.ack_nr;
+        let rst = self.send_to(&mut sent.packet).await;
+        sent.sent_at = Instant::now();
+        sent.transmissions += 1;
+        self.in_flight.push_front(sent);
+
+        rst
+    }
+
+    async fn send_to(&mut self, packet: &mut Packet) -> Result<(), Error> {
+        packet.timestamp = self.now_micros();
+        packet.timestamp_diff = self.reply_micros;
+        packet.wnd_size = RECV_WINDOW;
+
+        let data: Bytes = (&*packet).into();
+        self.last_send = Instant::now();
+        self.socket.send_to(&data, self.remote).await
+    }
+}
+
+/// a < b（考虑序号回绕）
+fn seq_lt(a: u16, b: u16) -> bool {
+    (a.wrapping_sub(b) as i16) < 0
+}
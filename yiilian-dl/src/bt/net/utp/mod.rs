@@ -0,0 +1,8 @@
+//! 基于 tokio 的 uTP (BEP29) 实现，使用 LEDBAT 拥塞控制
+
+mod packet;
+mod connection;
+mod socket;
+
+pub use packet::{Packet, PacketType, HEADER_SIZE};
+pub use socket::{UtpSocket, UtpStream};
@@ -0,0 +1,162 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use yiilian_core::common::{
+    error::Error,
+    util::{be_bytes_to_u16, be_bytes_to_u32},
+};
+
+pub const HEADER_SIZE: usize = 20;
+const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive, IntoPrimitive)]
+#[repr(u8)]
+pub enum PacketType {
+    Data = 0,
+    Fin = 1,
+    State = 2,
+    Reset = 3,
+    Syn = 4,
+}
+
+/// uTP 数据包，扩展头（如 SACK）在解析时被跳过，发送时不携带
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub packet_type: PacketType,
+    pub connection_id: u16,
+    /// 发送时刻的微秒时间戳
+    pub timestamp: u32,
+    /// 对方上一个包的单向延迟（本地接收时间 - 对方发送时间）
+    pub timestamp_diff: u32,
+    /// 接收窗口剩余字节数
+    pub wnd_size: u32,
+    pub seq_nr: u16,
+    pub ack_nr: u16,
+    pub payload: Bytes,
+}
+
+impl Packet {
+    pub fn new(packet_type: PacketType, connection_id: u16, seq_nr: u16, ack_nr: u16) -> Self {
+        Packet {
+            packet_type,
+            connection_id,
+            timestamp: 0,
+            timestamp_diff: 0,
+            wnd_size: 0,
+            seq_nr,
+            ack_nr,
+            payload: Bytes::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        HEADER_SIZE + self.payload.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.payload.is_empty()
+    }
+}
+
+impl TryFrom<&[u8]> for Packet {
+    type Error = Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() < HEADER_SIZE {
+            Err(Error::new_frame(
+                None,
+                Some(format!("utp packet is too short: {}", value.len())),
+            ))?
+        }
+
+        if value[0] & 0x0f != VERSION {
+            Err(Error::new_frame(
+                None,
+                Some(format!("utp version is not support: {}", value[0] & 0x0f)),
+            ))?
+        }
+
+        let packet_type = PacketType::try_from(value[0] >> 4).map_err(|_| {
+            Error::new_frame(None, Some(format!("utp packet type is invalid: {}", value[0] >> 4)))
+        })?;
+
+        // 跳过扩展头链：[next_extension, len, data...]
+        let mut extension = value[1];
+        let mut pos = HEADER_SIZE;
+        while extension != 0 {
+            if value.len() < pos + 2 || value.len() < pos + 2 + value[pos + 1] as usize {
+                Err(Error::new_frame(None, Some("utp extension is truncated".to_owned())))?
+            }
+
+            extension = value[pos];
+            pos += 2 + value[pos + 1] as usize;
+        }
+
+        Ok(Packet {
+            packet_type,
+            connection_id: be_bytes_to_u16(&value[2..4])?,
+            timestamp: be_bytes_to_u32(&value[4..8])?,
+            timestamp_diff: be_bytes_to_u32(&value[8..12])?,
+            wnd_size: be_bytes_to_u32(&value[12..16])?,
+            seq_nr: be_bytes_to_u16(&value[16..18])?,
+            ack_nr: be_bytes_to_u16(&value[18..20])?,
+            payload: value[pos..].to_owned().into(),
+        })
+    }
+}
+
+impl From<&Packet> for Bytes {
+    fn from(value: &Packet) -> Self {
+        let mut rst = BytesMut::with_capacity(value.len());
+
+        rst.put_u8(u8::from(value.packet_type) << 4 | VERSION);
+        rst.put_u8(0);
+        rst.put_u16(value.connection_id);
+        rst.put_u32(value.timestamp);
+        rst.put_u32(value.timestamp_diff);
+        rst.put_u32(value.wnd_size);
+        rst.put_u16(value.seq_nr);
+        rst.put_u16(value.ack_nr);
+        rst.extend(&value.payload);
+
+        rst.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{Packet, PacketType};
+
+    #[test]
+    fn test_encode_decode() {
+        let mut packet = Packet::new(PacketType::Data, 0x1234, 2, 1);
+        packet.timestamp = 10;
+        packet.timestamp_diff = 20;
+        packet.wnd_size = 0x100000;
+        packet.payload = b"ab"[..].into();
+
+        let data: Bytes = (&packet).into();
+        assert_eq!(
+            &[
+                0x01, 0, 0x12, 0x34, 0, 0, 0, 10, 0, 0, 0, 20, 0, 0x10, 0, 0, 0, 2, 0, 1, b'a', b'b'
+            ][..],
+            &data[..]
+        );
+        assert_eq!(packet, Packet::try_from(&data[..]).unwrap());
+
+        // 带 SACK 扩展头的 STATE 包
+        let mut data = data.to_vec();
+        data[0] = 0x21;
+        data[1] = 1;
+        data.truncate(20);
+        data.extend([0, 4, 0xff, 0, 0, 0]);
+        let packet = Packet::try_from(&data[..]).unwrap();
+        assert_eq!(PacketType::State, packet.packet_type);
+        assert!(packet.is_empty());
+
+        // 扩展头被截断
+        data.truncate(23);
+        assert!(Packet::try_from(&data[..]).is_err());
+    }
+}
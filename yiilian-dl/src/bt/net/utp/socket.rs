@@ -0,0 +1,320 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf},
+    net::UdpSocket,
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+use yiilian_core::common::error::Error;
+
+use super::{
+    connection::Connection,
+    packet::{Packet, PacketType},
+};
+
+/// 应用层与连接任务之间的缓冲区大小
+const DUPLEX_BUFFER_SIZE: usize = 256 * 1024;
+const PACKET_CHANNEL_SIZE: usize = 256;
+const ACCEPT_CHANNEL_SIZE: usize = 32;
+const RECV_BUFFER_SIZE: usize = 64 * 1024;
+
+/// (对端地址, 本端接收的 connection_id)
+pub(super) type ConnectionKey = (SocketAddr, u16);
+
+pub(super) struct SocketInner {
+    udp: UdpSocket,
+    connections: Mutex<HashMap<ConnectionKey, mpsc::Sender<Packet>>>,
+    accepting: AtomicBool,
+    accept_tx: mpsc::Sender<UtpStream>,
+}
+
+impl SocketInner {
+    pub(super) async fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<(), Error> {
+        self.udp
+            .send_to(data, addr)
+            .await
+            .map_err(|error| Error::new_net(Some(error.into()), Some("utp send_to".to_owned()), Some(addr)))?;
+
+        Ok(())
+    }
+
+    pub(super) fn remove_connection(&self, key: &ConnectionKey) {
+        self.connections.lock().expect("lock connections").remove(key);
+    }
+}
+
+/// uTP (BEP29) socket，一个 UDP 端口上复用多条 uTP 连接
+pub struct UtpSocket {
+    inner: Arc<SocketInner>,
+    accept_rx: tokio::sync::Mutex<mpsc::Receiver<UtpStream>>,
+    dispatcher: JoinHandle<()>,
+}
+
+impl UtpSocket {
+    pub async fn bind(addr: SocketAddr) -> Result<Self, Error> {
+        let udp = UdpSocket::bind(addr)
+            .await
+            .map_err(|error| Error::new_bind(Some(error.into())))?;
+        let (accept_tx, accept_rx) = mpsc::channel(ACCEPT_CHANNEL_SIZE);
+
+        let inner = Arc::new(SocketInner {
+            udp,
+            connections: Mutex::new(HashMap::new()),
+            accepting: AtomicBool::new(false),
+            accept_tx,
+        });
+        let dispatcher = tokio::spawn(dispatch_loop(inner.clone()));
+
+        Ok(UtpSocket {
+            inner,
+            accept_rx: tokio::sync::Mutex::new(accept_rx),
+            dispatcher,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.inner
+            .udp
+            .local_addr()
+            .map_err(|error| Error::new_net(Some(error.into()), Some("utp local_addr".to_owned()), None))
+    }
+
+    /// 连接远端，SYN 多次重传仍无响应则返回超时错误
+    pub async fn connect(&self, remote: SocketAddr) -> Result<UtpStream, Error> {
+        let (packet_tx, packet_rx) = mpsc::channel(PACKET_CHANNEL_SIZE);
+
+        let conn_id_recv = {
+            let mut connections = self.inner.connections.lock().expect("lock connections");
+            let conn_id_recv = loop {
+                let id: u16 = rand::random();
+                if !connections.contains_key(&(remote, id)) {
+                    break id;
+                }
+            };
+            connections.insert((remote, conn_id_recv), packet_tx);
+            conn_id_recv
+        };
+
+        let (app, conn) = tokio::io::duplex(DUPLEX_BUFFER_SIZE);
+        let (connected_tx, connected_rx) = oneshot::channel();
+        tokio::spawn(Connection::run_initiator(
+            self.inner.clone(),
+            remote,
+            conn_id_recv,
+            conn,
+            packet_rx,
+            connected_tx,
+        ));
+
+        match connected_rx.await {
+            Ok(Ok(_)) => Ok(UtpStream { inner: app, peer_addr: remote }),
+            Ok(Err(error)) => Err(error),
+            Err(_) => Err(Error::new_net(None, Some("utp connect aborted".to_owned()), Some(remote))),
+        }
+    }
+
+    /// 接受一条传入连接。首次调用后 socket 才开始接受 SYN，之前收到的 SYN 会被 RESET
+    pub async fn accept(&self) -> Result<UtpStream, Error> {
+        self.inner.accepting.store(true, Ordering::Release);
+
+        self.accept_rx
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or_else(|| Error::new_general("utp socket closed"))
+    }
+}
+
+impl Drop for UtpSocket {
+    fn drop(&mut self) {
+        self.dispatcher.abort();
+    }
+}
+
+async fn dispatch_loop(inner: Arc<SocketInner>) {
+    let mut buf = vec![0; RECV_BUFFER_SIZE];
+
+    loop {
+        let (len, addr) = match inner.udp.recv_from(&mut buf).await {
+            Ok(val) => val,
+            Err(error) => {
+                log::trace!(target: "yiilian_dl::bt::net::utp", "utp recv_from error: {}", error);
+                continue;
+            }
+        };
+
+        let packet = match Packet::try_from(&buf[..len]) {
+            Ok(packet) => packet,
+            Err(_) => continue,
+        };
+
+        // SYN 中携带的是发起方的 conn_id_recv，本端的接收 id 为其 + 1
+        let conn_id = if packet.packet_type == PacketType::Syn {
+            packet.connection_id.wrapping_add(1)
+        } else {
+            packet.connection_id
+        };
+        let key = (addr, conn_id);
+
+        let packet_tx = inner.connections.lock().expect("lock connections").get(&key).cloned();
+        if let Some(packet_tx) = packet_tx {
+            // 通道满时丢包，由对方重传
+            let _ = packet_tx.try_send(packet);
+            continue;
+        }
+
+        if packet.packet_type == PacketType::Syn && inner.accepting.load(Ordering::Acquire) {
+            let (packet_tx, packet_rx) = mpsc::channel(PACKET_CHANNEL_SIZE);
+            let (app, conn) = tokio::io::duplex(DUPLEX_BUFFER_SIZE);
+
+            if inner.accept_tx.try_send(UtpStream { inner: app, peer_addr: addr }).is_ok() {
+                inner.connections.lock().expect("lock connections").insert(key, packet_tx);
+                tokio::spawn(Connection::run_acceptor(inner.clone(), addr, packet, conn, packet_rx));
+                continue;
+            }
+        }
+
+        if packet.packet_type != PacketType::Reset {
+            let reset = Packet::new(PacketType::Reset, packet.connection_id, rand::random(), packet.seq_nr);
+            let data: Bytes = (&reset).into();
+            let _ = inner.send_to(&data, addr).await;
+        }
+    }
+}
+
+/// 一条 uTP 连接，可像 TcpStream 一样读写
+pub struct UtpStream {
+    inner: DuplexStream,
+    peer_addr: SocketAddr,
+}
+
+impl UtpStream {
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+}
+
+impl AsyncRead for UtpStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UtpStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::UdpSocket,
+    };
+
+    use super::UtpSocket;
+
+    /// 随机丢弃 5% 数据包的 UDP 转发器
+    async fn lossy_relay(server: SocketAddr) -> SocketAddr {
+        let relay = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let relay_addr = relay.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = vec![0; 65536];
+            let mut client = None;
+
+            loop {
+                let (len, addr) = relay.recv_from(&mut buf).await.unwrap();
+                if rand::random::<u8>() < 13 {
+                    continue;
+                }
+
+                if addr == server {
+                    if let Some(client) = client {
+                        relay.send_to(&buf[..len], client).await.unwrap();
+                    }
+                } else {
+                    client = Some(addr);
+                    relay.send_to(&buf[..len], server).await.unwrap();
+                }
+            }
+        });
+
+        relay_addr
+    }
+
+    #[tokio::test]
+    async fn test_connect_accept() {
+        let server = UtpSocket::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let client = UtpSocket::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let relay_addr = lossy_relay(server_addr).await;
+
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+
+        let expect = data.clone();
+        let server_task = tokio::spawn(async move {
+            let mut stream = server.accept().await.unwrap();
+
+            let mut buf = vec![];
+            stream.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(expect, buf);
+
+            stream.write_all(b"done").await.unwrap();
+            stream.shutdown().await.unwrap();
+        });
+
+        let mut stream = client.connect(relay_addr).await.unwrap();
+        stream.write_all(&data).await.unwrap();
+        stream.shutdown().await.unwrap();
+
+        let mut buf = vec![];
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(b"done", &buf[..]);
+
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connect_refused() {
+        // 未调用 accept 的 socket 回复 RESET
+        let server = UtpSocket::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let client = UtpSocket::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+        assert!(client.connect(server.local_addr().unwrap()).await.is_err());
+    }
+}
@@ -1,28 +1,74 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, net::SocketAddr, sync::Mutex, time::Duration};
 
 use bytes::{BufMut, Bytes, BytesMut};
 use sha1::{Digest, Sha1};
-use tokio::net::TcpStream;
+use sha2::Sha256;
+use tokio::{
+    io::{ReadHalf, WriteHalf},
+    sync::{broadcast, mpsc, watch},
+    time::interval,
+};
 use yiilian_core::{
     common::error::Error,
     data::{decode, BencodeData}, net::tcp::{read_bt_handshake, send_bt_handshake},
 };
 
 use crate::bt::{
+    download::{Bitfield, BlockRequest, DownloadSession},
     data::frame::{
         extension::{
-            ExtensionHeader, UtMetadata, METADATA_PIECE_BLOCK, UT_METADATA_ID, UT_METADATA_NAME,
+            ExtensionHeader, UtMetadata, UtPex, MAX_PEX_PEERS, METADATA_PIECE_BLOCK, UT_METADATA_ID,
+            UT_METADATA_NAME, UT_PEX_ID,
         },
         PeerMessage,
     },
-    net::tcp::{read_message, send_message},
+    net::{
+        tcp::{read_message, read_message_idle, send_message},
+        Transport,
+    },
 };
 
-pub struct PeerWire;
+/// 每个 peer 同时在途的块请求数
+pub const PIPELINE_DEPTH: usize = 16;
+/// 允许对方单次请求的最大块长度
+pub const MAX_REQUEST_LEN: u32 = 128 * 1024;
+/// 超过该时长未收到任何消息（包括 keep-alive）则断开
+pub const PEER_IDLE_TIMEOUT_SEC: u64 = 180;
+pub const KEEP_ALIVE_INTERVAL_SEC: u64 = 90;
+const MESSAGE_CHANNEL_SIZE: usize = 32;
+
+pub struct PeerWire {
+    /// 对方通过 ut_pex 告知的 peers
+    pex_peers: Mutex<Vec<SocketAddr>>,
+}
 
 impl PeerWire {
     pub fn new() -> Self {
-        PeerWire
+        PeerWire {
+            pex_peers: Mutex::new(vec![]),
+        }
+    }
+
+    /// 取出交换过程中通过 ut_pex 收到的 peers
+    pub fn take_pex_peers(&self) -> Vec<SocketAddr> {
+        std::mem::take(&mut *self.pex_peers.lock().expect("lock pex_peers"))
+    }
+
+    fn add_pex_peers(&self, payload: Bytes) {
+        let pex: UtPex = match payload.try_into() {
+            Ok(pex) => pex,
+            Err(error) => {
+                log::trace!(target:"yiilian_dl::bt::peer_wire", "invalid ut_pex message: {:?}", error);
+                return;
+            }
+        };
+
+        let mut pex_peers = self.pex_peers.lock().expect("lock pex_peers");
+        for peer in pex.added_peers().into_iter().take(MAX_PEX_PEERS) {
+            if !pex_peers.contains(&peer) {
+                pex_peers.push(peer);
+            }
+        }
     }
 
     // pub async fn download_metadata(
@@ -41,9 +87,9 @@ impl PeerWire {
     //     Ok(())
     // }
 
-    pub async fn fetch_info(
+    pub async fn fetch_info<S: Transport>(
         &self,
-        stream: TcpStream,
+        stream: S,
         info_hash: &[u8],
         local_peer_id: &[u8],
         is_hook: bool,
@@ -59,9 +105,9 @@ impl PeerWire {
         decode(&info)?.as_map().map(|m| m.to_owned())
     }
 
-    pub async fn fetch_metdata(
+    pub async fn fetch_metdata<S: Transport>(
         &self,
-        mut stream: TcpStream,
+        mut stream: S,
         info_hash: &[u8],
         local_peer_id: &[u8],
         is_hook: bool,
@@ -175,16 +221,12 @@ impl PeerWire {
                                             metadata_info.extend(item);
                                         }
 
-                                        let mut hasher = Sha1::new();
-                                        hasher.update(&metadata_info);
-                                        let i_hash = hasher.finalize().to_vec();
-
-                                        if i_hash != info_hash {
+                                        if !is_metadata_valid(&metadata_info, info_hash) {
                                             return Err(Error::new_frame(
                                                 None,
                                                 Some(format!(
                                                     "metadata info_hash is invalid: {:?}",
-                                                    i_hash
+                                                    info_hash
                                                 )),
                                             ));
                                         }
@@ -198,6 +240,8 @@ impl PeerWire {
                             _ => {}
                         }
                     }
+                    // UT_PEX 消息
+                    UT_PEX_ID => self.add_pex_peers(payload),
                     _ => {}
                 },
                 // PeerMessage::Bitfield {bitfield} => {
@@ -225,9 +269,275 @@ impl PeerWire {
     }
 }
 
+/// 与对端 peer 交换分片过程中的连接状态
+struct PeerState {
+    peer_id: u64,
+    bitfield: Bitfield,
+    /// 对方是否阻塞我们
+    peer_choking: bool,
+    /// 我们是否阻塞对方
+    am_choking: bool,
+    am_interested: bool,
+    in_flight: Vec<BlockRequest>,
+}
+
+impl PeerWire {
+    /// 与 peer 交换分片数据：下载缺少的分片，并在 choker 解除阻塞时上传已校验的分片。
+    /// 双方都拥有全部分片或连接出错时返回
+    pub async fn exchange_pieces<S: Transport>(
+        &self,
+        mut stream: S,
+        session: &DownloadSession,
+        local_peer_id: &[u8],
+        is_hook: bool,
+    ) -> Result<(), Error> {
+        if !is_hook {
+            send_bt_handshake(&mut stream, session.info_hash(), local_peer_id).await?;
+
+            let handshake = read_bt_handshake(&mut stream).await?;
+            if handshake.info_hash()[..] != session.info_hash()[..] {
+                return Err(Error::new_frame(
+                    None,
+                    Some(format!("handshake info_hash mismatch: {:?}", handshake.info_hash())),
+                ));
+            }
+        }
+
+        let (peer_id, choke_rx) = session.register_peer();
+        let have_rx = session.subscribe_have();
+        let mut peer = PeerState {
+            peer_id,
+            bitfield: Bitfield::new(session.piece_count()),
+            peer_choking: true,
+            am_choking: true,
+            am_interested: false,
+            in_flight: vec![],
+        };
+
+        // 读写分离：读取放在单独的任务中，主循环可以同时响应 choker 和本地 Have 通知
+        let (reader, mut writer) = tokio::io::split(stream);
+        let (msg_tx, mut msg_rx) = mpsc::channel(MESSAGE_CHANNEL_SIZE);
+        let reader_task = tokio::spawn(read_loop(reader, msg_tx));
+
+        let rst = exchange_loop(&mut writer, &mut msg_rx, choke_rx, have_rx, session, &mut peer).await;
+
+        reader_task.abort();
+        session.unregister_peer(peer.peer_id, &peer.bitfield, &peer.in_flight);
+
+        rst
+    }
+}
+
+async fn read_loop<S: Transport>(
+    mut reader: ReadHalf<S>,
+    msg_tx: mpsc::Sender<Result<PeerMessage, Error>>,
+) {
+    loop {
+        let rst = read_message_idle(&mut reader, Duration::from_secs(PEER_IDLE_TIMEOUT_SEC))
+            .await
+            .and_then(|msg| msg.try_into());
+        let is_err = rst.is_err();
+
+        if msg_tx.send(rst).await.is_err() || is_err {
+            break;
+        }
+    }
+}
+
+async fn exchange_loop<S: Transport>(
+    writer: &mut WriteHalf<S>,
+    msg_rx: &mut mpsc::Receiver<Result<PeerMessage, Error>>,
+    mut choke_rx: watch::Receiver<bool>,
+    mut have_rx: broadcast::Receiver<u32>,
+    session: &DownloadSession,
+    peer: &mut PeerState,
+) -> Result<(), Error> {
+    let have = session.have();
+    if have.count() > 0 {
+        let p_msg: Bytes = PeerMessage::Bitfield { bitfield: have.as_bytes().to_owned().into() }.into();
+        send_message(writer, &p_msg).await?;
+    }
+
+    if !session.is_complete() {
+        peer.am_interested = true;
+        let p_msg: Bytes = PeerMessage::Interested.into();
+        send_message(writer, &p_msg).await?;
+    }
+
+    let mut keep_alive = interval(Duration::from_secs(KEEP_ALIVE_INTERVAL_SEC));
+    keep_alive.tick().await;
+
+    loop {
+        if session.is_complete() {
+            if peer.am_interested {
+                peer.am_interested = false;
+                let p_msg: Bytes = PeerMessage::NotInterested.into();
+                send_message(writer, &p_msg).await?;
+            }
+
+            // 双方都是做种者，无需保持连接
+            if peer.bitfield.is_full() {
+                return Ok(());
+            }
+        }
+
+        tokio::select! {
+            msg = msg_rx.recv() => {
+                let msg = match msg {
+                    Some(msg) => msg?,
+                    None => return Err(Error::new_net(None, Some("peer reader closed".to_owned()), None)),
+                };
+                handle_message(writer, session, peer, msg).await?;
+            }
+            rst = choke_rx.changed() => {
+                if rst.is_err() {
+                    return Ok(());
+                }
+
+                let choked = *choke_rx.borrow_and_update();
+                if choked != peer.am_choking {
+                    peer.am_choking = choked;
+                    let p_msg: Bytes = if choked { PeerMessage::Choke } else { PeerMessage::UnChoke }.into();
+                    send_message(writer, &p_msg).await?;
+                }
+            }
+            rst = have_rx.recv() => {
+                match rst {
+                    Ok(index) => {
+                        let p_msg: Bytes = PeerMessage::Have { index }.into();
+                        send_message(writer, &p_msg).await?;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                }
+            }
+            _ = keep_alive.tick() => {
+                let p_msg: Bytes = PeerMessage::KeepAlive.into();
+                send_message(writer, &p_msg).await?;
+            }
+        }
+
+        if peer.peer_choking || !peer.am_interested {
+            continue;
+        }
+
+        // 取消已被其它 peer 完成的分片请求（endgame）
+        let mut canceled = vec![];
+        peer.in_flight.retain(|req| {
+            if session.has_piece(req.index) {
+                canceled.push(*req);
+                false
+            } else {
+                true
+            }
+        });
+        for req in canceled {
+            let p_msg: Bytes = PeerMessage::Cancel {
+                index: req.index,
+                offset_begin: req.begin,
+                offset_length: req.length,
+            }
+            .into();
+            send_message(writer, &p_msg).await?;
+        }
+
+        // 补满请求流水线
+        let max = PIPELINE_DEPTH.saturating_sub(peer.in_flight.len());
+        let reqs = session.pick(&peer.bitfield, &peer.in_flight, max);
+        for req in reqs {
+            peer.in_flight.push(req);
+
+            let p_msg: Bytes = PeerMessage::Request {
+                index: req.index,
+                offset_begin: req.begin,
+                offset_length: req.length,
+            }
+            .into();
+            send_message(writer, &p_msg).await?;
+        }
+    }
+}
+
+async fn handle_message<S: Transport>(
+    writer: &mut WriteHalf<S>,
+    session: &DownloadSession,
+    peer: &mut PeerState,
+    msg: PeerMessage,
+) -> Result<(), Error> {
+    match msg {
+        PeerMessage::Bitfield { bitfield } => {
+            let bitfield = Bitfield::from_bytes(&bitfield, session.piece_count())?;
+            session.remove_peer(&peer.bitfield);
+            session.add_peer(&bitfield);
+            peer.bitfield = bitfield;
+        }
+        PeerMessage::Have { index } => {
+            if (index as usize) < peer.bitfield.len() && !peer.bitfield.has(index as usize) {
+                peer.bitfield.set(index as usize);
+                session.add_have(index);
+            }
+        }
+        PeerMessage::Choke => {
+            peer.peer_choking = true;
+            session.cancel(&peer.in_flight);
+            peer.in_flight.clear();
+        }
+        PeerMessage::UnChoke => {
+            peer.peer_choking = false;
+        }
+        PeerMessage::Interested => {
+            session.set_interested(peer.peer_id, true);
+        }
+        PeerMessage::NotInterested => {
+            session.set_interested(peer.peer_id, false);
+        }
+        PeerMessage::Piece {
+            index,
+            offset_begin,
+            piece,
+        } => {
+            peer.in_flight
+                .retain(|req| !(req.index == index && req.begin == offset_begin));
+            session.add_downloaded(peer.peer_id, piece.len() as u64);
+            session.on_block(index, offset_begin, &piece)?;
+        }
+        PeerMessage::Request {
+            index,
+            offset_begin,
+            offset_length,
+        } => {
+            // 阻塞中的请求直接丢弃，对方收到 Choke 后会自行重发
+            if peer.am_choking {
+                return Ok(());
+            }
+
+            if offset_length > MAX_REQUEST_LEN {
+                return Err(Error::new_frame(
+                    None,
+                    Some(format!("request length is too large: {}", offset_length)),
+                ));
+            }
+
+            let piece = session.read_block(index, offset_begin, offset_length)?;
+            let p_msg: Bytes = PeerMessage::Piece {
+                index,
+                offset_begin,
+                piece,
+            }
+            .into();
+            send_message(writer, &p_msg).await?;
+            session.add_uploaded(peer.peer_id, offset_length as u64);
+        }
+        // 请求在收到时已立即响应，无需处理 Cancel
+        _ => {}
+    }
+
+    Ok(())
+}
+
 /// 请求分片
-async fn request_pieces(
-    stream: &mut TcpStream,
+async fn request_pieces<S: Transport>(
+    stream: &mut S,
     ut_metadata_id: u8,
     metadata_size: usize,
 ) -> Result<usize, Error> {
@@ -248,8 +558,111 @@ async fn request_pieces(
     Ok(pieces_num)
 }
 
+/// v1 的 info_hash 为 SHA-1，纯 v2 种子在握手中使用截断到 20 字节的 SHA-256 (BEP52)
+fn is_metadata_valid(metadata: &[u8], info_hash: &[u8]) -> bool {
+    if Sha1::digest(metadata)[..] == info_hash[..] {
+        return true;
+    }
+
+    Sha256::digest(metadata)[..info_hash.len().min(32)] == info_hash[..]
+}
+
 fn is_pieces_done(pieces: &Vec<Bytes>) -> bool {
     let rst = !pieces.iter().any(|item| item.len() == 0);
 
     rst
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc, time::Duration};
+
+    use bytes::BytesMut;
+    use sha1::{Digest, Sha1};
+    use tokio::net::{TcpListener, TcpStream};
+    use yiilian_core::data::{FileInfo, MetaInfo};
+
+    use crate::bt::download::{DownloadSession, BLOCK_SIZE};
+
+    use super::PeerWire;
+
+    #[tokio::test]
+    async fn test_exchange_pieces() {
+        let piece_length = BLOCK_SIZE as usize * 2;
+        let data: Vec<u8> = (0..piece_length * 3 + 100).map(|i| (i % 251) as u8).collect();
+        let pieces = {
+            let mut rst = BytesMut::new();
+            for chunk in data.chunks(piece_length) {
+                let mut hasher = Sha1::new();
+                hasher.update(chunk);
+                rst.extend(hasher.finalize());
+            }
+            rst.into()
+        };
+        let meta = MetaInfo::MultiFile {
+            files: vec![
+                FileInfo { length: 1000, path: "a".to_owned() },
+                FileInfo { length: data.len() as i64 - 1000, path: "dir/b".to_owned() },
+            ],
+            name: "exchange".to_owned(),
+            pieces,
+            piece_length,
+        };
+        let info_hash = [7; 20];
+
+        let seed_dir = std::env::temp_dir().join("yiilian_dl_test_exchange_seed");
+        let leech_dir = std::env::temp_dir().join("yiilian_dl_test_exchange_leech");
+        let _ = fs::remove_dir_all(&seed_dir);
+        let _ = fs::remove_dir_all(&leech_dir);
+
+        // 准备做种端数据
+        let setup = DownloadSession::new(&info_hash, &meta, &seed_dir, None).unwrap();
+        let mut bitfield = setup.have();
+        for (index, chunk) in data.chunks(piece_length).enumerate() {
+            setup.storage().write_piece(index as u32, chunk).unwrap();
+            bitfield.set(index);
+        }
+        setup.storage().save_bitfield(&bitfield).unwrap();
+        let seeder = Arc::new(DownloadSession::new(&info_hash, &meta, &seed_dir, None).unwrap());
+        assert!(seeder.is_complete());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let seed_session = seeder.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            PeerWire::new()
+                .exchange_pieces(stream, &seed_session, &[1; 20], false)
+                .await
+        });
+        let choke_session = seeder.clone();
+        tokio::spawn(async move {
+            loop {
+                choke_session.rechoke();
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        });
+
+        let leecher = DownloadSession::new(&info_hash, &meta, &leech_dir, None).unwrap();
+        let stream = TcpStream::connect(addr).await.unwrap();
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            PeerWire::new().exchange_pieces(stream, &leecher, &[2; 20], false),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert!(leecher.is_complete());
+        assert_eq!(data[..1000], fs::read(leech_dir.join("exchange/a")).unwrap());
+        assert_eq!(data[1000..], fs::read(leech_dir.join("exchange/dir/b")).unwrap());
+
+        // 重新打开会话时从续传 bitfield 恢复
+        let reopened = DownloadSession::new(&info_hash, &meta, &leech_dir, None).unwrap();
+        assert!(reopened.is_complete());
+
+        fs::remove_dir_all(&seed_dir).unwrap();
+        fs::remove_dir_all(&leech_dir).unwrap();
+    }
+}
@@ -0,0 +1,434 @@
+use std::{collections::HashMap, net::{IpAddr, SocketAddr}};
+
+use bytes::{Bytes, BytesMut};
+use percent_encoding::{percent_encode, NON_ALPHANUMERIC};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    time::{sleep, timeout},
+};
+use tokio_native_tls::{native_tls, TlsConnector};
+use url::Url;
+use yiilian_core::{
+    common::error::Error,
+    data::{decode, BencodeData},
+};
+use yiilian_dht::common::ID_SIZE;
+
+use crate::bt::net::tcp::read_all;
+
+use super::{parse_compact_peers, AnnounceRequest, AnnounceResponse, ScrapeInfo, TrackerClient};
+
+pub(super) async fn announce(
+    client: &TrackerClient,
+    url: &Url,
+    req: &AnnounceRequest,
+) -> Result<AnnounceResponse, Error> {
+    let mut query = format!(
+        "info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}&compact=1&numwant={}",
+        percent_encode(&req.info_hash, NON_ALPHANUMERIC),
+        percent_encode(&req.peer_id, NON_ALPHANUMERIC),
+        req.port,
+        req.uploaded,
+        req.downloaded,
+        req.left,
+        req.num_want,
+    );
+    if let Some(event) = req.event.as_str() {
+        query += &format!("&event={}", event);
+    }
+
+    let body = get(client, url, &query).await?;
+
+    parse_announce(&body)
+}
+
+pub(super) async fn scrape(
+    client: &TrackerClient,
+    url: &Url,
+    info_hashes: &[[u8; ID_SIZE]],
+) -> Result<HashMap<[u8; ID_SIZE], ScrapeInfo>, Error> {
+    let url = scrape_url(url)?;
+    let query = info_hashes
+        .iter()
+        .map(|info_hash| format!("info_hash={}", percent_encode(info_hash, NON_ALPHANUMERIC)))
+        .collect::<Vec<String>>()
+        .join("&");
+
+    let body = get(client, &url, &query).await?;
+
+    parse_scrape(&body)
+}
+
+/// 按约定将路径最后一段中的 "announce" 替换为 "scrape"，不符合约定的 tracker 不支持 scrape
+fn scrape_url(url: &Url) -> Result<Url, Error> {
+    let path = url.path();
+    let pos = path.rfind('/').map(|pos| pos + 1).unwrap_or(0);
+
+    if !path[pos..].starts_with("announce") {
+        Err(Error::new_general(&format!("tracker not support scrape: {}", url)))?
+    }
+
+    let mut rst = url.clone();
+    rst.set_path(&format!("{}scrape{}", &path[..pos], &path[pos + "announce".len()..]));
+
+    Ok(rst)
+}
+
+/// 发送 GET 请求，失败时以指数增长的超时重试
+async fn get(client: &TrackerClient, url: &Url, query: &str) -> Result<Bytes, Error> {
+    let mut last_error = None;
+
+    for attempt in 0..=client.retries {
+        let attempt_timeout = client.attempt_timeout(attempt);
+
+        match timeout(attempt_timeout, http_get(url, query)).await {
+            Ok(Ok(body)) => return Ok(body),
+            Ok(Err(error)) => {
+                last_error = Some(error);
+                if attempt < client.retries {
+                    sleep(attempt_timeout).await;
+                }
+            }
+            Err(_) => last_error = Some(Error::new_timeout(&format!("http tracker timeout: {}", url))),
+        }
+    }
+
+    Err(last_error.expect("last_error can't be none"))
+}
+
+async fn http_get(url: &Url, query: &str) -> Result<Bytes, Error> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| Error::new_general(&format!("tracker url without host: {}", url)))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let host_header = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_owned(),
+    };
+    let query = match url.query() {
+        Some(val) => format!("{}&{}", val, query),
+        None => query.to_owned(),
+    };
+
+    let host = host.trim_matches(|c| c == '[' || c == ']');
+    let stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|error| Error::new_net(Some(error.into()), Some(format!("connect tracker {}", url)), None))?;
+
+    let request = format!(
+        "GET {}?{} HTTP/1.1\r\nHost: {}\r\nUser-Agent: yiilian\r\nAccept-Encoding: identity\r\nConnection: close\r\n\r\n",
+        url.path(),
+        query,
+        host_header
+    );
+
+    let rsp = if url.scheme() == "https" {
+        let connector = native_tls::TlsConnector::new()
+            .map_err(|error| Error::new_net(Some(error.into()), Some("create tls connector".to_owned()), None))?;
+        let mut stream = TlsConnector::from(connector)
+            .connect(host, stream)
+            .await
+            .map_err(|error| Error::new_net(Some(error.into()), Some(format!("tls handshake {}", url)), None))?;
+
+        send_request(&mut stream, &request).await?
+    } else {
+        let mut stream = stream;
+        send_request(&mut stream, &request).await?
+    };
+
+    parse_http_response(&rsp)
+}
+
+/// 发送请求并读取完整响应（请求头中 Connection: close）
+async fn send_request<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, request: &str) -> Result<Bytes, Error> {
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|error| Error::new_net(Some(error.into()), Some("send tracker request".to_owned()), None))?;
+
+    read_all(stream).await
+}
+
+/// 解析 HTTP 响应，返回 body
+fn parse_http_response(rsp: &[u8]) -> Result<Bytes, Error> {
+    let head_end = rsp
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| Error::new_frame(None, Some("http response without header end".to_owned())))?;
+    let head = String::from_utf8_lossy(&rsp[..head_end]);
+    let body = &rsp[head_end + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or("");
+    if status != "200" {
+        Err(Error::new_net(None, Some(format!("tracker http status: {}", status)), None))?
+    }
+
+    let mut is_chunked = false;
+    let mut content_length = None;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("transfer-encoding") && value.eq_ignore_ascii_case("chunked") {
+                is_chunked = true;
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse::<usize>().ok();
+            }
+        }
+    }
+
+    if is_chunked {
+        decode_chunked(body)
+    } else {
+        let len = content_length.unwrap_or(body.len()).min(body.len());
+        Ok(body[..len].to_owned().into())
+    }
+}
+
+fn decode_chunked(mut body: &[u8]) -> Result<Bytes, Error> {
+    let mut rst = BytesMut::new();
+
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| Error::new_frame(None, Some("invalid chunked body".to_owned())))?;
+        let size_str = String::from_utf8_lossy(&body[..line_end]);
+        let size_str = size_str.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_str, 16)
+            .map_err(|error| Error::new_frame(Some(error.into()), Some("invalid chunk size".to_owned())))?;
+
+        body = &body[line_end + 2..];
+        if size == 0 {
+            break;
+        }
+        if body.len() < size {
+            Err(Error::new_frame(None, Some("chunk is truncated".to_owned())))?
+        }
+
+        rst.extend(&body[..size]);
+        body = &body[size..];
+        if body.starts_with(b"\r\n") {
+            body = &body[2..];
+        }
+    }
+
+    Ok(rst.into())
+}
+
+fn check_failure(data: &BencodeData) -> Result<(), Error> {
+    if let Some(reason) = data.get_dict_item("failure reason") {
+        let reason = String::from_utf8_lossy(reason.as_bstr()?).to_string();
+        Err(Error::new_general(&format!("tracker failure: {}", reason)))?
+    }
+
+    Ok(())
+}
+
+fn get_u32(data: &BencodeData, key: &str) -> Result<Option<u32>, Error> {
+    match data.get_dict_item(key) {
+        Some(val) => Ok(Some(val.as_int()? as u32)),
+        None => Ok(None),
+    }
+}
+
+fn parse_announce(body: &[u8]) -> Result<AnnounceResponse, Error> {
+    let data = decode(body)?;
+    check_failure(&data)?;
+
+    let mut peers = vec![];
+    match data.get_dict_item("peers") {
+        Some(BencodeData::Str(val)) => peers.extend(parse_compact_peers(val, 6)?),
+        Some(BencodeData::List(val)) => {
+            // 非紧凑格式：[{ip, peer id, port}]
+            for item in val {
+                let ip = item
+                    .get_dict_item("ip")
+                    .ok_or_else(|| Error::new_decode("tracker peer without ip"))?
+                    .as_bstr()?;
+                let ip: IpAddr = String::from_utf8_lossy(ip)
+                    .parse()
+                    .map_err(|error| Error::new_frame(Some(Box::new(error)), Some("tracker peer ip".to_owned())))?;
+                let port = item
+                    .get_dict_item("port")
+                    .ok_or_else(|| Error::new_decode("tracker peer without port"))?
+                    .as_int()? as u16;
+
+                peers.push(SocketAddr::new(ip, port));
+            }
+        }
+        _ => {}
+    }
+    if let Some(val) = data.get_dict_item("peers6") {
+        peers.extend(parse_compact_peers(val.as_bstr()?, 18)?);
+    }
+
+    Ok(AnnounceResponse {
+        interval: get_u32(&data, "interval")?.unwrap_or(0),
+        leechers: get_u32(&data, "incomplete")?,
+        seeders: get_u32(&data, "complete")?,
+        peers,
+    })
+}
+
+fn parse_scrape(body: &[u8]) -> Result<HashMap<[u8; ID_SIZE], ScrapeInfo>, Error> {
+    let data = decode(body)?;
+    check_failure(&data)?;
+
+    let mut rst = HashMap::new();
+    if let Some(files) = data.get_dict_item("files") {
+        for (info_hash, item) in files.as_map()? {
+            let info_hash: [u8; ID_SIZE] = match info_hash[..].try_into() {
+                Ok(val) => val,
+                Err(_) => continue,
+            };
+
+            rst.insert(
+                info_hash,
+                ScrapeInfo {
+                    seeders: get_u32(item, "complete")?.unwrap_or(0),
+                    completed: get_u32(item, "downloaded")?.unwrap_or(0),
+                    leechers: get_u32(item, "incomplete")?.unwrap_or(0),
+                },
+            );
+        }
+    }
+
+    Ok(rst)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use url::Url;
+    use yiilian_core::common::error::Kind;
+
+    use crate::bt::tracker::{AnnounceRequest, ScrapeInfo, TrackerClient};
+
+    use super::{decode_chunked, scrape_url};
+
+    /// 本地 HTTP tracker 桩：校验请求行后返回固定的 bencode 响应
+    async fn stub_tracker(expect: &'static str, body: Vec<u8>, chunked: bool) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_string();
+            assert!(request.starts_with(expect), "{}", request);
+
+            let rsp = if chunked {
+                let mut rsp = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+                rsp.extend(format!("{:x}\r\n", body.len()).as_bytes());
+                rsp.extend(&body);
+                rsp.extend(b"\r\n0\r\n\r\n");
+                rsp
+            } else {
+                let mut rsp = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+                rsp.extend(&body);
+                rsp
+            };
+            stream.write_all(&rsp).await.unwrap();
+        });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_announce() {
+        let mut body = b"d8:completei3e10:incompletei5e8:intervali1800e5:peers12:".to_vec();
+        body.extend([127, 0, 0, 1, 0x1a, 0xe1, 10, 0, 0, 2, 0x1a, 0xe2]);
+        body.extend(b"e");
+
+        let addr = stub_tracker(
+            "GET /announce?key=1&info_hash=%01%01%01%01%01%01%01%01%01%01%01%01%01%01%01%01%01%01%01%01&peer_id=",
+            body,
+            false,
+        )
+        .await;
+
+        let client = TrackerClient::new(Duration::from_secs(1), 0);
+        let req = AnnounceRequest::new([1; 20], [2; 20], 6881, 100);
+        let rst = client
+            .announce(&format!("http://{}/announce?key=1", addr), &req)
+            .await
+            .unwrap();
+
+        assert_eq!(1800, rst.interval);
+        assert_eq!(Some(3), rst.seeders);
+        assert_eq!(Some(5), rst.leechers);
+        assert_eq!(
+            vec!["127.0.0.1:6881".parse::<SocketAddr>().unwrap(), "10.0.0.2:6882".parse().unwrap()],
+            rst.peers
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failure_and_scrape() {
+        let addr = stub_tracker("GET /announce", b"d14:failure reason4:oopse".to_vec(), true).await;
+        let client = TrackerClient::new(Duration::from_secs(1), 0);
+        let req = AnnounceRequest::new([1; 20], [2; 20], 6881, 100);
+        assert!(client.announce(&format!("http://{}/announce", addr), &req).await.is_err());
+
+        let mut body = b"d5:filesd20:".to_vec();
+        body.extend([1; 20]);
+        body.extend(b"d8:completei1e10:downloadedi2e10:incompletei3eeee");
+        let addr = stub_tracker("GET /x/scrape.php?info_hash=", body, true).await;
+
+        let rst = client
+            .scrape(&format!("http://{}/x/announce.php", addr), &[[1; 20]])
+            .await
+            .unwrap();
+        assert_eq!(
+            Some(&ScrapeInfo { seeders: 1, completed: 2, leechers: 3 }),
+            rst.get(&[1; 20])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_https() {
+        // https 走 TLS：对方收到的是 ClientHello，而不是明文的 GET 请求
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await.unwrap();
+
+            buf[..n].to_vec()
+        });
+
+        let client = TrackerClient::new(Duration::from_secs(1), 0);
+        let req = AnnounceRequest::new([1; 20], [2; 20], 6881, 100);
+        let error = client
+            .announce(&format!("https://{}/announce", addr), &req)
+            .await
+            .unwrap_err();
+        assert_eq!(Kind::Net, error.get_kind());
+
+        // TLS 握手记录的类型为 0x16
+        assert_eq!(0x16, server.await.unwrap()[0]);
+    }
+
+    #[test]
+    fn test_helpers() {
+        assert_eq!(&b"hello world"[..], decode_chunked(b"5\r\nhello\r\n6;ext=1\r\n world\r\n0\r\n\r\n").unwrap());
+
+        let url = Url::parse("http://t.example/announce?passkey=1").unwrap();
+        assert_eq!("http://t.example/scrape?passkey=1", scrape_url(&url).unwrap().as_str());
+        let url = Url::parse("http://t.example/a").unwrap();
+        assert!(scrape_url(&url).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+mod http;
+mod udp;
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use url::Url;
+use yiilian_core::common::error::Error;
+use yiilian_dht::common::ID_SIZE;
+
+/// 第 n 次重试的超时为 timeout_base * 2^n (BEP15)
+pub const DEFAULT_TRACKER_TIMEOUT_SEC: u64 = 15;
+pub const DEFAULT_TRACKER_RETRIES: u32 = 2;
+pub const DEFAULT_NUM_WANT: i32 = 50;
+const CONNECTION_ID_TTL: Duration = Duration::from_secs(60);
+
+/// announce 中的 event 字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnounceEvent {
+    None = 0,
+    Completed = 1,
+    Started = 2,
+    Stopped = 3,
+}
+
+impl AnnounceEvent {
+    fn as_str(&self) -> Option<&'static str> {
+        match self {
+            AnnounceEvent::None => None,
+            AnnounceEvent::Completed => Some("completed"),
+            AnnounceEvent::Started => Some("started"),
+            AnnounceEvent::Stopped => Some("stopped"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnnounceRequest {
+    pub info_hash: [u8; ID_SIZE],
+    pub peer_id: [u8; ID_SIZE],
+    pub port: u16,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub left: u64,
+    pub event: AnnounceEvent,
+    pub num_want: i32,
+}
+
+impl AnnounceRequest {
+    pub fn new(info_hash: [u8; ID_SIZE], peer_id: [u8; ID_SIZE], port: u16, left: u64) -> Self {
+        AnnounceRequest {
+            info_hash,
+            peer_id,
+            port,
+            uploaded: 0,
+            downloaded: 0,
+            left,
+            event: AnnounceEvent::Started,
+            num_want: DEFAULT_NUM_WANT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnnounceResponse {
+    /// 再次 announce 前应等待的秒数
+    pub interval: u32,
+    pub leechers: Option<u32>,
+    pub seeders: Option<u32>,
+    pub peers: Vec<SocketAddr>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrapeInfo {
+    pub seeders: u32,
+    /// 已完成下载的次数
+    pub completed: u32,
+    pub leechers: u32,
+}
+
+/// HTTP (BEP3 / BEP23) 与 UDP (BEP15) tracker 客户端
+#[derive(Debug)]
+pub struct TrackerClient {
+    timeout_base: Duration,
+    retries: u32,
+    /// announce 中的 key，用于 tracker 在 IP 变化时识别本客户端
+    key: u32,
+    /// UDP tracker 的 connection_id 缓存，有效期 1 分钟
+    connection_ids: Mutex<HashMap<SocketAddr, (u64, Instant)>>,
+}
+
+impl Default for TrackerClient {
+    fn default() -> Self {
+        TrackerClient::new(Duration::from_secs(DEFAULT_TRACKER_TIMEOUT_SEC), DEFAULT_TRACKER_RETRIES)
+    }
+}
+
+impl TrackerClient {
+    pub fn new(timeout_base: Duration, retries: u32) -> Self {
+        TrackerClient {
+            timeout_base,
+            retries,
+            key: rand::random(),
+            connection_ids: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get_connection_id(&self, addr: &SocketAddr) -> Option<u64> {
+        let connection_ids = self.connection_ids.lock().expect("lock connection_ids");
+        match connection_ids.get(addr) {
+            Some((connection_id, created_at)) if created_at.elapsed() < CONNECTION_ID_TTL => {
+                Some(*connection_id)
+            }
+            _ => None,
+        }
+    }
+
+    fn set_connection_id(&self, addr: SocketAddr, connection_id: u64) {
+        self.connection_ids
+            .lock()
+            .expect("lock connection_ids")
+            .insert(addr, (connection_id, Instant::now()));
+    }
+
+    fn remove_connection_id(&self, addr: &SocketAddr) {
+        self.connection_ids
+            .lock()
+            .expect("lock connection_ids")
+            .remove(addr);
+    }
+
+    /// 第 attempt 次尝试（从 0 开始）的超时
+    fn attempt_timeout(&self, attempt: u32) -> Duration {
+        self.timeout_base * 2u32.pow(attempt.min(8))
+    }
+
+    /// 一次 announce/scrape 含全部重试（及 HTTP 重试前的等待）的最长耗时
+    pub fn max_duration(&self) -> Duration {
+        let attempts: Duration = (0..=self.retries).map(|attempt| self.attempt_timeout(attempt)).sum();
+        let waits: Duration = (0..self.retries).map(|attempt| self.attempt_timeout(attempt)).sum();
+
+        attempts + waits
+    }
+
+    pub async fn announce(&self, tracker: &str, req: &AnnounceRequest) -> Result<AnnounceResponse, Error> {
+        let url = parse_tracker_url(tracker)?;
+
+        match url.scheme() {
+            "http" | "https" => http::announce(self, &url, req).await,
+            "udp" => udp::announce(self, &url, req).await,
+            scheme => Err(Error::new_general(&format!("unsupported tracker scheme: {}", scheme))),
+        }
+    }
+
+    pub async fn scrape(
+        &self,
+        tracker: &str,
+        info_hashes: &[[u8; ID_SIZE]],
+    ) -> Result<HashMap<[u8; ID_SIZE], ScrapeInfo>, Error> {
+        let url = parse_tracker_url(tracker)?;
+
+        match url.scheme() {
+            "http" | "https" => http::scrape(self, &url, info_hashes).await,
+            "udp" => udp::scrape(self, &url, info_hashes).await,
+            scheme => Err(Error::new_general(&format!("unsupported tracker scheme: {}", scheme))),
+        }
+    }
+}
+
+fn parse_tracker_url(tracker: &str) -> Result<Url, Error> {
+    Url::parse(tracker).map_err(|error| {
+        Error::new_general(&format!("invalid tracker url {}: {}", tracker, error))
+    })
+}
+
+/// 解析紧凑格式的 peers，每项为 ip + port（IPv4 为 6 字节，IPv6 为 18 字节）
+fn parse_compact_peers(bytes: &[u8], addr_size: usize) -> Result<Vec<SocketAddr>, Error> {
+    if !bytes.len().is_multiple_of(addr_size) {
+        Err(Error::new_frame(
+            None,
+            Some(format!("compact peers len {} is not multiple of {}", bytes.len(), addr_size)),
+        ))?
+    }
+
+    bytes
+        .chunks(addr_size)
+        .map(yiilian_core::common::util::bytes_to_sockaddr)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_duration() {
+        // 15 + 30 + 60 秒的尝试，加上 HTTP 重试前 15 + 30 秒的等待
+        assert_eq!(Duration::from_secs(150), TrackerClient::default().max_duration());
+        assert_eq!(Duration::from_secs(1), TrackerClient::new(Duration::from_secs(1), 0).max_duration());
+    }
+}
@@ -0,0 +1,340 @@
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio::{
+    net::{lookup_host, UdpSocket},
+    time::{timeout, Instant},
+};
+use url::Url;
+use yiilian_core::common::{error::Error, util::be_bytes_to_u32};
+use yiilian_dht::common::ID_SIZE;
+
+use super::{parse_compact_peers, AnnounceRequest, AnnounceResponse, ScrapeInfo, TrackerClient};
+
+const PROTOCOL_ID: u64 = 0x41727101980;
+const ACTION_CONNECT: u32 = 0;
+const ACTION_ANNOUNCE: u32 = 1;
+const ACTION_SCRAPE: u32 = 2;
+const ACTION_ERROR: u32 = 3;
+const MAX_PACKET_SIZE: usize = 4096;
+/// 单次 scrape 最多携带的 info_hash 数
+const MAX_SCRAPE_HASHES: usize = 74;
+
+pub(super) async fn announce(
+    client: &TrackerClient,
+    url: &Url,
+    req: &AnnounceRequest,
+) -> Result<AnnounceResponse, Error> {
+    let (socket, addr) = open_socket(url).await?;
+
+    let rsp = transact(client, &socket, addr, ACTION_ANNOUNCE, |connection_id, transaction_id| {
+        let mut rst = BytesMut::with_capacity(98);
+        rst.put_u64(connection_id);
+        rst.put_u32(ACTION_ANNOUNCE);
+        rst.put_u32(transaction_id);
+        rst.extend(req.info_hash);
+        rst.extend(req.peer_id);
+        rst.put_u64(req.downloaded);
+        rst.put_u64(req.left);
+        rst.put_u64(req.uploaded);
+        rst.put_u32(req.event as u32);
+        rst.put_u32(0);
+        rst.put_u32(client.key);
+        rst.put_i32(req.num_want);
+        rst.put_u16(req.port);
+
+        rst.into()
+    })
+    .await?;
+
+    if rsp.len() < 20 {
+        Err(Error::new_frame(None, Some(format!("udp announce response is too short: {}", rsp.len()))))?
+    }
+
+    let addr_size = if addr.is_ipv4() { 6 } else { 18 };
+    let peers_len = (rsp.len() - 20) / addr_size * addr_size;
+
+    Ok(AnnounceResponse {
+        interval: be_bytes_to_u32(&rsp[8..12])?,
+        leechers: Some(be_bytes_to_u32(&rsp[12..16])?),
+        seeders: Some(be_bytes_to_u32(&rsp[16..20])?),
+        peers: parse_compact_peers(&rsp[20..20 + peers_len], addr_size)?,
+    })
+}
+
+pub(super) async fn scrape(
+    client: &TrackerClient,
+    url: &Url,
+    info_hashes: &[[u8; ID_SIZE]],
+) -> Result<HashMap<[u8; ID_SIZE], ScrapeInfo>, Error> {
+    let (socket, addr) = open_socket(url).await?;
+    let mut rst = HashMap::new();
+
+    for info_hashes in info_hashes.chunks(MAX_SCRAPE_HASHES) {
+        let rsp = transact(client, &socket, addr, ACTION_SCRAPE, |connection_id, transaction_id| {
+            let mut rst = BytesMut::with_capacity(16 + info_hashes.len() * ID_SIZE);
+            rst.put_u64(connection_id);
+            rst.put_u32(ACTION_SCRAPE);
+            rst.put_u32(transaction_id);
+            for info_hash in info_hashes {
+                rst.extend(info_hash);
+            }
+
+            rst.into()
+        })
+        .await?;
+
+        for (info_hash, item) in info_hashes.iter().zip(rsp[8..].chunks_exact(12)) {
+            rst.insert(
+                *info_hash,
+                ScrapeInfo {
+                    seeders: be_bytes_to_u32(&item[0..4])?,
+                    completed: be_bytes_to_u32(&item[4..8])?,
+                    leechers: be_bytes_to_u32(&item[8..12])?,
+                },
+            );
+        }
+    }
+
+    Ok(rst)
+}
+
+async fn open_socket(url: &Url) -> Result<(UdpSocket, SocketAddr), Error> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| Error::new_general(&format!("tracker url without host: {}", url)))?;
+    let port = url
+        .port()
+        .ok_or_else(|| Error::new_general(&format!("udp tracker url without port: {}", url)))?;
+
+    let addr = lookup_host((host.trim_matches(|c| c == '[' || c == ']'), port))
+        .await
+        .map_err(|error| Error::new_net(Some(error.into()), Some(format!("resolve tracker {}", url)), None))?
+        .next()
+        .ok_or_else(|| Error::new_not_found(&format!("resolve tracker {}", url)))?;
+
+    let bind_addr: SocketAddr = if addr.is_ipv4() {
+        "0.0.0.0:0".parse().expect("parse bind addr")
+    } else {
+        "[::]:0".parse().expect("parse bind addr")
+    };
+    let socket = UdpSocket::bind(bind_addr)
+        .await
+        .map_err(|error| Error::new_bind(Some(error.into())))?;
+    socket
+        .connect(addr)
+        .await
+        .map_err(|error| Error::new_net(Some(error.into()), Some(format!("connect tracker {}", url)), Some(addr)))?;
+
+    Ok((socket, addr))
+}
+
+/// connect -> request 状态机：connection_id 缓存 1 分钟，超时后丢弃缓存并以 15 * 2^n 秒的超时重试
+async fn transact<F>(
+    client: &TrackerClient,
+    socket: &UdpSocket,
+    addr: SocketAddr,
+    action: u32,
+    build_request: F,
+) -> Result<Bytes, Error>
+where
+    F: Fn(u64, u32) -> Bytes,
+{
+    let mut last_error = None;
+
+    for attempt in 0..=client.retries {
+        let attempt_timeout = client.attempt_timeout(attempt);
+
+        let connection_id = match client.get_connection_id(&addr) {
+            Some(val) => val,
+            None => match connect(socket, addr, attempt_timeout).await {
+                Ok(val) => {
+                    client.set_connection_id(addr, val);
+                    val
+                }
+                Err(error) if error.is_timeout() => {
+                    last_error = Some(error);
+                    continue;
+                }
+                Err(error) => return Err(error),
+            },
+        };
+
+        let transaction_id: u32 = rand::random();
+        send(socket, addr, &build_request(connection_id, transaction_id)).await?;
+
+        match recv_response(socket, transaction_id, action, attempt_timeout).await {
+            Ok(rsp) => return Ok(rsp),
+            Err(error) if error.is_timeout() => {
+                // connection_id 可能已过期
+                client.remove_connection_id(&addr);
+                last_error = Some(error);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    Err(last_error.expect("last_error can't be none"))
+}
+
+async fn connect(socket: &UdpSocket, addr: SocketAddr, recv_timeout: Duration) -> Result<u64, Error> {
+    let transaction_id: u32 = rand::random();
+
+    let mut req = BytesMut::with_capacity(16);
+    req.put_u64(PROTOCOL_ID);
+    req.put_u32(ACTION_CONNECT);
+    req.put_u32(transaction_id);
+    send(socket, addr, &req).await?;
+
+    let rsp = recv_response(socket, transaction_id, ACTION_CONNECT, recv_timeout).await?;
+    if rsp.len() < 16 {
+        Err(Error::new_frame(None, Some(format!("udp connect response is too short: {}", rsp.len()))))?
+    }
+
+    Ok(u64::from_be_bytes(rsp[8..16].try_into().expect("bytes len is invalid")))
+}
+
+async fn send(socket: &UdpSocket, addr: SocketAddr, data: &[u8]) -> Result<(), Error> {
+    socket
+        .send(data)
+        .await
+        .map_err(|error| Error::new_net(Some(error.into()), Some("send udp tracker".to_owned()), Some(addr)))?;
+
+    Ok(())
+}
+
+/// 接收 transaction_id 匹配的响应，忽略过期事务的响应
+async fn recv_response(
+    socket: &UdpSocket,
+    transaction_id: u32,
+    action: u32,
+    recv_timeout: Duration,
+) -> Result<Bytes, Error> {
+    let deadline = Instant::now() + recv_timeout;
+    let mut buf = vec![0; MAX_PACKET_SIZE];
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let len = match timeout(remaining, socket.recv(&mut buf)).await {
+            Ok(Ok(len)) => len,
+            // 对端端口不可达等错误，按超时处理以便重试
+            Ok(Err(_)) | Err(_) => Err(Error::new_timeout("udp tracker timeout"))?,
+        };
+
+        if len < 8 || be_bytes_to_u32(&buf[4..8])? != transaction_id {
+            continue;
+        }
+
+        let rsp_action = be_bytes_to_u32(&buf[0..4])?;
+        if rsp_action == ACTION_ERROR {
+            let message = String::from_utf8_lossy(&buf[8..len]).to_string();
+            Err(Error::new_general(&format!("tracker error: {}", message)))?
+        }
+        if rsp_action != action {
+            Err(Error::new_frame(None, Some(format!("udp tracker action mismatch: {}", rsp_action))))?
+        }
+
+        return Ok(buf[..len].to_owned().into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use bytes::{BufMut, BytesMut};
+    use tokio::net::UdpSocket;
+    use yiilian_core::common::util::be_bytes_to_u32;
+
+    use crate::bt::tracker::{AnnounceRequest, ScrapeInfo, TrackerClient};
+
+    use super::{ACTION_ANNOUNCE, ACTION_CONNECT, ACTION_ERROR, ACTION_SCRAPE, PROTOCOL_ID};
+
+    const CONNECTION_ID: u64 = 0x1234;
+
+    /// 本地 UDP tracker 桩，丢弃收到的第一个包以验证重试
+    async fn stub_tracker(error: bool) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = vec![0; 1024];
+            let mut is_first = true;
+
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+                if is_first {
+                    is_first = false;
+                    continue;
+                }
+
+                let action = be_bytes_to_u32(&buf[8..12]).unwrap();
+                let transaction_id = be_bytes_to_u32(&buf[12..16]).unwrap();
+                let mut rsp = BytesMut::new();
+
+                if action == ACTION_CONNECT {
+                    assert_eq!(PROTOCOL_ID, u64::from_be_bytes(buf[0..8].try_into().unwrap()));
+                    rsp.put_u32(ACTION_CONNECT);
+                    rsp.put_u32(transaction_id);
+                    rsp.put_u64(CONNECTION_ID);
+                } else if error {
+                    rsp.put_u32(ACTION_ERROR);
+                    rsp.put_u32(transaction_id);
+                    rsp.extend(b"denied");
+                } else if action == ACTION_ANNOUNCE {
+                    assert_eq!(98, len);
+                    assert_eq!(CONNECTION_ID, u64::from_be_bytes(buf[0..8].try_into().unwrap()));
+                    assert_eq!(&[1; 20], &buf[16..36]);
+                    rsp.put_u32(ACTION_ANNOUNCE);
+                    rsp.put_u32(transaction_id);
+                    rsp.put_u32(900);
+                    rsp.put_u32(4);
+                    rsp.put_u32(2);
+                    rsp.extend([127, 0, 0, 1, 0x1a, 0xe1]);
+                } else if action == ACTION_SCRAPE {
+                    rsp.put_u32(ACTION_SCRAPE);
+                    rsp.put_u32(transaction_id);
+                    for _ in 0..(len - 16) / 20 {
+                        rsp.put_u32(1);
+                        rsp.put_u32(2);
+                        rsp.put_u32(3);
+                    }
+                }
+
+                socket.send_to(&rsp, from).await.unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_announce_and_scrape() {
+        let addr = stub_tracker(false).await;
+        let tracker = format!("udp://{}/announce", addr);
+        let client = TrackerClient::new(Duration::from_millis(100), 2);
+
+        let req = AnnounceRequest::new([1; 20], [2; 20], 6881, 100);
+        let rst = client.announce(&tracker, &req).await.unwrap();
+        assert_eq!(900, rst.interval);
+        assert_eq!(Some(4), rst.leechers);
+        assert_eq!(Some(2), rst.seeders);
+        assert_eq!(vec!["127.0.0.1:6881".parse::<SocketAddr>().unwrap()], rst.peers);
+
+        // 复用缓存的 connection_id
+        assert!(client.get_connection_id(&addr).is_some());
+        let rst = client.scrape(&tracker, &[[1; 20], [3; 20]]).await.unwrap();
+        assert_eq!(2, rst.len());
+        assert_eq!(Some(&ScrapeInfo { seeders: 1, completed: 2, leechers: 3 }), rst.get(&[3; 20]));
+    }
+
+    #[tokio::test]
+    async fn test_error() {
+        let addr = stub_tracker(true).await;
+        let client = TrackerClient::new(Duration::from_millis(100), 2);
+
+        let req = AnnounceRequest::new([1; 20], [2; 20], 6881, 100);
+        let rst = client.announce(&format!("udp://{}", addr), &req).await;
+        assert!(format!("{}", rst.unwrap_err()).contains("denied"));
+    }
+}
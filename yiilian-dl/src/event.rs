@@ -2,5 +2,19 @@ use yiilian_dht::common::Id;
 
 #[derive(Clone, Debug)]
 pub enum Event {
-    CompleteDownloadBtMeta(Id)
-}
\ No newline at end of file
+    CompleteDownloadBtMeta(Id),
+    /// 分片校验通过后的下载进度
+    DownloadProgress {
+        info_hash: Id,
+        verified_pieces: usize,
+        total_pieces: usize,
+        verified_bytes: u64,
+        total_bytes: u64,
+    },
+    /// 分片 SHA-1 校验失败，分片会被重新下载
+    PieceHashFailed {
+        info_hash: Id,
+        index: u32,
+    },
+    CompleteDownload(Id),
+}
@@ -24,6 +24,8 @@ fn main() -> tantivy::Result<()> {
 
     let res_doc = ResInfoDoc {
         info_hash: "00000000000000000001".to_owned(),
+        info_hash_v2: None,
+        magnet: None,
         res_type: 0,
         create_time: "2024-11-10T11:00:00".to_owned(),
         file_paths: vec!["file1".to_owned()],
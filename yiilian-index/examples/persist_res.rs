@@ -1,4 +1,4 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use yiilian_core::common::working_dir::WorkingDir;
 use yiilian_index::info_mq_to_db::InfoMqToDbBuilder;
@@ -11,9 +11,9 @@ async fn main() {
     // let log4rs_path = wd.get_path_by_entry("log4rs.yml");
     // setup_log4rs_from_file(&log4rs_path.unwrap());
 
-    let mut mq_engine = Engine::new(LOG_DATA_SIZE, wd.home_dir()).unwrap();
+    let mq_engine = Engine::new(LOG_DATA_SIZE, wd.home_dir()).unwrap();
     mq_engine.open_topic("info_index").unwrap();
-    let mq_engine = Arc::new(Mutex::new(mq_engine));
+    let mq_engine = Arc::new(mq_engine);
     
     let db_uri = wd.home_dir().join(".yiilian/db/res.db");
     let db_uri = db_uri.to_str().unwrap();
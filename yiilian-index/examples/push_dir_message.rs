@@ -9,8 +9,8 @@ async fn main() {
     dl_path.push(".yiilian/dl");
 
     let topic_name = "info_index";
-    let mut engine = {
-        let mut engine = Engine::new(LOG_DATA_SIZE, wd.home_dir()).expect("create mq engine");
+    let engine = {
+        let engine = Engine::new(LOG_DATA_SIZE, wd.home_dir()).expect("create mq engine");
         engine
             .open_topic(topic_name)
             .expect("open info_index topic");
@@ -0,0 +1,3 @@
+-- v2 / 混合种子的 SHA-256 info_hash (BEP52)
+
+ALTER TABLE res_info ADD COLUMN info_hash_v2 VARCHAR(100);
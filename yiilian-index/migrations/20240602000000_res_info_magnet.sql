@@ -0,0 +1,3 @@
+-- 种子生成的磁力链接，包含 dn、xl 和 tr
+
+ALTER TABLE res_info ADD COLUMN magnet TEXT;
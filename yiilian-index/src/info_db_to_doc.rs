@@ -158,6 +158,8 @@ impl InfoDbToDoc {
 
         let res_doc = ResInfoDoc {
             info_hash: res_info.info_hash.clone(),
+            info_hash_v2: res_info.info_hash_v2.clone(),
+            magnet: res_info.magnet.clone(),
             res_type: res_info.res_type,
             create_time: res_info.create_time.clone(),
             file_paths: file_paths_value,
@@ -241,6 +243,8 @@ impl InfoDbToDocBuilder {
             Err(_) => {
                 let mut schema_builder = Schema::builder();
                 schema_builder.add_text_field("info_hash", STRING | STORED);
+                schema_builder.add_text_field("info_hash_v2", STRING | STORED);
+                schema_builder.add_text_field("magnet", STORED);
                 schema_builder.add_u64_field("res_type", INDEXED | STORED);
                 schema_builder.add_text_field("create_time", STORED);
                 schema_builder.add_text_field("file_paths", TEXT | STORED);
@@ -316,6 +320,8 @@ mod tests {
             r#"
             CREATE TABLE res_info (
                 info_hash VARCHAR(100) PRIMARY KEY,
+                info_hash_v2 VARCHAR(100),
+                magnet TEXT,
                 res_type INT NOT NULL,
                 create_time VARCHAR(100) NOT NULL,
                 mod_time VARCHAR(100) NOT NULL,
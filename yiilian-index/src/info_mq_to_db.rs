@@ -2,8 +2,6 @@
 use std::fs;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::sync::Mutex;
-use std::time::Duration;
 
 use chrono::Utc;
 use dysql::execute;
@@ -13,7 +11,7 @@ use sqlx::{
     ConnectOptions, Connection, SqliteConnection,
 };
 
-use tokio::time::sleep;
+use futures::StreamExt;
 use yiilian_core::data::MetaInfo;
 use yiilian_core::{common::error::Error, data::BtTorrent};
 use yiilian_mq::engine::Engine;
@@ -26,41 +24,45 @@ const MQ_CLIENT_PERSIST: &str = "persist_info_client";
 
 pub struct InfoMqToDb {
     db_connection: SqliteConnection,
-    mq_engine: Arc<Mutex<Engine>>,
+    mq_engine: Arc<Engine>,
 }
 
 impl InfoMqToDb {
-    pub fn new(db_connection: SqliteConnection, mq_engine: Arc<Mutex<Engine>>) -> Self {
+    pub fn new(db_connection: SqliteConnection, mq_engine: Arc<Engine>) -> Self {
         InfoMqToDb { db_connection, mq_engine }
     }
 
     pub async fn persist_loop(&mut self) {
-        loop {
-            let message = self.mq_engine.lock().expect("lock mq_engine").poll_message(INDEX_TOPIC_NAME, MQ_CLIENT_PERSIST);
-            if let Some(message) = message {
-                let meta_path = unsafe { String::from_utf8_unchecked(message.value().into()) };
-                match fs::read(meta_path) {
-                    Ok(val) => {
-                        match BtTorrent::try_from(&val[..]) {
-                            Ok(bt_torrent) => {
-                                if let Err(error) = self.add_bt_info_record(&bt_torrent).await {
-                                    log::trace!(target: "yiilian_index::info_mq_to_db::persist_loop", "add_bt_info_record error: {}", error);
-                                } else {
-                                    log::trace!(target: "yiilian_index::info_mq_to_db::persist_loop", "persisted bt: {}", bt_torrent.info_hash);
-                                }
-                            },
-                            Err(error) => {
-                                log::trace!(target: "yiilian_index::info_mq_to_db::persist_loop", "Decode bt_torrent error: {}", error);
-                            },
-                        }
-                    },
-                    Err(error) => {
-                        log::trace!(target: "yiilian_index::info_mq_to_db::persist_loop", "Read bt_torrent error: {}", error);
-                    },
-                }
+        let mq_engine = self.mq_engine.clone();
+        let mut messages = match mq_engine.subscribe(INDEX_TOPIC_NAME, MQ_CLIENT_PERSIST) {
+            Ok(messages) => Box::pin(messages),
+            Err(error) => {
+                log::error!(target: "yiilian_index::info_mq_to_db::persist_loop", "subscribe {} error: {}", INDEX_TOPIC_NAME, error);
+                return;
             }
+        };
 
-            sleep(Duration::from_secs(1)).await;
+        while let Some(message) = messages.next().await {
+            let meta_path = unsafe { String::from_utf8_unchecked(message.value().into()) };
+            match fs::read(meta_path) {
+                Ok(val) => {
+                    match BtTorrent::try_from(&val[..]) {
+                        Ok(bt_torrent) => {
+                            if let Err(error) = self.add_bt_info_record(&bt_torrent).await {
+                                log::trace!(target: "yiilian_index::info_mq_to_db::persist_loop", "add_bt_info_record error: {}", error);
+                            } else {
+                                log::trace!(target: "yiilian_index::info_mq_to_db::persist_loop", "persisted bt: {}", bt_torrent.info_hash);
+                            }
+                        },
+                        Err(error) => {
+                            log::trace!(target: "yiilian_index::info_mq_to_db::persist_loop", "Decode bt_torrent error: {}", error);
+                        },
+                    }
+                },
+                Err(error) => {
+                    log::trace!(target: "yiilian_index::info_mq_to_db::persist_loop", "Read bt_torrent error: {}", error);
+                },
+            }
         }
     }
 
@@ -69,6 +71,8 @@ impl InfoMqToDb {
 
         let dto = ResInfoRecord {
             info_hash: bt_torrent.info_hash.clone(),
+            info_hash_v2: bt_torrent.info_hash_v2.clone(),
+            magnet: bt_torrent.to_magnet().ok().map(|magnet| magnet.to_string()),
             res_type: 1,
             create_time: now.clone(),
             mod_time: now.clone(),
@@ -83,9 +87,9 @@ impl InfoMqToDb {
 
         let _ = execute!(|&mut *tran, dto| {r#"
             insert into res_info
-                (info_hash, res_type, create_time, mod_time, is_indexed) 
+                (info_hash, info_hash_v2, magnet, res_type, create_time, mod_time, is_indexed) 
             values 
-                (:info_hash, :res_type,:create_time, :mod_time, :is_indexed)
+                (:info_hash, :info_hash_v2, :magnet, :res_type,:create_time, :mod_time, :is_indexed)
         "#})
         .map_err(|error| Error::new_db(Some(error.into()), None))?;
 
@@ -116,6 +120,19 @@ impl InfoMqToDb {
                     res_files.push(file);
                 }
             }
+            MetaInfo::V2 { files, .. } => {
+                for f in files {
+                    let file = ResFileRecord {
+                        info_hash: bt_torrent.info_hash.clone(),
+                        file_path: f.path.clone(),
+                        file_size: f.length,
+                        create_time: now.clone(),
+                        mod_time: now.clone(),
+                    };
+
+                    res_files.push(file);
+                }
+            }
         }
 
         for res_file in res_files {
@@ -140,7 +157,7 @@ impl InfoMqToDb {
 #[derive(Default)]
 pub struct InfoMqToDbBuilder {
     db_connection: Option<SqliteConnection>,
-    mq_engine: Option<Arc<Mutex<Engine>>>,
+    mq_engine: Option<Arc<Engine>>,
 }
 
 impl InfoMqToDbBuilder {
@@ -167,7 +184,7 @@ impl InfoMqToDbBuilder {
         self
     }
 
-    pub fn mq_engine(mut self, mq_engine: Arc<Mutex<Engine>>) -> Self {
+    pub fn mq_engine(mut self, mq_engine: Arc<Engine>) -> Self {
         self.mq_engine = Some(mq_engine);
         self
     }
@@ -189,9 +206,9 @@ mod tests {
     async fn test_add_single_and_fetch() {
         let wd = WorkingDir::new();
         
-        let mut mq_engine = Engine::new(LOG_DATA_SIZE, wd.home_dir()).unwrap();
+        let mq_engine = Engine::new(LOG_DATA_SIZE, wd.home_dir()).unwrap();
         mq_engine.open_topic("test_info_mq").unwrap();
-        let mq_engine = Arc::new(Mutex::new(mq_engine));
+        let mq_engine = Arc::new(mq_engine);
 
         let conn = connect_db().await;
 
@@ -204,6 +221,7 @@ mod tests {
 
         let bt_torrent = BtTorrent {
             info_hash: info_hash.clone(),
+            info_hash_v2: None,
             announce: "".to_owned(),
             info: MetaInfo::SingleFile {
                 length: 1200,
@@ -211,19 +229,20 @@ mod tests {
                 pieces: b"pieces"[..].into(),
                 piece_length: 1000,
             },
+            raw: Default::default(),
         };
 
         ri.add_bt_info_record(&bt_torrent).await.unwrap();
-        mq_engine.lock().expect("lock mq_engine").remove_topic("test_info_mq");
+        mq_engine.remove_topic("test_info_mq");
     }
 
     #[tokio::test]
     async fn test_add_multiple() {
         let wd = WorkingDir::new();
 
-        let mut mq_engine = Engine::new(LOG_DATA_SIZE, wd.home_dir()).unwrap();
+        let mq_engine = Engine::new(LOG_DATA_SIZE, wd.home_dir()).unwrap();
         mq_engine.open_topic("test_info_mq1").unwrap();
-        let mq_engine = Arc::new(Mutex::new(mq_engine));
+        let mq_engine = Arc::new(mq_engine);
 
         let conn = connect_db().await;
 
@@ -250,12 +269,14 @@ mod tests {
 
         let bt_torrent = BtTorrent {
             info_hash: "00000000000000000001".to_owned(),
+            info_hash_v2: None,
             announce: "".to_owned(),
             info: mf,
+            raw: Default::default(),
         };
 
         ri.add_bt_info_record(&bt_torrent).await.unwrap();
-        mq_engine.lock().expect("lock mq_engine").remove_topic("test_info_mq1");
+        mq_engine.remove_topic("test_info_mq1");
     }
 
     async fn connect_db() -> sqlx::SqliteConnection {
@@ -276,6 +297,8 @@ mod tests {
             r#"
             CREATE TABLE res_info (
                 info_hash VARCHAR(100) PRIMARY KEY,
+                info_hash_v2 VARCHAR(100),
+                magnet TEXT,
                 res_type INT NOT NULL,
                 create_time VARCHAR(100) NOT NULL,
                 mod_time VARCHAR(100) NOT NULL,
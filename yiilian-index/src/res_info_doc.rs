@@ -3,6 +3,10 @@ use serde::{Deserialize, Serialize};
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ResInfoDoc {
     pub info_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info_hash_v2: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub magnet: Option<String>,
     pub res_type: i32,
     pub create_time: String,
     pub file_paths: Vec<String>,
@@ -5,6 +5,10 @@ use sqlx::FromRow;
 #[derive(FromRow, Content, Clone, Debug)]
 pub struct ResInfoRecord {
     pub info_hash: String,
+    /// v2 / 混合种子的 SHA-256 info_hash
+    pub info_hash_v2: Option<String>,
+    /// 种子生成的磁力链接
+    pub magnet: Option<String>,
     pub res_type: i32,
     pub create_time: String,
     pub mod_time: String,
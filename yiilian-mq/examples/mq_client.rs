@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use yiilian_mq::{message::in_message::InMessage, net::client::MqClient};
+
+/// 需要先启动 yiilian-mq 服务：cargo run -p yiilian-mq
+#[tokio::main]
+async fn main() {
+    let mut client = MqClient::connect("127.0.0.1:9530".parse().unwrap()).await.unwrap();
+
+    client.open_topic("info_hash").await.unwrap();
+    println!("topics: {:?}", client.topic_names().await.unwrap());
+
+    for i in 0..5 {
+        let value = format!("value_{}", i);
+        client.push_message("info_hash", InMessage(value.into())).await.unwrap();
+    }
+
+    loop {
+        let messages = client
+            .fetch_messages("info_hash", "client_4", 10, Duration::from_secs(10))
+            .await
+            .unwrap();
+
+        for message in &messages {
+            println!("message: {:?}", message);
+        }
+
+        if let Some(last_message) = messages.last() {
+            client.commit_offset("info_hash", "client_4", last_message.offset()).await.unwrap();
+        }
+    }
+}
@@ -9,7 +9,7 @@ async fn main() {
     
     let (mut shutdown_tx, _shutdown_rx) = create_shutdown();
 
-    let mut engine = Engine::new(LOG_DATA_SIZE, wd.home_dir()).unwrap();
+    let engine = Engine::new(LOG_DATA_SIZE, wd.home_dir()).unwrap();
     engine.open_topic("info_hash").unwrap();
     // for i in 0..5 {
     //     let value = format!("value_{}", i);
@@ -33,7 +33,7 @@ async fn main() {
     let topic = engine.open_topic("info_hash").unwrap();
     println!(
         "customer_offsets: {:?}",
-        topic.consumer_offsets()
+        topic.lock().unwrap().consumer_offsets()
     );
 
     // topic.lock().unwrap().purge_segment();
@@ -0,0 +1,266 @@
+use std::{path::PathBuf, process::exit};
+
+use chrono::{TimeZone, Utc};
+use yiilian_core::common::{error::Error, util::bytes_to_sockaddr, working_dir::WorkingDir};
+use yiilian_mq::{message::Message, tool};
+
+const USAGE: &str = "Usage: yiilian-mq-tool [--dir <mq_dir>] <command>
+
+Commands:
+    topics                                   列出 topic
+    segments <topic>                         列出 segment 及其 offset 范围、压缩批次数
+    dump <topic> [--from <offset>] [--count <n>] [--format raw|hex|info]
+                                             输出消息，info 按 InfoMessage 解码
+    lag <topic>                              各消费者已提交的位置及积压的消息数
+    set-offset <topic> <consumer> <offset>   设置消费者已提交的位置
+    reset-offset <topic> <consumer>          删除消费者的位置，从最旧的消息开始消费
+    verify <topic>                           校验 crc 和索引，有错误时返回非 0
+
+mq_dir 默认为 ~/.yiilian/mq，修改位置前需要先停止 yiilian-mq 服务";
+
+const DEFAULT_DUMP_COUNT: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DumpFormat {
+    Raw,
+    Hex,
+    Info,
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    let mq_path = match take_option(&mut args, "--dir") {
+        Some(dir) => PathBuf::from(dir),
+        None => WorkingDir::new().home_dir().join(".yiilian/mq"),
+    };
+
+    let rst = match args.first().map(|command| command.as_str()) {
+        Some("topics") => topics(&mq_path),
+        Some("segments") => segments(&mq_path, &args[1..]),
+        Some("dump") => dump(&mq_path, &mut args[1..].to_vec()),
+        Some("lag") => lag(&mq_path, &args[1..]),
+        Some("set-offset") => set_offset(&mq_path, &args[1..]),
+        Some("reset-offset") => reset_offset(&mq_path, &args[1..]),
+        Some("verify") => verify(&mq_path, &args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    };
+
+    match rst {
+        Ok(true) => (),
+        Ok(false) => exit(1),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            exit(1);
+        }
+    }
+}
+
+fn topics(mq_path: &PathBuf) -> Result<bool, Error> {
+    for topic_name in tool::topic_names(mq_path)? {
+        println!("{}", topic_name);
+    }
+
+    Ok(true)
+}
+
+fn segments(mq_path: &PathBuf, args: &[String]) -> Result<bool, Error> {
+    let topic_path = topic_path(mq_path, args)?;
+
+    println!(
+        "{:>20} {:>20} {:>20} {:>10} {:>10} {:>10} {:>12}",
+        "segment", "first", "last", "messages", "indexes", "batches", "bytes"
+    );
+    for segment_offset in tool::segment_offsets(&topic_path)? {
+        match tool::segment_summary(&topic_path, segment_offset) {
+            Ok(summary) => println!(
+                "{:>20} {:>20} {:>20} {:>10} {:>10} {:>10} {:>12}",
+                summary.segment_offset,
+                display_offset(summary.first_offset),
+                display_offset(summary.last_offset),
+                summary.message_count,
+                summary.index_count,
+                summary.batch_count,
+                summary.data_len,
+            ),
+            Err(error) => println!("{:>20} error: {}", segment_offset, error),
+        }
+    }
+
+    Ok(true)
+}
+
+fn dump(mq_path: &PathBuf, args: &mut Vec<String>) -> Result<bool, Error> {
+    let from_offset = match take_option(args, "--from") {
+        Some(from) => parse_number(&from)?,
+        None => 0,
+    };
+    let count = match take_option(args, "--count") {
+        Some(count) => parse_number(&count)? as usize,
+        None => DEFAULT_DUMP_COUNT,
+    };
+    let format = match take_option(args, "--format").as_deref() {
+        None | Some("raw") => DumpFormat::Raw,
+        Some("hex") => DumpFormat::Hex,
+        Some("info") => DumpFormat::Info,
+        Some(format) => Err(Error::new_general(&format!("Unknown format: {}", format)))?,
+    };
+    let topic_path = topic_path(mq_path, args)?;
+
+    for message in tool::read_messages(&topic_path, from_offset, count)? {
+        println!("{}", display_message(&message, format));
+    }
+
+    Ok(true)
+}
+
+fn lag(mq_path: &PathBuf, args: &[String]) -> Result<bool, Error> {
+    let topic_path = topic_path(mq_path, args)?;
+
+    println!("{:<30} {:>20} {:>12}", "consumer", "committed", "lag");
+    for consumer_lag in tool::consumer_lags(&topic_path)? {
+        println!(
+            "{:<30} {:>20} {:>12}",
+            consumer_lag.consumer,
+            display_offset(consumer_lag.committed_offset),
+            consumer_lag.lag
+        );
+    }
+
+    Ok(true)
+}
+
+fn set_offset(mq_path: &PathBuf, args: &[String]) -> Result<bool, Error> {
+    let topic_path = topic_path(mq_path, args)?;
+    let (consumer_name, offset) = match args {
+        [_, consumer_name, offset] => (consumer_name, parse_number(offset)?),
+        _ => Err(Error::new_general("Usage: set-offset <topic> <consumer> <offset>"))?,
+    };
+
+    tool::set_consumer_offset(&topic_path, consumer_name, Some(offset))?;
+
+    Ok(true)
+}
+
+fn reset_offset(mq_path: &PathBuf, args: &[String]) -> Result<bool, Error> {
+    let topic_path = topic_path(mq_path, args)?;
+    let consumer_name = match args {
+        [_, consumer_name] => consumer_name,
+        _ => Err(Error::new_general("Usage: reset-offset <topic> <consumer>"))?,
+    };
+
+    tool::set_consumer_offset(&topic_path, consumer_name, None)?;
+
+    Ok(true)
+}
+
+fn verify(mq_path: &PathBuf, args: &[String]) -> Result<bool, Error> {
+    let topic_path = topic_path(mq_path, args)?;
+    let mut is_ok = true;
+
+    for segment_offset in tool::segment_offsets(&topic_path)? {
+        match tool::check_segment(&topic_path, segment_offset) {
+            Ok(check) if check.is_ok() => {
+                println!("{:>20} ok, {} messages", segment_offset, check.valid_count);
+            }
+            Ok(check) => {
+                is_ok = false;
+                println!(
+                    "{:>20} {} messages, corrupt at: {}, missing index: {}, bad index items: {}",
+                    segment_offset,
+                    check.valid_count,
+                    check.corrupt_pos.map(|pos| pos.to_string()).unwrap_or("-".to_owned()),
+                    check.missing_index,
+                    check.bad_index_items,
+                );
+            }
+            Err(error) => {
+                is_ok = false;
+                println!("{:>20} error: {}", segment_offset, error);
+            }
+        }
+    }
+
+    Ok(is_ok)
+}
+
+fn topic_path(mq_path: &PathBuf, args: &[String]) -> Result<PathBuf, Error> {
+    let topic_name = args
+        .first()
+        .ok_or_else(|| Error::new_general("Missing topic name"))?;
+    let topic_path = mq_path.join(topic_name);
+
+    if !topic_path.is_dir() {
+        Err(Error::new_not_found(&format!("Not found topic: {}", topic_name)))?;
+    }
+
+    Ok(topic_path)
+}
+
+/// 从 args 中移除 "name value"，返回 value
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let pos = args.iter().position(|arg| arg == name)?;
+    if pos + 1 >= args.len() {
+        eprintln!("Missing value of {}\n\n{}", name, USAGE);
+        exit(2);
+    }
+
+    let value = args.remove(pos + 1);
+    args.remove(pos);
+
+    Some(value)
+}
+
+fn parse_number(value: &str) -> Result<u64, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::new_general(&format!("Invalid number: {}", value)))
+}
+
+fn display_offset(offset: Option<u64>) -> String {
+    offset.map(|offset| offset.to_string()).unwrap_or("-".to_owned())
+}
+
+fn display_message(message: &Message, format: DumpFormat) -> String {
+    let timestamp = Utc
+        .timestamp_millis_opt(message.timestamp())
+        .single()
+        .map(|time| time.to_rfc3339())
+        .unwrap_or(message.timestamp().to_string());
+    let key = message
+        .key()
+        .map(|key| hex::encode(key))
+        .unwrap_or("-".to_owned());
+
+    let value = match format {
+        DumpFormat::Raw => String::from_utf8_lossy(message.value()).into_owned(),
+        DumpFormat::Hex => hex::encode(message.value()),
+        DumpFormat::Info => display_info_message(message.value()),
+    };
+
+    format!("{}\t{}\tkey={}\t{}", message.offset(), timestamp, key, value)
+}
+
+/// 按 yiilian-crawler 的 InfoMessage 格式解码：
+/// try_times(1) + type(1) + info_hash(20) + addr(x)，type 0 - Normal, 1 - AnnouncePeer。
+/// yiilian-crawler 依赖 yiilian-mq，所以这里不能直接引用 InfoMessage
+fn display_info_message(value: &[u8]) -> String {
+    if value.len() < 22 {
+        return format!("invalid info message: {}", hex::encode(value));
+    }
+
+    let try_times = value[0];
+    let info_hash = hex::encode(&value[2..22]);
+
+    match value[1] {
+        0 => format!("try_times={} type=normal info_hash={}", try_times, info_hash),
+        1 => match bytes_to_sockaddr(&value[22..]) {
+            Ok(addr) => format!("try_times={} type=announce_peer info_hash={} addr={}", try_times, info_hash, addr),
+            Err(_) => format!("invalid info message: {}", hex::encode(value)),
+        },
+        _ => format!("invalid info message: {}", hex::encode(value)),
+    }
+}
@@ -0,0 +1,79 @@
+use std::{collections::BTreeMap, time::Duration};
+
+/// 成员超过该秒数没有请求时被移出消费组
+pub const GROUP_SESSION_TIMEOUT_SECS: u64 = 30;
+
+/// 共享同一个 topic 的一组消费者，每个分区同一时间只分配给一个成员。
+/// 只保存在内存中，消费位置按消费组名称保存在各分区中
+#[derive(Debug, Default)]
+pub struct ConsumerGroup {
+    /// member -> 最近一次请求的 utc 毫秒
+    members: BTreeMap<String, i64>,
+}
+
+impl ConsumerGroup {
+    pub fn new() -> Self {
+        ConsumerGroup::default()
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = &String> {
+        self.members.keys()
+    }
+
+    /// 记录成员的请求时间，并移出超时的成员
+    pub fn heartbeat(&mut self, member_name: &str, now: i64, session_timeout: Duration) {
+        let expired_before = now - session_timeout.as_millis() as i64;
+        self.members.retain(|_, last_seen| *last_seen > expired_before);
+
+        self.members.insert(member_name.to_owned(), now);
+    }
+
+    pub fn leave(&mut self, member_name: &str) {
+        self.members.remove(member_name);
+    }
+
+    /// 成员按名称排序，分区 p 分配给第 p % 成员数 个成员。成员多于分区时，多出的成员没有分区
+    pub fn assignment(&self, member_name: &str, partitions: u32) -> Vec<u32> {
+        let member_index = match self.members.keys().position(|name| name == member_name) {
+            Some(index) => index as u32,
+            None => return vec![],
+        };
+        let member_count = self.members.len() as u32;
+
+        (0..partitions)
+            .filter(|partition| partition % member_count == member_index)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consumer_group() {
+        let session_timeout = Duration::from_secs(10);
+        let mut group = ConsumerGroup::new();
+
+        group.heartbeat("member_b", 1000, session_timeout);
+        assert_eq!(vec![0, 1, 2], group.assignment("member_b", 3));
+        assert!(group.assignment("member_a", 3).is_empty());
+
+        group.heartbeat("member_a", 2000, session_timeout);
+        assert_eq!(vec![0, 2], group.assignment("member_a", 3));
+        assert_eq!(vec![1], group.assignment("member_b", 3));
+
+        group.heartbeat("member_c", 3000, session_timeout);
+        group.heartbeat("member_d", 3000, session_timeout);
+        assert_eq!(vec![0], group.assignment("member_a", 3));
+        assert!(group.assignment("member_d", 3).is_empty());
+
+        // member_b 超时
+        group.heartbeat("member_a", 11000, session_timeout);
+        assert_eq!(vec!["member_a", "member_c", "member_d"], group.members().collect::<Vec<_>>());
+
+        group.leave("member_c");
+        group.leave("member_d");
+        assert_eq!(vec![0, 1, 2], group.assignment("member_a", 3));
+    }
+}
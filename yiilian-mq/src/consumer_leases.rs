@@ -0,0 +1,177 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use yiilian_core::common::error::Error;
+
+/// 已分发但尚未确认的消息
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lease {
+    pub offset: u64,
+    /// 可见性超时的截止时间，utc 毫秒，超时后消息会被重新分发
+    pub deadline: i64,
+    /// 已分发的次数，首次分发为 1
+    pub delivery_count: u32,
+    /// 消息所在的分区
+    #[serde(default)]
+    pub partition: u32,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ConsumerLease {
+    /// 下一个待分发的新消息 offset
+    pub next_offset: Option<u64>,
+    /// offset -> lease
+    pub in_flight: BTreeMap<u64, Lease>,
+}
+
+impl ConsumerLease {
+    /// 最小的未确认 offset 之前的消息都已确认，返回其中最大的 offset
+    pub fn committable_offset(&self) -> Option<u64> {
+        let first_unacked = match self.in_flight.keys().next() {
+            Some(offset) => *offset,
+            None => self.next_offset?,
+        };
+
+        first_unacked.checked_sub(1)
+    }
+}
+
+/// 租约变更后最多间隔多久落盘
+const LEASE_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 各消费者的 in-flight 状态，变更按 LEASE_FLUSH_INTERVAL 批量落盘，释放时写入剩余的变更，重启后可恢复。
+/// 崩溃时最多丢失最近一个间隔内的变更，对应的消息会被重新分发
+#[derive(Debug)]
+pub struct ConsumerLeases {
+    inner: HashMap<String, ConsumerLease>,
+    path: PathBuf,
+    /// 是否有尚未落盘的变更
+    dirty: bool,
+    last_flush: Instant,
+}
+
+impl ConsumerLeases {
+    pub fn new(inner: HashMap<String, ConsumerLease>, path: PathBuf) -> Self {
+        Self {
+            inner,
+            path,
+            dirty: false,
+            last_flush: Instant::now(),
+        }
+    }
+
+    pub fn get(&self, consumer_name: &str) -> Option<&ConsumerLease> {
+        self.inner.get(consumer_name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.inner.keys()
+    }
+
+    pub fn insert(&mut self, consumer_name: &str, lease: ConsumerLease) -> Result<(), Error> {
+        self.inner.insert(consumer_name.to_owned(), lease);
+        self.dirty = true;
+
+        self.flush_if_due()
+    }
+
+    pub fn remove(&mut self, consumer_name: &str) {
+        self.inner.remove(consumer_name);
+        self.dirty = true;
+
+        self.flush_if_due().ok();
+    }
+
+    /// 距上次落盘超过 LEASE_FLUSH_INTERVAL 时写入未落盘的变更
+    pub fn flush_if_due(&mut self) -> Result<(), Error> {
+        if self.last_flush.elapsed() >= LEASE_FLUSH_INTERVAL {
+            self.flush()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// 先写临时文件再改名，避免中途崩溃留下损坏的文件
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let data = serde_yaml::to_string(&self.inner).expect("serde_yaml::to_string() failed");
+        let tmp_path = self.path.with_extension("tmp");
+
+        fs::write(&tmp_path, data.as_bytes())
+            .map_err(|error| Error::new_file(Some(error.into()), None))?;
+        fs::rename(&tmp_path, &self.path)
+            .map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+        self.dirty = false;
+        self.last_flush = Instant::now();
+
+        Ok(())
+    }
+
+    pub fn new_from_file(path: PathBuf) -> Result<Self, Error> {
+        let buf = match fs::read_to_string(&path) {
+            Ok(buf) => buf,
+            Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+            Err(error) => Err(Error::new_file(Some(error.into()), None))?,
+        };
+
+        if buf.trim().is_empty() {
+            return Ok(ConsumerLeases::new(HashMap::new(), path));
+        }
+
+        match serde_yaml::from_str::<HashMap<String, ConsumerLease>>(&buf) {
+            Ok(inner) => Ok(ConsumerLeases::new(inner, path)),
+            Err(error) => Err(Error::new_file(Some(error.into()), None)),
+        }
+    }
+}
+
+impl Drop for ConsumerLeases {
+    fn drop(&mut self) {
+        // topic 目录已被删除时不再写入
+        if self.path.parent().is_some_and(|parent| parent.exists()) {
+            if let Err(error) = self.flush() {
+                log::warn!(target: "yiilian-mq::consumer_leases", "flush {:?} error: {}", self.path, error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::TestHome;
+
+    use super::*;
+
+    #[test]
+    fn test_flush() {
+        let wd = TestHome::new("consumer_leases");
+        let dir = wd.home_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("_consumer_leases");
+
+        // 间隔内的变更先不落盘，释放时写入
+        let mut leases = ConsumerLeases::new_from_file(path.clone()).unwrap();
+        let lease = ConsumerLease { next_offset: Some(3), ..Default::default() };
+        leases.insert("client_1", lease).unwrap();
+        assert!(!path.exists());
+        drop(leases);
+
+        let mut leases = ConsumerLeases::new_from_file(path.clone()).unwrap();
+        assert_eq!(Some(3), leases.get("client_1").unwrap().next_offset);
+
+        leases.remove("client_1");
+        leases.flush().unwrap();
+        assert!(ConsumerLeases::new_from_file(path.clone()).unwrap().get("client_1").is_none());
+        assert!(!path.with_extension("tmp").exists());
+    }
+}
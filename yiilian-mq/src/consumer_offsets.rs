@@ -20,6 +20,10 @@ impl ConsumerOffsets {
         self.inner.get(customer_name).map(|v| *v)
     }
 
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.inner.keys()
+    }
+
     pub fn insert(&mut self, consumer_name: &str, offset: u64) -> Result<(), Error> {
         self.inner.insert(consumer_name.to_owned(), offset);
 
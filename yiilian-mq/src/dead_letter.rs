@@ -0,0 +1,93 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use yiilian_core::common::error::Error;
+
+/// 死信 topic 名称的后缀，topic 的死信保存在 "{topic}.dead_letter" 中
+pub const DEAD_LETTER_SUFFIX: &str = ".dead_letter";
+/// 重放死信时在死信 topic 上使用的消费者
+pub const DEAD_LETTER_REPLAY_CONSUMER: &str = "_replay";
+
+pub fn dead_letter_topic_name(topic_name: &str) -> String {
+    format!("{}{}", topic_name, DEAD_LETTER_SUFFIX)
+}
+
+/// 多次分发仍未处理成功的消息
+///
+/// consumer_len(2) + consumer(x) + offset(8) + delivery_count(4) + timestamp(8) + value(x)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetter {
+    /// 处理失败的消费者
+    pub consumer: String,
+    /// 消息在原 topic 中的 offset
+    pub offset: u64,
+    pub delivery_count: u32,
+    /// 原消息的 timestamp，utc 毫秒
+    pub timestamp: i64,
+    pub value: Bytes,
+}
+
+impl From<DeadLetter> for Bytes {
+    fn from(dead_letter: DeadLetter) -> Self {
+        let mut buf = BytesMut::with_capacity(22 + dead_letter.consumer.len() + dead_letter.value.len());
+        buf.put_u16(dead_letter.consumer.len() as u16);
+        buf.extend_from_slice(dead_letter.consumer.as_bytes());
+        buf.put_u64(dead_letter.offset);
+        buf.put_u32(dead_letter.delivery_count);
+        buf.put_i64(dead_letter.timestamp);
+        buf.extend(dead_letter.value);
+
+        buf.into()
+    }
+}
+
+impl TryFrom<&[u8]> for DeadLetter {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 2 {
+            Err(Error::new_decode(&format!("Data is too short to decode dead letter: {}", data.len())))?;
+        }
+
+        let consumer_len = u16::from_be_bytes(data[0..2].try_into().expect("data[0..2] is not satisfy")) as usize;
+        let pos = 2 + consumer_len;
+        if data.len() < pos + 20 {
+            Err(Error::new_decode(&format!("Data is too short to decode dead letter: {}", data.len())))?;
+        }
+
+        let consumer = String::from_utf8(data[2..pos].to_vec())
+            .map_err(|_| Error::new_decode("Consumer of dead letter is not valid utf8"))?;
+        let offset = u64::from_be_bytes(data[pos..pos + 8].try_into().expect("offset is not satisfy"));
+        let delivery_count = u32::from_be_bytes(data[pos + 8..pos + 12].try_into().expect("delivery_count is not satisfy"));
+        let timestamp = i64::from_be_bytes(data[pos + 12..pos + 20].try_into().expect("timestamp is not satisfy"));
+        let value: Bytes = data[pos + 20..].to_owned().into();
+
+        Ok(DeadLetter {
+            consumer,
+            offset,
+            delivery_count,
+            timestamp,
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dead_letter() {
+        let dead_letter = DeadLetter {
+            consumer: "test_client".to_owned(),
+            offset: 12,
+            delivery_count: 3,
+            timestamp: 1000,
+            value: "value_12".into(),
+        };
+
+        let data: Bytes = dead_letter.clone().into();
+        assert_eq!(dead_letter, DeadLetter::try_from(&data[..]).unwrap());
+
+        assert!(DeadLetter::try_from(&data[..20]).is_err());
+        assert_eq!("info_hash.dead_letter", dead_letter_topic_name("info_hash"));
+    }
+}
@@ -0,0 +1,81 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use bytes::Bytes;
+
+/// 按 key 去重的时间窗口，key 首次写入后的 window 内重复写入会被丢弃。
+/// 只保存在内存中，重启后重新计算
+#[derive(Debug, Default)]
+pub struct DedupWindow {
+    /// key -> 首次写入的 utc 毫秒
+    keys: HashMap<Bytes, i64>,
+    /// 按写入时间排列，用于淘汰过期的 key
+    queue: VecDeque<(i64, Bytes)>,
+}
+
+impl DedupWindow {
+    pub fn new() -> Self {
+        DedupWindow::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// key 在窗口内已写入过时返回 true，否则记录 key 并返回 false
+    pub fn check_and_insert(&mut self, key: &Bytes, now: i64, window: Duration) -> bool {
+        self.evict(now - window.as_millis() as i64);
+
+        if self.keys.contains_key(key) {
+            return true;
+        }
+
+        self.keys.insert(key.clone(), now);
+        self.queue.push_back((now, key.clone()));
+
+        false
+    }
+
+    /// 淘汰 before 及之前写入的 key
+    fn evict(&mut self, before: i64) {
+        while let Some((timestamp, _)) = self.queue.front() {
+            if *timestamp > before {
+                break;
+            }
+
+            let (_, key) = self.queue.pop_front().expect("pop front");
+            self.keys.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_window() {
+        let window = Duration::from_secs(10);
+        let mut dedup_window = DedupWindow::new();
+        let key_1: Bytes = "key_1".into();
+        let key_2: Bytes = "key_2".into();
+
+        assert!(!dedup_window.check_and_insert(&key_1, 1000, window));
+        assert!(dedup_window.check_and_insert(&key_1, 2000, window));
+        assert!(!dedup_window.check_and_insert(&key_2, 5000, window));
+
+        // 窗口从首次写入开始计算
+        assert!(!dedup_window.check_and_insert(&key_1, 11000, window));
+        assert!(dedup_window.check_and_insert(&key_2, 11000, window));
+        assert_eq!(2, dedup_window.len());
+
+        assert!(!dedup_window.check_and_insert(&key_2, 15000, window));
+        assert_eq!(2, dedup_window.len());
+    }
+}
@@ -1,24 +1,41 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::{
     collections::HashMap,
     fs,
     path::PathBuf,
 };
 
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream};
 use std::time::Duration;
-use tokio::time::sleep;
+use tokio::{sync::watch, time::{sleep, timeout}};
 use yiilian_core::common::error::Error;
 
 use crate::{
+    consumer_group::{ConsumerGroup, GROUP_SESSION_TIMEOUT_SECS},
+    consumer_leases::Lease,
+    dead_letter::{dead_letter_topic_name, DeadLetter, DEAD_LETTER_REPLAY_CONSUMER},
     message::{in_message::InMessage, Message},
+    partition::{parse_partition_topic_name, partition_topic_name, select_partition},
     topic::Topic,
+    topic_config::TopicConfig,
 };
 
+/// purge_loop 检查各 topic 是否需要清理的间隔
+const PURGE_TICK_SECS: u64 = 5;
+/// 租约订阅在没有新消息时，检查租约是否超时的间隔
+const LEASE_RECHECK_MILLIS: u64 = 1000;
+
+/// 内部对每个 topic 单独加锁，可以直接通过 Arc<Engine> 在多个任务间共享。
+/// 分区 topic 的每个分区各是一个 Topic，分区 0 即 topic 本身，保存 topic 的配置
 #[derive(Debug)]
 pub struct Engine {
     log_data_size: usize,
     path: PathBuf,
-    topics: HashMap<String, Topic>,
+    topics: RwLock<HashMap<String, Arc<Mutex<Topic>>>>,
+    /// (topic, group) -> 消费组
+    groups: Mutex<HashMap<(String, String), ConsumerGroup>>,
 }
 
 impl Engine {
@@ -47,22 +64,39 @@ impl Engine {
 
                     let topic = Topic::new(topic_name, topic_path, log_data_size)?;
 
-                    topics.insert(topic_name.to_owned(),topic);
+                    topics.insert(topic_name.to_owned(), Arc::new(Mutex::new(topic)));
                 }
             }
         }
 
         Ok(Engine {
             path,
-            topics,
+            topics: RwLock::new(topics),
             log_data_size,
+            groups: Mutex::new(HashMap::new()),
         })
     }
 
-    pub fn open_topic(&mut self, topic_name: &str) -> Result<&mut Topic, Error> {
-        if self.topics.contains_key(topic_name) {
-            let topic = self.topics.get_mut(topic_name).expect("get topic");
-            return Ok(topic);
+    /// 打开 topic 及其所有分区，返回分区 0
+    pub fn open_topic(&self, topic_name: &str) -> Result<Arc<Mutex<Topic>>, Error> {
+        if parse_partition_topic_name(topic_name).is_some() {
+            Err(Error::new_general(&format!("Invalid topic name: {}", topic_name)))?;
+        }
+
+        let topic = self.open_partition(topic_name)?;
+        let partitions = topic.lock().expect("lock topic").config().partition_count();
+        for partition in 1..partitions {
+            self.open_partition(&partition_topic_name(topic_name, partition))?;
+        }
+
+        Ok(topic)
+    }
+
+    fn open_partition(&self, topic_name: &str) -> Result<Arc<Mutex<Topic>>, Error> {
+        let mut topics = self.topics.write().expect("write topics");
+
+        if let Some(topic) = topics.get(topic_name) {
+            return Ok(topic.clone());
         }
 
         let topic_path = {
@@ -74,17 +108,81 @@ impl Engine {
         fs::create_dir_all(topic_path.clone())
             .map_err(|error| Error::new_file(Some(error.into()), None))?;
 
-        let topic = Topic::new(topic_name, topic_path, self.log_data_size)?;
+        let topic = Arc::new(Mutex::new(Topic::new(topic_name, topic_path, self.log_data_size)?));
 
-        self.topics
-            .insert(topic_name.to_owned(), topic);
+        topics.insert(topic_name.to_owned(), topic.clone());
 
-        Ok(self.topics.get_mut(topic_name).expect("get topic"))
+        Ok(topic)
     }
 
-    pub fn remove_topic(&mut self, topic_name: &str) {
-        if self.topics.contains_key(topic_name) {
-            self.topics.remove(topic_name);
+    /// 打开 topic 并设置其配置，配置会保存到 topic 各分区的元数据文件中。
+    /// 分区数只能增加，增加后带 key 的消息可能写入与之前不同的分区
+    pub fn open_topic_with(&self, topic_name: &str, config: TopicConfig) -> Result<Arc<Mutex<Topic>>, Error> {
+        let topic = self.open_topic(topic_name)?;
+
+        let partitions = topic.lock().expect("lock topic").config().partition_count();
+        if config.partition_count() < partitions {
+            Err(Error::new_general(&format!(
+                "Can't reduce partitions of {} from {} to {}",
+                topic_name,
+                partitions,
+                config.partition_count()
+            )))?;
+        }
+
+        for partition in 0..config.partition_count() {
+            self.open_partition(&partition_topic_name(topic_name, partition))?
+                .lock()
+                .expect("lock topic")
+                .set_config(config.clone())?;
+        }
+
+        Ok(topic)
+    }
+
+    /// 获取已打开的 topic，分区 topic 返回分区 0
+    pub fn topic(&self, topic_name: &str) -> Option<Arc<Mutex<Topic>>> {
+        self.topics.read().expect("read topics").get(topic_name).cloned()
+    }
+
+    /// 已打开的 topic 名称，按名称排序，不包含分区
+    pub fn topic_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .topics
+            .read()
+            .expect("read topics")
+            .keys()
+            .filter(|name| parse_partition_topic_name(name).is_none())
+            .cloned()
+            .collect();
+        names.sort();
+
+        names
+    }
+
+    pub fn partition_count(&self, topic_name: &str) -> Option<u32> {
+        self.with_topic(topic_name, |topic| topic.config().partition_count())
+    }
+
+    /// 删除 topic 及其所有分区
+    pub fn remove_topic(&self, topic_name: &str) {
+        let partitions = self.partition_count(topic_name).unwrap_or(1);
+        for partition in (0..partitions).rev() {
+            self.remove_partition(&partition_topic_name(topic_name, partition));
+        }
+
+        self.groups
+            .lock()
+            .expect("lock groups")
+            .retain(|(group_topic_name, _), _| group_topic_name != topic_name);
+    }
+
+    fn remove_partition(&self, topic_name: &str) {
+        let topic = self.topics.write().expect("write topics").remove(topic_name);
+
+        if let Some(topic) = topic {
+            // 等待正在进行的操作结束后再删除文件
+            let _topic = topic.lock().expect("lock topic");
 
             let topic_path = {
                 let mut p = self.path.clone();
@@ -96,55 +194,436 @@ impl Engine {
         }
     }
 
-    pub fn push_message(&mut self, topic_name: &str, message: InMessage) -> Result<(), Error> {
-        if let Some(topic) = self.topics.get_mut(topic_name) {
-            topic.push_message(message)
-        } else {
-            Err(Error::new_general("Not found topic"))
+    fn with_topic<T>(&self, topic_name: &str, f: impl FnOnce(&mut Topic) -> T) -> Option<T> {
+        let topic = self.topic(topic_name)?;
+        let mut topic = topic.lock().expect("lock topic");
+
+        Some(f(&mut topic))
+    }
+
+    fn with_partition<T>(&self, topic_name: &str, partition: u32, f: impl FnOnce(&mut Topic) -> T) -> Option<T> {
+        self.with_topic(&partition_topic_name(topic_name, partition), f)
+    }
+
+    /// 从轮流选择的分区开始，返回所有分区
+    fn partition_order(&self, topic_name: &str) -> Vec<u32> {
+        match self.with_topic(topic_name, |topic| (topic.next_partition(), topic.config().partition_count())) {
+            Some((start, partitions)) => (0..partitions).map(|i| (start + i) % partitions).collect(),
+            None => vec![],
+        }
+    }
+
+    /// 消息轮流写入各分区
+    pub fn push_message(&self, topic_name: &str, message: InMessage) -> Result<(), Error> {
+        let partition = self
+            .with_topic(topic_name, |topic| topic.next_partition())
+            .ok_or_else(|| Error::new_general("Not found topic"))?;
+
+        self.push_partition(topic_name, partition, |topic| topic.push_message(message))
+    }
+
+    /// 写入带 key 的消息，按 key 选择分区，在分区的去重窗口内重复时丢弃并返回 false
+    pub fn push_keyed_message(&self, topic_name: &str, key: Bytes, message: InMessage) -> Result<bool, Error> {
+        let partitions = self
+            .partition_count(topic_name)
+            .ok_or_else(|| Error::new_general("Not found topic"))?;
+        let partition = select_partition(&key, partitions);
+
+        self.push_partition(topic_name, partition, |topic| topic.push_keyed_message(key, message))
+    }
+
+    /// 订阅只监听分区 0，写入其他分区后需要通知分区 0 的订阅者
+    fn push_partition<T>(
+        &self,
+        topic_name: &str,
+        partition: u32,
+        f: impl FnOnce(&mut Topic) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let rst = self
+            .with_partition(topic_name, partition, f)
+            .unwrap_or_else(|| Err(Error::new_general("Not found partition")))?;
+
+        if partition != 0 {
+            self.with_topic(topic_name, |topic| topic.notify_push());
         }
+
+        Ok(rst)
     }
 
-    pub fn poll_message(&mut self, topic_name: &str, consumer_name: &str) -> Option<Message> {
-        if let Some(topic) = self.topics.get_mut(topic_name) {
-            let message = topic.poll_message(consumer_name);
+    /// 立即压缩 topic 各分区已写满的 segment，返回删除的消息条数
+    pub fn compact_topic(&self, topic_name: &str) -> Result<u64, Error> {
+        let partitions = self
+            .partition_count(topic_name)
+            .ok_or_else(|| Error::new_general("Not found topic"))?;
 
-            message
-        } else {
-            None
+        let mut count = 0;
+        for partition in 0..partitions {
+            count += self
+                .with_partition(topic_name, partition, |topic| topic.compact())
+                .unwrap_or_else(|| Err(Error::new_general("Not found partition")))?;
         }
+
+        Ok(count)
     }
 
-    pub fn message_count(&self, topic_name: &str, consumer_name: &str) -> u64 {
-        if let Some(topic) = self.topics.get(topic_name) {
-            topic.count(consumer_name)
-        } else {
-            0
+    /// 立即按 topic 的 compression 压缩各分区已写满的 segment，返回压缩的 segment 数
+    pub fn compress_topic(&self, topic_name: &str) -> Result<usize, Error> {
+        let partitions = self
+            .partition_count(topic_name)
+            .ok_or_else(|| Error::new_general("Not found topic"))?;
+
+        let mut count = 0;
+        for partition in 0..partitions {
+            count += self
+                .with_partition(topic_name, partition, |topic| topic.compress())
+                .unwrap_or_else(|| Err(Error::new_general("Not found partition")))?;
+        }
+
+        Ok(count)
+    }
+
+    /// 从各分区轮流获取消息
+    pub fn poll_message(&self, topic_name: &str, consumer_name: &str) -> Option<Message> {
+        self.partition_order(topic_name).into_iter().find_map(|partition| {
+            self.with_partition(topic_name, partition, |topic| topic.poll_message(consumer_name))
+                .flatten()
+        })
+    }
+
+    /// 从各分区批量获取最多 count 条消息
+    pub fn poll_messages(&self, topic_name: &str, consumer_name: &str, count: usize) -> Vec<Message> {
+        let mut messages = vec![];
+
+        for partition in self.partition_order(topic_name) {
+            if messages.len() >= count {
+                break;
+            }
+
+            let batch = self
+                .with_partition(topic_name, partition, |topic| topic.poll_messages(consumer_name, count - messages.len()))
+                .unwrap_or_default();
+            messages.extend(batch);
+        }
+
+        messages
+    }
+
+    /// 读取最多 count 条消息但不提交位置，需要调用 commit_offset 提交。
+    /// 分区 topic 中只读取分区 0，其他分区使用 fetch_partition_messages 或消费组
+    pub fn fetch_messages(&self, topic_name: &str, consumer_name: &str, count: usize) -> Vec<Message> {
+        self.fetch_partition_messages(topic_name, 0, consumer_name, count)
+    }
+
+    pub fn fetch_partition_messages(&self, topic_name: &str, partition: u32, consumer_name: &str, count: usize) -> Vec<Message> {
+        self.with_partition(topic_name, partition, |topic| topic.fetch_messages(consumer_name, count))
+            .unwrap_or_default()
+    }
+
+    /// 提交分区 0 的位置
+    pub fn commit_offset(&self, topic_name: &str, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        self.commit_partition_offset(topic_name, 0, consumer_name, offset)
+    }
+
+    pub fn commit_partition_offset(&self, topic_name: &str, partition: u32, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        self.with_partition(topic_name, partition, |topic| topic.commit_offset(consumer_name, offset))
+            .unwrap_or_else(|| Err(Error::new_general("Not found partition")))
+    }
+
+    /// 加入消费组，返回分配给 member 的分区。成员需要定期调用以保持在消费组中，
+    /// 超过 GROUP_SESSION_TIMEOUT_SECS 没有请求的成员被移出，其分区分配给其他成员
+    pub fn join_group(&self, topic_name: &str, group_name: &str, member_name: &str) -> Result<Vec<u32>, Error> {
+        let partitions = self
+            .partition_count(topic_name)
+            .ok_or_else(|| Error::new_general("Not found topic"))?;
+
+        let mut groups = self.groups.lock().expect("lock groups");
+        let group = groups
+            .entry((topic_name.to_owned(), group_name.to_owned()))
+            .or_default();
+        group.heartbeat(
+            member_name,
+            Utc::now().timestamp_millis(),
+            Duration::from_secs(GROUP_SESSION_TIMEOUT_SECS),
+        );
+
+        Ok(group.assignment(member_name, partitions))
+    }
+
+    /// 离开消费组，其分区立即分配给其他成员
+    pub fn leave_group(&self, topic_name: &str, group_name: &str, member_name: &str) {
+        let mut groups = self.groups.lock().expect("lock groups");
+
+        if let Some(group) = groups.get_mut(&(topic_name.to_owned(), group_name.to_owned())) {
+            group.leave(member_name);
+        }
+    }
+
+    /// 从分配给 member 的分区读取最多 count 条消息，返回 (分区, 消息)，同时刷新成员的心跳。
+    /// 位置按消费组名称保存，需要调用 commit_group_offset 提交
+    pub fn fetch_group_messages(
+        &self,
+        topic_name: &str,
+        group_name: &str,
+        member_name: &str,
+        count: usize,
+    ) -> Result<Vec<(u32, Message)>, Error> {
+        let mut partitions = self.join_group(topic_name, group_name, member_name)?;
+        if partitions.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // 轮流从不同的分区开始，避免前面的分区一直占满 count
+        let start = self
+            .with_topic(topic_name, |topic| topic.next_partition())
+            .unwrap_or(0) as usize;
+        let len = partitions.len();
+        partitions.rotate_left(start % len);
+
+        let mut messages = vec![];
+        for partition in partitions {
+            if messages.len() >= count {
+                break;
+            }
+
+            let batch = self.fetch_partition_messages(topic_name, partition, group_name, count - messages.len());
+            messages.extend(batch.into_iter().map(|message| (partition, message)));
+        }
+
+        Ok(messages)
+    }
+
+    /// 提交消费组在分区上的位置，分区已分配给其他成员时返回错误
+    pub fn commit_group_offset(
+        &self,
+        topic_name: &str,
+        group_name: &str,
+        member_name: &str,
+        partition: u32,
+        offset: u64,
+    ) -> Result<(), Error> {
+        if !self.join_group(topic_name, group_name, member_name)?.contains(&partition) {
+            Err(Error::new_general(&format!(
+                "Partition {} is not assigned to {} of group {}",
+                partition, member_name, group_name
+            )))?;
+        }
+
+        self.commit_partition_offset(topic_name, partition, group_name, offset)
+    }
+
+    /// 以租约方式从各分区轮流分发消息，需要调用 ack_partition / nack_partition 确认
+    pub fn poll_lease(
+        &self,
+        topic_name: &str,
+        consumer_name: &str,
+        visibility: Duration,
+    ) -> Option<(Message, Lease)> {
+        for partition in self.partition_order(topic_name) {
+            let rst = self
+                .with_partition(topic_name, partition, |topic| topic.poll_lease(consumer_name, visibility))
+                .flatten();
+
+            // 超时且分发次数已用完的消息在 poll_lease 时转入死信
+            if let Err(error) = self.route_dead_letters(topic_name, partition) {
+                log::warn!(target: "yiilian_mq::engine", "route dead letters of {} error: {}", topic_name, error);
+            }
+
+            if rst.is_some() {
+                return rst;
+            }
         }
+
+        None
+    }
+
+    /// 确认分区 0 的消息
+    pub fn ack(&self, topic_name: &str, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        self.ack_partition(topic_name, 0, consumer_name, offset)
+    }
+
+    /// 确认 lease.partition 中的消息
+    pub fn ack_partition(&self, topic_name: &str, partition: u32, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        self.with_partition(topic_name, partition, |topic| topic.ack(consumer_name, offset))
+            .unwrap_or_else(|| Err(Error::new_general("Not found partition")))
+    }
+
+    pub fn nack(&self, topic_name: &str, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        self.nack_partition(topic_name, 0, consumer_name, offset)
+    }
+
+    pub fn nack_partition(&self, topic_name: &str, partition: u32, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        self.with_partition(topic_name, partition, |topic| topic.nack(consumer_name, offset))
+            .unwrap_or_else(|| Err(Error::new_general("Not found partition")))?;
+
+        self.route_dead_letters(topic_name, partition)
+    }
+
+    /// 不再重新分发，直接将消息转入死信 topic
+    pub fn dead_letter(&self, topic_name: &str, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        self.dead_letter_partition(topic_name, 0, consumer_name, offset)
+    }
+
+    pub fn dead_letter_partition(&self, topic_name: &str, partition: u32, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        self.with_partition(topic_name, partition, |topic| topic.dead_letter(consumer_name, offset))
+            .unwrap_or_else(|| Err(Error::new_general("Not found partition")))?;
+
+        self.route_dead_letters(topic_name, partition)
+    }
+
+    /// 查看 topic 的死信，返回死信 topic 中的 offset 及死信
+    pub fn dead_letters(&self, topic_name: &str, offset: u64, count: usize) -> Vec<(u64, DeadLetter)> {
+        self.with_topic(&dead_letter_topic_name(topic_name), |topic| topic.read_messages(offset, count))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|message| {
+                DeadLetter::try_from(message.value())
+                    .ok()
+                    .map(|dead_letter| (message.offset(), dead_letter))
+            })
+            .collect()
+    }
+
+    /// 将最多 count 条尚未重放的死信重新写入原 topic，返回重放的条数
+    pub fn replay_dead_letters(&self, topic_name: &str, count: usize) -> Result<usize, Error> {
+        let dead_letter_topic = dead_letter_topic_name(topic_name);
+        let messages = self.fetch_messages(&dead_letter_topic, DEAD_LETTER_REPLAY_CONSUMER, count);
+
+        for message in &messages {
+            match DeadLetter::try_from(message.value()) {
+                Ok(dead_letter) => self.push_message(topic_name, InMessage(dead_letter.value))?,
+                Err(error) => {
+                    log::warn!(target: "yiilian_mq::engine", "decode dead letter {} error: {}", message.offset(), error);
+                }
+            }
+        }
+
+        if let Some(last_message) = messages.last() {
+            self.commit_offset(&dead_letter_topic, DEAD_LETTER_REPLAY_CONSUMER, last_message.offset())?;
+        }
+
+        Ok(messages.len())
+    }
+
+    /// 各分区的死信都转入 topic 的死信 topic
+    fn route_dead_letters(&self, topic_name: &str, partition: u32) -> Result<(), Error> {
+        let dead_letters = self
+            .with_partition(topic_name, partition, |topic| topic.take_dead_letters())
+            .unwrap_or_default();
+
+        if dead_letters.is_empty() {
+            return Ok(());
+        }
+
+        let dead_letter_topic = self.open_topic(&dead_letter_topic_name(topic_name))?;
+        let mut dead_letter_topic = dead_letter_topic.lock().expect("lock topic");
+        for dead_letter in dead_letters {
+            dead_letter_topic.push_message(InMessage(dead_letter.into()))?;
+        }
+
+        Ok(())
+    }
+
+    /// 分区 0 中第一条 timestamp 不早于 time 的消息 offset
+    pub fn offset_for_time(&self, topic_name: &str, time: DateTime<Utc>) -> Option<u64> {
+        self.with_topic(topic_name, |topic| topic.offset_for_time(time))
+            .flatten()
+    }
+
+    /// 各分区中消费者未消费的消息数之和
+    pub fn message_count(&self, topic_name: &str, consumer_name: &str) -> u64 {
+        (0..self.partition_count(topic_name).unwrap_or(0))
+            .filter_map(|partition| self.with_partition(topic_name, partition, |topic| topic.count(consumer_name)))
+            .sum()
+    }
+
+    /// 订阅 topic，消息按 poll_message 的方式消费，没有消息时等待 push_message 唤醒
+    pub fn subscribe(
+        self: &Arc<Self>,
+        topic_name: &str,
+        consumer_name: &str,
+    ) -> Result<impl Stream<Item = Message>, Error> {
+        let notify_rx = self.notify_receiver(topic_name)?;
+        let state = (self.clone(), topic_name.to_owned(), consumer_name.to_owned(), notify_rx);
+
+        Ok(stream::unfold(state, |(engine, topic_name, consumer_name, mut notify_rx)| async move {
+            loop {
+                // 先标记已读，再取消息，避免漏掉两者之间的 push
+                notify_rx.borrow_and_update();
+
+                if let Some(message) = engine.poll_message(&topic_name, &consumer_name) {
+                    return Some((message, (engine, topic_name, consumer_name, notify_rx)));
+                }
+
+                // topic 被删除时结束
+                notify_rx.changed().await.ok()?;
+            }
+        }))
+    }
+
+    /// 以租约方式订阅 topic，收到的消息需要调用 ack / nack 确认
+    pub fn subscribe_lease(
+        self: &Arc<Self>,
+        topic_name: &str,
+        consumer_name: &str,
+        visibility: Duration,
+    ) -> Result<impl Stream<Item = (Message, Lease)>, Error> {
+        let notify_rx = self.notify_receiver(topic_name)?;
+        let state = (self.clone(), topic_name.to_owned(), consumer_name.to_owned(), notify_rx);
+
+        Ok(stream::unfold(state, move |(engine, topic_name, consumer_name, mut notify_rx)| async move {
+            loop {
+                notify_rx.borrow_and_update();
+
+                if let Some(rst) = engine.poll_lease(&topic_name, &consumer_name, visibility) {
+                    return Some((rst, (engine, topic_name, consumer_name, notify_rx)));
+                }
+
+                // 超时或被 nack 的消息不会触发通知，需要定期检查
+                if let Ok(rst) = timeout(Duration::from_millis(LEASE_RECHECK_MILLIS), notify_rx.changed()).await {
+                    rst.ok()?;
+                }
+            }
+        }))
+    }
+
+    pub(crate) fn notify_receiver(&self, topic_name: &str) -> Result<watch::Receiver<u64>, Error> {
+        self.with_topic(topic_name, |topic| topic.subscribe_push())
+            .ok_or_else(|| Error::new_general("Not found topic"))
     }
 }
 
-pub async fn purge_loop(engine: Arc<Mutex<Engine>>) {
+pub async fn purge_loop(engine: Arc<Engine>) {
     loop {
+        let topic_list: Vec<Arc<Mutex<Topic>>> = engine
+            .topics
+            .read()
+            .expect("read topics")
+            .values()
+            .cloned()
+            .collect();
 
-        let mut engine = engine.lock().expect("lock engine");
-
-        let topic_list: Vec<&mut Topic> = engine.topics.values_mut().map(|v| v).collect();
         for topic in topic_list {
-            topic.purge_segment();
+            topic.lock().expect("lock topic").purge_segment_if_due();
         }
 
-        // 不 drop 的话，由于后面跟了 sleep().await，导致锁跨 future了。engine 无法释放，所以会一直 lock，形成死锁
-        drop(engine);
-
-        sleep(Duration::from_secs(60)).await;
+        sleep(Duration::from_secs(PURGE_TICK_SECS)).await;
     }
 }
 
 #[cfg(test)]
 mod tests {
 
+    use futures::StreamExt;
     use yiilian_core::common::working_dir::WorkingDir;
 
+    use crate::{
+        message::batch::Compression,
+        segment::{gen_mq_file_name, LOG_DATA_FILE_EXTENSION, LOG_INDEX_FILE_EXTENSION},
+        test_util::TestHome,
+        tool::{check_segment, segment_offsets, segment_summary},
+        topic::{COMPACT_COMMIT_FILE_NAME, COMPACT_TMP_DIR_NAME},
+    };
+
     use super::*;
 
     #[tokio::test]
@@ -153,8 +632,8 @@ mod tests {
         let consumer_name = "test_client";
         let wd = WorkingDir::new();
 
-        let mut engine = {
-            let mut engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+        let engine = {
+            let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
             engine
                 .open_topic(topic_name)
                 .expect("open test_count topic");
@@ -177,4 +656,645 @@ mod tests {
 
         engine.remove_topic(topic_name);
     }
+
+    #[tokio::test]
+    async fn test_lease() {
+        let topic_name = "test_lease";
+        let consumer_name = "test_client";
+        let visibility = Duration::from_secs(60);
+        let wd = TestHome::new("lease");
+
+        let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+        engine.open_topic(topic_name).expect("open test_lease topic");
+
+        for i in 0..5 {
+            let message = InMessage(format!("value_{}", i).into());
+            engine.push_message(topic_name, message).unwrap();
+        }
+
+        let (m0, _) = engine.poll_lease(topic_name, consumer_name, visibility).unwrap();
+        let (m1, _) = engine.poll_lease(topic_name, consumer_name, visibility).unwrap();
+        let (m2, _) = engine.poll_lease(topic_name, consumer_name, visibility).unwrap();
+        assert_eq!(b"value_0", m0.value());
+
+        // 未连续确认时不提交
+        engine.ack(topic_name, consumer_name, m1.offset()).unwrap();
+        let offset = engine.topic(topic_name).unwrap().lock().unwrap().consumer_offsets().get(consumer_name);
+        assert_eq!(None, offset);
+
+        engine.ack(topic_name, consumer_name, m0.offset()).unwrap();
+        let offset = engine.topic(topic_name).unwrap().lock().unwrap().consumer_offsets().get(consumer_name);
+        assert_eq!(Some(m1.offset()), offset);
+        assert!(engine.ack(topic_name, consumer_name, m0.offset()).is_err());
+
+        // nack 后立即重新分发
+        engine.nack(topic_name, consumer_name, m2.offset()).unwrap();
+        let (m, lease) = engine.poll_lease(topic_name, consumer_name, visibility).unwrap();
+        assert_eq!(m2, m);
+        assert_eq!(2, lease.delivery_count);
+
+        // 超时后重新分发
+        let (m3, _) = engine.poll_lease(topic_name, consumer_name, Duration::ZERO).unwrap();
+        let (m, lease) = engine.poll_lease(topic_name, consumer_name, visibility).unwrap();
+        assert_eq!(m3, m);
+        assert_eq!(2, lease.delivery_count);
+
+        // 重启后 in-flight 状态仍在
+        drop(engine);
+        let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+                let in_flight: Vec<u64> = {
+            let topic = engine.topic(topic_name).unwrap();
+            let topic = topic.lock().unwrap();
+            let state = topic.consumer_leases().get(consumer_name).unwrap();
+            state.in_flight.keys().copied().collect()
+        };
+        assert_eq!(vec![m2.offset(), m3.offset()], in_flight);
+
+        let (m4, _) = engine.poll_lease(topic_name, consumer_name, visibility).unwrap();
+        assert_eq!(b"value_4", m4.value());
+        assert!(engine.poll_lease(topic_name, consumer_name, visibility).is_none());
+
+        engine.ack(topic_name, consumer_name, m2.offset()).unwrap();
+        engine.ack(topic_name, consumer_name, m3.offset()).unwrap();
+        engine.ack(topic_name, consumer_name, m4.offset()).unwrap();
+        let offset = engine.topic(topic_name).unwrap().lock().unwrap().consumer_offsets().get(consumer_name);
+        assert_eq!(Some(m4.offset()), offset);
+
+        engine.remove_topic(topic_name);
+    }
+
+    #[tokio::test]
+    async fn test_offset_for_time() {
+        let topic_name = "test_offset_for_time";
+        let consumer_name = "test_client";
+        let wd = TestHome::new("offset_for_time");
+
+        for log_data_size in [100, 64 * 1024] {
+            let engine = Engine::new(log_data_size, wd.home_dir()).expect("create mq engine");
+            engine.open_topic(topic_name).expect("open test_offset_for_time topic");
+
+            let start = Utc::now();
+            for i in 0..150 {
+                let message = InMessage(format!("before_{}", i).into());
+                engine.push_message(topic_name, message).unwrap();
+            }
+
+            sleep(Duration::from_millis(10)).await;
+            let middle = Utc::now();
+            sleep(Duration::from_millis(10)).await;
+
+            for i in 0..50 {
+                let message = InMessage(format!("after_{}", i).into());
+                engine.push_message(topic_name, message).unwrap();
+            }
+
+            assert_eq!(Some(0), engine.offset_for_time(topic_name, start - chrono::Duration::hours(1)));
+            assert_eq!(Some(150), engine.offset_for_time(topic_name, middle));
+            assert_eq!(None, engine.offset_for_time(topic_name, Utc::now() + chrono::Duration::hours(1)));
+
+            let topic = engine.topic(topic_name).unwrap();
+            let mut topic = topic.lock().unwrap();
+            assert_eq!(150, topic.seek_to_time(consumer_name, middle).unwrap());
+            let message = topic.poll_message(consumer_name).unwrap();
+            assert_eq!(b"after_0", message.value());
+
+            assert_eq!(0, topic.seek_to_time(consumer_name, start - chrono::Duration::hours(1)).unwrap());
+            let message = topic.poll_message(consumer_name).unwrap();
+            assert_eq!(b"before_0", message.value());
+
+            drop(topic);
+            engine.remove_topic(topic_name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dead_letter() {
+        let topic_name = "test_dead_letter";
+        let consumer_name = "test_client";
+        let visibility = Duration::from_secs(60);
+        let wd = TestHome::new("dead_letter");
+
+        let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+        engine.remove_topic(topic_name);
+        engine.remove_topic(&dead_letter_topic_name(topic_name));
+
+        let config = TopicConfig::new()
+            .max_deliveries(Some(2))
+            .redelivery_delay_secs(1);
+        engine.open_topic_with(topic_name, config).expect("open test_dead_letter topic");
+
+        for i in 0..3 {
+            let message = InMessage(format!("value_{}", i).into());
+            engine.push_message(topic_name, message).unwrap();
+        }
+
+        // nack 后延迟重新分发
+        let (m0, _) = engine.poll_lease(topic_name, consumer_name, visibility).unwrap();
+        engine.nack(topic_name, consumer_name, m0.offset()).unwrap();
+        let (m1, _) = engine.poll_lease(topic_name, consumer_name, visibility).unwrap();
+        assert_eq!(b"value_1", m1.value());
+        engine.ack(topic_name, consumer_name, m1.offset()).unwrap();
+
+        sleep(Duration::from_millis(1100)).await;
+        let (message, lease) = engine.poll_lease(topic_name, consumer_name, visibility).unwrap();
+        assert_eq!(m0, message);
+        assert_eq!(2, lease.delivery_count);
+
+        // 分发次数用完后 nack 转入死信
+        engine.nack(topic_name, consumer_name, m0.offset()).unwrap();
+        let (m2, _) = engine.poll_lease(topic_name, consumer_name, visibility).unwrap();
+        assert_eq!(b"value_2", m2.value());
+        engine.dead_letter(topic_name, consumer_name, m2.offset()).unwrap();
+        assert!(engine.poll_lease(topic_name, consumer_name, visibility).is_none());
+
+        let offset = engine.topic(topic_name).unwrap().lock().unwrap().consumer_offsets().get(consumer_name);
+        assert_eq!(Some(2), offset);
+
+        let dead_letters = engine.dead_letters(topic_name, 0, 10);
+        assert_eq!(2, dead_letters.len());
+        assert_eq!(0, dead_letters[0].1.offset);
+        assert_eq!(2, dead_letters[0].1.delivery_count);
+        assert_eq!(consumer_name, dead_letters[0].1.consumer);
+        assert_eq!(b"value_2", &dead_letters[1].1.value[..]);
+
+        // 重放后重新分发
+        assert_eq!(2, engine.replay_dead_letters(topic_name, 10).unwrap());
+        assert_eq!(0, engine.replay_dead_letters(topic_name, 10).unwrap());
+
+        let (message, lease) = engine.poll_lease(topic_name, consumer_name, Duration::ZERO).unwrap();
+        assert_eq!(b"value_0", message.value());
+        assert_eq!(3, lease.offset);
+
+        // 租约超时且分发次数用完时也转入死信
+        let (_, lease) = engine.poll_lease(topic_name, consumer_name, Duration::ZERO).unwrap();
+        assert_eq!(3, lease.offset);
+        assert_eq!(2, lease.delivery_count);
+
+        let (message, _) = engine.poll_lease(topic_name, consumer_name, visibility).unwrap();
+        assert_eq!(b"value_2", message.value());
+        assert_eq!(3, engine.dead_letters(topic_name, 0, 10).len());
+
+        engine.remove_topic(topic_name);
+        engine.remove_topic(&dead_letter_topic_name(topic_name));
+    }
+
+    #[tokio::test]
+    async fn test_compaction() {
+        let topic_name = "test_compaction";
+        let wd = TestHome::new("compaction");
+
+        {
+            let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+            engine.remove_topic(topic_name);
+            engine.open_topic(topic_name).expect("open test_compaction topic");
+
+            // 每个 segment 2 条消息：[0, 1] [2, 3] [4, 5] [6]
+            for (i, key) in ["a", "b", "a", "c", "b", "", "a"].iter().enumerate() {
+                let message = InMessage(format!("value_{}", i).into());
+                if key.is_empty() {
+                    engine.push_message(topic_name, message).unwrap();
+                } else {
+                    assert!(engine.push_keyed_message(topic_name, Bytes::from(*key), message).unwrap());
+                }
+            }
+
+            engine.commit_offset(topic_name, "old_client", 1).unwrap();
+
+            // 活动 segment 不压缩，segment 0 的消息都被之后的消息覆盖
+            assert_eq!(3, engine.compact_topic(topic_name).unwrap());
+            assert_eq!(0, engine.compact_topic(topic_name).unwrap());
+
+            let segment_offsets: Vec<u64> = engine.topic(topic_name).unwrap().lock().unwrap()
+                .segment_offsets().iter().map(|info| info.offset).collect();
+            assert_eq!(vec![2, 4, 6], segment_offsets);
+        }
+
+        let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+        assert!(engine.topic(topic_name).unwrap().lock().unwrap().recovery_reports().is_empty());
+
+        let messages = engine.poll_messages(topic_name, "new_client", 10);
+        let offsets: Vec<u64> = messages.iter().map(|m| m.offset()).collect();
+        assert_eq!(vec![3, 4, 5, 6], offsets);
+        assert_eq!(Some(&b"c"[..]), messages[0].key());
+        assert_eq!(None, messages[2].key());
+        assert_eq!(b"value_6", messages[3].value());
+
+        // 已提交的位置被压缩掉时从其后的第一条开始
+        let message = engine.poll_message(topic_name, "old_client").unwrap();
+        assert_eq!(3, message.offset());
+
+        let (message, _) = engine.poll_lease(topic_name, "lease_client", Duration::from_secs(60)).unwrap();
+        assert_eq!(3, message.offset());
+
+        let time = DateTime::<Utc>::from_timestamp_millis(0).unwrap();
+        assert_eq!(Some(3), engine.offset_for_time(topic_name, time));
+
+        // 去重窗口内重复的 key 被丢弃
+        engine.open_topic_with(topic_name, TopicConfig::new().dedup_window_secs(Some(60))).unwrap();
+        assert!(engine.push_keyed_message(topic_name, "a".into(), InMessage("value_7".into())).unwrap());
+        assert!(!engine.push_keyed_message(topic_name, "a".into(), InMessage("value_8".into())).unwrap());
+        assert!(engine.push_keyed_message(topic_name, "d".into(), InMessage("value_9".into())).unwrap());
+        assert!(engine.push_keyed_message(topic_name, Bytes::new(), InMessage("value_10".into())).is_err());
+
+        let offsets: Vec<u64> = engine
+            .poll_messages(topic_name, "new_client", 10)
+            .iter()
+            .map(|m| m.offset())
+            .collect();
+        assert_eq!(vec![7, 8], offsets);
+
+        engine.remove_topic(topic_name);
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        let topic_name = "test_compression";
+        let wd = TestHome::new("compression");
+        let topic_path = wd.home_dir().join(".yiilian/mq").join(topic_name);
+        let config = TopicConfig::new().compression(Some(Compression::Lz4));
+
+        let expected = {
+            let engine = Engine::new(1000, wd.home_dir()).expect("create mq engine");
+            engine.remove_topic(topic_name);
+            engine.open_topic_with(topic_name, config.clone()).expect("open test_compression topic");
+
+            for i in 0..100 {
+                let message = InMessage(format!("value_{}", i).into());
+                if i % 3 == 0 {
+                    let key = Bytes::from(format!("key_{}", i));
+                    assert!(engine.push_keyed_message(topic_name, key, message).unwrap());
+                } else {
+                    engine.push_message(topic_name, message).unwrap();
+                }
+            }
+
+            let topic = engine.topic(topic_name).unwrap();
+            let expected = topic.lock().unwrap().read_messages(0, 200);
+            assert_eq!(100, expected.len());
+
+            let segment_count = topic.lock().unwrap().segment_offsets().len();
+            let data_size = |engine: &Engine| -> u64 {
+                let topic = engine.topic(topic_name).unwrap();
+                let topic = topic.lock().unwrap();
+                topic.segment_offsets()
+                    .iter()
+                    .map(|info| fs::metadata(topic_path.join(gen_mq_file_name(info.offset, LOG_DATA_FILE_EXTENSION))).unwrap().len())
+                    .sum()
+            };
+            let uncompressed_size = data_size(&engine);
+
+            // 活动 segment 不压缩
+            assert_eq!(segment_count - 1, engine.compress_topic(topic_name).unwrap());
+            assert_eq!(0, engine.compress_topic(topic_name).unwrap());
+            assert!(data_size(&engine) < uncompressed_size);
+
+            assert_eq!(expected, topic.lock().unwrap().read_messages(0, 200));
+
+            expected
+        };
+
+        for segment_offset in segment_offsets(&topic_path).unwrap() {
+            assert!(check_segment(&topic_path, segment_offset).unwrap().is_ok());
+        }
+        let summary = segment_summary(&topic_path, 0).unwrap();
+        assert_eq!(1, summary.batch_count);
+        assert_eq!(summary.message_count, summary.index_count);
+
+        // 删除 .index 后重新打开，按批次重建索引
+        fs::remove_file(topic_path.join(gen_mq_file_name(0, LOG_INDEX_FILE_EXTENSION))).unwrap();
+
+        let engine = Engine::new(1000, wd.home_dir()).expect("create mq engine");
+        let topic = engine.topic(topic_name).unwrap();
+        assert_eq!(expected, topic.lock().unwrap().read_messages(0, 200));
+
+        let offsets: Vec<u64> = topic.lock().unwrap().read_messages(40, 3).iter().map(|m| m.offset()).collect();
+        assert_eq!(vec![40, 41, 42], offsets);
+
+        engine.commit_offset(topic_name, "client", 9).unwrap();
+        let messages = engine.poll_messages(topic_name, "client", 5);
+        assert_eq!(expected[10..15].to_vec(), messages);
+
+        let (message, _) = engine.poll_lease(topic_name, "lease_client", Duration::from_secs(60)).unwrap();
+        assert_eq!(0, message.offset());
+
+        let time = DateTime::<Utc>::from_timestamp_millis(0).unwrap();
+        assert_eq!(Some(0), engine.offset_for_time(topic_name, time));
+
+        engine.remove_topic(topic_name);
+    }
+
+    #[tokio::test]
+    async fn test_compression_interrupted() {
+        let topic_name = "test_compression_interrupted";
+        let wd = TestHome::new("compression_interrupted");
+        let topic_path = wd.home_dir().join(".yiilian/mq").join(topic_name);
+        let tmp_path = topic_path.join(COMPACT_TMP_DIR_NAME);
+        let data_name = gen_mq_file_name(0, LOG_DATA_FILE_EXTENSION);
+        let config = TopicConfig::new().compression(Some(Compression::Lz4));
+
+        let (expected, raw_data) = {
+            let engine = Engine::new(1000, wd.home_dir()).expect("create mq engine");
+            engine.remove_topic(topic_name);
+            engine.open_topic_with(topic_name, config).expect("open test_compression_interrupted topic");
+
+            for i in 0..100 {
+                engine.push_message(topic_name, InMessage(format!("value_{}", i).into())).unwrap();
+            }
+
+            let expected = engine.topic(topic_name).unwrap().lock().unwrap().read_messages(0, 200);
+            let raw_data = fs::read(topic_path.join(&data_name)).unwrap();
+            assert!(engine.compress_topic(topic_name).unwrap() > 0);
+
+            (expected, raw_data)
+        };
+
+        // 已提交但只替换了索引：新的 .log 还在临时目录中，topic 目录中是原来的 .log
+        fs::create_dir_all(&tmp_path).unwrap();
+        fs::rename(topic_path.join(&data_name), tmp_path.join(&data_name)).unwrap();
+        fs::write(topic_path.join(&data_name), &raw_data).unwrap();
+        fs::write(tmp_path.join(COMPACT_COMMIT_FILE_NAME), b"").unwrap();
+
+        {
+            let engine = Engine::new(1000, wd.home_dir()).expect("create mq engine");
+            assert!(!tmp_path.exists());
+            assert!(engine.topic(topic_name).unwrap().lock().unwrap().recovery_reports().is_empty());
+            assert_eq!(expected, engine.topic(topic_name).unwrap().lock().unwrap().read_messages(0, 200));
+            assert_eq!(1, segment_summary(&topic_path, 0).unwrap().batch_count);
+        }
+
+        // 未提交时原 segment 不变，临时目录被丢弃
+        fs::create_dir_all(&tmp_path).unwrap();
+        fs::write(tmp_path.join(&data_name), b"broken").unwrap();
+
+        let engine = Engine::new(1000, wd.home_dir()).expect("create mq engine");
+        assert!(!tmp_path.exists());
+        assert_eq!(expected, engine.topic(topic_name).unwrap().lock().unwrap().read_messages(0, 200));
+        assert!(check_segment(&topic_path, 0).unwrap().is_ok());
+
+        engine.remove_topic(topic_name);
+    }
+
+    #[tokio::test]
+    async fn test_retention() {
+        let topic_name = "test_retention";
+        let consumer_name = "test_client";
+        let wd = TestHome::new("retention");
+
+        let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+        let config = TopicConfig::new()
+            .retention_secs(None)
+            .retention_bytes(Some(0))
+            .keep_unconsumed(true);
+        engine.open_topic_with(topic_name, config.clone()).expect("open test_retention topic");
+
+        for i in 0..20 {
+            let message = InMessage(format!("value_{}", i).into());
+            engine.push_message(topic_name, message).unwrap();
+        }
+
+        // 消费者未消费的 segment 不会被删除
+        for _ in 0..5 {
+            engine.poll_message(topic_name, consumer_name).unwrap();
+        }
+
+        {
+            let topic = engine.topic(topic_name).unwrap();
+            let mut topic = topic.lock().unwrap();
+            let segment_count = topic.segment_offsets().len();
+            topic.purge_segment();
+            let first_offset = topic.segment_offsets()[0].offset;
+            assert!(topic.segment_offsets().len() < segment_count);
+            assert!(first_offset > 0 && first_offset <= 5);
+        }
+
+        let message = engine.poll_message(topic_name, consumer_name).unwrap();
+        assert_eq!(b"value_5", message.value());
+
+        // 配置持久化到元数据文件
+        drop(engine);
+        let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+        let topic = engine.open_topic(topic_name).unwrap();
+        assert_eq!(config, *topic.lock().unwrap().config());
+
+        engine.remove_topic(topic_name);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let topic_name = "test_subscribe";
+        let consumer_name = "test_client";
+        let wd = TestHome::new("subscribe");
+
+        let engine = Arc::new(Engine::new(100, wd.home_dir()).expect("create mq engine"));
+        engine.open_topic(topic_name).expect("open test_subscribe topic");
+
+        for i in 0..7 {
+            let message = InMessage(format!("value_{}", i).into());
+            engine.push_message(topic_name, message).unwrap();
+        }
+
+        // 批量获取跨越多个 segment
+        let messages = engine.poll_messages(topic_name, consumer_name, 5);
+        let offsets: Vec<u64> = messages.iter().map(|m| m.offset()).collect();
+        assert_eq!(vec![0, 1, 2, 3, 4], offsets);
+
+        let mut stream = Box::pin(engine.subscribe(topic_name, consumer_name).unwrap());
+        assert_eq!(5, stream.next().await.unwrap().offset());
+        assert_eq!(6, stream.next().await.unwrap().offset());
+
+        // 没有消息时等待 push_message 唤醒
+        let producer = {
+            let engine = engine.clone();
+            tokio::spawn(async move {
+                sleep(Duration::from_millis(50)).await;
+                let message = InMessage("value_7".into());
+                engine.push_message(topic_name, message).unwrap();
+            })
+        };
+
+        let message = timeout(Duration::from_secs(5), stream.next()).await.unwrap().unwrap();
+        assert_eq!(b"value_7", message.value());
+        producer.await.unwrap();
+
+        // topic 删除后订阅结束
+        engine.remove_topic(topic_name);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_recovery() {
+        use std::{fs::OpenOptions, io::{Seek, SeekFrom, Write}};
+
+        use crate::segment::{gen_mq_file_name, LOG_DATA_FILE_EXTENSION, LOG_INDEX_FILE_EXTENSION};
+
+        let topic_name = "test_recovery";
+        let consumer_name = "test_client";
+        let wd = TestHome::new("recovery");
+        let topic_path = wd.home_dir().join(".yiilian/mq").join(topic_name);
+
+        {
+            let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+            engine.open_topic(topic_name).expect("open test_recovery topic");
+
+            // 每个 segment 2 条消息，活动 segment 为 6..8
+            for i in 0..8 {
+                let message = InMessage(format!("value_{}", i).into());
+                engine.push_message(topic_name, message).unwrap();
+            }
+        }
+
+        // 已写满的 segment 丢失 .index
+        fs::remove_file(topic_path.join(gen_mq_file_name(2, LOG_INDEX_FILE_EXTENSION))).unwrap();
+
+        // 活动 segment 的 .index 少了最后一项，log 末尾有未写完的数据
+        let mut index_file = OpenOptions::new()
+            .write(true)
+            .open(topic_path.join(gen_mq_file_name(6, LOG_INDEX_FILE_EXTENSION)))
+            .unwrap();
+        index_file.write_all(&16_usize.to_be_bytes()).unwrap();
+
+        let mut data_file = OpenOptions::new()
+            .write(true)
+            .open(topic_path.join(gen_mq_file_name(6, LOG_DATA_FILE_EXTENSION)))
+            .unwrap();
+        data_file.write_all(&78_usize.to_be_bytes()).unwrap();
+        data_file.seek(SeekFrom::Start(8 + 68)).unwrap();
+        data_file.write_all(&[0, 0, 0, 30, 1, 2, 3, 4, 5, 6]).unwrap();
+        drop(index_file);
+        drop(data_file);
+
+        let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+        let reports = engine.topic(topic_name).unwrap().lock().unwrap().recovery_reports().clone();
+        assert_eq!(2, reports.len());
+        assert_eq!(2, reports[0].segment_offset);
+        assert_eq!(2, reports[0].rebuilt_index_items);
+        assert_eq!(6, reports[1].segment_offset);
+        assert_eq!(1, reports[1].rebuilt_index_items);
+        assert_eq!(10, reports[1].truncated_bytes);
+
+        let offsets: Vec<u64> = engine
+            .poll_messages(topic_name, consumer_name, 10)
+            .iter()
+            .map(|m| m.offset())
+            .collect();
+        assert_eq!((0..8).collect::<Vec<u64>>(), offsets);
+
+        engine.push_message(topic_name, InMessage("value_8".into())).unwrap();
+        let message = engine.poll_message(topic_name, consumer_name).unwrap();
+        assert_eq!(8, message.offset());
+        assert_eq!(b"value_8", message.value());
+
+        // 修复后再次打开不再报告
+        drop(engine);
+        let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+        assert!(engine.topic(topic_name).unwrap().lock().unwrap().recovery_reports().is_empty());
+
+        engine.remove_topic(topic_name);
+    }
+
+    #[tokio::test]
+    async fn test_partition() {
+        let topic_name = "test_partition";
+        let group_name = "test_group";
+        let wd = TestHome::new("partition");
+
+        let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+        engine.remove_topic(topic_name);
+        engine.open_topic(topic_name).expect("open test_partition topic");
+        engine.push_message(topic_name, InMessage("value_0".into())).unwrap();
+
+        // 增加分区，原有的消息在分区 0 中
+        engine.open_topic_with(topic_name, TopicConfig::new().partitions(3)).unwrap();
+        assert_eq!(Some(3), engine.partition_count(topic_name));
+        assert!(engine.open_topic_with(topic_name, TopicConfig::new().partitions(2)).is_err());
+        assert!(engine.topic_names().contains(&topic_name.to_owned()));
+        assert!(!engine.topic_names().contains(&partition_topic_name(topic_name, 1)));
+
+        // 轮流写入各分区
+        for i in 1..6 {
+            let message = InMessage(format!("value_{}", i).into());
+            engine.push_message(topic_name, message).unwrap();
+        }
+
+        let partition_values = |engine: &Engine, partition: u32| -> Vec<Vec<u8>> {
+            engine
+                .fetch_partition_messages(topic_name, partition, "test_check", 10)
+                .iter()
+                .map(|m| m.value().to_vec())
+                .collect()
+        };
+        assert_eq!(vec![b"value_0".to_vec(), b"value_3".to_vec()], partition_values(&engine, 0));
+        assert_eq!(vec![b"value_1".to_vec(), b"value_4".to_vec()], partition_values(&engine, 1));
+        assert_eq!(vec![b"value_2".to_vec(), b"value_5".to_vec()], partition_values(&engine, 2));
+
+        // 相同 key 的消息写入同一个分区
+        let key_partition = select_partition(b"key_a", 3);
+        for value in ["keyed_0", "keyed_1"] {
+            engine.push_keyed_message(topic_name, "key_a".into(), InMessage(value.into())).unwrap();
+        }
+        let values = partition_values(&engine, key_partition);
+        assert_eq!(b"keyed_0", &values[2][..]);
+        assert_eq!(b"keyed_1", &values[3][..]);
+
+        // 消费组的成员分到不同的分区
+        assert_eq!(vec![0, 1, 2], engine.join_group(topic_name, group_name, "member_a").unwrap());
+        assert_eq!(vec![1], engine.join_group(topic_name, group_name, "member_b").unwrap());
+
+        let messages_a = engine.fetch_group_messages(topic_name, group_name, "member_a", 10).unwrap();
+        let messages_b = engine.fetch_group_messages(topic_name, group_name, "member_b", 10).unwrap();
+        assert!(messages_a.iter().all(|(partition, _)| *partition != 1));
+        assert!(messages_b.iter().all(|(partition, _)| *partition == 1));
+        assert_eq!(8, messages_a.len() + messages_b.len());
+
+        // 不能提交其他成员的分区
+        assert!(engine.commit_group_offset(topic_name, group_name, "member_b", 0, 0).is_err());
+        for (partition, message) in &messages_a {
+            engine
+                .commit_group_offset(topic_name, group_name, "member_a", *partition, message.offset())
+                .unwrap();
+        }
+        assert!(engine.fetch_group_messages(topic_name, group_name, "member_a", 10).unwrap().is_empty());
+
+        // member_b 离开后其分区分配给 member_a，从消费组已提交的位置继续
+        engine.leave_group(topic_name, group_name, "member_b");
+        let messages = engine.fetch_group_messages(topic_name, group_name, "member_a", 10).unwrap();
+        assert_eq!(messages_b, messages);
+
+        // 租约从各分区分发，按 lease.partition 确认
+        let mut leases = vec![];
+        while let Some((_, lease)) = engine.poll_lease(topic_name, "test_lease", Duration::from_secs(60)) {
+            leases.push(lease);
+        }
+        assert_eq!(8, leases.len());
+        for lease in leases {
+            engine.ack_partition(topic_name, lease.partition, "test_lease", lease.offset).unwrap();
+        }
+
+        // 重启后分区仍在
+        drop(engine);
+        let engine = Arc::new(Engine::new(100, wd.home_dir()).expect("create mq engine"));
+        assert_eq!(Some(3), engine.partition_count(topic_name));
+        assert_eq!(8, engine.poll_messages(topic_name, "test_client", 20).len());
+        assert!(engine.poll_message(topic_name, "test_client").is_none());
+
+        // 写入任意分区都会唤醒订阅者
+        let mut messages = Box::pin(engine.subscribe(topic_name, "test_client").unwrap());
+        let producer = {
+            let engine = engine.clone();
+            tokio::spawn(async move {
+                for i in 6..9 {
+                    sleep(Duration::from_millis(20)).await;
+                    engine.push_message(topic_name, InMessage(format!("value_{}", i).into())).unwrap();
+                }
+            })
+        };
+        for _ in 6..9 {
+            timeout(Duration::from_secs(5), messages.next()).await.unwrap().unwrap();
+        }
+        producer.await.unwrap();
+
+        drop(messages);
+        engine.remove_topic(topic_name);
+        assert!(engine.topic(&partition_topic_name(topic_name, 1)).is_none());
+    }
 }
@@ -1,5 +1,16 @@
 pub mod message;
 pub mod segment;
 pub mod consumer_offsets;
+pub mod consumer_leases;
+pub mod consumer_group;
+pub mod dead_letter;
+pub mod dedup_window;
+pub mod partition;
 pub mod topic;
-pub mod engine;
\ No newline at end of file
+pub mod topic_config;
+pub mod engine;
+pub mod net;
+pub mod tool;
+
+#[cfg(test)]
+mod test_util;
@@ -0,0 +1,48 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use yiilian_core::common::{
+    shutdown::create_shutdown,
+    util::setup_log4rs_from_file,
+    working_dir::WorkingDir,
+};
+use yiilian_mq::{
+    engine::{self, Engine},
+    net::server::MqServer,
+    segment::LOG_DATA_SIZE,
+};
+
+const LOG_CONFIG_FILE: &str = "log4rs.yml";
+const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0:9530";
+
+/// 用法：yiilian-mq [listen_addr]，数据保存在 ~/.yiilian/mq/ 下
+#[tokio::main]
+async fn main() {
+    let wd = WorkingDir::new();
+    if let Some(log4rs_path) = wd.get_path_by_entry(LOG_CONFIG_FILE) {
+        setup_log4rs_from_file(&log4rs_path);
+    }
+
+    let listen_addr: SocketAddr = std::env::args()
+        .nth(1)
+        .unwrap_or(DEFAULT_LISTEN_ADDR.to_owned())
+        .parse()
+        .expect("parse listen_addr");
+
+    let (mut shutdown_tx, _shutdown_rx) = create_shutdown();
+
+    let engine = Arc::new(Engine::new(LOG_DATA_SIZE, wd.home_dir()).expect("create mq engine"));
+    let server = MqServer::bind(engine.clone(), listen_addr)
+        .await
+        .expect("bind mq server");
+
+    log::info!(target: "yiilian_mq", "mq server is listening on {}", listen_addr);
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {
+            shutdown_tx.shutdown().await;
+            println!("\nCtrl + c shutdown");
+        },
+        _ = server.run() => (),
+        _ = engine::purge_loop(engine) => (),
+    }
+}
@@ -1,3 +1,4 @@
+pub mod batch;
 pub mod in_message;
 
 use bytes::{BufMut, Bytes, BytesMut};
@@ -8,27 +9,83 @@ pub const MESSAGE_OFFSET_LEN: usize = 8;
 pub const MESSAGE_CRC_LEN: usize = 4;
 pub const MESSAGE_TIMESTAMP_LEN: usize = 8;
 pub const MIN_MESSAGE_LEN: usize = MESSAGE_CRC_LEN + MESSAGE_TIMESTAMP_LEN;
+/// offset + crc + timestamp
+pub const MESSAGE_HEADER_LEN: usize = MESSAGE_OFFSET_LEN + MESSAGE_CRC_LEN + MESSAGE_TIMESTAMP_LEN;
+/// version(1) + key_len(2)
+pub const MESSAGE_KEY_HEADER_LEN: usize = 3;
+/// message_len 的最高位为 1 表示带版本号的消息，旧格式的消息该位总为 0
+pub const MESSAGE_VERSION_FLAG: u32 = 0x8000_0000;
+/// 旧格式，没有版本号和 key
+pub const MESSAGE_VERSION_LEGACY: u8 = 0;
+pub const MESSAGE_VERSION_KEYED: u8 = 1;
+/// 压缩批次，value 为压缩后的多条消息，见 batch 模块
+pub const MESSAGE_VERSION_BATCH: u8 = 2;
+
+/// 从长度前缀中取出 message_len
+pub fn decode_message_len(prefix: [u8; MESSAGE_PREFIX_LEN]) -> usize {
+    (u32::from_be_bytes(prefix) & !MESSAGE_VERSION_FLAG) as usize
+}
 
-
-/// message_len(4) + offset(8) + crc32(4) + timestamp(8) + value(x)
-/// message = offset + crc + timestamp + value
+/// 旧格式：message_len(4) + offset(8) + crc32(4) + timestamp(8) + value(x)，crc 只校验 value
+///
+/// 新格式：message_len(4, 最高位为 1) + offset(8) + crc32(4) + timestamp(8) + version(1) + key_len(2) + key(x) + value(x)，
+/// crc 校验 version 之后的全部内容
+///
+/// message = offset + crc + timestamp + ...
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Message {
     length: usize,
     offset: u64,
     /// utc 毫秒
     timestamp: i64,
+    version: u8,
+    key: Option<Bytes>,
     value: Bytes,
 }
 
 impl Message {
     pub fn new(offset: u64, timestamp: i64, value: Bytes) -> Self {
-        let length = 20 + value.len();
+        Message::new_with_key(offset, timestamp, None, value)
+    }
+
+    pub fn new_with_key(offset: u64, timestamp: i64, key: Option<Bytes>, value: Bytes) -> Self {
+        let key_len = key.as_ref().map(|key| key.len()).unwrap_or(0);
+        let length = MESSAGE_HEADER_LEN + MESSAGE_KEY_HEADER_LEN + key_len + value.len();
+
+        Self {
+            length,
+            offset,
+            timestamp,
+            version: MESSAGE_VERSION_KEYED,
+            key,
+            value,
+        }
+    }
+
+    /// 压缩批次，只在 segment 中使用，读取时展开为其中的消息
+    pub fn new_batch(offset: u64, timestamp: i64, value: Bytes) -> Self {
+        let length = MESSAGE_HEADER_LEN + MESSAGE_KEY_HEADER_LEN + value.len();
 
         Self {
             length,
             offset,
             timestamp,
+            version: MESSAGE_VERSION_BATCH,
+            key: None,
+            value,
+        }
+    }
+
+    /// 旧格式的消息，只用于兼容已有的 segment
+    pub fn new_legacy(offset: u64, timestamp: i64, value: Bytes) -> Self {
+        let length = MESSAGE_HEADER_LEN + value.len();
+
+        Self {
+            length,
+            offset,
+            timestamp,
+            version: MESSAGE_VERSION_LEGACY,
+            key: None,
             value,
         }
     }
@@ -42,32 +99,70 @@ impl Message {
     }
 
     pub fn crc(&self) -> u32 {
-        crc32fast::hash(&self.value)
+        crc32fast::hash(&self.crc_content())
     }
 
     pub fn offset(&self) -> u64 {
         self.offset
     }
 
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn is_batch(&self) -> bool {
+        self.version == MESSAGE_VERSION_BATCH
+    }
+
+    pub fn key(&self) -> Option<&[u8]> {
+        self.key.as_deref()
+    }
+
     pub fn value(&self) -> &[u8] {
         &self.value
     }
+
+    /// crc 校验的内容，新格式为 version 之后的全部内容
+    fn crc_content(&self) -> Bytes {
+        if self.version == MESSAGE_VERSION_LEGACY {
+            return self.value.clone();
+        }
+
+        let key = self.key.clone().unwrap_or_default();
+        let mut buf = BytesMut::with_capacity(MESSAGE_KEY_HEADER_LEN + key.len() + self.value.len());
+        buf.put_u8(self.version);
+        // key 为空与没有 key 等同
+        buf.put_u16(key.len() as u16);
+        buf.extend(key);
+        buf.extend_from_slice(&self.value);
+
+        buf.into()
+    }
 }
 
 impl From<Message> for Bytes {
     fn from(message: Message) -> Self {
-        let value_len = message.value.len();
-        let message_len = MESSAGE_OFFSET_LEN + MESSAGE_CRC_LEN + MESSAGE_TIMESTAMP_LEN + value_len;
-        let total_len = 4 + message_len;
+        let content = message.crc_content();
+        let crc = crc32fast::hash(&content);
+        let message_len = MESSAGE_HEADER_LEN + content.len();
+        let total_len = MESSAGE_PREFIX_LEN + message_len;
 
-        let crc = crc32fast::hash(&message.value);
+        let prefix = if message.version == MESSAGE_VERSION_LEGACY {
+            message_len as u32
+        } else {
+            message_len as u32 | MESSAGE_VERSION_FLAG
+        };
 
         let mut buf = BytesMut::with_capacity(total_len);
-        buf.put_u32(message_len as u32);
+        buf.put_u32(prefix);
         buf.put_u64(message.offset);
         buf.put_u32(crc);
         buf.put_i64(message.timestamp);
-        buf.extend(message.value);
+        buf.extend(content);
 
         buf.into()
     }
@@ -82,28 +177,57 @@ impl TryFrom<&[u8]> for Message {
             Err(Error::new_decode(&format!("Data is too short to decode message: {:?}", data.len())))?;
         }
 
-        let message_len = u32::from_be_bytes(data[0..4].try_into().expect("data[0..4] is not satisfy"));
-        if message_len < 20 {
+        let prefix = u32::from_be_bytes(data[0..4].try_into().expect("data[0..4] is not satisfy"));
+        let is_versioned = prefix & MESSAGE_VERSION_FLAG != 0;
+        let message_len = (prefix & !MESSAGE_VERSION_FLAG) as usize;
+
+        let min_len = if is_versioned { MESSAGE_HEADER_LEN + MESSAGE_KEY_HEADER_LEN } else { MESSAGE_HEADER_LEN };
+        if message_len < min_len {
             Err(Error::new_decode(&format!("Decoding message is failed at verify message_size: {:?}", message_len)))?;
         }
-        let total_size = 4 + message_len;
+        let total_size = MESSAGE_PREFIX_LEN + message_len;
 
-        if data.len() < total_size as usize {
+        if data.len() < total_size {
             Err(Error::new_decode(&format!("Decoding message is failed at verify length: {:?}", total_size)))?;
         }
 
-        let value_len = (message_len - 20) as usize;
         let crc = u32::from_be_bytes(data[12..16].try_into().expect("data[12..16] is not satisfy"));
-        let value: Bytes = data[24..24 + value_len].to_owned().into();
+        let content = &data[24..total_size];
 
-        if crc32fast::hash(&value) != crc {
+        if crc32fast::hash(content) != crc {
             Err(Error::new_decode("Decoding message is failed at verify crc"))?;
         }
 
         let offset = u64::from_be_bytes(data[4..12].try_into().expect("data[4..12] is not satisfy"));
         let timestamp = i64::from_be_bytes(data[16..24].try_into().expect("data[16..24] is not satisfy"));
 
-        Ok(Message::new(offset, timestamp, value))
+        if !is_versioned {
+            return Ok(Message::new_legacy(offset, timestamp, content.to_owned().into()));
+        }
+
+        let version = content[0];
+        if version != MESSAGE_VERSION_KEYED && version != MESSAGE_VERSION_BATCH {
+            Err(Error::new_decode(&format!("Unsupported message version: {}", version)))?;
+        }
+
+        let key_len = u16::from_be_bytes(content[1..3].try_into().expect("content[1..3] is not satisfy")) as usize;
+        if content.len() < MESSAGE_KEY_HEADER_LEN + key_len {
+            Err(Error::new_decode(&format!("Decoding message is failed at verify key_len: {:?}", key_len)))?;
+        }
+
+        let key_end = MESSAGE_KEY_HEADER_LEN + key_len;
+        let key: Option<Bytes> = if key_len > 0 {
+            Some(content[MESSAGE_KEY_HEADER_LEN..key_end].to_owned().into())
+        } else {
+            None
+        };
+        let value: Bytes = content[key_end..].to_owned().into();
+
+        if version == MESSAGE_VERSION_BATCH {
+            return Ok(Message::new_batch(offset, timestamp, value));
+        }
+
+        Ok(Message::new_with_key(offset, timestamp, key, value))
     }
 }
 
@@ -122,7 +246,7 @@ mod tests {
 
     use crate::message::MESSAGE_PREFIX_LEN;
 
-    use super::Message;
+    use super::*;
 
     #[test]
     fn test() {
@@ -132,7 +256,7 @@ mod tests {
 
         let msg = Message::new(offset, timestamp, value);
         let msg_len = msg.len();
-        assert_eq!(25, msg_len);
+        assert_eq!(28, msg_len);
 
         let data: Bytes = msg.clone().into();
         assert_eq!(msg_len, data.len() - MESSAGE_PREFIX_LEN);
@@ -165,4 +289,35 @@ mod tests {
         let msg = Message::try_from(data);
         assert_eq!(true, msg.is_ok());
     }
+
+    #[test]
+    fn test_version() {
+        let timestamp = Utc::now().timestamp_millis();
+
+        // 旧格式的消息仍可读取
+        let legacy = Message::new_legacy(1, timestamp, "hello".into());
+        let data: Bytes = legacy.clone().into();
+        assert_eq!(0, data[0] & 0x80);
+        assert_eq!(25 + MESSAGE_PREFIX_LEN, data.len());
+
+        let msg = Message::try_from(data).unwrap();
+        assert_eq!(legacy, msg);
+        assert_eq!(MESSAGE_VERSION_LEGACY, msg.version());
+        assert_eq!(None, msg.key());
+
+        let keyed = Message::new_with_key(2, timestamp, Some("key".into()), "hello".into());
+        let data: Bytes = keyed.clone().into();
+        assert_eq!(keyed.total_size(), data.len());
+        assert_eq!(keyed.len(), decode_message_len(data[0..4].try_into().unwrap()));
+
+        let msg = Message::try_from(data.clone()).unwrap();
+        assert_eq!(keyed, msg);
+        assert_eq!(Some(&b"key"[..]), msg.key());
+        assert_eq!(b"hello", msg.value());
+
+        // 修改 key 后 crc 校验失败
+        let mut data = data.to_vec();
+        data[27] = b'x';
+        assert!(Message::try_from(&data[..]).is_err());
+    }
 }
@@ -0,0 +1,211 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use yiilian_core::common::error::Error;
+
+use super::{decode_message_len, Message, MESSAGE_PREFIX_LEN};
+
+/// 一个批次中未压缩消息的总字节数上限
+pub const BATCH_SIZE: usize = 64 * 1024;
+/// compression(1) + count(4)
+pub const BATCH_HEADER_LEN: usize = 5;
+/// lz4 压缩后数据前的原始长度 (u32 小端)
+const LZ4_SIZE_PREFIX_LEN: usize = 4;
+/// lz4 的最大压缩比，用于限制解压后的大小
+const LZ4_MAX_RATIO: usize = 255;
+
+/// segment 中消息批次的压缩方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Lz4,
+}
+
+impl Compression {
+    fn code(&self) -> u8 {
+        match self {
+            Compression::Lz4 => 1,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+}
+
+/// 把多条消息压缩为一条批次消息，批次的 offset 和 timestamp 取第一条消息的。
+///
+/// 批次的 value = compression(1) + count(4) + 压缩后的数据，解压后为连续的消息编码
+pub fn encode_batch(messages: &[Message], compression: Compression) -> Result<Message, Error> {
+    let first = match messages.first() {
+        Some(first) => first,
+        None => Err(Error::new_general("Can't encode empty message batch"))?,
+    };
+
+    let mut data = BytesMut::with_capacity(messages.iter().map(|message| message.total_size()).sum());
+    for message in messages {
+        if message.is_batch() {
+            Err(Error::new_general("Message batch can't be nested"))?;
+        }
+
+        let message: Bytes = message.clone().into();
+        data.extend(message);
+    }
+
+    let compressed = match compression {
+        Compression::Lz4 => lz4_flex::compress_prepend_size(&data),
+    };
+
+    let mut value = BytesMut::with_capacity(BATCH_HEADER_LEN + compressed.len());
+    value.put_u8(compression.code());
+    value.put_u32(messages.len() as u32);
+    value.extend_from_slice(&compressed);
+
+    Ok(Message::new_batch(first.offset(), first.timestamp(), value.into()))
+}
+
+/// 解压批次中的消息
+pub fn decode_batch(batch: &Message) -> Result<Vec<Message>, Error> {
+    let value = batch.value();
+    if !batch.is_batch() || value.len() < BATCH_HEADER_LEN {
+        Err(Error::new_decode("Message is not a valid batch"))?;
+    }
+
+    let compression = Compression::from_code(value[0])
+        .ok_or_else(|| Error::new_decode(&format!("Unsupported batch compression: {}", value[0])))?;
+    let count = u32::from_be_bytes(value[1..5].try_into().expect("value[1..5] is not satisfy")) as usize;
+
+    let data = match compression {
+        Compression::Lz4 => {
+            let compressed = &value[BATCH_HEADER_LEN..];
+            if compressed.len() < LZ4_SIZE_PREFIX_LEN {
+                Err(Error::new_decode("Batch compressed data is too short"))?;
+            }
+
+            // 单条超过 BATCH_SIZE 的消息也会单独成批，上限再按最大压缩比放宽，避免按损坏的长度前缀分配内存
+            let size = u32::from_le_bytes(compressed[..LZ4_SIZE_PREFIX_LEN].try_into().expect("compressed[..4] is not satisfy")) as usize;
+            let max_size = BATCH_SIZE.max(compressed.len() * LZ4_MAX_RATIO);
+            if size > max_size {
+                Err(Error::new_decode(&format!("Batch decompressed size {} exceeds {}", size, max_size)))?;
+            }
+
+            lz4_flex::decompress_size_prepended(compressed)
+                .map_err(|error| Error::new_decode(&format!("Decompressing batch is failed: {}", error)))?
+        }
+    };
+
+    // count 来自数据本身，按数据能容纳的消息数限制预分配
+    let mut messages = Vec::with_capacity(count.min(data.len() / MESSAGE_PREFIX_LEN));
+    let mut pos = 0;
+    while pos + MESSAGE_PREFIX_LEN <= data.len() {
+        let message_len = decode_message_len(data[pos..pos + MESSAGE_PREFIX_LEN].try_into().expect("Message length bytes is invalid"));
+        let end_pos = (pos + MESSAGE_PREFIX_LEN + message_len).min(data.len());
+
+        let message: Message = data[pos..end_pos].try_into()?;
+        if message.is_batch() {
+            Err(Error::new_decode("Message batch can't be nested"))?;
+        }
+
+        messages.push(message);
+        pos = end_pos;
+    }
+
+    if pos != data.len() || messages.len() != count {
+        Err(Error::new_decode(&format!("Decoding batch is failed at verify count: {}", count)))?;
+    }
+
+    Ok(messages)
+}
+
+/// 批次展开为其中的消息，普通消息原样返回
+pub fn unbatch(message: Message) -> Result<Vec<Message>, Error> {
+    if message.is_batch() {
+        decode_batch(&message)
+    } else {
+        Ok(vec![message])
+    }
+}
+
+/// 按 BATCH_SIZE 把消息分为多个批次
+pub fn split_batches(messages: Vec<Message>) -> Vec<Vec<Message>> {
+    let mut batches = vec![];
+    let mut batch = vec![];
+    let mut batch_size = 0;
+
+    for message in messages {
+        if batch_size + message.total_size() > BATCH_SIZE && !batch.is_empty() {
+            batches.push(std::mem::take(&mut batch));
+            batch_size = 0;
+        }
+
+        batch_size += message.total_size();
+        batch.push(message);
+    }
+
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn test_batch() {
+        let messages: Vec<Message> = (10..20)
+            .map(|offset| {
+                let key = if offset % 2 == 0 { Some(Bytes::from(format!("key_{}", offset))) } else { None };
+                Message::new_with_key(offset, 1000 + offset as i64, key, Bytes::from(format!("value_{}", offset)))
+            })
+            .collect();
+
+        let batch = encode_batch(&messages, Compression::Lz4).unwrap();
+        assert!(batch.is_batch());
+        assert_eq!(10, batch.offset());
+        assert_eq!(1010, batch.timestamp());
+
+        // 经过编码后仍是同一个批次
+        let data: Bytes = batch.clone().into();
+        let decoded: Message = data.try_into().unwrap();
+        assert_eq!(batch, decoded);
+
+        assert_eq!(messages, unbatch(decoded).unwrap());
+        assert_eq!(vec![messages[0].clone()], unbatch(messages[0].clone()).unwrap());
+
+        assert!(encode_batch(&[], Compression::Lz4).is_err());
+        assert!(encode_batch(&[batch.clone()], Compression::Lz4).is_err());
+
+        let mut value = batch.value().to_vec();
+        value[0] = 9;
+        assert!(decode_batch(&Message::new_batch(10, 1010, value.into())).is_err());
+
+        // 损坏的 count 和长度前缀不会导致按其分配内存
+        let mut value = batch.value().to_vec();
+        value[1..5].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(decode_batch(&Message::new_batch(10, 1010, value.into())).is_err());
+
+        let mut value = batch.value().to_vec();
+        value[BATCH_HEADER_LEN..BATCH_HEADER_LEN + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decode_batch(&Message::new_batch(10, 1010, value.into())).is_err());
+        assert!(decode_batch(&Message::new_batch(10, 1010, Bytes::from(vec![1, 0, 0, 0, 1, 0]))).is_err());
+
+        // 超过 BATCH_SIZE 的单条消息仍能解压
+        let single = vec![Message::new(0, 0, Bytes::from(vec![0u8; BATCH_SIZE * 2]))];
+        let batch = encode_batch(&single, Compression::Lz4).unwrap();
+        assert_eq!(single, decode_batch(&batch).unwrap());
+
+        let large: Vec<Message> = (0..100)
+            .map(|offset| Message::new(offset, 0, Bytes::from(vec![0u8; BATCH_SIZE / 40])))
+            .collect();
+        let batches = split_batches(large);
+        assert_eq!(3, batches.len());
+        assert_eq!(100, batches.iter().map(|batch| batch.len()).sum::<usize>());
+    }
+}
@@ -0,0 +1,179 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bytes::Bytes;
+use tokio::net::TcpStream;
+use yiilian_core::common::error::Error;
+
+use crate::message::{in_message::InMessage, Message};
+
+use super::protocol::{read_frame, write_frame, Request, Response};
+
+/// MqServer 的客户端，同一时间只有一个请求在途
+#[derive(Debug)]
+pub struct MqClient {
+    stream: TcpStream,
+    remote_addr: SocketAddr,
+}
+
+impl MqClient {
+    pub async fn connect(remote_addr: SocketAddr) -> Result<Self, Error> {
+        let stream = TcpStream::connect(remote_addr)
+            .await
+            .map_err(|error| Error::new_net(Some(error.into()), None, Some(remote_addr)))?;
+        stream.set_nodelay(true).ok();
+
+        Ok(MqClient { stream, remote_addr })
+    }
+
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+
+    /// topic 不存在时创建
+    pub async fn open_topic(&mut self, topic_name: &str) -> Result<(), Error> {
+        let request = Request::OpenTopic { topic: topic_name.to_owned() };
+
+        self.request_ok(request).await
+    }
+
+    pub async fn push_message(&mut self, topic_name: &str, message: InMessage) -> Result<(), Error> {
+        self.push_messages(topic_name, vec![message]).await
+    }
+
+    pub async fn push_messages(&mut self, topic_name: &str, messages: Vec<InMessage>) -> Result<(), Error> {
+        let request = Request::Produce {
+            topic: topic_name.to_owned(),
+            values: messages.into_iter().map(|message| message.0).collect(),
+        };
+
+        self.request_ok(request).await
+    }
+
+    /// 写入带 key 的消息，服务端在 topic 的去重窗口内丢弃重复的 key
+    pub async fn push_keyed_messages(&mut self, topic_name: &str, messages: Vec<(Bytes, InMessage)>) -> Result<(), Error> {
+        let request = Request::ProduceKeyed {
+            topic: topic_name.to_owned(),
+            entries: messages.into_iter().map(|(key, message)| (key, message.0)).collect(),
+        };
+
+        self.request_ok(request).await
+    }
+
+    /// 读取已提交位置之后最多 count 条消息，没有消息时最多等待 max_wait。
+    /// 不会提交位置，处理完成后需要调用 commit_offset
+    pub async fn fetch_messages(
+        &mut self,
+        topic_name: &str,
+        consumer_name: &str,
+        count: u32,
+        max_wait: Duration,
+    ) -> Result<Vec<Message>, Error> {
+        let request = Request::Fetch {
+            topic: topic_name.to_owned(),
+            consumer: consumer_name.to_owned(),
+            max_count: count,
+            max_wait_millis: max_wait.as_millis().min(u32::MAX as u128) as u32,
+        };
+
+        match self.request(request).await? {
+            Response::Messages(messages) => Ok(messages),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// 提交消费者已消费的最后一条消息的 offset
+    pub async fn commit_offset(&mut self, topic_name: &str, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        let request = Request::Commit {
+            topic: topic_name.to_owned(),
+            consumer: consumer_name.to_owned(),
+            offset,
+        };
+
+        self.request_ok(request).await
+    }
+
+    /// 以消费组成员的身份读取分配给它的分区，返回 (分区, 消息)。
+    /// 需要定期调用以保持在消费组中，处理完成后调用 commit_group_offset
+    pub async fn fetch_group_messages(
+        &mut self,
+        topic_name: &str,
+        group_name: &str,
+        member_name: &str,
+        count: u32,
+        max_wait: Duration,
+    ) -> Result<Vec<(u32, Message)>, Error> {
+        let request = Request::FetchGroup {
+            topic: topic_name.to_owned(),
+            group: group_name.to_owned(),
+            member: member_name.to_owned(),
+            max_count: count,
+            max_wait_millis: max_wait.as_millis().min(u32::MAX as u128) as u32,
+        };
+
+        match self.request(request).await? {
+            Response::PartitionMessages(messages) => Ok(messages),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// 分区已分配给其他成员时返回错误
+    pub async fn commit_group_offset(
+        &mut self,
+        topic_name: &str,
+        group_name: &str,
+        member_name: &str,
+        partition: u32,
+        offset: u64,
+    ) -> Result<(), Error> {
+        let request = Request::CommitGroup {
+            topic: topic_name.to_owned(),
+            group: group_name.to_owned(),
+            member: member_name.to_owned(),
+            partition,
+            offset,
+        };
+
+        self.request_ok(request).await
+    }
+
+    pub async fn leave_group(&mut self, topic_name: &str, group_name: &str, member_name: &str) -> Result<(), Error> {
+        let request = Request::LeaveGroup {
+            topic: topic_name.to_owned(),
+            group: group_name.to_owned(),
+            member: member_name.to_owned(),
+        };
+
+        self.request_ok(request).await
+    }
+
+    pub async fn topic_names(&mut self) -> Result<Vec<String>, Error> {
+        match self.request(Request::ListTopics).await? {
+            Response::Topics(topics) => Ok(topics),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    async fn request_ok(&mut self, request: Request) -> Result<(), Error> {
+        match self.request(request).await? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    async fn request(&mut self, request: Request) -> Result<Response, Error> {
+        write_frame(&mut self.stream, &request.encode()).await?;
+
+        let data = read_frame(&mut self.stream)
+            .await?
+            .ok_or_else(|| Error::new_net(None, Some("Connection closed".to_owned()), Some(self.remote_addr)))?;
+
+        match Response::decode(data)? {
+            Response::Error(description) => Err(Error::new_general(&description)),
+            response => Ok(response),
+        }
+    }
+}
+
+fn unexpected_response(response: Response) -> Error {
+    Error::new_frame(None, Some(format!("Unexpected response: {:?}", response)))
+}
@@ -0,0 +1,491 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use yiilian_core::common::error::Error;
+
+use crate::message::Message;
+
+/// 帧长度前缀的字节数
+pub const FRAME_PREFIX_LEN: usize = 4;
+/// 单个帧的最大字节数
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+const REQ_OPEN_TOPIC: u8 = 1;
+const REQ_PRODUCE: u8 = 2;
+const REQ_FETCH: u8 = 3;
+const REQ_COMMIT: u8 = 4;
+const REQ_LIST_TOPICS: u8 = 5;
+const REQ_PRODUCE_KEYED: u8 = 6;
+const REQ_FETCH_GROUP: u8 = 7;
+const REQ_COMMIT_GROUP: u8 = 8;
+const REQ_LEAVE_GROUP: u8 = 9;
+
+const RESP_OK: u8 = 0;
+const RESP_ERROR: u8 = 1;
+const RESP_MESSAGES: u8 = 2;
+const RESP_TOPICS: u8 = 3;
+const RESP_PARTITION_MESSAGES: u8 = 4;
+
+/// frame = frame_len(4) + kind(1) + body，字符串为 len(2) + utf8，字节串为 len(4) + data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    OpenTopic {
+        topic: String,
+    },
+    Produce {
+        topic: String,
+        values: Vec<Bytes>,
+    },
+    /// entries 为 (key, value)，去重窗口内重复的 key 会被丢弃
+    ProduceKeyed {
+        topic: String,
+        entries: Vec<(Bytes, Bytes)>,
+    },
+    /// 读取消费者已提交位置之后的消息，没有消息时最多等待 max_wait_millis
+    Fetch {
+        topic: String,
+        consumer: String,
+        max_count: u32,
+        max_wait_millis: u32,
+    },
+    Commit {
+        topic: String,
+        consumer: String,
+        offset: u64,
+    },
+    ListTopics,
+    /// 以消费组成员的身份读取分配给它的分区，同时刷新成员的心跳
+    FetchGroup {
+        topic: String,
+        group: String,
+        member: String,
+        max_count: u32,
+        max_wait_millis: u32,
+    },
+    CommitGroup {
+        topic: String,
+        group: String,
+        member: String,
+        partition: u32,
+        offset: u64,
+    },
+    LeaveGroup {
+        topic: String,
+        group: String,
+        member: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    Ok,
+    Error(String),
+    Messages(Vec<Message>),
+    Topics(Vec<String>),
+    /// (分区, 消息)
+    PartitionMessages(Vec<(u32, Message)>),
+}
+
+impl Request {
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        match self {
+            Request::OpenTopic { topic } => {
+                buf.put_u8(REQ_OPEN_TOPIC);
+                put_str(&mut buf, topic);
+            }
+            Request::Produce { topic, values } => {
+                buf.put_u8(REQ_PRODUCE);
+                put_str(&mut buf, topic);
+                buf.put_u32(values.len() as u32);
+                for value in values {
+                    put_bytes(&mut buf, value);
+                }
+            }
+            Request::ProduceKeyed { topic, entries } => {
+                buf.put_u8(REQ_PRODUCE_KEYED);
+                put_str(&mut buf, topic);
+                buf.put_u32(entries.len() as u32);
+                for (key, value) in entries {
+                    put_bytes(&mut buf, key);
+                    put_bytes(&mut buf, value);
+                }
+            }
+            Request::Fetch { topic, consumer, max_count, max_wait_millis } => {
+                buf.put_u8(REQ_FETCH);
+                put_str(&mut buf, topic);
+                put_str(&mut buf, consumer);
+                buf.put_u32(*max_count);
+                buf.put_u32(*max_wait_millis);
+            }
+            Request::Commit { topic, consumer, offset } => {
+                buf.put_u8(REQ_COMMIT);
+                put_str(&mut buf, topic);
+                put_str(&mut buf, consumer);
+                buf.put_u64(*offset);
+            }
+            Request::ListTopics => buf.put_u8(REQ_LIST_TOPICS),
+            Request::FetchGroup { topic, group, member, max_count, max_wait_millis } => {
+                buf.put_u8(REQ_FETCH_GROUP);
+                put_str(&mut buf, topic);
+                put_str(&mut buf, group);
+                put_str(&mut buf, member);
+                buf.put_u32(*max_count);
+                buf.put_u32(*max_wait_millis);
+            }
+            Request::CommitGroup { topic, group, member, partition, offset } => {
+                buf.put_u8(REQ_COMMIT_GROUP);
+                put_str(&mut buf, topic);
+                put_str(&mut buf, group);
+                put_str(&mut buf, member);
+                buf.put_u32(*partition);
+                buf.put_u64(*offset);
+            }
+            Request::LeaveGroup { topic, group, member } => {
+                buf.put_u8(REQ_LEAVE_GROUP);
+                put_str(&mut buf, topic);
+                put_str(&mut buf, group);
+                put_str(&mut buf, member);
+            }
+        }
+
+        buf.into()
+    }
+
+    pub fn decode(mut data: Bytes) -> Result<Self, Error> {
+        let kind = get_u8(&mut data)?;
+
+        let request = match kind {
+            REQ_OPEN_TOPIC => Request::OpenTopic { topic: get_str(&mut data)? },
+            REQ_PRODUCE => {
+                let topic = get_str(&mut data)?;
+                let count = get_u32(&mut data)?;
+                let mut values = vec![];
+                for _ in 0..count {
+                    values.push(get_bytes(&mut data)?);
+                }
+
+                Request::Produce { topic, values }
+            }
+            REQ_PRODUCE_KEYED => {
+                let topic = get_str(&mut data)?;
+                let count = get_u32(&mut data)?;
+                let mut entries = vec![];
+                for _ in 0..count {
+                    entries.push((get_bytes(&mut data)?, get_bytes(&mut data)?));
+                }
+
+                Request::ProduceKeyed { topic, entries }
+            }
+            REQ_FETCH => Request::Fetch {
+                topic: get_str(&mut data)?,
+                consumer: get_str(&mut data)?,
+                max_count: get_u32(&mut data)?,
+                max_wait_millis: get_u32(&mut data)?,
+            },
+            REQ_COMMIT => Request::Commit {
+                topic: get_str(&mut data)?,
+                consumer: get_str(&mut data)?,
+                offset: get_u64(&mut data)?,
+            },
+            REQ_LIST_TOPICS => Request::ListTopics,
+            REQ_FETCH_GROUP => Request::FetchGroup {
+                topic: get_str(&mut data)?,
+                group: get_str(&mut data)?,
+                member: get_str(&mut data)?,
+                max_count: get_u32(&mut data)?,
+                max_wait_millis: get_u32(&mut data)?,
+            },
+            REQ_COMMIT_GROUP => Request::CommitGroup {
+                topic: get_str(&mut data)?,
+                group: get_str(&mut data)?,
+                member: get_str(&mut data)?,
+                partition: get_u32(&mut data)?,
+                offset: get_u64(&mut data)?,
+            },
+            REQ_LEAVE_GROUP => Request::LeaveGroup {
+                topic: get_str(&mut data)?,
+                group: get_str(&mut data)?,
+                member: get_str(&mut data)?,
+            },
+            _ => Err(Error::new_decode(&format!("Unknown request kind: {}", kind)))?,
+        };
+
+        if data.has_remaining() {
+            Err(Error::new_decode(&format!("Request has {} trailing bytes", data.remaining())))?;
+        }
+
+        Ok(request)
+    }
+}
+
+impl Response {
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        match self {
+            Response::Ok => buf.put_u8(RESP_OK),
+            Response::Error(description) => {
+                buf.put_u8(RESP_ERROR);
+                put_bytes(&mut buf, description.as_bytes());
+            }
+            Response::Messages(messages) => {
+                buf.put_u8(RESP_MESSAGES);
+                buf.put_u32(messages.len() as u32);
+                // 直接使用消息在 segment 中的编码
+                for message in messages {
+                    let data: Bytes = message.clone().into();
+                    buf.extend(data);
+                }
+            }
+            Response::Topics(topics) => {
+                buf.put_u8(RESP_TOPICS);
+                buf.put_u32(topics.len() as u32);
+                for topic in topics {
+                    put_str(&mut buf, topic);
+                }
+            }
+            Response::PartitionMessages(messages) => {
+                buf.put_u8(RESP_PARTITION_MESSAGES);
+                buf.put_u32(messages.len() as u32);
+                for (partition, message) in messages {
+                    buf.put_u32(*partition);
+                    let data: Bytes = message.clone().into();
+                    buf.extend(data);
+                }
+            }
+        }
+
+        buf.into()
+    }
+
+    pub fn decode(mut data: Bytes) -> Result<Self, Error> {
+        let kind = get_u8(&mut data)?;
+
+        let response = match kind {
+            RESP_OK => Response::Ok,
+            RESP_ERROR => {
+                let description = get_bytes(&mut data)?;
+                Response::Error(String::from_utf8_lossy(&description).to_string())
+            }
+            RESP_MESSAGES => {
+                let count = get_u32(&mut data)?;
+                let mut messages = vec![];
+                for _ in 0..count {
+                    let message = Message::try_from(&data[..])?;
+                    data.advance(message.total_size());
+                    messages.push(message);
+                }
+
+                Response::Messages(messages)
+            }
+            RESP_TOPICS => {
+                let count = get_u32(&mut data)?;
+                let mut topics = vec![];
+                for _ in 0..count {
+                    topics.push(get_str(&mut data)?);
+                }
+
+                Response::Topics(topics)
+            }
+            RESP_PARTITION_MESSAGES => {
+                let count = get_u32(&mut data)?;
+                let mut messages = vec![];
+                for _ in 0..count {
+                    let partition = get_u32(&mut data)?;
+                    let message = Message::try_from(&data[..])?;
+                    data.advance(message.total_size());
+                    messages.push((partition, message));
+                }
+
+                Response::PartitionMessages(messages)
+            }
+            _ => Err(Error::new_decode(&format!("Unknown response kind: {}", kind)))?,
+        };
+
+        if data.has_remaining() {
+            Err(Error::new_decode(&format!("Response has {} trailing bytes", data.remaining())))?;
+        }
+
+        Ok(response)
+    }
+}
+
+/// 读取一个帧，对端正常关闭连接时返回 None
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Bytes>, Error> {
+    let mut prefix = [0u8; FRAME_PREFIX_LEN];
+
+    match reader.read_exact(&mut prefix).await {
+        Ok(_) => (),
+        Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => Err(Error::new_io(Some(error.into()), None))?,
+    }
+
+    let frame_len = u32::from_be_bytes(prefix) as usize;
+    if frame_len > MAX_FRAME_LEN {
+        Err(Error::new_frame(None, Some(format!("Frame is too large: {}", frame_len))))?;
+    }
+
+    let mut data = vec![0u8; frame_len];
+    reader
+        .read_exact(&mut data)
+        .await
+        .map_err(|error| Error::new_io(Some(error.into()), None))?;
+
+    Ok(Some(data.into()))
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, data: &[u8]) -> Result<(), Error> {
+    if data.len() > MAX_FRAME_LEN {
+        Err(Error::new_frame(None, Some(format!("Frame is too large: {}", data.len()))))?;
+    }
+
+    let mut buf = BytesMut::with_capacity(FRAME_PREFIX_LEN + data.len());
+    buf.put_u32(data.len() as u32);
+    buf.extend_from_slice(data);
+
+    writer
+        .write_all(&buf)
+        .await
+        .map_err(|error| Error::new_io(Some(error.into()), None))?;
+    writer
+        .flush()
+        .await
+        .map_err(|error| Error::new_io(Some(error.into()), None))
+}
+
+fn put_str(buf: &mut BytesMut, value: &str) {
+    buf.put_u16(value.len() as u16);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn put_bytes(buf: &mut BytesMut, value: &[u8]) {
+    buf.put_u32(value.len() as u32);
+    buf.extend_from_slice(value);
+}
+
+fn check_remaining(data: &Bytes, len: usize) -> Result<(), Error> {
+    if data.remaining() < len {
+        Err(Error::new_decode(&format!(
+            "Data is too short: expected {}, remaining {}",
+            len,
+            data.remaining()
+        )))?;
+    }
+
+    Ok(())
+}
+
+fn get_u8(data: &mut Bytes) -> Result<u8, Error> {
+    check_remaining(data, 1)?;
+    Ok(data.get_u8())
+}
+
+fn get_u32(data: &mut Bytes) -> Result<u32, Error> {
+    check_remaining(data, 4)?;
+    Ok(data.get_u32())
+}
+
+fn get_u64(data: &mut Bytes) -> Result<u64, Error> {
+    check_remaining(data, 8)?;
+    Ok(data.get_u64())
+}
+
+fn get_str(data: &mut Bytes) -> Result<String, Error> {
+    check_remaining(data, 2)?;
+    let len = data.get_u16() as usize;
+    check_remaining(data, len)?;
+
+    String::from_utf8(data.split_to(len).to_vec())
+        .map_err(|_| Error::new_decode("String is not valid utf8"))
+}
+
+fn get_bytes(data: &mut Bytes) -> Result<Bytes, Error> {
+    check_remaining(data, 4)?;
+    let len = data.get_u32() as usize;
+    check_remaining(data, len)?;
+
+    Ok(data.split_to(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request() {
+        let requests = vec![
+            Request::OpenTopic { topic: "info_hash".to_owned() },
+            Request::Produce {
+                topic: "info_hash".to_owned(),
+                values: vec!["value_0".into(), Bytes::new(), "value_2".into()],
+            },
+            Request::ProduceKeyed {
+                topic: "info_hash".to_owned(),
+                entries: vec![("key_0".into(), "value_0".into()), ("key_1".into(), Bytes::new())],
+            },
+            Request::Fetch {
+                topic: "info_hash".to_owned(),
+                consumer: "client_1".to_owned(),
+                max_count: 10,
+                max_wait_millis: 500,
+            },
+            Request::Commit {
+                topic: "info_hash".to_owned(),
+                consumer: "client_1".to_owned(),
+                offset: 42,
+            },
+            Request::ListTopics,
+            Request::FetchGroup {
+                topic: "info_hash".to_owned(),
+                group: "downloader".to_owned(),
+                member: "member_1".to_owned(),
+                max_count: 10,
+                max_wait_millis: 500,
+            },
+            Request::CommitGroup {
+                topic: "info_hash".to_owned(),
+                group: "downloader".to_owned(),
+                member: "member_1".to_owned(),
+                partition: 2,
+                offset: 42,
+            },
+            Request::LeaveGroup {
+                topic: "info_hash".to_owned(),
+                group: "downloader".to_owned(),
+                member: "member_1".to_owned(),
+            },
+        ];
+
+        for request in requests {
+            let data = request.encode();
+            assert_eq!(request, Request::decode(data.clone()).unwrap());
+
+            // 截断的数据不能解码
+            assert!(Request::decode(data.slice(0..data.len() - 1)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_response() {
+        let responses = vec![
+            Response::Ok,
+            Response::Error("Not found topic".to_owned()),
+            Response::Messages(vec![
+                Message::new(0, 1000, "value_0".into()),
+                Message::new_with_key(1, 1001, Some("key_1".into()), "value_1".into()),
+                Message::new_legacy(2, 1002, "value_2".into()),
+            ]),
+            Response::Topics(vec!["info_hash".to_owned(), "info_index".to_owned()]),
+            Response::PartitionMessages(vec![
+                (0, Message::new(0, 1000, "value_0".into())),
+                (3, Message::new_with_key(0, 1001, Some("key_1".into()), "value_1".into())),
+            ]),
+        ];
+
+        for response in responses {
+            let data = response.encode();
+            assert_eq!(response, Response::decode(data).unwrap());
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::{timeout, Instant},
+};
+use yiilian_core::common::error::Error;
+
+use crate::{engine::Engine, message::{in_message::InMessage, Message}};
+
+use super::protocol::{read_frame, write_frame, Request, Response};
+
+/// 单次 fetch 最多返回的消息条数
+pub const MAX_FETCH_COUNT: u32 = 1024;
+/// 单次 fetch 最长等待时间
+pub const MAX_FETCH_WAIT_MILLIS: u32 = 30 * 1000;
+
+/// 通过 TCP 对外提供 Engine 的服务，每个连接上的请求按顺序处理
+pub struct MqServer {
+    engine: Arc<Engine>,
+    listener: TcpListener,
+}
+
+impl MqServer {
+    pub async fn bind(engine: Arc<Engine>, addr: SocketAddr) -> Result<Self, Error> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .map_err(|error| Error::new_bind(Some(error.into())))?;
+
+        Ok(MqServer { engine, listener })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.listener
+            .local_addr()
+            .map_err(|error| Error::new_bind(Some(error.into())))
+    }
+
+    pub async fn run(self) {
+        loop {
+            let (stream, remote_addr) = match self.listener.accept().await {
+                Ok(rst) => rst,
+                Err(error) => {
+                    log::debug!(target: "yiilian_mq::net::server", "accept failed: {}", error);
+                    continue;
+                }
+            };
+
+            let engine = self.engine.clone();
+            tokio::spawn(async move {
+                if let Err(error) = handle_connection(engine, stream).await {
+                    log::debug!(target: "yiilian_mq::net::server", "connection {} closed: {}", remote_addr, error);
+                }
+            });
+        }
+    }
+}
+
+async fn handle_connection(engine: Arc<Engine>, mut stream: TcpStream) -> Result<(), Error> {
+    while let Some(data) = read_frame(&mut stream).await? {
+        let response = match Request::decode(data) {
+            Ok(request) => handle_request(&engine, request).await,
+            Err(error) => Response::Error(error.to_string()),
+        };
+
+        write_frame(&mut stream, &response.encode()).await?;
+    }
+
+    Ok(())
+}
+
+async fn handle_request(engine: &Arc<Engine>, request: Request) -> Response {
+    let rst = match request {
+        Request::OpenTopic { topic } => engine.open_topic(&topic).map(|_| Response::Ok),
+        Request::Produce { topic, values } => produce(engine, &topic, values).map(|_| Response::Ok),
+        Request::ProduceKeyed { topic, entries } => produce_keyed(engine, &topic, entries).map(|_| Response::Ok),
+        Request::Fetch { topic, consumer, max_count, max_wait_millis } => {
+            let max_count = max_count.min(MAX_FETCH_COUNT) as usize;
+            let max_wait = Duration::from_millis(max_wait_millis.min(MAX_FETCH_WAIT_MILLIS) as u64);
+
+            fetch(engine, &topic, &consumer, max_count, max_wait)
+                .await
+                .map(Response::Messages)
+        }
+        Request::Commit { topic, consumer, offset } => {
+            engine.commit_offset(&topic, &consumer, offset).map(|_| Response::Ok)
+        }
+        Request::ListTopics => Ok(Response::Topics(engine.topic_names())),
+        Request::FetchGroup { topic, group, member, max_count, max_wait_millis } => {
+            let max_count = max_count.min(MAX_FETCH_COUNT) as usize;
+            let max_wait = Duration::from_millis(max_wait_millis.min(MAX_FETCH_WAIT_MILLIS) as u64);
+
+            wait_messages(engine, &topic, max_wait, || {
+                engine.fetch_group_messages(&topic, &group, &member, max_count)
+            })
+            .await
+            .map(Response::PartitionMessages)
+        }
+        Request::CommitGroup { topic, group, member, partition, offset } => engine
+            .commit_group_offset(&topic, &group, &member, partition, offset)
+            .map(|_| Response::Ok),
+        Request::LeaveGroup { topic, group, member } => {
+            engine.leave_group(&topic, &group, &member);
+            Ok(Response::Ok)
+        }
+    };
+
+    rst.unwrap_or_else(|error| Response::Error(error.to_string()))
+}
+
+fn produce(engine: &Engine, topic_name: &str, values: Vec<Bytes>) -> Result<(), Error> {
+    if engine.topic(topic_name).is_none() {
+        Err(Error::new_general("Not found topic"))?;
+    }
+
+    for value in values {
+        engine.push_message(topic_name, InMessage(value))?;
+    }
+
+    Ok(())
+}
+
+fn produce_keyed(engine: &Engine, topic_name: &str, entries: Vec<(Bytes, Bytes)>) -> Result<(), Error> {
+    if engine.topic(topic_name).is_none() {
+        Err(Error::new_general("Not found topic"))?;
+    }
+
+    for (key, value) in entries {
+        engine.push_keyed_message(topic_name, key, InMessage(value))?;
+    }
+
+    Ok(())
+}
+
+async fn fetch(
+    engine: &Engine,
+    topic_name: &str,
+    consumer_name: &str,
+    max_count: usize,
+    max_wait: Duration,
+) -> Result<Vec<Message>, Error> {
+    wait_messages(engine, topic_name, max_wait, || {
+        Ok(engine.fetch_messages(topic_name, consumer_name, max_count))
+    })
+    .await
+}
+
+/// 没有消息时等待新消息写入，直到超时
+async fn wait_messages<T>(
+    engine: &Engine,
+    topic_name: &str,
+    max_wait: Duration,
+    mut fetch: impl FnMut() -> Result<Vec<T>, Error>,
+) -> Result<Vec<T>, Error> {
+    let mut notify_rx = engine.notify_receiver(topic_name)?;
+    let deadline = Instant::now() + max_wait;
+
+    loop {
+        notify_rx.borrow_and_update();
+
+        let messages = fetch()?;
+        if !messages.is_empty() {
+            return Ok(messages);
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(messages);
+        }
+
+        match timeout(remaining, notify_rx.changed()).await {
+            Ok(Ok(_)) => (),
+            // topic 被删除
+            Ok(Err(_)) => Err(Error::new_general("Not found topic"))?,
+            Err(_) => return Ok(messages),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{net::client::MqClient, test_util::TestHome};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_server() {
+        let topic_name = "test_net";
+        let consumer_name = "test_client";
+        let wd = TestHome::new("server");
+
+        let engine = Arc::new(Engine::new(100, wd.home_dir()).expect("create mq engine"));
+        engine.remove_topic(topic_name);
+
+        let server = MqServer::bind(engine.clone(), "127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+
+        let mut client = MqClient::connect(server_addr).await.unwrap();
+
+        // topic 未创建
+        assert!(client.push_message(topic_name, InMessage("value_0".into())).await.is_err());
+
+        client.open_topic(topic_name).await.unwrap();
+        assert!(client.topic_names().await.unwrap().contains(&topic_name.to_owned()));
+
+        let messages = (0..5).map(|i| InMessage(format!("value_{}", i).into())).collect();
+        client.push_messages(topic_name, messages).await.unwrap();
+
+        let messages = client
+            .fetch_messages(topic_name, consumer_name, 3, Duration::ZERO)
+            .await
+            .unwrap();
+        let offsets: Vec<u64> = messages.iter().map(|m| m.offset()).collect();
+        assert_eq!(vec![0, 1, 2], offsets);
+        assert_eq!(None, messages[0].key());
+
+        // 未提交时重复读取到相同的消息
+        let messages = client
+            .fetch_messages(topic_name, consumer_name, 3, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(0, messages[0].offset());
+
+        client.commit_offset(topic_name, consumer_name, 2).await.unwrap();
+        let messages = client
+            .fetch_messages(topic_name, consumer_name, 10, Duration::ZERO)
+            .await
+            .unwrap();
+        let offsets: Vec<u64> = messages.iter().map(|m| m.offset()).collect();
+        assert_eq!(vec![3, 4], offsets);
+        assert_eq!(b"value_4", messages[1].value());
+
+        // 没有消息时等待新消息写入
+        client.commit_offset(topic_name, consumer_name, 4).await.unwrap();
+        let producer = tokio::spawn(async move {
+            let mut client = MqClient::connect(server_addr).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            client.push_message(topic_name, InMessage("value_5".into())).await.unwrap();
+        });
+
+        let messages = client
+            .fetch_messages(topic_name, consumer_name, 10, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(5, messages[0].offset());
+        producer.await.unwrap();
+
+        // 带 key 的消息
+        client.commit_offset(topic_name, consumer_name, 5).await.unwrap();
+        client
+            .push_keyed_messages(topic_name, vec![("key_6".into(), InMessage("value_6".into()))])
+            .await
+            .unwrap();
+        let messages = client
+            .fetch_messages(topic_name, consumer_name, 10, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(Some(&b"key_6"[..]), messages[0].key());
+
+        // 超时后返回空
+        client.commit_offset(topic_name, consumer_name, 6).await.unwrap();
+        let messages = client
+            .fetch_messages(topic_name, consumer_name, 10, Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert!(messages.is_empty());
+
+        // 消费组
+        let messages = client
+            .fetch_group_messages(topic_name, "test_group", "member_1", 10, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(7, messages.len());
+        assert!(messages.iter().all(|(partition, _)| *partition == 0));
+        client.commit_group_offset(topic_name, "test_group", "member_1", 0, 6).await.unwrap();
+        assert!(client.commit_group_offset(topic_name, "test_group", "member_1", 1, 6).await.is_err());
+        client.leave_group(topic_name, "test_group", "member_1").await.unwrap();
+
+        engine.remove_topic(topic_name);
+    }
+}
@@ -0,0 +1,50 @@
+/// 分区 n 保存在 "{topic}.partition_{n}" 中，分区 0 即原 topic，不分区的 topic 可以直接增加分区
+pub const PARTITION_SEPARATOR: &str = ".partition_";
+
+pub fn partition_topic_name(topic_name: &str, partition: u32) -> String {
+    if partition == 0 {
+        topic_name.to_owned()
+    } else {
+        format!("{}{}{}", topic_name, PARTITION_SEPARATOR, partition)
+    }
+}
+
+/// 解析分区的名称，返回 topic 名称及分区号，分区 0 及普通 topic 返回 None
+pub fn parse_partition_topic_name(name: &str) -> Option<(&str, u32)> {
+    let pos = name.rfind(PARTITION_SEPARATOR)?;
+    let partition: u32 = name[pos + PARTITION_SEPARATOR.len()..].parse().ok()?;
+
+    if partition == 0 {
+        return None;
+    }
+
+    Some((&name[..pos], partition))
+}
+
+/// 按 key 的 crc32 选择分区，分区数不变时相同的 key 总是写入同一个分区
+pub fn select_partition(key: &[u8], partitions: u32) -> u32 {
+    crc32fast::hash(key) % partitions.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_topic_name() {
+        assert_eq!("info_hash", partition_topic_name("info_hash", 0));
+        assert_eq!("info_hash.partition_3", partition_topic_name("info_hash", 3));
+
+        assert_eq!(Some(("info_hash", 3)), parse_partition_topic_name("info_hash.partition_3"));
+        assert_eq!(None, parse_partition_topic_name("info_hash"));
+        assert_eq!(None, parse_partition_topic_name("info_hash.partition_0"));
+        assert_eq!(None, parse_partition_topic_name("info_hash.partition_x"));
+        assert_eq!(None, parse_partition_topic_name("info_hash.dead_letter"));
+
+        let partition = select_partition(b"key_1", 4);
+        assert!(partition < 4);
+        assert_eq!(partition, select_partition(b"key_1", 4));
+        assert_eq!(0, select_partition(b"key_1", 1));
+        assert_eq!(0, select_partition(b"key_1", 0));
+    }
+}
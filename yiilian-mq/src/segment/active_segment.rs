@@ -3,11 +3,18 @@ use std::{fs::OpenOptions, path::PathBuf};
 use memmap::MmapMut;
 use yiilian_core::common::error::Error;
 
-use crate::{message::Message, segment::{
-    calc_log_index_size, LOG_DATA_FILE_EXTENSION, LOG_DATA_SIZE, LOG_INDEX_FILE_EXTENSION,
+use crate::{message::{batch::decode_batch, Message}, segment::{
+    calc_log_index_size, calc_time_index_size, LOG_DATA_FILE_EXTENSION, LOG_DATA_SIZE, LOG_INDEX_FILE_EXTENSION,
+    TIME_INDEX_FILE_EXTENSION,
 }};
 
-use super::{gen_mq_file_name, log_data::LogData, log_index::{LogIndex, LogIndexItem}};
+use super::{
+    gen_mq_file_name,
+    log_data::{LogData, LOGDATA_PREFIX_LEN},
+    log_index::{LogIndex, LogIndexItem, LOGINDEX_ITEM_LEN, LOGINDEX_PREFIX_LEN},
+    time_index::{TimeIndex, TimeIndexItem, TIMEINDEX_ITEM_LEN, TIMEINDEX_PREFIX_LEN},
+    RecoveryReport,
+};
 
 #[derive(Debug)]
 pub struct ActiveSegment {
@@ -18,6 +25,8 @@ pub struct ActiveSegment {
     
     log_data: LogData,
     log_index: LogIndex,
+    time_index: TimeIndex,
+    recovery_report: RecoveryReport,
 }
 
 impl ActiveSegment {
@@ -54,6 +63,21 @@ impl ActiveSegment {
             file
         };
 
+        let time_index_file = {
+            let mut path = base_path.clone();
+            path.push(gen_mq_file_name(offset, TIME_INDEX_FILE_EXTENSION));
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .map_err(|error| Error::new_file(Some(error.into()), None))?;
+            file.set_len(calc_time_index_size(log_data_size) as u64)
+                .map_err(|error| Error::new_file(Some(error.into()), None))?;
+            file
+        };
+
         let cache = unsafe {
             MmapMut::map_mut(&log_data_file)
                 .map_err(|error| Error::new_memory(Some(error.into()), None))?
@@ -66,18 +90,168 @@ impl ActiveSegment {
         };
         let log_index = LogIndex::new(offset, cache)?;
 
-        Ok(ActiveSegment {
+        let cache = unsafe {
+            MmapMut::map_mut(&time_index_file)
+                .map_err(|error| Error::new_memory(Some(error.into()), None))?
+        };
+        let time_index = TimeIndex::new(offset, cache)?;
+
+        let mut active_segment = ActiveSegment {
             offset,
             base_path,
             log_data,
             log_index,
-        })
+            time_index,
+            recovery_report: RecoveryReport::new(offset),
+        };
+        active_segment.recover();
+
+        if active_segment.recovery_report.is_repaired() {
+            log::warn!(target: "yiilian-mq::segment", "segment repaired: {:?}", active_segment.recovery_report);
+        }
+
+        Ok(active_segment)
     }
 
+    /// 打开时修复断电等原因导致的不完整写入：
+    /// 从最后一个有效的索引项开始逐条校验消息的 crc，截掉末尾无效的数据，并补建缺失的索引
+    fn recover(&mut self) {
+        let mut report = RecoveryReport::new(self.offset);
+
+        // 长度前缀本身损坏时先限制在容量以内
+        let max_data_len = self.log_data.capacity() - LOGDATA_PREFIX_LEN;
+        if self.log_data.len() > max_data_len {
+            report.truncated_bytes += self.log_data.len() - max_data_len;
+            self.log_data.set_len(max_data_len);
+        }
+
+        let max_index_count = (self.log_index.capacity() - LOGINDEX_PREFIX_LEN) / LOGINDEX_ITEM_LEN;
+        let index_count = self.log_index.count().min(max_index_count);
+        if self.log_index.len() != index_count * LOGINDEX_ITEM_LEN {
+            self.log_index.set_len(index_count * LOGINDEX_ITEM_LEN);
+        }
+
+        let max_time_index_count = (self.time_index.capacity() - TIMEINDEX_PREFIX_LEN) / TIMEINDEX_ITEM_LEN;
+        let time_index_count = self.time_index.count().min(max_time_index_count);
+        if self.time_index.len() != time_index_count * TIMEINDEX_ITEM_LEN {
+            self.time_index.set_len(time_index_count * TIMEINDEX_ITEM_LEN);
+        }
+
+        // 删除末尾指向无效消息的索引项，压缩批次中每条消息的索引项都指向批次的位置
+        while let Some(index_item) = self.log_index.last() {
+            let is_valid = match self.log_data.next_messages(index_item.message_pos()) {
+                Some((messages, _)) => messages
+                    .iter()
+                    .any(|message| message.offset() == index_item.message_offset()),
+                None => false,
+            };
+
+            if is_valid {
+                break;
+            }
+
+            self.log_index.truncate(self.log_index.count() - 1);
+            report.dropped_index_items += 1;
+        }
+
+        // 从最后一个索引项所在的消息或批次开始校验
+        let resume_pos = self.log_index.last().map(|index_item| index_item.message_pos());
+        let (mut pos, mut expected_offset) = match self.log_index.last() {
+            Some(index_item) => (index_item.message_pos(), index_item.message_offset() + 1),
+            None => (0, self.offset),
+        };
+
+        // 删除超出有效消息范围的时间索引项
+        while let Some(time_index_item) = self.time_index.last() {
+            if time_index_item.message_offset() < expected_offset {
+                break;
+            }
+
+            self.time_index.truncate(self.time_index.count() - 1);
+            report.dropped_time_index_items += 1;
+        }
+
+        // 时间索引在 .index 之后写入，可能缺少最后一个索引项对应的时间索引
+        if let Some(index_item) = self.log_index.last() {
+            let is_missing = self.time_index.should_index(index_item.message_offset())
+                && self.time_index.last().map(|item| item.message_offset()) != Some(index_item.message_offset());
+
+            if is_missing {
+                let message = self
+                    .log_data
+                    .get_message(index_item.message_offset(), index_item.message_pos())
+                    .expect("valid index item");
+                if self.time_index.push(TimeIndexItem::new(message.timestamp(), message.offset())).is_ok() {
+                    report.rebuilt_time_index_items += 1;
+                }
+            }
+        }
+
+        // 最后一个索引项之后的消息逐条校验，并补建索引。压缩过的 segment 中 offset 递增但不一定连续
+        'recover: while let Some((messages, next_pos)) = self.log_data.next_messages(pos) {
+            // 最后一个索引项所在的批次中，跳过已有索引的消息
+            let messages: Vec<Message> = if Some(pos) == resume_pos {
+                messages
+                    .into_iter()
+                    .filter(|message| message.offset() >= expected_offset)
+                    .collect()
+            } else {
+                messages
+            };
+
+            for message in messages {
+                if message.offset() < expected_offset {
+                    break 'recover;
+                }
+
+                if self.log_index.push(LogIndexItem::new(message.offset(), pos)).is_err() {
+                    break 'recover;
+                }
+                report.rebuilt_index_items += 1;
+
+                if self.time_index.should_index(message.offset()) {
+                    let time_index_item = TimeIndexItem::new(message.timestamp(), message.offset());
+                    if self.time_index.push(time_index_item).is_ok() {
+                        report.rebuilt_time_index_items += 1;
+                    }
+                }
+
+                expected_offset = message.offset() + 1;
+            }
+
+            pos = next_pos;
+        }
+
+        if pos < self.log_data.len() {
+            report.truncated_bytes += self.log_data.len() - pos;
+            self.log_data.truncate(pos);
+        }
+
+        self.recovery_report = report;
+    }
+
+    pub fn recovery_report(&self) -> &RecoveryReport {
+        &self.recovery_report
+    }
+
+    /// 压缩批次中的每条消息都写入指向批次位置的索引项
     pub fn push_message(&mut self, message: Message) -> Result<(), Error> {
-        let index_item = LogIndexItem::new(message.offset(), self.log_data.len());
+        let pos = self.log_data.len();
+        let messages = if message.is_batch() {
+            decode_batch(&message)?
+        } else {
+            vec![message.clone()]
+        };
+
         self.log_data.push(message)?;
-        self.log_index.push(index_item)?;
+
+        for message in messages {
+            self.log_index.push(LogIndexItem::new(message.offset(), pos))?;
+
+            if self.time_index.should_index(message.offset()) {
+                self.time_index.push(TimeIndexItem::new(message.timestamp(), message.offset()))?;
+            }
+        }
 
         Ok(())
     }
@@ -94,6 +268,25 @@ impl ActiveSegment {
         &self.log_index
     }
 
+    /// 从 target_offset 开始读取最多 count 条消息
+    pub fn get_messages(&self, target_offset: u64, count: usize) -> Vec<Message> {
+        if self.log_index.count() == 0 {
+            return vec![];
+        }
+
+        match self.log_index.get_by_offset(target_offset) {
+            Some(index_item) => self
+                .log_data
+                .get_messages(index_item.message_pos(), index_item.message_offset(), count)
+                .unwrap_or_default(),
+            None => vec![],
+        }
+    }
+
+    pub fn time_index(&self) -> &TimeIndex {
+        &self.time_index
+    }
+
     pub fn get_next_offset(&self) -> u64 {
 
         if self.log_index.count() == 0 {
@@ -6,7 +6,7 @@ use bytes::Bytes;
 use memmap::MmapMut;
 use yiilian_core::common::error::Error;
 
-use crate::message::{Message, MESSAGE_PREFIX_LEN};
+use crate::message::{batch::unbatch, decode_message_len, Message, MESSAGE_PREFIX_LEN};
 
 pub const LOGDATA_PREFIX_LEN: usize = 8;
 
@@ -67,6 +67,17 @@ impl LogData {
         self.length = 0;
     }
 
+    /// 截断到 length 字节，并清零被截掉的数据
+    pub fn truncate(&mut self, length: usize) {
+        if length >= self.len() {
+            return;
+        }
+
+        let end = (LOGDATA_PREFIX_LEN + self.len()).min(self.capacity());
+        self.cache[LOGDATA_PREFIX_LEN + length..end].fill(0);
+        self.set_len(length);
+    }
+
     pub fn enough_space(&self, message_size: usize) -> bool {
 
         self.free_space() >= message_size
@@ -108,11 +119,9 @@ impl LogData {
 
         let message_len = {
             let val = &cache[pos..pos + MESSAGE_PREFIX_LEN];
-            let val =
-                u32::from_be_bytes(val.try_into().expect("Message length bytes is invalid"));
 
-            val
-        } as usize;
+            decode_message_len(val.try_into().expect("Message length bytes is invalid"))
+        };
 
         let end_pos = pos + MESSAGE_PREFIX_LEN + message_len;
         if end_pos > self.len() {
@@ -127,16 +136,28 @@ impl LogData {
         }
     }
 
+    /// 返回 pos 处的消息及下一次起始位置，批次展开为其中的消息
+    pub fn next_messages(&self, pos: usize) -> Option<(Vec<Message>, usize)> {
+        let (message, next_pos) = self.next(pos)?;
+
+        match unbatch(message) {
+            Ok(messages) => Some((messages, next_pos)),
+            Err(_) => None,
+        }
+    }
+
     /// 从指定位置开始查找 offset 的消息
     pub fn get_message(&self, offset: u64, mut pos: usize) -> Option<Message> {
         while pos < self.len() {
 
-            if let Some((message, inner_pos)) = self.next(pos) {
-                
-                if message.offset() == offset {
-                    return Some(message)
-                } else if message.offset() > offset {
-                    return None
+            if let Some((messages, inner_pos)) = self.next_messages(pos) {
+
+                for message in messages {
+                    if message.offset() == offset {
+                        return Some(message)
+                    } else if message.offset() > offset {
+                        return None
+                    }
                 }
 
                 pos = inner_pos;
@@ -148,20 +169,23 @@ impl LogData {
         None
     }
 
-    pub fn get_messages(&self, mut pos: usize, expected_count: usize) -> Option<Vec<Message>> {
-        let mut count = 1;
+    /// 从指定位置开始读取最多 expected_count 条消息，跳过批次中 from_offset 之前的消息
+    pub fn get_messages(&self, mut pos: usize, from_offset: u64, expected_count: usize) -> Option<Vec<Message>> {
         let mut messages = vec![];
-        
-        while pos < self.len() && count <= expected_count {
 
-            if let Some((message, inner_pos)) = self.next(pos) {
-                messages.push(message);
+        while pos < self.len() && messages.len() < expected_count {
+
+            if let Some((batch, inner_pos)) = self.next_messages(pos) {
+                messages.extend(
+                    batch
+                        .into_iter()
+                        .filter(|message| message.offset() >= from_offset)
+                        .take(expected_count - messages.len()),
+                );
                 pos = inner_pos;
             } else {
                 break;
             }
-
-            count += 1;
         }
 
         if messages.len() > 0 {
@@ -214,7 +238,7 @@ mod tests {
 
         assert_eq!(cache_len, log_data.len());
 
-        let (message, pos) = log_data.next(26).unwrap();
+        let (message, pos) = log_data.next(29).unwrap();
         assert_eq!(2, message.offset());
 
         let (message, pos) = log_data.next(pos).unwrap();
@@ -222,19 +246,19 @@ mod tests {
 
         assert_eq!(pos, log_data.len());
 
-        let message = log_data.next(27);
+        let message = log_data.next(30);
         assert_eq!(true, message.is_none());
 
-        let messages = log_data.get_messages(26, 3).unwrap();
+        let messages = log_data.get_messages(29, 0, 3).unwrap();
         assert_eq!(2, messages.len());
 
-        let messages = log_data.get_messages(100, 3);
+        let messages = log_data.get_messages(100, 0, 3);
         assert_eq!(true, messages.is_none());
 
-        let message = log_data.get_message(3, 26).unwrap();
+        let message = log_data.get_message(3, 29).unwrap();
         assert_eq!(3, message.offset());
 
-        let message = log_data.get_message(4, 26);
+        let message = log_data.get_message(4, 29);
         assert_eq!(true, message.is_none());
     }
 }
@@ -3,7 +3,7 @@ use std::{fs::File, io::{Read, Seek, SeekFrom}};
 
 use yiilian_core::common::error::Error;
 
-use crate::message::{Message, MESSAGE_PREFIX_LEN};
+use crate::message::{batch::unbatch, decode_message_len, Message, MESSAGE_PREFIX_LEN};
 
 const LOGDATA_PREFIX_LEN: usize = 8;
 
@@ -67,10 +67,8 @@ impl LogDataFile {
             self.file.seek(SeekFrom::Start((pos + LOGDATA_PREFIX_LEN) as u64)).expect("seek error");
             self.file.read_exact(&mut buf).expect("read_exact error");
 
-            let val = u32::from_be_bytes(buf);
-
-            val
-        } as usize;
+            decode_message_len(buf)
+        };
 
         let end_pos = pos + MESSAGE_PREFIX_LEN + message_len;
         if end_pos > self.len() {
@@ -91,16 +89,28 @@ impl LogDataFile {
         }
     }
 
+    /// 返回 pos 处的消息及下一次起始位置，批次展开为其中的消息
+    pub fn next_messages(&mut self, pos: usize) -> Option<(Vec<Message>, usize)> {
+        let (message, next_pos) = self.next(pos)?;
+
+        match unbatch(message) {
+            Ok(messages) => Some((messages, next_pos)),
+            Err(_) => None,
+        }
+    }
+
     /// 从指定位置开始查找 offset 的消息
     pub fn get_message(&mut self, offset: u64, mut pos: usize) -> Option<Message> {
         while pos < self.len() {
 
-            if let Some((message, inner_pos)) = self.next(pos) {
-                
-                if message.offset() == offset {
-                    return Some(message)
-                } else if message.offset() > offset {
-                    return None
+            if let Some((messages, inner_pos)) = self.next_messages(pos) {
+
+                for message in messages {
+                    if message.offset() == offset {
+                        return Some(message)
+                    } else if message.offset() > offset {
+                        return None
+                    }
                 }
 
                 pos = inner_pos;
@@ -112,20 +122,23 @@ impl LogDataFile {
         None
     }
 
-    pub fn get_messages(&mut self, mut pos: usize, expected_count: usize) -> Option<Vec<Message>> {
-        let mut count = 1;
+    /// 从指定位置开始读取最多 expected_count 条消息，跳过批次中 from_offset 之前的消息
+    pub fn get_messages(&mut self, mut pos: usize, from_offset: u64, expected_count: usize) -> Option<Vec<Message>> {
         let mut messages = vec![];
-        
-        while pos < self.len() && count <= expected_count {
 
-            if let Some((message, inner_pos)) = self.next(pos) {
-                messages.push(message);
+        while pos < self.len() && messages.len() < expected_count {
+
+            if let Some((batch, inner_pos)) = self.next_messages(pos) {
+                messages.extend(
+                    batch
+                        .into_iter()
+                        .filter(|message| message.offset() >= from_offset)
+                        .take(expected_count - messages.len()),
+                );
                 pos = inner_pos;
             } else {
                 break;
             }
-
-            count += 1;
         }
 
         if messages.len() > 0 {
@@ -160,7 +173,7 @@ mod tests {
         file.set_len(100).unwrap();
 
         let mut buf = BytesMut::new();
-        buf.put_u64(78);
+        buf.put_u64(87);
 
         let offset = 1;
         let value: Bytes = b"11"[..].into();
@@ -184,13 +197,13 @@ mod tests {
 
         let mut log_data_file = LogDataFile::new(0, file).unwrap();
 
-        let messages = log_data_file.get_messages(0, 2).unwrap();
+        let messages = log_data_file.get_messages(0, 0, 2).unwrap();
         assert_eq!(2, messages.len());
 
-        let message = log_data_file.get_message(3, 26).unwrap();
+        let message = log_data_file.get_message(3, 29).unwrap();
         assert_eq!(3, message.offset());
 
-        let message = log_data_file.get_message(4, 26);
+        let message = log_data_file.get_message(4, 29);
         assert_eq!(true, message.is_none());
 
         std::fs::remove_file(path).unwrap();
@@ -109,6 +109,17 @@ impl LogIndex {
         self.length = 0;
     }
 
+    pub fn last(&self) -> Option<LogIndexItem> {
+        self.count().checked_sub(1).and_then(|index| self.get(index))
+    }
+
+    /// 只保留前 count 项
+    pub fn truncate(&mut self, count: usize) {
+        if count < self.count() {
+            self.set_len(count * LOGINDEX_ITEM_LEN);
+        }
+    }
+
     pub fn push(&mut self, item: LogIndexItem) -> Result<usize, Error> {
         let start_pos = LOGINDEX_PREFIX_LEN + self.len();
 
@@ -104,6 +104,25 @@ impl LogIndexFile {
 
         None
     }
+
+    /// 第一个 offset 不小于 target_offset 的索引项
+    pub fn get_by_ceiling_offset(&mut self, target_offset: u64) -> Option<LogIndexItem> {
+        let mut left = 0;
+        let mut right = self.count() as usize;
+
+        while left < right {
+            let mid = left + (right - left) / 2;
+            let mid_item = self.get(mid).expect("Not found mid item in LogIndex");
+
+            if mid_item.message_offset() < target_offset {
+                left = mid + 1;
+            } else {
+                right = mid;
+            }
+        }
+
+        self.get(left)
+    }
 }
 
 
@@ -163,6 +182,10 @@ mod tests {
         let item = log_index_file.last().unwrap();
         assert_eq!(2, item.message_offset());
 
+        let item = log_index_file.get_by_ceiling_offset(1).unwrap();
+        assert_eq!(1, item.message_offset());
+        assert!(log_index_file.get_by_ceiling_offset(3).is_none());
+
         fs::remove_file(path).unwrap();
     }
 }
\ No newline at end of file
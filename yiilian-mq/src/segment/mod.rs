@@ -2,13 +2,14 @@ use std::{fs::OpenOptions, path::PathBuf};
 
 use yiilian_core::common::error::Error;
 
-use crate::{message::Message, segment::{log_data::log_data_file::LogDataFile, log_index::log_index_file::LogIndexFile}};
+use crate::{message::Message, segment::{log_data::log_data_file::LogDataFile, log_index::log_index_file::LogIndexFile, time_index::time_index_file::TimeIndexFile}};
 
 pub mod active_segment;
 pub mod log_data;
 pub mod log_index;
+pub mod time_index;
 
-pub const CONSUMER_OFFSETS_FILE_NAME: &str = "__consumer_offsets";
+pub const CONSUMER_OFFSETS_FILE_NAME: &str = "_consumer_offsets";
 pub const LOG_DATA_FILE_EXTENSION: &str = "log";
 pub const LOG_INDEX_FILE_EXTENSION: &str = "index";
 pub const TIME_INDEX_FILE_EXTENSION: &str = "timeindex";
@@ -16,6 +17,39 @@ pub const TIME_INDEX_FILE_EXTENSION: &str = "timeindex";
 pub const  LOG_DATA_SIZE: usize = 10 * 1024 * 1024;
 // const LOG_DATA_SIZE: usize = 100;
 
+/// 打开 segment 时修复的内容
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RecoveryReport {
+    pub segment_offset: u64,
+    /// 截掉的 log 数据字节数（未写完整或 crc 校验失败的消息）
+    pub truncated_bytes: usize,
+    /// 删除的无效 .index 项数
+    pub dropped_index_items: usize,
+    /// 根据 log 数据补建的 .index 项数
+    pub rebuilt_index_items: usize,
+    /// 删除的无效 .timeindex 项数
+    pub dropped_time_index_items: usize,
+    /// 根据 log 数据补建的 .timeindex 项数
+    pub rebuilt_time_index_items: usize,
+}
+
+impl RecoveryReport {
+    pub fn new(segment_offset: u64) -> Self {
+        RecoveryReport {
+            segment_offset,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_repaired(&self) -> bool {
+        self.truncated_bytes > 0
+            || self.dropped_index_items > 0
+            || self.rebuilt_index_items > 0
+            || self.dropped_time_index_items > 0
+            || self.rebuilt_time_index_items > 0
+    }
+}
+
 pub struct Segment {
     // length: usize,
     // max_length: usize,
@@ -40,6 +74,29 @@ pub fn calc_log_index_size(log_data_size: usize) -> usize {
     index_size
 }
 
+pub fn calc_time_index_size(log_data_size: usize) -> usize {
+    let msg_cnt = (log_data_size - log_data::LOGDATA_PREFIX_LEN) / 24 + 1;
+    let item_cnt = msg_cnt / time_index::TIMEINDEX_INTERVAL as usize + 1;
+
+    item_cnt * time_index::TIMEINDEX_ITEM_LEN + time_index::TIMEINDEX_PREFIX_LEN
+}
+
+/// 读取 segment 的时间索引，旧版本没有 .timeindex 文件时返回 None
+pub fn read_time_index(topic_path: &PathBuf, segment_offset: u64) -> Option<TimeIndexFile> {
+    let mut time_index_path = topic_path.to_owned();
+    time_index_path.push(gen_mq_file_name(segment_offset, TIME_INDEX_FILE_EXTENSION));
+
+    let file = OpenOptions::new().read(true).open(&time_index_path).ok()?;
+
+    match TimeIndexFile::new(segment_offset, file) {
+        Ok(time_index_file) => Some(time_index_file),
+        Err(error) => {
+            log::trace!(target: "yiilian-mq::segment", "read time index error: {}", error);
+            None
+        }
+    }
+}
+
 pub fn poll_message_inner(
     topic_path: &PathBuf,
     segment_offset: u64,
@@ -81,6 +138,71 @@ pub fn poll_message_inner(
     }
 }
 
+/// 读取 segment 中第一条 offset 不小于 target_offset 的消息，压缩过的 segment 中 offset 不连续
+pub fn poll_next_message_inner(
+    topic_path: &PathBuf,
+    segment_offset: u64,
+    target_offset: u64,
+) -> Result<Option<Message>, Error> {
+    let mut index_path = topic_path.to_owned();
+    index_path.push(gen_mq_file_name(segment_offset, LOG_INDEX_FILE_EXTENSION));
+    let index_file = OpenOptions::new()
+        .read(true)
+        .open(&index_path)
+        .map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+    let mut log_index_file = LogIndexFile::new(segment_offset, index_file)?;
+    let index_item = match log_index_file.get_by_ceiling_offset(target_offset) {
+        Some(index_item) => index_item,
+        None => return Ok(None),
+    };
+
+    let mut data_path = topic_path.to_owned();
+    data_path.push(gen_mq_file_name(segment_offset, LOG_DATA_FILE_EXTENSION));
+    let data_file = OpenOptions::new()
+        .read(true)
+        .open(&data_path)
+        .map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+    let mut log_data_file = LogDataFile::new(segment_offset, data_file)?;
+
+    Ok(log_data_file.get_message(index_item.message_offset(), index_item.message_pos()))
+}
+
+/// 从 target_offset 开始读取同一 segment 中最多 count 条消息
+pub fn poll_messages_inner(
+    topic_path: &PathBuf,
+    segment_offset: u64,
+    target_offset: u64,
+    count: usize,
+) -> Result<Vec<Message>, Error> {
+    let mut index_path = topic_path.to_owned();
+    index_path.push(gen_mq_file_name(segment_offset, LOG_INDEX_FILE_EXTENSION));
+    let index_file = OpenOptions::new()
+        .read(true)
+        .open(&index_path)
+        .map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+    let mut log_index_file = LogIndexFile::new(segment_offset, index_file)?;
+    let index_item = match log_index_file.get_by_offset(target_offset) {
+        Some(index_item) => index_item,
+        None => return Ok(vec![]),
+    };
+
+    let mut data_path = topic_path.to_owned();
+    data_path.push(gen_mq_file_name(segment_offset, LOG_DATA_FILE_EXTENSION));
+    let data_file = OpenOptions::new()
+        .read(true)
+        .open(&data_path)
+        .map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+    let mut log_data_file = LogDataFile::new(segment_offset, data_file)?;
+
+    Ok(log_data_file
+        .get_messages(index_item.message_pos(), index_item.message_offset(), count)
+        .unwrap_or_default())
+}
+
 pub fn gen_mq_file_name(segment_offset: u64, ext: &str) -> String {
     format!("{:0>20}.{}", segment_offset, ext)
 }
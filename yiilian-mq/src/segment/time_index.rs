@@ -0,0 +1,213 @@
+pub mod time_index_file;
+
+use std::{fmt, io::Write};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use memmap::MmapMut;
+use yiilian_core::common::error::Error;
+
+pub const TIMEINDEX_PREFIX_LEN: usize = 8;
+pub const TIMEINDEX_ITEM_LEN: usize = 16;
+/// 每隔多少条消息记录一次时间索引，segment 的第一条消息总会被记录
+pub const TIMEINDEX_INTERVAL: u64 = 64;
+
+/// 稀疏时间索引，假定消息的 timestamp 单调不减
+///
+/// TimeIndex = len(8) + [ timestamp(8) + message_offset(8) .. ]
+pub struct TimeIndex {
+    /// TimeIndex 字节数
+    length: usize,
+    offset: u64,
+    cache: MmapMut,
+}
+
+impl TimeIndex {
+
+    pub fn new(offset: u64, cache: MmapMut) -> Result<Self, Error> {
+        let length = if cache.len() < TIMEINDEX_PREFIX_LEN {
+            Err(Error::new_memory(None, Some(format!("cache size can't less than {TIMEINDEX_PREFIX_LEN} bytes"))))?
+        } else {
+            usize::from_be_bytes(cache[0..8].try_into().expect("Incorrect mem cache length for TimeIndex"))
+        };
+
+        Ok(TimeIndex {
+            length,
+            offset,
+            cache,
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.length + TIMEINDEX_PREFIX_LEN
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn free_space(&self) -> usize {
+        self.capacity() - self.total_size()
+    }
+
+    pub fn set_len(&mut self, length: usize) {
+        self.length = length;
+        let length: [u8; 8] = length.to_be_bytes();
+        self.cache[0..8].copy_from_slice(&length);
+    }
+}
+
+impl TimeIndex {
+
+    /// 索引项数
+    pub fn count(&self) -> usize {
+        self.len() / TIMEINDEX_ITEM_LEN
+    }
+
+    pub fn get(&self, index: usize) -> Option<TimeIndexItem> {
+        if index >= self.count() {
+            return None
+        }
+
+        let cache = &self.cache[TIMEINDEX_PREFIX_LEN..];
+        let start = index * TIMEINDEX_ITEM_LEN;
+
+        cache[start..start + TIMEINDEX_ITEM_LEN].try_into().ok()
+    }
+
+    pub fn last(&self) -> Option<TimeIndexItem> {
+        self.count().checked_sub(1).and_then(|index| self.get(index))
+    }
+
+    /// 只保留前 count 项
+    pub fn truncate(&mut self, count: usize) {
+        if count < self.count() {
+            self.set_len(count * TIMEINDEX_ITEM_LEN);
+        }
+    }
+
+    /// 是否需要为该 offset 的消息记录时间索引
+    pub fn should_index(&self, message_offset: u64) -> bool {
+        (message_offset - self.offset).is_multiple_of(TIMEINDEX_INTERVAL)
+    }
+
+    pub fn push(&mut self, item: TimeIndexItem) -> Result<usize, Error> {
+        let start_pos = TIMEINDEX_PREFIX_LEN + self.len();
+
+        if TIMEINDEX_ITEM_LEN > self.free_space() {
+            Err(Error::new_general("Push item for TimeIndex over capacity limited"))?
+        }
+
+        let index_item: Bytes = item.into();
+
+        (&mut self.cache[start_pos..])
+            .write_all(&index_item)
+            .map_err(|error| {
+                Error::new_memory(
+                    Some(error.into()),
+                    Some("writing Cache for TimeIndex is failed".to_owned()),
+                )
+            })?;
+
+        self.set_len(self.length + TIMEINDEX_ITEM_LEN);
+
+        Ok(self.len())
+    }
+}
+
+impl fmt::Debug for TimeIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let items: Vec<TimeIndexItem> = (0..self.count()).filter_map(|i| self.get(i)).collect();
+
+        f.debug_struct("TimeIndex")
+            .field("length", &self.length)
+            .field("offset", &self.offset)
+            .field("cache", &items)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeIndexItem {
+    /// utc 毫秒
+    timestamp: i64,
+    message_offset: u64,
+}
+
+impl TimeIndexItem {
+    pub fn new(timestamp: i64, message_offset: u64) -> Self {
+        TimeIndexItem {
+            timestamp,
+            message_offset,
+        }
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn message_offset(&self) -> u64 {
+        self.message_offset
+    }
+}
+
+impl From<TimeIndexItem> for Bytes {
+    fn from(value: TimeIndexItem) -> Self {
+        let mut rst = BytesMut::with_capacity(TIMEINDEX_ITEM_LEN);
+        rst.put_i64(value.timestamp);
+        rst.put_u64(value.message_offset);
+
+        rst.into()
+    }
+}
+
+impl TryFrom<&[u8]> for TimeIndexItem {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < TIMEINDEX_ITEM_LEN {
+            Err(Error::new_decode(&format!("Data is too short to decode TimeIndexItem: {:?}", data.len())))?;
+        }
+
+        let timestamp = i64::from_be_bytes(data[0..8].try_into().expect("data[0..8] is not satisfy"));
+        let message_offset = u64::from_be_bytes(data[8..16].try_into().expect("data[8..16] is not satisfy"));
+
+        Ok(TimeIndexItem::new(timestamp, message_offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use memmap::MmapMut;
+
+    use super::{TimeIndex, TimeIndexItem, TIMEINDEX_INTERVAL};
+
+    #[test]
+    fn test_time_index() {
+        let cache = MmapMut::map_anon(40).unwrap();
+        let mut time_index = TimeIndex::new(10, cache).unwrap();
+
+        assert!(time_index.should_index(10));
+        assert!(!time_index.should_index(11));
+        assert!(time_index.should_index(10 + TIMEINDEX_INTERVAL));
+
+        time_index.push(TimeIndexItem::new(1000, 10)).unwrap();
+        time_index.push(TimeIndexItem::new(2000, 10 + TIMEINDEX_INTERVAL)).unwrap();
+        assert!(time_index.push(TimeIndexItem::new(3000, 10 + 2 * TIMEINDEX_INTERVAL)).is_err());
+
+        assert_eq!(2, time_index.count());
+        assert_eq!(Some(TimeIndexItem::new(2000, 10 + TIMEINDEX_INTERVAL)), time_index.get(1));
+        assert_eq!(None, time_index.get(2));
+    }
+}
@@ -0,0 +1,103 @@
+use std::{fs::File, io::Read};
+
+use yiilian_core::common::error::Error;
+
+use super::{TimeIndexItem, TIMEINDEX_ITEM_LEN, TIMEINDEX_PREFIX_LEN};
+
+/// 只读的 TimeIndex 文件，索引是稀疏的，直接整体读入内存
+///
+/// TimeIndex = len(8) + [ timestamp(8) + message_offset(8) .. ]
+pub struct TimeIndexFile {
+    offset: u64,
+    items: Vec<TimeIndexItem>,
+}
+
+impl TimeIndexFile {
+
+    pub fn new(offset: u64, mut file: File) -> Result<Self, Error> {
+        let mut buf = vec![];
+        file.read_to_end(&mut buf)
+            .map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+        if buf.len() < TIMEINDEX_PREFIX_LEN {
+            Err(Error::new_file(None, Some(format!("File size can't less than {TIMEINDEX_PREFIX_LEN} bytes"))))?
+        }
+
+        let length = usize::from_be_bytes(buf[0..8].try_into().expect("buf[0..8] is not satisfy"));
+        let end = (TIMEINDEX_PREFIX_LEN + length).min(buf.len());
+
+        let items = buf[TIMEINDEX_PREFIX_LEN..end]
+            .chunks_exact(TIMEINDEX_ITEM_LEN)
+            .map(TimeIndexItem::try_from)
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(TimeIndexFile { offset, items })
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn count(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn first(&self) -> Option<TimeIndexItem> {
+        self.items.first().copied()
+    }
+
+    /// timestamp 小于 target_timestamp 的最后一个索引项
+    pub fn lower_by_timestamp(&self, target_timestamp: i64) -> Option<TimeIndexItem> {
+        let pos = self.items.partition_point(|item| item.timestamp() < target_timestamp);
+
+        if pos == 0 {
+            None
+        } else {
+            self.items.get(pos - 1).copied()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::{self, OpenOptions}, io::Write, path::PathBuf};
+
+    use bytes::{BufMut, Bytes, BytesMut};
+
+    use crate::segment::time_index::TimeIndexItem;
+
+    use super::TimeIndexFile;
+
+    #[test]
+    fn test_time_index_file() {
+        let path: PathBuf = "./test_time_index_file.txt".into();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+
+        let mut buf = BytesMut::new();
+        buf.put_u64(48);
+        for (timestamp, offset) in [(1000, 0), (2000, 64), (3000, 128)] {
+            let item: Bytes = TimeIndexItem::new(timestamp, offset).into();
+            buf.put_slice(&item);
+        }
+        // 未使用的预分配空间
+        buf.put_bytes(0, 32);
+        file.write_all(&buf).unwrap();
+
+        let file = OpenOptions::new().read(true).open(&path).unwrap();
+        let time_index_file = TimeIndexFile::new(0, file).unwrap();
+
+        assert_eq!(3, time_index_file.count());
+        assert_eq!(None, time_index_file.lower_by_timestamp(1000));
+        assert_eq!(0, time_index_file.lower_by_timestamp(1001).unwrap().message_offset());
+        assert_eq!(64, time_index_file.lower_by_timestamp(3000).unwrap().message_offset());
+        assert_eq!(128, time_index_file.lower_by_timestamp(9000).unwrap().message_offset());
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,25 @@
+use std::{fs, path::PathBuf};
+
+/// 测试用的临时 home 目录，创建时清空，drop 时删除
+pub struct TestHome {
+    home_dir: PathBuf,
+}
+
+impl TestHome {
+    pub fn new(name: &str) -> Self {
+        let home_dir = std::env::temp_dir().join(format!("yiilian_test_mq_{}", name));
+        fs::remove_dir_all(&home_dir).ok();
+
+        TestHome { home_dir }
+    }
+
+    pub fn home_dir(&self) -> PathBuf {
+        self.home_dir.clone()
+    }
+}
+
+impl Drop for TestHome {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.home_dir).ok();
+    }
+}
@@ -0,0 +1,399 @@
+use std::{
+    fs::{self, OpenOptions},
+    path::Path,
+};
+
+use yiilian_core::common::{error::Error, util::atoi};
+
+use crate::{
+    consumer_offsets::ConsumerOffsets,
+    message::{batch::decode_batch, Message},
+    segment::{
+        gen_mq_file_name, log_data::log_data_file::LogDataFile, log_index::log_index_file::LogIndexFile,
+        CONSUMER_OFFSETS_FILE_NAME, LOG_DATA_FILE_EXTENSION, LOG_INDEX_FILE_EXTENSION,
+    },
+};
+
+/// segment 的 offset 范围及大小，直接读取文件，不经过 Engine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentSummary {
+    pub segment_offset: u64,
+    pub first_offset: Option<u64>,
+    pub last_offset: Option<u64>,
+    pub message_count: u64,
+    /// log 数据的字节数，不含长度前缀
+    pub data_len: usize,
+    pub index_count: u64,
+    /// 压缩批次数，未压缩的 segment 为 0
+    pub batch_count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumerLag {
+    pub consumer: String,
+    /// 已提交的最后一条消息的 offset
+    pub committed_offset: Option<u64>,
+    /// 按 offset 计算的未消费消息数，压缩过的 topic 中为近似值
+    pub lag: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SegmentCheck {
+    pub segment_offset: u64,
+    pub valid_count: u64,
+    /// 第一条无法解码（不完整、crc 错误或 offset 未递增）的消息位置
+    pub corrupt_pos: Option<usize>,
+    /// .index 文件缺失
+    pub missing_index: bool,
+    /// 不指向对应消息的 .index 项数
+    pub bad_index_items: u64,
+}
+
+impl SegmentCheck {
+    pub fn is_ok(&self) -> bool {
+        self.corrupt_pos.is_none() && !self.missing_index && self.bad_index_items == 0
+    }
+}
+
+/// mq 目录下的 topic 名称，按名称排序
+pub fn topic_names(mq_path: &Path) -> Result<Vec<String>, Error> {
+    let mut names = vec![];
+
+    for entry in fs::read_dir(mq_path).map_err(|error| Error::new_file(Some(error.into()), None))? {
+        let entry = entry.map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+        if entry.path().is_dir() {
+            if let Some(name) = entry.file_name().to_str() {
+                names.push(name.to_owned());
+            }
+        }
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+/// topic 目录下的 segment offset，升序
+pub fn segment_offsets(topic_path: &Path) -> Result<Vec<u64>, Error> {
+    let mut offsets = vec![];
+    let ext = format!(".{}", LOG_DATA_FILE_EXTENSION);
+
+    for entry in fs::read_dir(topic_path).map_err(|error| Error::new_file(Some(error.into()), None))? {
+        let entry = entry.map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+        if let Some(file_name) = entry.file_name().to_str() {
+            if entry.path().is_file() && file_name.ends_with(&ext) {
+                let offset: u64 = atoi(&file_name.as_bytes()[0..file_name.len() - ext.len()])?;
+                offsets.push(offset);
+            }
+        }
+    }
+    offsets.sort();
+
+    Ok(offsets)
+}
+
+pub fn segment_summary(topic_path: &Path, segment_offset: u64) -> Result<SegmentSummary, Error> {
+    let mut log_data_file = open_log_data_file(topic_path, segment_offset)?;
+    let index_count = match open_log_index_file(topic_path, segment_offset) {
+        Ok(log_index_file) => log_index_file.count(),
+        Err(_) => 0,
+    };
+
+    let mut summary = SegmentSummary {
+        segment_offset,
+        first_offset: None,
+        last_offset: None,
+        message_count: 0,
+        data_len: log_data_file.len(),
+        index_count,
+        batch_count: 0,
+    };
+
+    let mut pos = 0;
+    while let Some((message, next_pos)) = log_data_file.next(pos) {
+        let messages = if message.is_batch() {
+            summary.batch_count += 1;
+            decode_batch(&message)?
+        } else {
+            vec![message]
+        };
+
+        for message in messages {
+            if summary.first_offset.is_none() {
+                summary.first_offset = Some(message.offset());
+            }
+            summary.last_offset = Some(message.offset());
+            summary.message_count += 1;
+        }
+        pos = next_pos;
+    }
+
+    Ok(summary)
+}
+
+/// 从第一条 offset 不小于 from_offset 的消息开始读取最多 count 条
+pub fn read_messages(topic_path: &Path, from_offset: u64, count: usize) -> Result<Vec<Message>, Error> {
+    let offsets = segment_offsets(topic_path)?;
+    let mut messages = vec![];
+
+    for (i, segment_offset) in offsets.iter().enumerate() {
+        if messages.len() >= count {
+            break;
+        }
+
+        // 跳过之后的 segment 起始 offset 不大于 from_offset 的 segment
+        if let Some(next_segment_offset) = offsets.get(i + 1) {
+            if *next_segment_offset <= from_offset {
+                continue;
+            }
+        }
+
+        let mut log_index_file = open_log_index_file(topic_path, *segment_offset)?;
+        let index_item = match log_index_file.get_by_ceiling_offset(from_offset) {
+            Some(index_item) => index_item,
+            None => continue,
+        };
+
+        let mut log_data_file = open_log_data_file(topic_path, *segment_offset)?;
+        let batch = log_data_file
+            .get_messages(index_item.message_pos(), index_item.message_offset(), count - messages.len())
+            .unwrap_or_default();
+        messages.extend(batch);
+    }
+
+    Ok(messages)
+}
+
+/// 各消费者已提交的位置及积压的消息数
+pub fn consumer_lags(topic_path: &Path) -> Result<Vec<ConsumerLag>, Error> {
+    let offsets = segment_offsets(topic_path)?;
+
+    let mut first_offset = None;
+    let mut end_offset = 0;
+    for segment_offset in offsets {
+        let summary = segment_summary(topic_path, segment_offset)?;
+        if first_offset.is_none() {
+            first_offset = summary.first_offset;
+        }
+        if let Some(last_offset) = summary.last_offset {
+            end_offset = last_offset + 1;
+        }
+    }
+    let first_offset = first_offset.unwrap_or(end_offset);
+
+    // 只读取，不创建 _consumer_offsets 文件
+    if !topic_path.join(CONSUMER_OFFSETS_FILE_NAME).is_file() {
+        return Ok(vec![]);
+    }
+
+    let consumer_offsets = open_consumer_offsets(topic_path)?;
+    let mut names: Vec<&String> = consumer_offsets.names().collect();
+    names.sort();
+
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            let committed_offset = consumer_offsets.get(name);
+            let next_offset = committed_offset.map(|offset| offset + 1).unwrap_or(0).max(first_offset);
+
+            ConsumerLag {
+                consumer: name.to_owned(),
+                committed_offset,
+                lag: end_offset.saturating_sub(next_offset),
+            }
+        })
+        .collect())
+}
+
+/// 设置消费者已提交的位置，offset 为 None 时删除位置，从最旧的消息开始消费。
+/// 运行中的 yiilian-mq 会覆盖修改，需要先停止服务
+pub fn set_consumer_offset(topic_path: &Path, consumer_name: &str, offset: Option<u64>) -> Result<(), Error> {
+    let mut consumer_offsets = open_consumer_offsets(topic_path)?;
+
+    match offset {
+        Some(offset) => consumer_offsets.insert(consumer_name, offset),
+        None => {
+            consumer_offsets.remove(consumer_name);
+            Ok(())
+        }
+    }
+}
+
+/// 校验 segment 中所有消息的 crc，以及 .index 项是否指向对应的消息或包含该消息的批次
+pub fn check_segment(topic_path: &Path, segment_offset: u64) -> Result<SegmentCheck, Error> {
+    let mut log_data_file = open_log_data_file(topic_path, segment_offset)?;
+    let mut check = SegmentCheck {
+        segment_offset,
+        ..Default::default()
+    };
+
+    let mut pos = 0;
+    let mut next_offset = segment_offset;
+    'check: while let Some((messages, next_pos)) = log_data_file.next_messages(pos) {
+        for message in messages.iter() {
+            if message.offset() < next_offset {
+                break 'check;
+            }
+            next_offset = message.offset() + 1;
+        }
+
+        check.valid_count += messages.len() as u64;
+        pos = next_pos;
+    }
+
+    if pos < log_data_file.len() {
+        check.corrupt_pos = Some(pos);
+    }
+
+    match open_log_index_file(topic_path, segment_offset) {
+        Ok(mut log_index_file) => {
+            for i in 0..log_index_file.count() as usize {
+                let is_valid = match log_index_file.get(i) {
+                    Some(index_item) if index_item.message_pos() < log_data_file.len() => {
+                        match log_data_file.next_messages(index_item.message_pos()) {
+                            Some((messages, _)) => messages
+                                .iter()
+                                .any(|message| message.offset() == index_item.message_offset()),
+                            None => false,
+                        }
+                    }
+                    _ => false,
+                };
+
+                if !is_valid {
+                    check.bad_index_items += 1;
+                }
+            }
+        }
+        Err(_) => check.missing_index = true,
+    }
+
+    Ok(check)
+}
+
+/// 长度前缀超出文件大小时返回错误，避免读取时越界
+fn open_log_data_file(topic_path: &Path, segment_offset: u64) -> Result<LogDataFile, Error> {
+    let path = topic_path.join(gen_mq_file_name(segment_offset, LOG_DATA_FILE_EXTENSION));
+    let file = OpenOptions::new()
+        .read(true)
+        .open(&path)
+        .map_err(|error| Error::new_file(Some(error.into()), None))?;
+    let file_size = file
+        .metadata()
+        .map_err(|error| Error::new_file(Some(error.into()), None))?
+        .len() as usize;
+
+    let log_data_file = LogDataFile::new(segment_offset, file)?;
+    if log_data_file.total_size() > file_size {
+        Err(Error::new_file(
+            None,
+            Some(format!("Length of {:?} exceeds file size: {}", path, log_data_file.len())),
+        ))?;
+    }
+
+    Ok(log_data_file)
+}
+
+fn open_log_index_file(topic_path: &Path, segment_offset: u64) -> Result<LogIndexFile, Error> {
+    let path = topic_path.join(gen_mq_file_name(segment_offset, LOG_INDEX_FILE_EXTENSION));
+    let file = OpenOptions::new()
+        .read(true)
+        .open(&path)
+        .map_err(|error| Error::new_file(Some(error.into()), None))?;
+    let file_size = file
+        .metadata()
+        .map_err(|error| Error::new_file(Some(error.into()), None))?
+        .len() as usize;
+
+    let log_index_file = LogIndexFile::new(segment_offset, file)?;
+    if log_index_file.total_size() > file_size {
+        Err(Error::new_file(
+            None,
+            Some(format!("Length of {:?} exceeds file size: {}", path, log_index_file.len())),
+        ))?;
+    }
+
+    Ok(log_index_file)
+}
+
+fn open_consumer_offsets(topic_path: &Path) -> Result<ConsumerOffsets, Error> {
+    if !topic_path.is_dir() {
+        Err(Error::new_not_found(&format!("Not found topic: {:?}", topic_path)))?;
+    }
+
+    ConsumerOffsets::new_from_file(topic_path.join(CONSUMER_OFFSETS_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Seek, SeekFrom, Write};
+
+    use crate::{engine::Engine, message::in_message::InMessage, test_util::TestHome};
+
+    use super::*;
+
+    #[test]
+    fn test_tool() {
+        let topic_name = "test_tool";
+        let wd = TestHome::new("tool");
+        let mq_path = wd.home_dir().join(".yiilian/mq");
+        let topic_path = mq_path.join(topic_name);
+
+        {
+            let engine = Engine::new(100, wd.home_dir()).expect("create mq engine");
+            engine.remove_topic(topic_name);
+            engine.open_topic(topic_name).expect("open test_tool topic");
+
+            // 每个 segment 2 条消息：[0, 1] [2, 3] [4]
+            for i in 0..5 {
+                let message = InMessage(format!("value_{}", i).into());
+                engine.push_message(topic_name, message).unwrap();
+            }
+            engine.commit_offset(topic_name, "client_1", 1).unwrap();
+            engine.commit_offset(topic_name, "client_2", 4).unwrap();
+        }
+
+        assert!(topic_names(&mq_path).unwrap().contains(&topic_name.to_owned()));
+        assert_eq!(vec![0, 2, 4], segment_offsets(&topic_path).unwrap());
+
+        let summary = segment_summary(&topic_path, 2).unwrap();
+        assert_eq!(Some(2), summary.first_offset);
+        assert_eq!(Some(3), summary.last_offset);
+        assert_eq!(2, summary.message_count);
+        assert_eq!(2, summary.index_count);
+        assert_eq!(0, summary.batch_count);
+
+        let offsets: Vec<u64> = read_messages(&topic_path, 1, 3).unwrap().iter().map(|m| m.offset()).collect();
+        assert_eq!(vec![1, 2, 3], offsets);
+        assert!(read_messages(&topic_path, 5, 3).unwrap().is_empty());
+
+        let lags = consumer_lags(&topic_path).unwrap();
+        assert_eq!(("client_1", 3), (lags[0].consumer.as_str(), lags[0].lag));
+        assert_eq!(("client_2", 0), (lags[1].consumer.as_str(), lags[1].lag));
+
+        set_consumer_offset(&topic_path, "client_2", Some(2)).unwrap();
+        set_consumer_offset(&topic_path, "client_1", None).unwrap();
+        let lags = consumer_lags(&topic_path).unwrap();
+        assert_eq!(1, lags.len());
+        assert_eq!(2, lags[0].lag);
+
+        assert!(check_segment(&topic_path, 0).unwrap().is_ok());
+
+        // 破坏 segment 2 第二条消息的 value
+        let mut data_file = OpenOptions::new()
+            .write(true)
+            .open(topic_path.join(gen_mq_file_name(2, LOG_DATA_FILE_EXTENSION)))
+            .unwrap();
+        data_file.seek(SeekFrom::Start(8 + 34 + 30)).unwrap();
+        data_file.write_all(b"x").unwrap();
+
+        let check = check_segment(&topic_path, 2).unwrap();
+        assert_eq!(1, check.valid_count);
+        assert_eq!(Some(34), check.corrupt_pos);
+        assert_eq!(1, check.bad_index_items);
+        assert!(!check.is_ok());
+
+        fs::remove_dir_all(&topic_path).unwrap();
+    }
+}
@@ -1,25 +1,45 @@
 use std::{
+    collections::HashMap,
     fs::{self, OpenOptions},
-    path::PathBuf,
-    time::{Duration, SystemTime},
+    io::Read,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
 };
 
-use chrono::Utc;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use tokio::sync::watch;
 use yiilian_core::common::{
     error::Error,
     util::{atoi, binary_insert},
 };
 
 use crate::{
+    consumer_leases::{ConsumerLease, ConsumerLeases, Lease},
+    dead_letter::DeadLetter,
     consumer_offsets::ConsumerOffsets,
-    message::{in_message::InMessage, Message, MESSAGE_PREFIX_LEN},
+    dedup_window::DedupWindow,
+    message::{
+        batch::{encode_batch, split_batches, Compression},
+        in_message::InMessage,
+        Message, MESSAGE_HEADER_LEN, MESSAGE_KEY_HEADER_LEN, MESSAGE_PREFIX_LEN,
+    },
+    partition::parse_partition_topic_name,
+    topic_config::TopicConfig,
     segment::{
         active_segment::ActiveSegment, gen_mq_file_name, log_index::log_index_file::LogIndexFile,
-        poll_message_inner, LOG_DATA_FILE_EXTENSION, LOG_INDEX_FILE_EXTENSION,
+        log_data::{log_data_file::LogDataFile, LOGDATA_PREFIX_LEN},
+        log_index::{LOGINDEX_ITEM_LEN, LOGINDEX_PREFIX_LEN},
+        poll_message_inner, poll_messages_inner, poll_next_message_inner, read_time_index, RecoveryReport, CONSUMER_OFFSETS_FILE_NAME, LOG_DATA_FILE_EXTENSION, LOG_INDEX_FILE_EXTENSION,
+        TIME_INDEX_FILE_EXTENSION,
     },
 };
 
-const KEEP_SEGMENT_SECS: u64 = 24 * 60 * 60 * 3;
+const TOPIC_CONFIG_FILE_NAME: &str = "_topic_config";
+/// 压缩时新 segment 的临时目录
+pub(crate) const COMPACT_TMP_DIR_NAME: &str = "_compacting";
+/// 临时目录中的提交标记，存在时说明新 segment 已写完，可以替换原文件
+pub(crate) const COMPACT_COMMIT_FILE_NAME: &str = "_commit";
 
 #[derive(Debug)]
 pub struct Topic {
@@ -29,11 +49,28 @@ pub struct Topic {
     path: PathBuf,
     active_segment: ActiveSegment,
     consumers: ConsumerOffsets,
+    leases: ConsumerLeases,
     segment_offsets: Vec<SegmentInfo>,
+    config: TopicConfig,
+    last_purge_time: Option<Instant>,
+    /// 写入新消息时发送下一个 offset
+    push_tx: watch::Sender<u64>,
+    /// 打开 topic 时修复过的 segment
+    recovery_reports: Vec<RecoveryReport>,
+    /// 等待转入死信 topic 的消息
+    dead_letters: Vec<DeadLetter>,
+    dedup_window: DedupWindow,
+    /// topic 是分区时的分区号，否则为 0
+    partition: u32,
+    /// 轮流写入及读取分区时的下一个分区
+    partition_cursor: u32,
 }
 
 impl Topic {
     pub fn new(name: &str, path: PathBuf, log_data_size: usize) -> Result<Self, Error> {
+        // 上次重写 segment 时中断，先完成或丢弃
+        finish_rewrite(&path)?;
+
         let entries = fs::read_dir(path.clone())
             .map_err(|error| Error::new_file(Some(error.into()), None))?;
 
@@ -72,23 +109,66 @@ impl Topic {
             .get(segment_offsets.len() - 1)
             .expect("segment_offsets should exist")
             .offset;
+        let mut recovery_reports = vec![];
+
+        // 已写满的 segment 只在 .index 缺失或不完整时重建
+        for segment_info in &segment_offsets[..segment_offsets.len() - 1] {
+            if !is_log_index_broken(&path, segment_info.offset) {
+                continue;
+            }
+
+            let data_path = path.join(gen_mq_file_name(segment_info.offset, LOG_DATA_FILE_EXTENSION));
+            let data_size = fs::metadata(&data_path)
+                .map_err(|error| Error::new_file(Some(error.into()), None))?
+                .len() as usize;
+
+            let segment = ActiveSegment::new(segment_info.offset, path.clone(), data_size)?;
+            recovery_reports.push(segment.recovery_report().clone());
+        }
+
         let active_segment = ActiveSegment::new(last_segment_offset, path.clone(), log_data_size)?;
+        recovery_reports.push(active_segment.recovery_report().clone());
+        recovery_reports.retain(|report| report.is_repaired());
+        let (push_tx, _) = watch::channel(active_segment.get_next_offset());
 
         let consumer_offsets_path: PathBuf = {
             let mut p = path.clone();
-            p.push("_consumer_offsets");
+            p.push(CONSUMER_OFFSETS_FILE_NAME);
             p
         };
 
         let consumer_offsets = ConsumerOffsets::new_from_file(consumer_offsets_path)?;
 
+        let consumer_leases_path: PathBuf = {
+            let mut p = path.clone();
+            p.push("_consumer_leases");
+            p
+        };
+
+        let leases = ConsumerLeases::new_from_file(consumer_leases_path)?;
+
+        let config_path = path.join(TOPIC_CONFIG_FILE_NAME);
+        let config = TopicConfig::new_from_file(&config_path)?;
+        if !config_path.exists() {
+            config.flush(&config_path)?;
+        }
+
         Ok(Topic {
             name: name.to_owned(),
             path,
             active_segment,
             consumers: consumer_offsets,
+            leases,
             segment_offsets,
             log_data_size,
+            config,
+            last_purge_time: None,
+            push_tx,
+            recovery_reports,
+            dead_letters: vec![],
+            dedup_window: DedupWindow::new(),
+            partition: parse_partition_topic_name(name).map(|(_, partition)| partition).unwrap_or(0),
+            partition_cursor: 0,
         })
     }
 
@@ -97,7 +177,42 @@ impl Topic {
     }
 
     pub fn remove_consumer(&mut self, consumer_name: &str) {
-        self.consumers.remove(consumer_name)
+        self.consumers.remove(consumer_name);
+        self.leases.remove(consumer_name);
+    }
+
+    pub fn recovery_reports(&self) -> &Vec<RecoveryReport> {
+        &self.recovery_reports
+    }
+
+    pub fn config(&self) -> &TopicConfig {
+        &self.config
+    }
+
+    /// 修改并保存 topic 的配置
+    pub fn set_config(&mut self, config: TopicConfig) -> Result<(), Error> {
+        if config != self.config {
+            config.flush(&self.path.join(TOPIC_CONFIG_FILE_NAME))?;
+            self.config = config;
+        }
+
+        Ok(())
+    }
+
+    pub fn partition(&self) -> u32 {
+        self.partition
+    }
+
+    /// 按 config.partitions 轮流返回分区号
+    pub fn next_partition(&mut self) -> u32 {
+        let partition = self.partition_cursor % self.config.partition_count();
+        self.partition_cursor = partition + 1;
+
+        partition
+    }
+
+    pub fn consumer_leases(&self) -> &ConsumerLeases {
+        &self.leases
     }
 
     pub fn segment_offsets(&self) -> &Vec<SegmentInfo> {
@@ -105,7 +220,30 @@ impl Topic {
     }
 
     pub fn push_message(&mut self, message: InMessage) -> Result<(), Error> {
-        let message_size = 20 + message.0.len() + MESSAGE_PREFIX_LEN;
+        self.push_message_with_key(None, message)
+    }
+
+    /// 写入带 key 的消息，dedup_window_secs 内重复的 key 会被丢弃，丢弃时返回 false
+    pub fn push_keyed_message(&mut self, key: Bytes, message: InMessage) -> Result<bool, Error> {
+        if key.is_empty() || key.len() > u16::MAX as usize {
+            Err(Error::new_general(&format!("Invalid message key length: {}", key.len())))?;
+        }
+
+        if let Some(dedup_window_secs) = self.config.dedup_window_secs {
+            let now = Utc::now().timestamp_millis();
+            if self.dedup_window.check_and_insert(&key, now, Duration::from_secs(dedup_window_secs)) {
+                return Ok(false);
+            }
+        }
+
+        self.push_message_with_key(Some(key), message)?;
+
+        Ok(true)
+    }
+
+    fn push_message_with_key(&mut self, key: Option<Bytes>, message: InMessage) -> Result<(), Error> {
+        let key_len = key.as_ref().map(|key| key.len()).unwrap_or(0);
+        let message_size = MESSAGE_PREFIX_LEN + MESSAGE_HEADER_LEN + MESSAGE_KEY_HEADER_LEN + key_len + message.0.len();
 
         let enough_space = self.active_segment.enough_space(message_size);
 
@@ -119,9 +257,22 @@ impl Topic {
         }
 
         let new_offset = self.active_segment.get_next_offset();
-        let message = Message::new(new_offset, Utc::now().timestamp_millis(), message.0);
+        let message = Message::new_with_key(new_offset, Utc::now().timestamp_millis(), key, message.0);
 
-        self.active_segment.push_message(message)
+        self.active_segment.push_message(message)?;
+        self.push_tx.send_replace(new_offset + 1);
+
+        Ok(())
+    }
+
+    /// 每次 push_message 后会收到通知
+    pub fn subscribe_push(&self) -> watch::Receiver<u64> {
+        self.push_tx.subscribe()
+    }
+
+    /// 其他分区写入新消息时，通知订阅了分区 0 的消费者
+    pub(crate) fn notify_push(&self) {
+        self.push_tx.send_modify(|_| ());
     }
 
     pub fn count(&self, customer_name: &str) -> u64 {
@@ -205,87 +356,668 @@ impl Topic {
     }
 
     pub fn poll_message(&mut self, customer_name: &str) -> Option<Message> {
-        let (segment_offset, target_offset) =
-            if let Some(mut target_offset) = self.consumers.get(customer_name) {
-                target_offset += 1;
+        let message = self.poll_message_without_commit(customer_name);
 
-                // println!("{:?}", (self.get_segment_offset(target_offset), target_offset));
+        if let Some(message) = &message {
+            self.consumers.insert(customer_name, message.offset()).ok();
+        }
 
-                if let Some(segment_offset) = self.get_segment_offset(target_offset) {
-                    (segment_offset, target_offset)
-                } else {
-                    if let Some(segment_offset) = get_oldest_offset(&self.segment_offsets) {
-                        (segment_offset, segment_offset)
-                    } else {
-                        return None;
-                    }
-                }
+        message
+    }
+
+    /// 消费者下一条待消费的消息
+    fn poll_message_without_commit(&self, customer_name: &str) -> Option<Message> {
+        let target_offset = match self.consumers.get(customer_name) {
+            Some(offset) => offset + 1,
+            None => 0,
+        };
+
+        self.next_message(target_offset)
+    }
+
+    /// 第一条 offset 不小于 target_offset 的消息。
+    /// 压缩后 offset 不再连续，target_offset 已被清理时从 offset 最小的 segment 开始。
+    /// 重写后的 segment 修改时间可能与相邻 segment 相同，不能按修改时间判断最旧的 segment
+    fn next_message(&self, target_offset: u64) -> Option<Message> {
+        let start = self
+            .segment_offsets
+            .iter()
+            .rposition(|info| info.offset <= target_offset)
+            .unwrap_or(0);
+
+        for info in &self.segment_offsets[start..] {
+            if let Ok(Some(message)) = poll_next_message_inner(&self.path, info.offset, target_offset) {
+                return Some(message);
+            }
+        }
+
+        None
+    }
+
+    /// 批量获取最多 count 条消息，与 poll_message 一样在取出时提交位置
+    pub fn poll_messages(&mut self, consumer_name: &str, count: usize) -> Vec<Message> {
+        let messages = self.fetch_messages(consumer_name, count);
+
+        if let Some(last_message) = messages.last() {
+            self.consumers.insert(consumer_name, last_message.offset()).ok();
+        }
+
+        messages
+    }
+
+    /// 从消费者已提交位置之后读取最多 count 条消息，不提交位置，需要调用 commit_offset 提交
+    pub fn fetch_messages(&self, consumer_name: &str, count: usize) -> Vec<Message> {
+        // 第一条按 poll_message 的规则定位起始 offset
+        match self.poll_message_without_commit(consumer_name) {
+            Some(message) => self.read_messages(message.offset(), count),
+            None => vec![],
+        }
+    }
+
+    /// 从 offset 开始读取最多 count 条消息，与消费者无关，offset 不存在时从其后的第一条开始
+    pub fn read_messages(&self, offset: u64, count: usize) -> Vec<Message> {
+        let mut messages: Vec<Message> = vec![];
+
+        while messages.len() < count {
+            let message = match messages.last() {
+                None => match self.next_message(offset) {
+                    Some(message) => message,
+                    None => break,
+                },
+                Some(last_message) => match self.next_message(last_message.offset() + 1) {
+                    Some(message) => message,
+                    None => break,
+                },
+            };
+
+            let target_offset = message.offset();
+            let segment_offset = match self.get_segment_offset(target_offset) {
+                Some(segment_offset) => segment_offset,
+                None => break,
+            };
+
+            let batch = if segment_offset == self.active_segment.offset() {
+                self.active_segment.get_messages(target_offset, count - messages.len())
             } else {
-                if let Some(segment_offset) = get_oldest_offset(&self.segment_offsets) {
-                    (segment_offset, segment_offset)
-                } else {
-                    return None;
+                poll_messages_inner(&self.path, segment_offset, target_offset, count - messages.len())
+                    .unwrap_or_default()
+            };
+
+            if batch.is_empty() {
+                break;
+            }
+            messages.extend(batch);
+        }
+
+        messages
+    }
+
+    /// 提交消费者已消费的最后一条消息的 offset
+    pub fn commit_offset(&mut self, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        self.consumers.insert(consumer_name, offset)
+    }
+
+    /// 以租约方式分发消息：消息在 visibility 内未被 ack 则会重新分发。
+    /// 优先重新分发已超时或被 nack 的消息，其次分发新消息。
+    /// 同一个消费者不应与 poll_message 混用
+    pub fn poll_lease(&mut self, consumer_name: &str, visibility: Duration) -> Option<(Message, Lease)> {
+        let now = Utc::now().timestamp_millis();
+        let deadline = now + visibility.as_millis() as i64;
+        let mut state = self.leases.get(consumer_name).cloned().unwrap_or_default();
+        let mut is_changed = false;
+
+        let expired: Vec<u64> = state
+            .in_flight
+            .values()
+            .filter(|lease| lease.deadline <= now)
+            .map(|lease| lease.offset)
+            .collect();
+
+        let mut rst = None;
+        for offset in expired {
+            let lease = *state.in_flight.get(&offset).expect("get lease");
+            if self.config.is_delivery_exhausted(lease.delivery_count) {
+                state.in_flight.remove(&offset);
+                self.push_dead_letter(consumer_name, lease);
+                is_changed = true;
+                continue;
+            }
+
+            match self.read_message(offset) {
+                Some(message) => {
+                    let lease = state.in_flight.get_mut(&offset).expect("get lease");
+                    lease.deadline = deadline;
+                    lease.delivery_count += 1;
+
+                    rst = Some((message, *lease));
+                    is_changed = true;
+                    break;
+                }
+                None => {
+                    // 消息所在的 segment 已被清理，不再重新分发
+                    state.in_flight.remove(&offset);
+                    is_changed = true;
                 }
+            }
+        }
+
+        if rst.is_none() {
+            let next_offset = match state.next_offset {
+                Some(offset) => offset,
+                None => match self.consumers.get(consumer_name) {
+                    Some(offset) => offset + 1,
+                    None => 0,
+                },
             };
 
-        if let Ok(message) = poll_message_inner(&self.path, segment_offset, target_offset) {
-            if message.is_some() {
-                self.consumers.insert(customer_name, target_offset).ok();
+            // 待分发的 offset 已被清理或压缩时，从其后的第一条开始
+            if let Some(message) = self.next_message(next_offset) {
+                let lease = Lease {
+                    offset: message.offset(),
+                    deadline,
+                    delivery_count: 1,
+                    partition: self.partition,
+                };
+                state.in_flight.insert(message.offset(), lease);
+                state.next_offset = Some(message.offset() + 1);
+
+                rst = Some((message, lease));
+                is_changed = true;
+            }
+        }
+
+        if is_changed {
+            if let Err(error) = self.save_lease(consumer_name, state) {
+                log::trace!(target: "yiilian-mq::topic", "save lease error: {}", error);
+                return None;
+            }
+        }
+
+        rst
+    }
+
+    /// 确认消息已处理，提交位置只越过连续已确认的消息
+    pub fn ack(&mut self, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        let mut state = self
+            .leases
+            .get(consumer_name)
+            .cloned()
+            .ok_or_else(|| Error::new_general(&format!("Not found consumer: {}", consumer_name)))?;
+
+        if state.in_flight.remove(&offset).is_none() {
+            Err(Error::new_general(&format!("Message is not in flight: {}", offset)))?
+        }
+
+        self.save_lease(consumer_name, state)
+    }
+
+    /// 释放消息，按 redelivery_delay 等待后重新分发，分发次数达到 max_deliveries 时转入死信
+    pub fn nack(&mut self, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        let mut state = self
+            .leases
+            .get(consumer_name)
+            .cloned()
+            .ok_or_else(|| Error::new_general(&format!("Not found consumer: {}", consumer_name)))?;
+
+        let lease = match state.in_flight.get_mut(&offset) {
+            Some(lease) => lease,
+            None => Err(Error::new_general(&format!("Message is not in flight: {}", offset)))?,
+        };
+
+        if self.config.is_delivery_exhausted(lease.delivery_count) {
+            let lease = *lease;
+            state.in_flight.remove(&offset);
+            self.push_dead_letter(consumer_name, lease);
+        } else {
+            let delay = self.config.redelivery_delay(lease.delivery_count);
+            lease.deadline = Utc::now().timestamp_millis() + delay.as_millis() as i64;
+        }
+
+        self.save_lease(consumer_name, state)
+    }
+
+    /// 不再重新分发，直接将消息转入死信
+    pub fn dead_letter(&mut self, consumer_name: &str, offset: u64) -> Result<(), Error> {
+        let mut state = self
+            .leases
+            .get(consumer_name)
+            .cloned()
+            .ok_or_else(|| Error::new_general(&format!("Not found consumer: {}", consumer_name)))?;
+
+        match state.in_flight.remove(&offset) {
+            Some(lease) => self.push_dead_letter(consumer_name, lease),
+            None => Err(Error::new_general(&format!("Message is not in flight: {}", offset)))?,
+        }
+
+        self.save_lease(consumer_name, state)
+    }
+
+    /// 取出等待转入死信 topic 的消息
+    pub fn take_dead_letters(&mut self) -> Vec<DeadLetter> {
+        std::mem::take(&mut self.dead_letters)
+    }
+
+    fn push_dead_letter(&mut self, consumer_name: &str, lease: Lease) {
+        // 消息所在的 segment 已被清理时直接丢弃
+        if let Some(message) = self.read_message(lease.offset) {
+            self.dead_letters.push(DeadLetter {
+                consumer: consumer_name.to_owned(),
+                offset: lease.offset,
+                delivery_count: lease.delivery_count,
+                timestamp: message.timestamp(),
+                value: message.value().to_owned().into(),
+            });
+        }
+    }
+
+    /// 第一条 timestamp 不早于 time 的消息 offset，time 晚于所有消息时返回 None
+    pub fn offset_for_time(&self, time: DateTime<Utc>) -> Option<u64> {
+        let target_timestamp = time.timestamp_millis();
+
+        // segment_offsets 按 offset 升序，找到最后一个首条消息早于 time 的 segment
+        let pos = {
+            let mut left = 0;
+            let mut right = self.segment_offsets.len();
+            while left < right {
+                let mid = left + (right - left) / 2;
+                let segment_offset = self.segment_offsets[mid].offset;
+                // 压缩后 segment 的首条消息不一定是 segment_offset
+                match poll_next_message_inner(&self.path, segment_offset, segment_offset).ok().flatten() {
+                    Some(message) if message.timestamp() < target_timestamp => left = mid + 1,
+                    _ => right = mid,
+                }
             }
+            left
+        };
 
-            message
+        let mut target_offset = if pos == 0 {
+            self.segment_offsets.first()?.offset
         } else {
-            None
+            let segment_offset = self.segment_offsets[pos - 1].offset;
+
+            read_time_index(&self.path, segment_offset)
+                .and_then(|time_index| time_index.lower_by_timestamp(target_timestamp))
+                .map(|item| item.message_offset())
+                .unwrap_or(segment_offset)
+        };
+
+        // 从稀疏索引的位置开始顺序查找，可能跨入下一个 segment
+        loop {
+            let message = self.next_message(target_offset)?;
+            if message.timestamp() >= target_timestamp {
+                return Some(message.offset());
+            }
+            target_offset = message.offset() + 1;
         }
     }
 
+    /// 将消费者的位置移动到 time，之后 poll 到的第一条消息即 offset_for_time(time)。
+    /// 会清空消费者未确认的租约，返回移动后待消费的 offset
+    pub fn seek_to_time(&mut self, consumer_name: &str, time: DateTime<Utc>) -> Result<u64, Error> {
+        let target_offset = match self.offset_for_time(time) {
+            Some(offset) => offset,
+            None => self.active_segment.get_next_offset(),
+        };
+
+        match target_offset.checked_sub(1) {
+            Some(consumed_offset) => self.consumers.insert(consumer_name, consumed_offset)?,
+            None => self.consumers.remove(consumer_name),
+        }
+
+        if self.leases.get(consumer_name).is_some() {
+            let state = ConsumerLease {
+                next_offset: Some(target_offset),
+                ..Default::default()
+            };
+            self.leases.insert(consumer_name, state)?;
+        }
+
+        Ok(target_offset)
+    }
+
+    fn save_lease(&mut self, consumer_name: &str, state: ConsumerLease) -> Result<(), Error> {
+        let committable_offset = state.committable_offset();
+        self.leases.insert(consumer_name, state)?;
+
+        if let Some(committable_offset) = committable_offset {
+            if self.consumers.get(consumer_name) != Some(committable_offset) {
+                self.consumers.insert(consumer_name, committable_offset)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 所有消费者都已消费到的位置，该 offset 之前的消息都已被消费。没有消费者时返回 None
+    fn consumed_bound(&self) -> Option<u64> {
+        let mut consumer_names: Vec<&String> = self.consumers.names().collect();
+        consumer_names.extend(self.leases.names());
+
+        consumer_names
+            .into_iter()
+            .map(|name| self.consumers.get(name).map(|offset| offset + 1).unwrap_or(0))
+            .min()
+    }
+
+    /// segment 所有文件的字节数
+    fn segment_size(&self, segment_offset: u64) -> u64 {
+        [LOG_DATA_FILE_EXTENSION, LOG_INDEX_FILE_EXTENSION, TIME_INDEX_FILE_EXTENSION]
+            .iter()
+            .filter_map(|ext| fs::metadata(self.path.join(gen_mq_file_name(segment_offset, ext))).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+
+    fn read_message(&self, target_offset: u64) -> Option<Message> {
+        let segment_offset = self.get_segment_offset(target_offset)?;
+
+        poll_message_inner(&self.path, segment_offset, target_offset).ok()?
+    }
+
     pub fn get_segment_offset(&self, target_offset: u64) -> Option<u64> {
         get_floor_offset(target_offset, &self.segment_offsets)
     }
 
-    /// 删除过期文件
+    /// 距上次清理已超过 purge_interval_secs 时才清理
+    pub fn purge_segment_if_due(&mut self) {
+        if let Err(error) = self.leases.flush_if_due() {
+            log::warn!(target: "yiilian-mq::topic", "flush leases of {} error: {}", self.name, error);
+        }
+
+        let is_due = match self.last_purge_time {
+            Some(last_purge_time) => {
+                last_purge_time.elapsed() >= Duration::from_secs(self.config.purge_interval_secs)
+            }
+            None => true,
+        };
+
+        if is_due {
+            self.purge_segment();
+
+            if self.config.compact {
+                match self.compact() {
+                    Ok(removed_count) if removed_count > 0 => {
+                        log::debug!(target: "yiilian-mq::topic", "compact {}: removed {} messages", self.name, removed_count);
+                    }
+                    Ok(_) => (),
+                    Err(error) => {
+                        log::warn!(target: "yiilian-mq::topic", "compact {} error: {}", self.name, error);
+                    }
+                }
+            }
+
+            match self.compress() {
+                Ok(compressed_count) if compressed_count > 0 => {
+                    log::debug!(target: "yiilian-mq::topic", "compress {}: {} segments", self.name, compressed_count);
+                }
+                Ok(_) => (),
+                Err(error) => {
+                    log::warn!(target: "yiilian-mq::topic", "compress {} error: {}", self.name, error);
+                }
+            }
+
+            self.last_purge_time = Some(Instant::now());
+        }
+    }
+
+    /// 按保留策略删除 segment 文件，活动 segment 不会被删除
     pub fn purge_segment(&mut self) {
-        let outdate_segments =
-            find_outdate_segment(&self.segment_offsets, self.active_segment.offset());
+        let active_segment_offset = self.active_segment.offset();
+
+        let mut outdate_segments = match self.config.retention_secs {
+            Some(retention_secs) => {
+                find_outdate_segment(&self.segment_offsets, active_segment_offset, retention_secs)
+            }
+            None => vec![],
+        };
+
+        if let Some(retention_bytes) = self.config.retention_bytes {
+            let segment_sizes: Vec<u64> = self
+                .segment_offsets
+                .iter()
+                .map(|item| self.segment_size(item.offset))
+                .collect();
+
+            for offset in find_oversize_segment(
+                &self.segment_offsets,
+                &segment_sizes,
+                active_segment_offset,
+                retention_bytes,
+            ) {
+                if !outdate_segments.contains(&offset) {
+                    outdate_segments.push(offset);
+                }
+            }
+        }
+
+        if self.config.keep_unconsumed {
+            if let Some(consumed_bound) = self.consumed_bound() {
+                outdate_segments.retain(|offset| {
+                    is_segment_consumed(&self.segment_offsets, *offset, active_segment_offset, consumed_bound)
+                });
+            }
+        }
 
         self.segment_offsets
             .retain(|item| !outdate_segments.contains(&item.offset));
 
         for offset in outdate_segments {
-            let data_file_path = {
-                let file_name = gen_mq_file_name(offset, LOG_DATA_FILE_EXTENSION);
-                let mut base_path = self.path.clone();
-                base_path.push(file_name);
-                base_path
-            };
+            self.remove_segment_files(offset);
+        }
+    }
 
-            let index_file_path = {
-                let file_name = gen_mq_file_name(offset, LOG_INDEX_FILE_EXTENSION);
-                let mut base_path = self.path.clone();
-                base_path.push(file_name);
-                base_path
-            };
+    fn remove_segment_files(&self, segment_offset: u64) {
+        for ext in [LOG_DATA_FILE_EXTENSION, LOG_INDEX_FILE_EXTENSION, TIME_INDEX_FILE_EXTENSION] {
+            fs::remove_file(self.path.join(gen_mq_file_name(segment_offset, ext))).ok();
+        }
+    }
 
-            fs::remove_file(data_file_path).ok();
-            fs::remove_file(index_file_path).ok();
+    /// 压缩已写满的 segment：key 在之后的消息中再次出现时删除该消息，没有 key 的消息全部保留。
+    /// 保留下来的消息 offset 不变，返回删除的消息条数
+    pub fn compact(&mut self) -> Result<u64, Error> {
+        let active_segment_offset = self.active_segment.offset();
+
+        // key -> 最新消息的 offset
+        let mut latest_offsets: HashMap<Bytes, u64> = HashMap::new();
+        for info in &self.segment_offsets {
+            for message in read_segment_messages(&self.path, info.offset)? {
+                if let Some(key) = message.key() {
+                    latest_offsets.insert(Bytes::copy_from_slice(key), message.offset());
+                }
+            }
         }
+
+        let mut removed_count = 0;
+        let mut empty_segments = vec![];
+        for info in &self.segment_offsets {
+            if info.offset == active_segment_offset {
+                continue;
+            }
+
+            let messages = read_segment_messages(&self.path, info.offset)?;
+            let total_count = messages.len();
+            let kept: Vec<Message> = messages
+                .into_iter()
+                .filter(|message| match message.key() {
+                    Some(key) => latest_offsets.get(key) == Some(&message.offset()),
+                    None => true,
+                })
+                .collect();
+
+            if kept.len() == total_count {
+                continue;
+            }
+            removed_count += (total_count - kept.len()) as u64;
+
+            if kept.is_empty() {
+                empty_segments.push(info.offset);
+            } else {
+                rewrite_segment(&self.path, info, kept, self.config.compression)?;
+            }
+        }
+
+        self.segment_offsets.retain(|item| !empty_segments.contains(&item.offset));
+        for offset in empty_segments {
+            self.remove_segment_files(offset);
+        }
+
+        Ok(removed_count)
+    }
+
+    /// 按 config.compression 压缩还未压缩的已写满 segment，消息的 offset 不变，返回压缩的 segment 数
+    pub fn compress(&mut self) -> Result<usize, Error> {
+        let compression = match self.config.compression {
+            Some(compression) => compression,
+            None => return Ok(0),
+        };
+        let active_segment_offset = self.active_segment.offset();
+
+        let mut compressed_count = 0;
+        for info in &self.segment_offsets {
+            if info.offset == active_segment_offset || is_segment_compressed(&self.path, info.offset)? {
+                continue;
+            }
+
+            let messages = read_segment_messages(&self.path, info.offset)?;
+            if messages.is_empty() {
+                continue;
+            }
+
+            rewrite_segment(&self.path, info, messages, Some(compression))?;
+            compressed_count += 1;
+        }
+
+        Ok(compressed_count)
     }
 }
 
-fn get_oldest_offset(array: &Vec<SegmentInfo>) -> Option<u64> {
-    if array.len() == 0 {
-        return None;
+/// 读取 segment 中的全部消息
+fn read_segment_messages(topic_path: &Path, segment_offset: u64) -> Result<Vec<Message>, Error> {
+    let data_path = topic_path.join(gen_mq_file_name(segment_offset, LOG_DATA_FILE_EXTENSION));
+    let data_file = OpenOptions::new()
+        .read(true)
+        .open(&data_path)
+        .map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+    let mut log_data_file = LogDataFile::new(segment_offset, data_file)?;
+
+    Ok(log_data_file.get_messages(0, 0, usize::MAX).unwrap_or_default())
+}
+
+/// segment 的第一条记录是否为压缩批次，压缩时整个 segment 一起重写
+fn is_segment_compressed(topic_path: &Path, segment_offset: u64) -> Result<bool, Error> {
+    let data_path = topic_path.join(gen_mq_file_name(segment_offset, LOG_DATA_FILE_EXTENSION));
+    let data_file = OpenOptions::new()
+        .read(true)
+        .open(&data_path)
+        .map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+    let mut log_data_file = LogDataFile::new(segment_offset, data_file)?;
+
+    Ok(matches!(log_data_file.next(0), Some((message, _)) if message.is_batch()))
+}
+
+/// 先在临时目录中写入新的 segment 并写入提交标记，再替换原文件，并保留原 segment 的修改时间。
+/// compression 不为 None 时按 BATCH_SIZE 分批压缩
+fn rewrite_segment(
+    topic_path: &Path,
+    segment_info: &SegmentInfo,
+    messages: Vec<Message>,
+    compression: Option<Compression>,
+) -> Result<(), Error> {
+    let tmp_path = topic_path.join(COMPACT_TMP_DIR_NAME);
+    finish_rewrite(topic_path)?;
+    fs::create_dir_all(&tmp_path).map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+    // 时间索引的容量按未压缩的大小计算
+    let raw_size = LOGDATA_PREFIX_LEN + messages.iter().map(|message| message.total_size()).sum::<usize>();
+    let entries = match compression {
+        Some(compression) => split_batches(messages)
+            .iter()
+            .map(|batch| encode_batch(batch, compression))
+            .collect::<Result<Vec<Message>, Error>>()?,
+        None => messages,
+    };
+    let data_size = LOGDATA_PREFIX_LEN + entries.iter().map(|entry| entry.total_size()).sum::<usize>();
+
+    {
+        let mut segment = ActiveSegment::new(segment_info.offset, tmp_path.clone(), raw_size.max(data_size))?;
+        for entry in entries {
+            segment.push_message(entry)?;
+        }
+    }
+
+    // 压缩后的数据比文件小，截掉多余的容量
+    if data_size < raw_size {
+        let tmp_data_path = tmp_path.join(gen_mq_file_name(segment_info.offset, LOG_DATA_FILE_EXTENSION));
+        OpenOptions::new()
+            .write(true)
+            .open(&tmp_data_path)
+            .and_then(|file| file.set_len(data_size as u64))
+            .map_err(|error| Error::new_file(Some(error.into()), None))?;
     }
 
-    let mut oldest = array.first().expect("get oldest");
+    // 改名不改变修改时间，在临时目录中先设置好
+    let tmp_data_path = tmp_path.join(gen_mq_file_name(segment_info.offset, LOG_DATA_FILE_EXTENSION));
+    OpenOptions::new()
+        .write(true)
+        .open(&tmp_data_path)
+        .and_then(|file| file.set_modified(segment_info.mod_time))
+        .map_err(|error| Error::new_file(Some(error.into()), None))?;
 
-    for item in array {
-        if item.mod_time < oldest.mod_time {
-            oldest = item;
+    // 写入提交标记后才替换原文件，替换中途崩溃时由下次打开 topic 完成剩下的替换
+    fs::write(tmp_path.join(COMPACT_COMMIT_FILE_NAME), b"")
+        .map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+    finish_rewrite(topic_path)
+}
+
+/// 临时目录有提交标记时，把其中的 segment 文件移到 topic 目录，否则原 segment 未被改动，直接删除临时目录
+fn finish_rewrite(topic_path: &Path) -> Result<(), Error> {
+    let tmp_path = topic_path.join(COMPACT_TMP_DIR_NAME);
+    if !tmp_path.exists() {
+        return Ok(());
+    }
+
+    if tmp_path.join(COMPACT_COMMIT_FILE_NAME).exists() {
+        let entries = fs::read_dir(&tmp_path).map_err(|error| Error::new_file(Some(error.into()), None))?;
+        for entry in entries {
+            let entry = entry.map_err(|error| Error::new_file(Some(error.into()), None))?;
+            let file_name = entry.file_name();
+            if file_name == COMPACT_COMMIT_FILE_NAME {
+                continue;
+            }
+
+            fs::rename(entry.path(), topic_path.join(&file_name))
+                .map_err(|error| Error::new_file(Some(error.into()), None))?;
         }
     }
 
-    Some(oldest.offset)
+    fs::remove_dir_all(&tmp_path).map_err(|error| Error::new_file(Some(error.into()), None))
+}
+
+/// .index 文件缺失，或长度前缀超出文件大小
+fn is_log_index_broken(topic_path: &Path, segment_offset: u64) -> bool {
+    let index_path = topic_path.join(gen_mq_file_name(segment_offset, LOG_INDEX_FILE_EXTENSION));
+
+    let mut file = match fs::File::open(&index_path) {
+        Ok(file) => file,
+        Err(_) => return true,
+    };
+
+    let file_size = match file.metadata() {
+        Ok(metadata) => metadata.len() as usize,
+        Err(_) => return true,
+    };
+
+    let mut buf = [0; LOGINDEX_PREFIX_LEN];
+    if file_size < LOGINDEX_PREFIX_LEN || file.read_exact(&mut buf).is_err() {
+        return true;
+    }
+
+    let length = u64::from_be_bytes(buf) as usize;
+
+    length == 0 || !length.is_multiple_of(LOGINDEX_ITEM_LEN) || LOGINDEX_PREFIX_LEN + length > file_size
 }
 
 fn get_floor_offset(target_offset: u64, array: &Vec<SegmentInfo>) -> Option<u64> {
@@ -323,9 +1055,13 @@ fn get_floor_offset(target_offset: u64, array: &Vec<SegmentInfo>) -> Option<u64>
     Some(mid_offset)
 }
 
-fn find_outdate_segment(segment_infos: &Vec<SegmentInfo>, active_segment_offset: u64) -> Vec<u64> {
+fn find_outdate_segment(
+    segment_infos: &Vec<SegmentInfo>,
+    active_segment_offset: u64,
+    retention_secs: u64,
+) -> Vec<u64> {
     let now = SystemTime::now();
-    let retain_time = now - Duration::from_secs(KEEP_SEGMENT_SECS);
+    let retain_time = now - Duration::from_secs(retention_secs);
 
     let mut outdate_offsets = vec![];
 
@@ -338,6 +1074,47 @@ fn find_outdate_segment(segment_infos: &Vec<SegmentInfo>, active_segment_offset:
     outdate_offsets
 }
 
+/// 总字节数超过 retention_bytes 时，从最旧的 segment 开始删除
+fn find_oversize_segment(
+    segment_infos: &[SegmentInfo],
+    segment_sizes: &[u64],
+    active_segment_offset: u64,
+    retention_bytes: u64,
+) -> Vec<u64> {
+    let mut total_size: u64 = segment_sizes.iter().sum();
+    let mut oversize_offsets = vec![];
+
+    for (item, size) in segment_infos.iter().zip(segment_sizes) {
+        if total_size <= retention_bytes {
+            break;
+        }
+
+        if item.offset != active_segment_offset {
+            oversize_offsets.push(item.offset);
+            total_size -= size;
+        }
+    }
+
+    oversize_offsets
+}
+
+/// segment 中的消息是否都在 consumed_bound 之前
+fn is_segment_consumed(
+    segment_infos: &[SegmentInfo],
+    segment_offset: u64,
+    active_segment_offset: u64,
+    consumed_bound: u64,
+) -> bool {
+    if segment_offset == active_segment_offset {
+        return false;
+    }
+
+    match segment_infos.iter().find(|item| item.offset > segment_offset) {
+        Some(next_segment) => next_segment.offset <= consumed_bound,
+        None => false,
+    }
+}
+
 #[derive(Debug, Eq)]
 pub struct SegmentInfo {
     pub offset: u64,
@@ -370,6 +1147,8 @@ impl Ord for SegmentInfo {
 
 #[cfg(test)]
 mod tests {
+    use crate::topic_config::DEFAULT_RETENTION_SECS;
+
     use super::*;
 
     #[test]
@@ -402,30 +1181,36 @@ mod tests {
         let mod_time = SystemTime::now();
 
         let segment_infos = vec![
-            SegmentInfo::new(0, mod_time - Duration::from_secs(10 * KEEP_SEGMENT_SECS)),
-            SegmentInfo::new(2, mod_time - Duration::from_secs(20 * KEEP_SEGMENT_SECS)),
-            SegmentInfo::new(4, mod_time - Duration::from_secs(5 * KEEP_SEGMENT_SECS)),
+            SegmentInfo::new(0, mod_time - Duration::from_secs(10 * DEFAULT_RETENTION_SECS)),
+            SegmentInfo::new(2, mod_time - Duration::from_secs(20 * DEFAULT_RETENTION_SECS)),
+            SegmentInfo::new(4, mod_time - Duration::from_secs(5 * DEFAULT_RETENTION_SECS)),
             SegmentInfo::new(5, mod_time),
         ];
 
-        let rst = find_outdate_segment(&segment_infos, 4);
+        let rst = find_outdate_segment(&segment_infos, 4, DEFAULT_RETENTION_SECS);
 
         assert_eq!(2, rst.len())
     }
 
     #[test]
-    fn test_get_oldest_offset() {
+    fn test_find_oversize_segment() {
         let mod_time = SystemTime::now();
 
         let segment_infos = vec![
-            SegmentInfo::new(0, mod_time - Duration::from_secs(10 * KEEP_SEGMENT_SECS)),
-            SegmentInfo::new(2, mod_time - Duration::from_secs(20 * KEEP_SEGMENT_SECS)),
-            SegmentInfo::new(4, mod_time - Duration::from_secs(5 * KEEP_SEGMENT_SECS)),
+            SegmentInfo::new(0, mod_time),
+            SegmentInfo::new(2, mod_time),
+            SegmentInfo::new(4, mod_time),
             SegmentInfo::new(5, mod_time),
         ];
+        let segment_sizes = vec![10, 10, 10, 10];
 
-        let rst = get_oldest_offset(&segment_infos);
+        assert_eq!(vec![0, 2], find_oversize_segment(&segment_infos, &segment_sizes, 5, 25));
+        assert!(find_oversize_segment(&segment_infos, &segment_sizes, 5, 40).is_empty());
+        // 活动 segment 不删除
+        assert_eq!(vec![0, 2, 4], find_oversize_segment(&segment_infos, &segment_sizes, 5, 0));
 
-        assert_eq!(2, rst.unwrap());
+        assert!(is_segment_consumed(&segment_infos, 0, 5, 2));
+        assert!(!is_segment_consumed(&segment_infos, 2, 5, 3));
+        assert!(!is_segment_consumed(&segment_infos, 5, 5, 100));
     }
 }
@@ -0,0 +1,185 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use yiilian_core::common::error::Error;
+
+use crate::message::batch::Compression;
+
+/// 默认保留 3 天
+pub const DEFAULT_RETENTION_SECS: u64 = 24 * 60 * 60 * 3;
+pub const DEFAULT_PURGE_INTERVAL_SECS: u64 = 60;
+pub const DEFAULT_MAX_REDELIVERY_DELAY_SECS: u64 = 60 * 60;
+
+/// topic 的元数据，保存在 topic 目录下的 _topic_config 文件中
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopicConfig {
+    /// segment 最长保留秒数，None 表示不按时间清理
+    pub retention_secs: Option<u64>,
+    /// topic 所有 segment 文件的总字节数上限，超过时从最旧的 segment 开始清理
+    pub retention_bytes: Option<u64>,
+    /// 为 true 时，segment 必须被所有消费者消费过才会被清理
+    pub keep_unconsumed: bool,
+    /// 清理的间隔秒数
+    pub purge_interval_secs: u64,
+    /// 租约方式下消息最多分发的次数，超过后转入死信 topic，None 表示不限制
+    pub max_deliveries: Option<u32>,
+    /// nack 后首次重新分发前等待的秒数，之后每次翻倍
+    pub redelivery_delay_secs: u64,
+    /// 重新分发等待秒数的上限
+    pub max_redelivery_delay_secs: u64,
+    /// 带 key 的消息在首次写入后的秒数内重复写入会被丢弃，None 表示不去重
+    pub dedup_window_secs: Option<u64>,
+    /// 为 true 时，清理时同时压缩已写满的 segment，每个 key 只保留最新的消息
+    pub compact: bool,
+    /// 分区数，只能增加。带 key 的消息按 key 写入分区，其余消息轮流写入各分区
+    pub partitions: u32,
+    /// 已写满的 segment 在清理时按批压缩，None 表示不压缩
+    pub compression: Option<Compression>,
+}
+
+impl Default for TopicConfig {
+    fn default() -> Self {
+        TopicConfig {
+            retention_secs: Some(DEFAULT_RETENTION_SECS),
+            retention_bytes: None,
+            keep_unconsumed: false,
+            purge_interval_secs: DEFAULT_PURGE_INTERVAL_SECS,
+            max_deliveries: None,
+            redelivery_delay_secs: 0,
+            max_redelivery_delay_secs: DEFAULT_MAX_REDELIVERY_DELAY_SECS,
+            dedup_window_secs: None,
+            compact: false,
+            partitions: 1,
+            compression: None,
+        }
+    }
+}
+
+impl TopicConfig {
+    pub fn new() -> Self {
+        TopicConfig::default()
+    }
+
+    pub fn retention_secs(mut self, retention_secs: Option<u64>) -> Self {
+        self.retention_secs = retention_secs;
+        self
+    }
+
+    pub fn retention_bytes(mut self, retention_bytes: Option<u64>) -> Self {
+        self.retention_bytes = retention_bytes;
+        self
+    }
+
+    pub fn keep_unconsumed(mut self, keep_unconsumed: bool) -> Self {
+        self.keep_unconsumed = keep_unconsumed;
+        self
+    }
+
+    pub fn purge_interval_secs(mut self, purge_interval_secs: u64) -> Self {
+        self.purge_interval_secs = purge_interval_secs;
+        self
+    }
+
+    pub fn max_deliveries(mut self, max_deliveries: Option<u32>) -> Self {
+        self.max_deliveries = max_deliveries;
+        self
+    }
+
+    pub fn redelivery_delay_secs(mut self, redelivery_delay_secs: u64) -> Self {
+        self.redelivery_delay_secs = redelivery_delay_secs;
+        self
+    }
+
+    pub fn max_redelivery_delay_secs(mut self, max_redelivery_delay_secs: u64) -> Self {
+        self.max_redelivery_delay_secs = max_redelivery_delay_secs;
+        self
+    }
+
+    pub fn dedup_window_secs(mut self, dedup_window_secs: Option<u64>) -> Self {
+        self.dedup_window_secs = dedup_window_secs;
+        self
+    }
+
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+
+    pub fn partitions(mut self, partitions: u32) -> Self {
+        self.partitions = partitions;
+        self
+    }
+
+    pub fn compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// 至少 1 个分区
+    pub fn partition_count(&self) -> u32 {
+        self.partitions.max(1)
+    }
+
+    /// 第 delivery_count 次分发失败后，重新分发前等待的时长
+    pub fn redelivery_delay(&self, delivery_count: u32) -> Duration {
+        let factor = 1u64.checked_shl(delivery_count.saturating_sub(1)).unwrap_or(u64::MAX);
+        let secs = self
+            .redelivery_delay_secs
+            .saturating_mul(factor)
+            .min(self.max_redelivery_delay_secs);
+
+        Duration::from_secs(secs)
+    }
+
+    /// 已分发 delivery_count 次的消息是否不再重新分发
+    pub fn is_delivery_exhausted(&self, delivery_count: u32) -> bool {
+        match self.max_deliveries {
+            Some(max_deliveries) => delivery_count >= max_deliveries,
+            None => false,
+        }
+    }
+
+    /// 文件不存在时返回默认配置
+    pub fn new_from_file(path: &PathBuf) -> Result<Self, Error> {
+        if !path.exists() {
+            return Ok(TopicConfig::default());
+        }
+
+        let data = fs::read_to_string(path)
+            .map_err(|error| Error::new_file(Some(error.into()), None))?;
+
+        serde_yaml::from_str(&data)
+            .map_err(|error| Error::new_file(Some(error.into()), None))
+    }
+
+    pub fn flush(&self, path: &PathBuf) -> Result<(), Error> {
+        let data = serde_yaml::to_string(self).expect("serde_yaml::to_string() failed");
+
+        fs::write(path, data)
+            .map_err(|error| Error::new_file(Some(error.into()), None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redelivery_delay() {
+        let config = TopicConfig::new()
+            .redelivery_delay_secs(10)
+            .max_redelivery_delay_secs(60)
+            .max_deliveries(Some(3));
+
+        assert_eq!(Duration::from_secs(10), config.redelivery_delay(1));
+        assert_eq!(Duration::from_secs(20), config.redelivery_delay(2));
+        assert_eq!(Duration::from_secs(40), config.redelivery_delay(3));
+        assert_eq!(Duration::from_secs(60), config.redelivery_delay(4));
+        assert_eq!(Duration::from_secs(60), config.redelivery_delay(100));
+
+        assert!(!config.is_delivery_exhausted(2));
+        assert!(config.is_delivery_exhausted(3));
+        assert!(!TopicConfig::new().is_delivery_exhausted(u32::MAX));
+    }
+}
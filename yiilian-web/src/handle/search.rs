@@ -29,6 +29,9 @@ pub async fn search(Query(params): Query<HashMap<String, String>>) -> Result<Htm
             .ok_or(WebError::from_error(anyhow!(
                 "Field 'info_hash' not found in schema"
             )))?;
+        // 旧索引中没有该字段
+        let info_hash_v2 = schema.get_field("info_hash_v2");
+        let magnet = schema.get_field("magnet");
         let res_type = schema
             .get_field("res_type")
             .ok_or(WebError::from_error(anyhow!(
@@ -50,7 +53,11 @@ pub async fn search(Query(params): Query<HashMap<String, String>>) -> Result<Htm
                 "Field 'file_sizes' not found in schema"
             )))?;
 
-        let query_parser = QueryParser::for_index(app_state().index(), vec![info_hash, file_paths]);
+        let mut default_fields = vec![info_hash, file_paths];
+        if let Some(info_hash_v2) = info_hash_v2 {
+            default_fields.push(info_hash_v2);
+        }
+        let query_parser = QueryParser::for_index(app_state().index(), default_fields);
         let query = query_parser.parse_query(q)?;
         let top_docs = searcher.search(&query, &TopDocs::with_limit(10))?;
 
@@ -59,6 +66,14 @@ pub async fn search(Query(params): Query<HashMap<String, String>>) -> Result<Htm
             let retrieved_doc = searcher.doc(doc_address)?;
 
             let info_hash = retrieved_doc.get_first(info_hash).unwrap().as_text().unwrap().to_owned();
+            let info_hash_v2 = info_hash_v2
+                .and_then(|field| retrieved_doc.get_first(field))
+                .and_then(|value| value.as_text())
+                .map(|value| value.to_owned());
+            let magnet = magnet
+                .and_then(|field| retrieved_doc.get_first(field))
+                .and_then(|value| value.as_text())
+                .map(|value| value.to_owned());
             let res_type = retrieved_doc.get_first(res_type).unwrap().as_u64().unwrap() as i32;
             let create_time = retrieved_doc.get_first(create_time).unwrap().as_text().unwrap().to_owned();
 
@@ -74,6 +89,8 @@ pub async fn search(Query(params): Query<HashMap<String, String>>) -> Result<Htm
 
             let info_doc = ResInfoDoc { 
                 info_hash, 
+                info_hash_v2,
+                magnet,
                 res_type, 
                 create_time, 
                 file_paths: file_path_list, 